@@ -0,0 +1,206 @@
+//! Steps the core in lockstep with a register trace produced by another 8080 emulator and
+//! reports the first instruction where the two disagree.
+//!
+//! ```text
+//! cargo run -p core --example difftrace -- <rom> <trace> \
+//!     [--columns pc,a,f,b,c,d,e,h,l,sp] [--flags-mask D5]
+//! ```
+//!
+//! Every trace line holds the state *before* the instruction at `pc` runs, as whitespace-separated
+//! hex columns. Anything up to a `:` or `=` in a column is ignored, so both `1A2B` and `PC:1A2B`
+//! work. Column names: `pc`, `sp`, `a`, `f`, `b`, `c`, `d`, `e`, `h`, `l`, the pairs `af`, `bc`,
+//! `de`, `hl`, and `-` for columns to skip. `--flags-mask` selects which flag bits are compared,
+//! for references that don't model every flag.
+//!
+//! When the reference jumps to RST 1 or RST 2 (0x0008/0x0010) on its own, the matching interrupt is
+//! raised on the core so traces of Space Invaders itself can be followed.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::process::ExitCode;
+
+use core::{Emulator, Registers};
+
+const HISTORY: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    Pc,
+    Sp,
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Af,
+    Bc,
+    De,
+    Hl,
+    Skip,
+}
+
+impl Column {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name.trim().to_ascii_lowercase().as_str() {
+            "pc" => Self::Pc,
+            "sp" => Self::Sp,
+            "a" => Self::A,
+            "f" | "flags" | "psw" => Self::F,
+            "b" => Self::B,
+            "c" => Self::C,
+            "d" => Self::D,
+            "e" => Self::E,
+            "h" => Self::H,
+            "l" => Self::L,
+            "af" => Self::Af,
+            "bc" => Self::Bc,
+            "de" => Self::De,
+            "hl" => Self::Hl,
+            "-" | "_" => Self::Skip,
+            other => return Err(format!("unknown column: {}", other)),
+        })
+    }
+}
+
+/// Expected value of every register the trace provides, in the order they were parsed.
+type Expected = Vec<(&'static str, u16, u16)>;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> Result<bool, String> {
+    let mut args = std::env::args().skip(1);
+    let mut paths = Vec::new();
+    let mut columns = "pc,a,f,b,c,d,e,h,l,sp".to_string();
+    let mut flags_mask = 0xD5;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--columns" => columns = args.next().ok_or("missing value for --columns")?,
+            "--flags-mask" => {
+                let mask = args.next().ok_or("missing value for --flags-mask")?;
+                flags_mask = u8::from_str_radix(mask.trim_start_matches("0x"), 16).map_err(|e| e.to_string())?;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let [rom_path, trace_path] = paths.as_slice() else {
+        return Err("usage: difftrace <rom> <trace> [--columns ...] [--flags-mask XX]".to_string());
+    };
+    let columns = columns.split(',').map(Column::parse).collect::<Result<Vec<_>, _>>()?;
+
+    let rom = fs::read(rom_path).map_err(|e| format!("could not read {}: {}", rom_path, e))?;
+    let trace = fs::read_to_string(trace_path).map_err(|e| format!("could not read {}: {}", trace_path, e))?;
+
+    let mut emulator = Emulator::new(&rom);
+    let mut history: VecDeque<(usize, Registers)> = VecDeque::with_capacity(HISTORY);
+    let mut checked = 0;
+
+    for (i, line) in trace.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let expected = parse_line(line, &columns, flags_mask).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let expected_pc = expected.iter().find(|(name, ..)| *name == "pc").map(|&(_, val, _)| val);
+
+        // Follow interrupts taken by the reference
+        if let Some(pc @ (0x08 | 0x10)) = expected_pc {
+            if emulator.cpu().pc() != pc {
+                emulator.interrupt((pc >> 3) as u8);
+            }
+        }
+
+        let actual = emulator.cpu().registers();
+        let mismatches: Vec<_> = expected.iter()
+            .map(|&(name, val, mask)| (name, val, register(&actual, name), mask))
+            .filter(|&(_, expected, actual, mask)| expected & mask != actual & mask)
+            .collect();
+
+        if !mismatches.is_empty() {
+            println!("divergence at trace line {} after {} matching instructions", i + 1, checked);
+            println!("  trace: {}", line.trim());
+            for (name, expected, actual, _) in mismatches {
+                println!("  {:>2}: expected {:04X}, got {:04X}", name, expected, actual);
+            }
+            println!("  flags: {}", actual.flags);
+            println!("previous instructions:");
+            for (line, regs) in &history {
+                println!("  line {:>6}: PC={:04X} A={:02X} F={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} SP={:04X}",
+                    line + 1, regs.pc, regs.a, regs.flags.to_psw(), regs.b, regs.c, regs.d, regs.e, regs.h, regs.l, regs.sp);
+            }
+            return Ok(false);
+        }
+
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back((i, actual));
+
+        emulator.step().map_err(|e| format!("line {}: core error: {}", i + 1, e))?;
+        while emulator.event().is_some() {}
+        checked += 1;
+    }
+
+    println!("no divergence in {} instructions", checked);
+    Ok(true)
+}
+
+fn parse_line(line: &str, columns: &[Column], flags_mask: u8) -> Result<Expected, String> {
+    let fields: Vec<_> = line.split_whitespace().collect();
+    if fields.len() < columns.len() {
+        return Err(format!("expected {} columns, found {}", columns.len(), fields.len()));
+    }
+
+    let mut expected = Vec::new();
+    for (&column, field) in columns.iter().zip(fields) {
+        let digits = field.rsplit([':', '=']).next().unwrap().trim_start_matches("0x");
+        if column == Column::Skip { continue; }
+        let val = u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hex value: {}", field))?;
+
+        let flags_mask = flags_mask as u16;
+        match column {
+            Column::Pc => expected.push(("pc", val, 0xFFFF)),
+            Column::Sp => expected.push(("sp", val, 0xFFFF)),
+            Column::A => expected.push(("a", val, 0xFF)),
+            Column::F => expected.push(("f", val, flags_mask)),
+            Column::B => expected.push(("b", val, 0xFF)),
+            Column::C => expected.push(("c", val, 0xFF)),
+            Column::D => expected.push(("d", val, 0xFF)),
+            Column::E => expected.push(("e", val, 0xFF)),
+            Column::H => expected.push(("h", val, 0xFF)),
+            Column::L => expected.push(("l", val, 0xFF)),
+            Column::Af => expected.extend([("a", val >> 8, 0xFF), ("f", val & 0xFF, flags_mask)]),
+            Column::Bc => expected.extend([("b", val >> 8, 0xFF), ("c", val & 0xFF, 0xFF)]),
+            Column::De => expected.extend([("d", val >> 8, 0xFF), ("e", val & 0xFF, 0xFF)]),
+            Column::Hl => expected.extend([("h", val >> 8, 0xFF), ("l", val & 0xFF, 0xFF)]),
+            Column::Skip => unreachable!(),
+        }
+    }
+
+    Ok(expected)
+}
+
+fn register(regs: &Registers, name: &str) -> u16 {
+    match name {
+        "pc" => regs.pc,
+        "sp" => regs.sp,
+        "a" => regs.a as u16,
+        "f" => regs.flags.to_psw() as u16,
+        "b" => regs.b as u16,
+        "c" => regs.c as u16,
+        "d" => regs.d as u16,
+        "e" => regs.e as u16,
+        "h" => regs.h as u16,
+        "l" => regs.l as u16,
+        _ => unreachable!(),
+    }
+}
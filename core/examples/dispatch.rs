@@ -0,0 +1,70 @@
+//! Benchmarks the CPU's dispatch on the Space Invaders attract mode: [`CPU::step`], which jumps
+//! through a table of handlers specialized per opcode, against decoding each instruction with
+//! [`CPU::decode_at`] and running it through the single match of [`CPU::execute`].
+//!
+//! ```text
+//! cargo run --release -p core --example dispatch [-- <frames>]
+//! ```
+//!
+//! Both runs raise the two screen interrupts every half frame, but only `step` takes interrupts
+//! that were latched while disabled, so their instruction counts can differ slightly.
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use core::CPU;
+
+const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+const CYCLES_PER_HALF_FRAME: u32 = 2_000_000 / 120;
+const DEFAULT_FRAMES: u32 = 6_000;
+
+fn main() -> ExitCode {
+    let frames = match std::env::args().nth(1).map(|arg| arg.parse()) {
+        None => DEFAULT_FRAMES,
+        Some(Ok(frames)) => frames,
+        Some(Err(e)) => {
+            eprintln!("error: invalid frame count: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let table = run(frames, |cpu| cpu.step().expect("emulation error"));
+    let decoded = run(frames, |cpu| {
+        let instruction = cpu.decode_at(cpu.pc());
+        cpu.execute(instruction).expect("emulation error")
+    });
+
+    println!("{} frames", frames);
+    report("table (step)", table);
+    report("match (decode_at + execute)", decoded);
+    println!("table speedup: {:.2}x", mips(table) / mips(decoded));
+    ExitCode::SUCCESS
+}
+
+/// Runs `frames` frames from reset, executing every instruction with `execute`, and returns how
+/// many instructions ran and how long it took.
+fn run(frames: u32, mut execute: impl FnMut(&mut CPU) -> u32) -> (u64, Duration) {
+    let mut cpu = CPU::new(ROM);
+    let mut instructions = 0;
+    let start = Instant::now();
+
+    for _ in 0..frames {
+        for interrupt in [1, 2] {
+            let mut cycles = 0;
+            while cycles < CYCLES_PER_HALF_FRAME {
+                cycles += execute(&mut cpu);
+                instructions += 1;
+            }
+            cpu.interrupt(interrupt);
+        }
+    }
+    (instructions, start.elapsed())
+}
+
+fn mips((instructions, elapsed): (u64, Duration)) -> f64 {
+    instructions as f64 / elapsed.as_secs_f64() / 1_000_000.0
+}
+
+fn report(name: &str, result: (u64, Duration)) {
+    println!("{:<28} {:>11} instructions in {:>7.3}s, {:>7.2} MIPS", name, result.0, result.1.as_secs_f64(), mips(result));
+}
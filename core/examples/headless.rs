@@ -0,0 +1,196 @@
+//! Runs a ROM for a number of frames without SDL, holding scripted inputs, then prints the state
+//! hash, the framebuffer hash chain and the scores, and optionally saves screenshots.
+//!
+//! ```text
+//! cargo run -p core --release --example headless -- <rom> [--frames N] [--inputs <script> | --movie <simv>]
+//!     [--screenshot <png>] [--screenshot-dir <dir> --screenshot-every N]
+//! ```
+//!
+//! An input script sets the buttons held from a frame on, see `Movie::from_script`:
+//!
+//! ```text
+//! 100 coin
+//! 105
+//! 200 p1start
+//! 205
+//! 300 p1shoot p1left
+//! ```
+//!
+//! Without `--frames`, the run lasts as long as the script or movie. Past its end the last
+//! buttons stay held.
+//!
+//! The state hash is `Emulator::state_hash`, the one `frontend --headless` prints. Screenshots are
+//! upright grayscale PNGs of `Emulator::screen`, without the color overlay.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use core::movie::{Movie, Player};
+use core::video::{Orientation, PixelFormat, Renderer, WHITE};
+use core::{crc32, Emulator, FrameHashChain, SpaceInvadersState};
+
+/// Ten seconds of emulated time, like `frontend --headless`.
+const DEFAULT_FRAMES: u64 = 600;
+const RENDERER: Renderer = Renderer::new(PixelFormat::Indexed, Orientation::Upright);
+/// Largest payload of a stored deflate block.
+const STORED_BLOCK: usize = 0xFFFF;
+
+#[derive(Debug, Default)]
+struct Options {
+    rom: PathBuf,
+    frames: Option<u64>,
+    inputs: Option<PathBuf>,
+    movie: Option<PathBuf>,
+    screenshot: Option<PathBuf>,
+    screenshot_dir: Option<PathBuf>,
+    screenshot_every: u64,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_args()?;
+    let rom = read(&options.rom)?;
+    let mut emulator = Emulator::new(&rom);
+
+    let movie = match (&options.inputs, &options.movie) {
+        (Some(path), _) => Some(Movie::from_script(&String::from_utf8_lossy(&read(path)?)).map_err(|e| format!("{}: {}", path.display(), e))?),
+        (_, Some(path)) => Some(Movie::from_bytes(&read(path)?).map_err(|e| format!("{}: {}", path.display(), e))?),
+        _ => None,
+    };
+    let mut player = match movie {
+        Some(movie) => Some(Player::start(movie, &mut emulator).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let frames = options.frames
+        .or_else(|| player.as_ref().map(|player| player.movie().frames.len() as u64))
+        .unwrap_or(DEFAULT_FRAMES);
+
+    let mut chain = FrameHashChain::new();
+    for frame in 0..frames {
+        if let Some(player) = &mut player {
+            player.next_frame(&mut emulator);
+        }
+
+        emulator.run_frame(&mut ()).map_err(|e| format!("frame {}: {}", frame, e))?;
+        if let Some(player) = &player {
+            player.check_frame(&emulator).map_err(|e| e.to_string())?;
+        }
+        chain.push(emulator.frame_hash());
+
+        if let Some(dir) = &options.screenshot_dir {
+            if (frame + 1).is_multiple_of(options.screenshot_every) {
+                write_png(&dir.join(format!("frame-{:06}.png", frame + 1)), &emulator)?;
+            }
+        }
+    }
+
+    if let Some(path) = &options.screenshot {
+        write_png(path, &emulator)?;
+    }
+
+    let state = SpaceInvadersState::from_memory(&emulator.cpu().memory);
+    println!("frames:       {}", frames);
+    println!("state hash:   {:016X}", emulator.state_hash());
+    println!("frame chain:  {:016X}", chain.value());
+    println!("scores:       {} {}", state.scores[0], state.scores[1]);
+    println!("high score:   {}", state.high_score);
+    println!("ships:        {} {}", state.ships[0], state.ships[1]);
+    Ok(())
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options { screenshot_every: 1, ..Options::default() };
+    let mut rom = None;
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--frames" => options.frames = Some(value()?.parse().map_err(|_| "invalid value for --frames")?),
+            "--inputs" => options.inputs = Some(value()?.into()),
+            "--movie" => options.movie = Some(value()?.into()),
+            "--screenshot" => options.screenshot = Some(value()?.into()),
+            "--screenshot-dir" => options.screenshot_dir = Some(value()?.into()),
+            "--screenshot-every" => {
+                options.screenshot_every = value()?.parse().ok().filter(|&n| n > 0).ok_or("invalid value for --screenshot-every")?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => rom = Some(arg.into()),
+        }
+    }
+
+    options.rom = rom.ok_or("usage: headless <rom> [--frames N] [--inputs <script> | --movie <simv>] [--screenshot <png>] [--screenshot-dir <dir> --screenshot-every N]")?;
+    if options.inputs.is_some() && options.movie.is_some() {
+        return Err("--inputs and --movie can't be used together".to_string());
+    }
+    if let Some(dir) = &options.screenshot_dir {
+        fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    }
+    Ok(options)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+fn write_png(path: &Path, emulator: &Emulator) -> Result<(), String> {
+    let mut image = RENDERER.buffer();
+    RENDERER.render(emulator.screen(), &mut image, |_, _| WHITE);
+    fs::write(path, png(&image, RENDERER.width(), RENDERER.height()))
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+/// 8-bit grayscale PNG of `pixels`, one byte each, 0 for black and anything else for white. The
+/// image data is stored uncompressed.
+fn png(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    // Every row starts with filter type 0, none
+    let raw: Vec<u8> = pixels.chunks(width as usize)
+        .flat_map(|row| std::iter::once(0).chain(row.iter().map(|&pixel| if pixel != 0 { 0xFF } else { 0 })))
+        .collect();
+
+    // zlib header, stored deflate blocks, Adler-32
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(STORED_BLOCK).count();
+    for (i, block) in raw.chunks(STORED_BLOCK).enumerate() {
+        zlib.push((i + 1 == blocks) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, grayscale, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1A\n".to_vec();
+    for (kind, data) in [(b"IHDR", &header), (b"IDAT", &zlib), (b"IEND", &Vec::new())] {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
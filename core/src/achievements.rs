@@ -0,0 +1,318 @@
+//! Achievements described as memory conditions, checked once per frame.
+//!
+//! An achievement unlocks the first frame its `trigger` holds. Those with a `start` condition must
+//! be armed by it first, and `reset` disarms them again, which expresses goals like "clear a wave
+//! without losing a life": armed when a wave starts, reset when a ship is lost.
+
+use alloc::vec::Vec;
+use alloc::vec;
+use crate::conditions::{Cmp, Condition, Stat, Value};
+use crate::Memory;
+
+const RAM_P1_SHIELDS: u16 = 0x2142;
+const SHIELDS_LEN: u16 = 0xB0;
+const ALIENS_PER_WAVE: u32 = 55;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Achievement {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub trigger: Condition,
+    pub start: Option<Condition>,
+    pub reset: Option<Condition>,
+}
+
+/// The achievements that ship with the emulator. All of them require a game in progress, so the
+/// attract mode demo can't unlock anything.
+pub fn builtin() -> Vec<Achievement> {
+    let in_game = || Condition::stat_eq(Stat::InGame, 1);
+    let game_started = || Condition::stat_became(Stat::InGame, 1);
+    let wave_started = || Condition::All(vec![in_game(), Condition::stat_became(Stat::NumAliens, ALIENS_PER_WAVE)]);
+    let wave_cleared = || Condition::All(vec![in_game(), Condition::stat_became(Stat::NumAliens, 0)]);
+    let ship_lost = || Condition::compare(Value::State(Stat::Ships(0)), Cmp::Lt, Value::State(Stat::Ships(0)).prev());
+    let score_delta = |delta| Condition::Any(vec![
+        Condition::Difference(Value::State(Stat::Score(0)), Value::State(Stat::Score(0)).prev(), delta),
+        Condition::Difference(Value::State(Stat::Score(1)), Value::State(Stat::Score(1)).prev(), delta),
+    ]);
+
+    vec![
+        Achievement {
+            id: "first_blood",
+            title: "First Blood",
+            description: "Shoot down an invader",
+            trigger: Condition::All(vec![
+                in_game(),
+                Condition::Difference(Value::State(Stat::NumAliens), Value::State(Stat::NumAliens).prev(), -1),
+            ]),
+            start: None,
+            reset: None,
+        },
+        Achievement {
+            id: "saucer_300",
+            title: "Jackpot",
+            description: "Hit the UFO for 300 points",
+            trigger: Condition::All(vec![in_game(), score_delta(300)]),
+            start: None,
+            reset: None,
+        },
+        Achievement {
+            id: "saucer_first_shot",
+            title: "Sharpshooter",
+            description: "Hit the UFO with the first shot fired at it",
+            trigger: Condition::All(vec![in_game(), Condition::stat_became(Stat::UfoHit, 1)]),
+            // Armed only if no shot was already on its way when the UFO appeared, and disarmed by
+            // the first one that ends without hitting it
+            start: Some(Condition::All(vec![
+                in_game(),
+                Condition::stat_became(Stat::UfoActive, 1),
+                Condition::stat_eq(Stat::PlayerShot, 0),
+            ])),
+            reset: Some(Condition::All(vec![
+                Condition::stat_became(Stat::PlayerShot, 0),
+                Condition::stat_eq(Stat::UfoHit, 0),
+            ])),
+        },
+        Achievement {
+            id: "wave_1",
+            title: "Earth Defender",
+            description: "Clear a wave",
+            trigger: wave_cleared(),
+            start: None,
+            reset: None,
+        },
+        Achievement {
+            id: "wave_5",
+            title: "Veteran",
+            description: "Clear five waves in one game",
+            trigger: Condition::All(vec![
+                in_game(),
+                Condition::stat_became(Stat::RackCount(0), 5),
+            ]),
+            start: None,
+            reset: None,
+        },
+        Achievement {
+            id: "flawless_wave",
+            title: "Untouchable",
+            description: "Clear a wave without losing a ship",
+            trigger: wave_cleared(),
+            start: Some(wave_started()),
+            reset: Some(ship_lost()),
+        },
+        Achievement {
+            id: "shields_intact",
+            title: "Not A Scratch",
+            description: "Clear a wave without losing any of the shields",
+            trigger: wave_cleared(),
+            start: Some(wave_started()),
+            reset: Some(Condition::All(vec![
+                in_game(),
+                Condition::Changed { address: RAM_P1_SHIELDS, length: SHIELDS_LEN },
+            ])),
+        },
+        Achievement {
+            id: "score_5000",
+            title: "High Roller",
+            description: "Score 5000 points",
+            trigger: Condition::All(vec![
+                in_game(),
+                Condition::compare(Value::State(Stat::Score(0)), Cmp::Ge, Value::Const(5000)),
+            ]),
+            start: None,
+            reset: None,
+        },
+        Achievement {
+            id: "score_10000_flawless",
+            title: "Perfect Ten",
+            description: "Score 10000 points without losing a ship",
+            // The score only has four digits, so reaching 10000 shows as it rolling over
+            trigger: Condition::All(vec![
+                in_game(),
+                Condition::compare(Value::State(Stat::InGame).prev(), Cmp::Eq, Value::Const(1)),
+                Condition::compare(Value::State(Stat::Score(0)), Cmp::Lt, Value::State(Stat::Score(0)).prev()),
+            ]),
+            start: Some(game_started()),
+            reset: Some(ship_lost()),
+        },
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub struct AchievementTracker {
+    achievements: Vec<Achievement>,
+    armed: Vec<bool>,
+    unlocked: Vec<bool>,
+    previous: Option<Memory>,
+}
+
+impl AchievementTracker {
+    pub fn new(achievements: Vec<Achievement>) -> Self {
+        let len = achievements.len();
+        Self { achievements, armed: vec![false; len], unlocked: vec![false; len], previous: None }
+    }
+
+    pub fn achievements(&self) -> &[Achievement] {
+        &self.achievements
+    }
+
+    pub fn is_unlocked(&self, id: &str) -> bool {
+        self.index(id).is_some_and(|i| self.unlocked[i])
+    }
+
+    /// Marks an achievement unlocked in an earlier session so it isn't reported again.
+    pub fn set_unlocked(&mut self, id: &str) {
+        if let Some(i) = self.index(id) {
+            self.unlocked[i] = true;
+        }
+    }
+
+    /// Checks every locked achievement against this frame's memory and returns the ones that
+    /// just unlocked.
+    pub fn update(&mut self, memory: &Memory) -> Vec<&Achievement> {
+        let Some(previous) = self.previous.replace(memory.clone()) else { return Vec::new() };
+        let mut unlocked = Vec::new();
+
+        for (i, achievement) in self.achievements.iter().enumerate() {
+            if self.unlocked[i] {
+                continue;
+            }
+
+            if achievement.reset.as_ref().is_some_and(|c| c.eval(memory, &previous)) {
+                self.armed[i] = false;
+            }
+            if achievement.start.as_ref().is_some_and(|c| c.eval(memory, &previous)) {
+                self.armed[i] = true;
+            }
+
+            let armed = achievement.start.is_none() || self.armed[i];
+            if armed && achievement.trigger.eval(memory, &previous) {
+                self.unlocked[i] = true;
+                unlocked.push(achievement);
+            }
+        }
+
+        unlocked
+    }
+
+    fn index(&self, id: &str) -> Option<usize> {
+        self.achievements.iter().position(|a| a.id == id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RAM_PLAYER_SHOT_STATUS: u16 = 0x2025;
+    const RAM_NUM_ALIENS: u16 = 0x2082;
+    const RAM_UFO_ACTIVE: u16 = 0x2084;
+    const RAM_UFO_HIT: u16 = 0x2085;
+    const RAM_GAME_MODE: u16 = 0x20EF;
+    const RAM_P1_SCORE: u16 = 0x20F8;
+    const RAM_P1_SHIPS: u16 = 0x21FF;
+
+    fn ids(unlocked: Vec<&Achievement>) -> Vec<&'static str> {
+        unlocked.iter().map(|a| a.id).collect()
+    }
+
+    #[test]
+    fn test_flawless_wave() {
+        let mut tracker = AchievementTracker::new(builtin());
+        let mut memory = Memory::new([0; 0x2000]);
+        memory.write(RAM_GAME_MODE, 1);
+        memory.write(RAM_P1_SHIPS, 3);
+        tracker.update(&memory);
+
+        memory.write(RAM_NUM_ALIENS, ALIENS_PER_WAVE as u8);
+        assert!(tracker.update(&memory).is_empty());
+
+        memory.write(RAM_NUM_ALIENS, 54);
+        memory.write(RAM_P1_SHIELDS, 0xFF);
+        assert_eq!(ids(tracker.update(&memory)), ["first_blood"]);
+
+        memory.write(RAM_NUM_ALIENS, 0);
+        assert_eq!(ids(tracker.update(&memory)), ["wave_1", "flawless_wave"]);
+        assert!(!tracker.is_unlocked("shields_intact"));
+    }
+
+    #[test]
+    fn test_losing_a_ship_resets() {
+        let mut tracker = AchievementTracker::new(builtin());
+        tracker.set_unlocked("wave_1");
+        let mut memory = Memory::new([0; 0x2000]);
+        memory.write(RAM_GAME_MODE, 1);
+        memory.write(RAM_P1_SHIPS, 3);
+        tracker.update(&memory);
+
+        memory.write(RAM_NUM_ALIENS, ALIENS_PER_WAVE as u8);
+        tracker.update(&memory);
+        memory.write(RAM_P1_SHIPS, 2);
+        tracker.update(&memory);
+
+        memory.write(RAM_NUM_ALIENS, 0);
+        assert_eq!(ids(tracker.update(&memory)), ["shields_intact"]);
+    }
+
+    #[test]
+    fn test_nothing_outside_a_game() {
+        let mut tracker = AchievementTracker::new(builtin());
+        let mut memory = Memory::new([0; 0x2000]);
+        tracker.update(&memory);
+
+        memory.write(RAM_NUM_ALIENS, 0);
+        memory.write(RAM_P1_SCORE + 1, 0x50);
+        assert!(tracker.update(&memory).is_empty());
+    }
+
+    #[test]
+    fn test_saucer_first_shot() {
+        let mut tracker = AchievementTracker::new(builtin());
+        let mut memory = Memory::new([0; 0x2000]);
+        memory.write(RAM_GAME_MODE, 1);
+        tracker.update(&memory);
+
+        // A shot that misses the UFO disarms it until the next one appears
+        memory.write(RAM_UFO_ACTIVE, 1);
+        tracker.update(&memory);
+        memory.write(RAM_PLAYER_SHOT_STATUS, 2);
+        tracker.update(&memory);
+        memory.write(RAM_PLAYER_SHOT_STATUS, 0);
+        tracker.update(&memory);
+        memory.write(RAM_PLAYER_SHOT_STATUS, 2);
+        tracker.update(&memory);
+        memory.write(RAM_UFO_HIT, 1);
+        assert!(tracker.update(&memory).is_empty());
+
+        memory.write(RAM_UFO_ACTIVE, 0);
+        memory.write(RAM_UFO_HIT, 0);
+        memory.write(RAM_PLAYER_SHOT_STATUS, 0);
+        tracker.update(&memory);
+        memory.write(RAM_UFO_ACTIVE, 1);
+        tracker.update(&memory);
+        memory.write(RAM_PLAYER_SHOT_STATUS, 2);
+        tracker.update(&memory);
+        memory.write(RAM_PLAYER_SHOT_STATUS, 4);
+        memory.write(RAM_UFO_HIT, 1);
+        assert_eq!(ids(tracker.update(&memory)), ["saucer_first_shot"]);
+    }
+
+    #[test]
+    fn test_score_rollover() {
+        let mut tracker = AchievementTracker::new(builtin());
+        tracker.set_unlocked("score_5000");
+        let mut memory = Memory::new([0; 0x2000]);
+        tracker.update(&memory);
+
+        memory.write(RAM_GAME_MODE, 1);
+        memory.write(RAM_P1_SHIPS, 3);
+        tracker.update(&memory);
+
+        memory.write(RAM_P1_SCORE + 1, 0x99);
+        memory.write(RAM_P1_SCORE, 0x90);
+        tracker.update(&memory);
+        memory.write(RAM_P1_SCORE + 1, 0x00);
+        memory.write(RAM_P1_SCORE, 0x20);
+        assert_eq!(ids(tracker.update(&memory)), ["score_10000_flawless"]);
+    }
+}
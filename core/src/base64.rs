@@ -0,0 +1,36 @@
+use alloc::string::String;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard padded base64 (RFC 4648).
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_encode() {
+        assert_eq!(super::encode(b""), "");
+        assert_eq!(super::encode(b"f"), "Zg==");
+        assert_eq!(super::encode(b"fo"), "Zm8=");
+        assert_eq!(super::encode(b"foo"), "Zm9v");
+        assert_eq!(super::encode(b"foobar"), "Zm9vYmFy");
+    }
+}
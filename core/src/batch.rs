@@ -0,0 +1,65 @@
+//! Runs many independent emulators for a number of frames, each with its own inputs, for RL
+//! rollouts and Monte-Carlo testing. Instances run on rayon's thread pool with the `parallel`
+//! feature, and one after another without it.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use alloc::vec::Vec;
+use crate::{Button, Emulator, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchResult {
+    pub frames: u64,
+    /// [`Emulator::state_hash`] after the last frame, the same one `--headless` prints.
+    pub state_hash: u64,
+}
+
+/// Runs every emulator for `frames` frames. `input(instance, frame)` gives the buttons held
+/// during each frame; buttons not returned are released. An instance that fails stops there and
+/// reports its error without affecting the others.
+pub fn run_batch<F>(emulators: &mut [Emulator], frames: u64, input: F) -> Vec<Result<BatchResult>>
+where
+    F: Fn(usize, u64) -> Vec<Button> + Sync,
+{
+    let run = |(instance, emulator): (usize, &mut Emulator)| run_instance(emulator, frames, |frame| input(instance, frame));
+
+    #[cfg(feature = "parallel")]
+    return emulators.par_iter_mut().enumerate().map(run).collect();
+
+    #[cfg(not(feature = "parallel"))]
+    emulators.iter_mut().enumerate().map(run).collect()
+}
+
+fn run_instance(emulator: &mut Emulator, frames: u64, input: impl Fn(u64) -> Vec<Button>) -> Result<BatchResult> {
+    for frame in 0..frames {
+        emulator.set_buttons(input(frame).into_iter().collect());
+
+        emulator.run_frame(&mut ())?;
+    }
+
+    Ok(BatchResult { frames, state_hash: emulator.state_hash() })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    #[test]
+    fn test_run_batch() {
+        let mut emulators = vec![Emulator::new(ROM); 4];
+        // Instances 0 and 2 insert a coin, 1 and 3 don't
+        let results = run_batch(&mut emulators, 200, |instance, frame| {
+            if instance % 2 == 0 && (100..105).contains(&frame) { vec![Button::Coin] } else { vec![] }
+        });
+
+        let hashes: Vec<u64> = results.into_iter().map(|r| r.unwrap().state_hash).collect();
+        assert_eq!(hashes[0], hashes[2]);
+        assert_eq!(hashes[1], hashes[3]);
+        assert_ne!(hashes[0], hashes[1]);
+        assert_eq!(emulators[0].stats().frames, 200);
+    }
+}
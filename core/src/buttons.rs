@@ -0,0 +1,115 @@
+use alloc::format;
+use bitflags::bitflags;
+
+/// A switch on the cabinet. Each sits on a bit of input port 1 or 2, see [`Button::port_bit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Button {
+    P1Start,
+    P2Start,
+    P1Shoot,
+    P2Shoot,
+    P1Left,
+    P2Left,
+    P1Right,
+    P2Right,
+    /// The cabinet's tilt switch, which ends the game when it's jolted.
+    Tilt,
+    /// The coin switch, labeled CREDIT in the schematics.
+    Coin,
+}
+
+impl Button {
+    /// Every button, in the order of the bits of [`Buttons`].
+    pub const ALL: [Button; 10] = [
+        Button::Coin,
+        Button::P1Start,
+        Button::P2Start,
+        Button::P1Left,
+        Button::P1Right,
+        Button::P1Shoot,
+        Button::P2Left,
+        Button::P2Right,
+        Button::P2Shoot,
+        Button::Tilt,
+    ];
+
+    /// The button called `name` in any case, e.g. `coin` or `P1Start`.
+    pub fn from_name(name: &str) -> Option<Button> {
+        Button::ALL.into_iter().find(|button| format!("{:?}", button).eq_ignore_ascii_case(name))
+    }
+
+    /// Input port and bit of the button. Coin is active-low, everything else active-high.
+    pub const fn port_bit(self) -> (u8, u8) {
+        match self {
+            Self::Coin => (1, 0b0000_0001),
+            Self::P2Start => (1, 0b0000_0010),
+            Self::P1Start => (1, 0b0000_0100),
+            Self::P1Shoot => (1, 0b0001_0000),
+            Self::P1Left => (1, 0b0010_0000),
+            Self::P1Right => (1, 0b0100_0000),
+            Self::Tilt => (2, 0b0000_0100),
+            Self::P2Shoot => (2, 0b0001_0000),
+            Self::P2Left => (2, 0b0010_0000),
+            Self::P2Right => (2, 0b0100_0000),
+        }
+    }
+}
+
+bitflags! {
+    /// A set of held buttons, one bit each in [`Button::ALL`] order. The bits have nothing to do
+    /// with the input ports; movies store these as they are.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Buttons: u16 {
+        const COIN = 1 << 0;
+        const P1_START = 1 << 1;
+        const P2_START = 1 << 2;
+        const P1_LEFT = 1 << 3;
+        const P1_RIGHT = 1 << 4;
+        const P1_SHOOT = 1 << 5;
+        const P2_LEFT = 1 << 6;
+        const P2_RIGHT = 1 << 7;
+        const P2_SHOOT = 1 << 8;
+        const TILT = 1 << 9;
+    }
+}
+
+impl Buttons {
+    /// The buttons in the set, in [`Button::ALL`] order.
+    pub fn buttons(self) -> impl Iterator<Item = Button> {
+        Button::ALL.into_iter().filter(move |&button| self.contains(button.into()))
+    }
+}
+
+impl From<Button> for Buttons {
+    fn from(button: Button) -> Self {
+        let bit = Button::ALL.iter().position(|&b| b == button).unwrap_or_default();
+        Self::from_bits_retain(1 << bit)
+    }
+}
+
+impl FromIterator<Button> for Buttons {
+    fn from_iter<T: IntoIterator<Item = Button>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::empty(), |buttons, button| buttons | button.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_buttons() {
+        assert_eq!(Buttons::from(Button::Coin), Buttons::COIN);
+        assert_eq!(Buttons::from(Button::P2Shoot), Buttons::P2_SHOOT);
+        assert_eq!(Buttons::from(Button::Tilt), Buttons::TILT);
+        assert_eq!(Button::ALL.into_iter().collect::<Buttons>(), Buttons::all());
+        assert_eq!(Button::from_name("p1start"), Some(Button::P1Start));
+        assert_eq!(Button::from_name("jump"), None);
+
+        let held = Buttons::P1_START | Buttons::TILT;
+        assert_eq!(held.buttons().collect::<Vec<_>>(), [Button::P1Start, Button::Tilt]);
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+
+/// Frames kept at most, so code that never returns can't grow the stack without bound.
+pub const CALL_STACK_LEN: usize = 256;
+/// Return mismatches kept until taken.
+const MISMATCH_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallKind {
+    Call,
+    Rst,
+    /// The instruction supplied by an interrupt, normally an RST.
+    Interrupt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallFrame {
+    pub kind: CallKind,
+    /// Address of the calling instruction, or PC when the interrupt was taken.
+    pub call_site: u16,
+    pub target: u16,
+    pub return_address: u16,
+    /// Where the return address was pushed.
+    pub sp: u16,
+}
+
+/// A return that doesn't match any tracked call, such as a `PUSH`ed address used as a jump
+/// table, or a return address that was overwritten on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReturnMismatch {
+    /// Address of the return instruction.
+    pub pc: u16,
+    /// Where it returned to.
+    pub target: u16,
+    /// SP before the return.
+    pub sp: u16,
+    /// The frame expected to return at this SP, if its return address differs from `target`.
+    pub expected: Option<CallFrame>,
+}
+
+/// Shadow stack of the calls made by the CPU, for backtraces. See [`CPU::set_call_stack_tracking`].
+///
+/// Frames are matched to returns by the stack address of the return address rather than by
+/// nesting alone, so code that drops frames by resetting SP or popping the return address
+/// doesn't leave stale frames behind.
+///
+/// [`CPU::set_call_stack_tracking`]: crate::CPU::set_call_stack_tracking
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    mismatches: Vec<ReturnMismatch>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Outermost frame first.
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Takes the mismatched returns seen since the last call, oldest first.
+    pub fn take_mismatches(&mut self) -> Vec<ReturnMismatch> {
+        core::mem::take(&mut self.mismatches)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.mismatches.clear();
+    }
+
+    pub(crate) fn call(&mut self, frame: CallFrame) {
+        // A push at or above a frame's return address means that frame is gone
+        self.unwind_to(frame.sp);
+        if self.frames.len() == CALL_STACK_LEN {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    pub(crate) fn ret(&mut self, pc: u16, sp: u16, target: u16) {
+        // Frames below SP were dropped without returning
+        while self.frames.last().is_some_and(|frame| frame.sp < sp) {
+            self.frames.pop();
+        }
+
+        let expected = match self.frames.last() {
+            Some(frame) if frame.sp == sp => self.frames.pop(),
+            _ => None,
+        };
+        match expected {
+            Some(frame) if frame.return_address == target => {}
+            expected => {
+                if self.mismatches.len() == MISMATCH_LEN {
+                    self.mismatches.remove(0);
+                }
+                self.mismatches.push(ReturnMismatch { pc, target, sp, expected });
+            }
+        }
+    }
+
+    fn unwind_to(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|frame| frame.sp <= sp) {
+            self.frames.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CPU;
+
+    // LXI SP,$2400; CALL $0010; LXI H,$0020; PUSH H; RET
+    // $0010: RST 3; RET
+    // $0018: RET
+    fn cpu() -> CPU {
+        let mut program = vec![0x31, 0x00, 0x24, 0xCD, 0x10, 0x00, 0x21, 0x20, 0x00, 0xE5, 0xC9];
+        program.resize(0x19, 0);
+        program[0x10..0x12].copy_from_slice(&[0xDF, 0xC9]);
+        program[0x18] = 0xC9;
+
+        let mut cpu = CPU::new(&program);
+        cpu.set_call_stack_tracking(true);
+        cpu
+    }
+
+    #[test]
+    fn test_calls_and_returns() {
+        let mut cpu = cpu();
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(cpu.call_stack().unwrap().frames(), [
+            CallFrame { kind: CallKind::Call, call_site: 0x0003, target: 0x0010, return_address: 0x0006, sp: 0x23FE },
+            CallFrame { kind: CallKind::Rst, call_site: 0x0010, target: 0x0018, return_address: 0x0011, sp: 0x23FC },
+        ]);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc(), 0x0006);
+        assert_eq!(cpu.call_stack().unwrap().depth(), 0);
+        assert!(cpu.call_stack_mut().unwrap().take_mismatches().is_empty());
+    }
+
+    #[test]
+    fn test_return_without_call() {
+        let mut cpu = cpu();
+        for _ in 0..8 {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(cpu.pc(), 0x0020);
+        let mismatch = ReturnMismatch { pc: 0x000A, target: 0x0020, sp: 0x23FE, expected: None };
+        assert_eq!(cpu.call_stack_mut().unwrap().take_mismatches(), [mismatch]);
+    }
+
+    #[test]
+    fn test_interrupt_and_abandoned_frames() {
+        let mut cpu = cpu();
+        cpu.step().unwrap();
+        assert!(cpu.interrupt(1));
+        assert_eq!(cpu.call_stack().unwrap().frames()[0].kind, CallKind::Interrupt);
+        assert_eq!(cpu.call_stack().unwrap().frames()[0].call_site, 0x0003);
+
+        // Resetting SP drops the frame without a return
+        cpu.set_sp(0x2400);
+        cpu.set_pc(0x0003);
+        cpu.step().unwrap();
+        assert_eq!(cpu.call_stack().unwrap().depth(), 1);
+        assert_eq!(cpu.call_stack().unwrap().frames()[0].kind, CallKind::Call);
+    }
+}
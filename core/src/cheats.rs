@@ -0,0 +1,92 @@
+//! Memory patches applied by the [`Emulator`] at frame boundaries, before the VBlank interrupt is
+//! raised, and the named cheats built from them.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::vec;
+use crate::Emulator;
+
+const RAM_PLAYER_ALIVE: u16 = 0x2015;
+/// Set while the fire button is held after a shot, so holding it doesn't fire again.
+const RAM_FIRE_BOUNCE: u16 = 0x202D;
+const RAM_P1_SHIPS: u16 = 0x21FF;
+const RAM_P2_SHIPS: u16 = 0x22FF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CheatKind {
+    /// Written on the next frame boundary, then dropped.
+    Once,
+    /// Written again on every frame boundary, keeping the value in place.
+    Freeze,
+    /// Written over ROM on the next frame boundary, changing the game's code or data, and undone
+    /// when the cheat is removed.
+    RomPatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    pub kind: CheatKind,
+}
+
+impl Cheat {
+    pub fn once(address: u16, value: u8) -> Self {
+        Self { address, value, kind: CheatKind::Once }
+    }
+
+    pub fn freeze(address: u16, value: u8) -> Self {
+        Self { address, value, kind: CheatKind::Freeze }
+    }
+
+    pub fn rom_patch(address: u16, value: u8) -> Self {
+        Self { address, value, kind: CheatKind::RomPatch }
+    }
+}
+
+/// A group of patches toggled together under a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedCheat {
+    pub id: String,
+    pub name: String,
+    pub patches: Vec<Cheat>,
+}
+
+impl NamedCheat {
+    pub fn enable(&self, emulator: &mut Emulator) {
+        for patch in &self.patches {
+            emulator.add_cheat(patch.clone());
+        }
+    }
+
+    pub fn disable(&self, emulator: &mut Emulator) {
+        for patch in &self.patches {
+            emulator.remove_cheats(patch.address);
+        }
+    }
+}
+
+/// Cheats for the Space Invaders ROM and its clones.
+pub fn builtin() -> Vec<NamedCheat> {
+    vec![
+        NamedCheat {
+            id: "invincibility".into(),
+            name: "Invincibility".into(),
+            // Undoes a hit before the explosion is over; the aliens can still land
+            patches: vec![Cheat::freeze(RAM_PLAYER_ALIVE, 0xFF)],
+        },
+        NamedCheat {
+            id: "infinite_lives".into(),
+            name: "Infinite lives".into(),
+            patches: vec![Cheat::freeze(RAM_P1_SHIPS, 3), Cheat::freeze(RAM_P2_SHIPS, 3)],
+        },
+        NamedCheat {
+            id: "rapid_fire".into(),
+            name: "Rapid fire".into(),
+            // Keeps firing while the button is held
+            patches: vec![Cheat::freeze(RAM_FIRE_BOUNCE, 0)],
+        },
+    ]
+}
@@ -0,0 +1,43 @@
+/// CRC-32 (IEEE 802.3), as used by ZIP archives and MAME ROM listings.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// 64-bit FNV-1a, cheap enough to run on every frame.
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    fnv1a64_continue(FNV_OFFSET_BASIS, data)
+}
+
+/// Feeds more bytes into a hash returned by [`fnv1a64`].
+pub fn fnv1a64_continue(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_crc32() {
+        assert_eq!(super::crc32(b""), 0);
+        assert_eq!(super::crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_fnv1a64() {
+        assert_eq!(super::fnv1a64(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(super::fnv1a64(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(super::fnv1a64_continue(super::fnv1a64(b"fo"), b"o"), super::fnv1a64(b"foo"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Declarative conditions over memory, evaluated once per frame against the current memory and
+//! the memory at the end of the previous frame. Used to detect game events such as achievements
+//! without patching the game code.
+//!
+//! Values are read either straight from memory or from the [`SpaceInvadersState`] decoded from
+//! it, which spares conditions about the game from knowing its RAM layout.
+
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::vec;
+use crate::{Memory, SpaceInvadersState};
+
+/// A field of the [`SpaceInvadersState`], with `true` read as 1. Per-player fields take the player
+/// index, 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    InGame,
+    NumAliens,
+    Score(usize),
+    Ships(usize),
+    RackCount(usize),
+    HighScore,
+    Credits,
+    /// The player's shot is in flight or exploding.
+    PlayerShot,
+    UfoActive,
+    /// The UFO was hit and is exploding.
+    UfoHit,
+}
+
+impl Stat {
+    fn eval(self, memory: &Memory) -> u32 {
+        let state = SpaceInvadersState::from_memory(memory);
+        match self {
+            Self::InGame => state.in_game as u32,
+            Self::NumAliens => state.num_aliens as u32,
+            Self::Score(player) => state.scores[player],
+            Self::Ships(player) => state.ships[player] as u32,
+            Self::RackCount(player) => state.rack_counts[player] as u32,
+            Self::HighScore => state.high_score,
+            Self::Credits => state.credits,
+            Self::PlayerShot => state.player_shot.is_some() as u32,
+            Self::UfoActive => state.ufo.is_some() as u32,
+            Self::UfoHit => state.ufo.is_some_and(|ufo| ufo.hit) as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Const(u32),
+    Byte(u16),
+    /// Four BCD digits stored least significant byte first, like the scores.
+    Bcd16(u16),
+    State(Stat),
+    /// The value at the end of the previous frame.
+    Prev(Box<Value>),
+}
+
+impl Value {
+    pub fn prev(self) -> Self {
+        Self::Prev(Box::new(self))
+    }
+
+    fn eval(&self, current: &Memory, previous: &Memory) -> u32 {
+        match self {
+            Self::Const(val) => *val,
+            Self::Byte(address) => current[*address] as u32,
+            Self::Bcd16(address) => bcd(current[address.wrapping_add(1)]) * 100 + bcd(current[*address]),
+            Self::State(stat) => stat.eval(current),
+            Self::Prev(value) => value.eval(previous, previous),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Value, Cmp, Value),
+    /// The difference `lhs - rhs` (which may be negative) equals `delta`.
+    Difference(Value, Value, i64),
+    /// Any byte in the range changed since the previous frame.
+    Changed { address: u16, length: u16 },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn compare(lhs: Value, cmp: Cmp, rhs: Value) -> Self {
+        Self::Compare(lhs, cmp, rhs)
+    }
+
+    /// The byte at `address` holds `val`.
+    pub fn byte_eq(address: u16, val: u8) -> Self {
+        Self::Compare(Value::Byte(address), Cmp::Eq, Value::Const(val as u32))
+    }
+
+    /// The byte at `address` changed from anything else to `val` this frame.
+    pub fn became(address: u16, val: u8) -> Self {
+        Self::All(vec![
+            Self::Compare(Value::Byte(address).prev(), Cmp::Ne, Value::Const(val as u32)),
+            Self::byte_eq(address, val),
+        ])
+    }
+
+    /// `stat` holds `val`.
+    pub fn stat_eq(stat: Stat, val: u32) -> Self {
+        Self::Compare(Value::State(stat), Cmp::Eq, Value::Const(val))
+    }
+
+    /// `stat` changed from anything else to `val` this frame.
+    pub fn stat_became(stat: Stat, val: u32) -> Self {
+        Self::All(vec![
+            Self::Compare(Value::State(stat).prev(), Cmp::Ne, Value::Const(val)),
+            Self::stat_eq(stat, val),
+        ])
+    }
+
+    pub fn eval(&self, current: &Memory, previous: &Memory) -> bool {
+        match self {
+            Self::Compare(lhs, cmp, rhs) => {
+                let (lhs, rhs) = (lhs.eval(current, previous), rhs.eval(current, previous));
+                match cmp {
+                    Cmp::Eq => lhs == rhs,
+                    Cmp::Ne => lhs != rhs,
+                    Cmp::Lt => lhs < rhs,
+                    Cmp::Le => lhs <= rhs,
+                    Cmp::Gt => lhs > rhs,
+                    Cmp::Ge => lhs >= rhs,
+                }
+            }
+            Self::Difference(lhs, rhs, delta) => {
+                lhs.eval(current, previous) as i64 - rhs.eval(current, previous) as i64 == *delta
+            }
+            Self::Changed { address, length } => {
+                (0..*length).any(|i| current[address.wrapping_add(i)] != previous[address.wrapping_add(i)])
+            }
+            Self::All(conditions) => conditions.iter().all(|c| c.eval(current, previous)),
+            Self::Any(conditions) => conditions.iter().any(|c| c.eval(current, previous)),
+            Self::Not(condition) => !condition.eval(current, previous),
+        }
+    }
+}
+
+fn bcd(byte: u8) -> u32 {
+    (byte >> 4) as u32 * 10 + (byte & 0x0F) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let previous = Memory::new([0; 0x2000]);
+        let mut current = previous.clone();
+        current.write(0x20F8, 0x50);
+        current.write(0x20F9, 0x12);
+        current.write(0x2100, 7);
+
+        assert!(Condition::byte_eq(0x2100, 7).eval(&current, &previous));
+        assert!(Condition::became(0x2100, 7).eval(&current, &previous));
+        assert!(!Condition::became(0x2100, 7).eval(&current, &current));
+        assert!(Condition::Difference(Value::Bcd16(0x20F8), Value::Bcd16(0x20F8).prev(), 1250).eval(&current, &previous));
+        assert!(Condition::Changed { address: 0x20F0, length: 16 }.eval(&current, &previous));
+        assert!(!Condition::Changed { address: 0x2101, length: 16 }.eval(&current, &previous));
+        assert!(Condition::Not(Box::new(Condition::Any(vec![]))).eval(&current, &previous));
+        assert!(Condition::Difference(Value::State(Stat::Score(0)), Value::State(Stat::Score(0)).prev(), 1250).eval(&current, &previous));
+        assert!(Condition::stat_eq(Stat::Score(1), 0).eval(&current, &previous));
+        assert!(!Condition::stat_became(Stat::InGame, 0).eval(&current, &previous));
+    }
+}
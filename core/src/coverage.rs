@@ -0,0 +1,214 @@
+//! Map of the memory the CPU fetched instructions from, see [`CPU::set_coverage`].
+//!
+//! Every byte fetched as an opcode or as an operand is marked, so after playing for a while the
+//! unmarked bytes of a ROM are data, or code that never ran. [`Coverage::report`] lists the
+//! covered and uncovered runs of a range, disassembling the covered ones:
+//!
+//! ```text
+//! $0000-$0007 code (8 bytes)
+//!   0000  00        NOP
+//!   ...
+//! $0008-$0009 data (2 bytes)
+//! ```
+//!
+//! [`CPU::set_coverage`]: crate::CPU::set_coverage
+
+use core::fmt::Write;
+use core::ops::RangeInclusive;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::vec;
+use crate::disasm;
+use crate::Memory;
+
+const WORDS: usize = 0x10000 / 64;
+
+/// One bit per address of the 64K address space for opcodes, and another for operands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    opcodes: Box<[u64; WORDS]>,
+    operands: Box<[u64; WORDS]>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self { opcodes: Box::new([0; WORDS]), operands: Box::new([0; WORDS]) }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether an instruction was fetched starting at `address`.
+    pub fn is_opcode(&self, address: u16) -> bool {
+        get(&self.opcodes, address)
+    }
+
+    /// Whether `address` was fetched as an opcode or operand.
+    pub fn is_executed(&self, address: u16) -> bool {
+        get(&self.opcodes, address) || get(&self.operands, address)
+    }
+
+    /// Executed bytes in `range`.
+    pub fn count(&self, range: RangeInclusive<u16>) -> usize {
+        range.filter(|&address| self.is_executed(address)).count()
+    }
+
+    /// One bit per byte of `range` set if it was executed, least significant bit first, padded
+    /// with 0 to a whole byte.
+    pub fn bitmap(&self, range: RangeInclusive<u16>) -> Vec<u8> {
+        let mut bitmap = vec![0; range.len().div_ceil(8)];
+        for (i, address) in range.enumerate() {
+            if self.is_executed(address) {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bitmap
+    }
+
+    /// Lists the runs of executed (code) and unexecuted (data) bytes of `range`, each code run
+    /// followed by its disassembly read from `memory`, and a summary line at the end.
+    pub fn report(&self, memory: &Memory, range: RangeInclusive<u16>) -> String {
+        let mut report = String::new();
+        let mut run_start = *range.start();
+        let mut addresses = range.clone().map(|address| (address, self.is_executed(address))).peekable();
+
+        while let Some((address, executed)) = addresses.next() {
+            let run_ends = match addresses.peek() {
+                Some(&(_, next)) => next != executed,
+                None => true,
+            };
+            if !run_ends {
+                continue;
+            }
+
+            let len = address as usize - run_start as usize + 1;
+            if executed {
+                writeln!(report, "${:04X}-${:04X} code ({} bytes)", run_start, address, len).unwrap();
+                self.write_code(&mut report, memory, run_start..=address);
+            } else {
+                writeln!(report, "${:04X}-${:04X} data ({} bytes)", run_start, address, len).unwrap();
+            }
+            run_start = address.wrapping_add(1);
+        }
+
+        let executed = self.count(range.clone());
+        let total = range.len();
+        writeln!(report, "{} of {} bytes executed ({:.1}%)", executed, total, executed as f64 * 100.0 / total as f64).unwrap();
+        report
+    }
+
+    /// Adds the coverage of `other`, e.g. to combine several play sessions.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (word, other) in self.opcodes.iter_mut().zip(other.opcodes.iter()) {
+            *word |= other;
+        }
+        for (word, other) in self.operands.iter_mut().zip(other.operands.iter()) {
+            *word |= other;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.opcodes.fill(0);
+        self.operands.fill(0);
+    }
+
+    /// Marks the `len` byte instruction fetched from `address`.
+    pub(crate) fn mark(&mut self, address: u16, len: u8) {
+        set(&mut self.opcodes, address);
+        for i in 1..len as u16 {
+            set(&mut self.operands, address.wrapping_add(i));
+        }
+    }
+
+    /// Disassembles from each opcode of a code run. Operands of instructions that weren't fetched
+    /// from their first byte, such as one only ever jumped into the middle of, are listed as `DB`.
+    fn write_code(&self, report: &mut String, memory: &Memory, run: RangeInclusive<u16>) {
+        let end = *run.end();
+        let mut address = *run.start();
+        loop {
+            let len = if self.is_opcode(address) {
+                let bytes = [0, 1, 2].map(|i| memory[address.wrapping_add(i)]);
+                let instruction = disasm::disassemble(address, &bytes);
+                writeln!(report, "  {:04X}  {}", address, instruction).unwrap();
+                instruction.len as u16
+            } else {
+                writeln!(report, "  {:04X}  {:02X}        DB ${:02X}", address, memory[address], memory[address]).unwrap();
+                1
+            };
+            if end.wrapping_sub(address) < len {
+                break;
+            }
+            address += len;
+        }
+    }
+}
+
+fn get(bits: &[u64; WORDS], address: u16) -> bool {
+    bits[address as usize / 64] & (1 << (address % 64)) != 0
+}
+
+fn set(bits: &mut [u64; WORDS], address: u16) {
+    bits[address as usize / 64] |= 1 << (address % 64);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CPU;
+
+    // JMP $0005; DB $FF, $FF; MVI A,$01; HLT
+    fn covered() -> CPU {
+        let mut cpu = CPU::new(&[0xC3, 0x05, 0x00, 0xFF, 0xFF, 0x3E, 0x01, 0x76]);
+        cpu.set_coverage(Some(Coverage::new()));
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_marks_fetched_bytes() {
+        let cpu = covered();
+        let coverage = cpu.coverage().unwrap();
+
+        let executed: Vec<bool> = (0..8).map(|address| coverage.is_executed(address)).collect();
+        assert_eq!(executed, [true, true, true, false, false, true, true, true]);
+        assert!(coverage.is_opcode(0x0005));
+        assert!(!coverage.is_opcode(0x0006));
+        assert_eq!(coverage.count(0x0000..=0x00FF), 6);
+        assert_eq!(coverage.bitmap(0x0000..=0x0008), [0b1110_0111, 0]);
+    }
+
+    #[test]
+    fn test_report() {
+        let cpu = covered();
+        let report = cpu.coverage().unwrap().report(&cpu.memory, 0x0000..=0x0007);
+
+        assert_eq!(report, concat!(
+            "$0000-$0002 code (3 bytes)\n",
+            "  0000  C3 05 00  JMP $0005\n",
+            "$0003-$0004 data (2 bytes)\n",
+            "$0005-$0007 code (3 bytes)\n",
+            "  0005  3E 01     MVI A,$01\n",
+            "  0007  76        HLT\n",
+            "6 of 8 bytes executed (75.0%)\n",
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut coverage = Coverage::new();
+        coverage.mark(0x0010, 1);
+        let mut other = Coverage::new();
+        other.mark(0x0020, 3);
+
+        coverage.merge(&other);
+        assert_eq!(coverage.count(0x0000..=0xFFFF), 4);
+        coverage.clear();
+        assert_eq!(coverage, Coverage::new());
+    }
+}
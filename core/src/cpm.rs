@@ -0,0 +1,102 @@
+//! Minimal CP/M environment for running 8080 test programs such as TST8080, CPUTEST and
+//! 8080EXM against the CPU.
+//!
+//! The `.COM` program is loaded at 0x100 in 64K of flat RAM. Calls to the BDOS at 0x0005 are
+//! trapped and only console output is implemented: function 2 (C_WRITE) prints the character
+//! in E and function 9 (C_WRITESTR) the `$`-terminated string at DE. Jumping to 0x0000, the warm
+//! boot, ends the program.
+
+use alloc::string::String;
+use crate::{Memory, RegisterPair, Register, Result, CPU};
+
+pub const LOAD_ADDRESS: u16 = 0x0100;
+const BDOS: u16 = 0x0005;
+/// Where the BDOS jump at 0x0005 leads. Programs read its address as the top of their memory.
+const BDOS_RETURN: u16 = 0xFE00;
+
+const C_WRITE: u8 = 2;
+const C_WRITESTR: u8 = 9;
+
+#[derive(Debug, Clone)]
+pub struct Cpm {
+    cpu: CPU,
+    output: String,
+    cycles: u64,
+}
+
+impl Cpm {
+    /// # Panics
+    /// If `program` doesn't fit between [`LOAD_ADDRESS`] and the BDOS.
+    pub fn new(program: &[u8]) -> Self {
+        assert!(program.len() <= (BDOS_RETURN - LOAD_ADDRESS) as usize, "program too large for CP/M");
+
+        let mut cpu = CPU::with_memory(Memory::flat());
+        for (i, &byte) in program.iter().enumerate() {
+            cpu.memory.write(LOAD_ADDRESS + i as u16, byte);
+        }
+
+        // JMP to a RET, so BDOS calls return once they're handled
+        let [lo, hi] = BDOS_RETURN.to_le_bytes();
+        for (address, byte) in [(BDOS, 0xC3), (BDOS + 1, lo), (BDOS + 2, hi), (BDOS_RETURN, 0xC9)] {
+            cpu.memory.write(address, byte);
+        }
+
+        // Returning from the program also ends up in the warm boot
+        cpu.set_sp(BDOS_RETURN - 2);
+        cpu.set_pc(LOAD_ADDRESS);
+
+        Self { cpu, output: String::new(), cycles: 0 }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    /// Everything printed so far.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Whether the program has exited through the warm boot.
+    pub fn finished(&self) -> bool {
+        self.cpu.pc() == 0
+    }
+
+    /// Executes one instruction, handling the BDOS call first if it's at one.
+    pub fn step(&mut self) -> Result<()> {
+        if self.cpu.pc() == BDOS {
+            self.bdos();
+        }
+        self.cycles += self.cpu.step()? as u64;
+        Ok(())
+    }
+
+    /// Runs until the program exits or `max_cycles` have run, returning whether it exited.
+    pub fn run(&mut self, max_cycles: u64) -> Result<bool> {
+        while !self.finished() {
+            if self.cycles >= max_cycles {
+                return Ok(false);
+            }
+            self.step()?;
+        }
+        Ok(true)
+    }
+
+    fn bdos(&mut self) {
+        match self.cpu.reg(Register::C) {
+            C_WRITE => self.output.push(self.cpu.reg(Register::E) as char),
+            C_WRITESTR => {
+                let start = self.cpu.pair(RegisterPair::DE);
+                let text = (0..=u16::MAX)
+                    .map(|i| self.cpu.memory[start.wrapping_add(i)])
+                    .take_while(|&byte| byte != b'$');
+                self.output.extend(text.map(char::from));
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,40 +1,173 @@
-use std::mem;
-use crate::{concat_u16, Result, Memory};
+use core::mem;
+use alloc::collections::VecDeque;
+use alloc::collections::vec_deque::Drain;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::format;
+use crate::{concat_u16, Result, Error, Memory, Flags};
+use crate::memory::{Access, WatchHit, OPEN_BUS};
+use crate::call_stack::{CallFrame, CallKind, CallStack};
+use crate::instruction::{Condition, Instruction, Operand, Pair};
+#[cfg(feature = "coverage")]
+use crate::coverage::Coverage;
+#[cfg(feature = "profiler")]
+use crate::profiler::Profiler;
+use crate::savestate::StateReader;
+use crate::opcodes;
 
-pub const CARRY_FLAG: u8 = 1 << 0;
-pub const PARITY_FLAG: u8 = 1 << 2;
-pub const ZERO_FLAG: u8 = 1 << 6;
-pub const SIGN_FLAG: u8 = 1 << 7;
-
-macro_rules! mov {
-    ($from:expr,$to:expr,$cycles:expr) => {
-        {
-            $to = $from;
-            $cycles
-        }
-    };
-    ($from:expr,$to:expr) => { mov!($from, $to, 1) };
-}
+const HALT_IDLE_CYCLES: u32 = 4;
+const HOOK_SKIP_CYCLES: u32 = 4;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterruptStatus {
     Enabled,
     Disabled,
+    /// Set by EI. Interrupts are accepted once the instruction after it has finished.
+    EnablePending,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
+    /// Execution can't go on: HLT ran with interrupts disabled, so nothing can wake the CPU up
+    /// again, or the pre-exec hook returned [`HookAction::Halt`].
     Halt,
     PortWrite(u8, u8),
     PortRead(u8),
 }
 
+/// Snapshot of the programmer-visible registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub pc: u16,
+    pub sp: u16,
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub flags: Flags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+/// Register pairs as used by 16-bit instructions. `PSW` is A in the high byte and the flags
+/// byte pushed by `PUSH PSW` in the low byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegisterPair {
+    BC,
+    DE,
+    HL,
+    PSW,
+}
+
+/// What the CPU does with the opcodes Intel left undefined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UndefinedOpcodePolicy {
+    /// Runs them like the 8080 does, as the documented instruction they alias.
+    #[default]
+    TreatAsDocumented,
+    /// Runs them as a one-byte NOP.
+    Nop,
+    /// Fails with [`Error::UnimplementedOpcode`](crate::Error::UnimplementedOpcode), leaving PC
+    /// on the opcode. Useful for validating homebrew ROMs.
+    Error,
+}
+
+/// What the CPU does with writes to ROM, which the board ignores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RomWritePolicy {
+    #[default]
+    Ignore,
+    /// Fails the instruction with [`Error::RomWriteViolation`](crate::Error::RomWriteViolation),
+    /// leaving PC on it. Useful for debugging homebrew and corrupted save states, which write
+    /// there when a pointer goes astray.
+    Error,
+}
+
+/// What [`CPU::step`] does after the pre-exec hook has seen the next instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HookAction {
+    Continue,
+    /// Moves PC past the instruction without executing it, taking 4 T-states like a NOP.
+    Skip,
+    /// Stops before the instruction, reporting [`Event::Halt`]. Stepping again calls the hook again.
+    Halt,
+}
+
+/// Called by [`CPU::step`] with the CPU, PC and opcode of every instruction before it runs.
+pub type PreExecHook = fn(&CPU, u16, u8) -> HookAction;
+
+/// Addresses the stack must stay within while a guard is set with [`CPU::set_stack_guard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StackGuard {
+    /// Lowest address a push may write, the stack's low-water mark.
+    pub low: u16,
+    /// Address just past the stack. Popping from here or above underflows it.
+    pub top: u16,
+}
+
+impl StackGuard {
+    /// The Space Invaders stack, which starts at the bottom of video RAM and has the top page
+    /// of work RAM to itself.
+    pub const SPACE_INVADERS: Self = Self { low: 0x2300, top: 0x2400 };
+
+    fn contains(&self, address: u16) -> bool {
+        (self.low..self.top).contains(&address)
+    }
+}
+
+/// Intel 8080. Like the chip's address counters, PC, SP and every address computed from them
+/// wrap around between 0xFFFF and 0x0000.
 #[derive(Debug, Clone)]
 pub struct CPU {
     pub memory: Memory,
     interrupt_status: InterruptStatus,
-    event: Option<Event>,
-    flags: u8,
+    halted: bool,
+    /// Instruction of an interrupt raised while interrupts were disabled, held until they're enabled.
+    pending_interrupt: Option<u8>,
+    undefined_opcodes: UndefinedOpcodePolicy,
+    rom_writes: RomWritePolicy,
+    stack_guard: Option<StackGuard>,
+    pre_exec_hook: Option<PreExecHook>,
+    /// SP of a push or pop the guard stopped during the current instruction.
+    stack_fault: Option<u16>,
+    /// Address of a ROM write the policy stopped during the current instruction.
+    rom_write: Option<u16>,
+    /// Unmapped address accessed during the current instruction.
+    memory_fault: Option<u16>,
+    /// Address of the instruction being executed, for watchpoint hits.
+    instruction_pc: u16,
+    /// First watched access of the last instruction, see [`Memory::add_watchpoint`].
+    watch_hit: Option<WatchHit>,
+    call_stack: Option<CallStack>,
+    /// Whether the instruction being executed was supplied by an interrupt.
+    in_interrupt: bool,
+    #[cfg(feature = "coverage")]
+    coverage: Option<Coverage>,
+    #[cfg(feature = "profiler")]
+    profiler: Option<Profiler>,
+    /// Raised by instructions and not yet taken with [`CPU::event`], oldest first.
+    events: VecDeque<Event>,
+    flags: Flags,
     pc: u16,
     sp: u16,
     a: u8,
@@ -53,11 +186,32 @@ impl CPU {
             rom[i] = *val;
         }
 
+        Self::with_memory(Memory::new(rom))
+    }
+
+    pub fn with_memory(memory: Memory) -> Self {
         Self {
-            memory: Memory::new(rom),
+            memory,
             interrupt_status: InterruptStatus::Enabled,
-            event: None,
-            flags: 0,
+            halted: false,
+            pending_interrupt: None,
+            undefined_opcodes: UndefinedOpcodePolicy::default(),
+            rom_writes: RomWritePolicy::default(),
+            stack_guard: None,
+            pre_exec_hook: None,
+            stack_fault: None,
+            rom_write: None,
+            memory_fault: None,
+            instruction_pc: 0,
+            watch_hit: None,
+            call_stack: None,
+            in_interrupt: false,
+            #[cfg(feature = "coverage")]
+            coverage: None,
+            #[cfg(feature = "profiler")]
+            profiler: None,
+            events: VecDeque::new(),
+            flags: Flags::empty(),
             pc: 0,
             sp: 0,
             a: 0,
@@ -71,10 +225,16 @@ impl CPU {
     }
 
     pub fn reset(&mut self) {
-        self.memory.reset_ram();
+        self.memory.reset();
         self.interrupt_status = InterruptStatus::Enabled;
-        self.event = None;
-        self.flags = 0;
+        self.halted = false;
+        self.pending_interrupt = None;
+        self.watch_hit = None;
+        if let Some(stack) = &mut self.call_stack {
+            stack.clear();
+        }
+        self.events.clear();
+        self.flags = Flags::empty();
         self.pc = 0;
         self.sp = 0;
         self.a = 0;
@@ -86,668 +246,777 @@ impl CPU {
         self.l = 0;
     }
 
-    pub fn interrupt(&mut self, interrupt_num: u8) {
-        if let InterruptStatus::Enabled = self.interrupt_status {
-            self.rst(interrupt_num);
+    /// Raises an interrupt with `RST interrupt_num` on the data bus, which is what the Space
+    /// Invaders board does. Returns whether the interrupt was taken.
+    pub fn interrupt(&mut self, interrupt_num: u8) -> bool {
+        matches!(self.interrupt_with(rst(interrupt_num)), Ok(Some(_)))
+    }
+
+    /// Raises an interrupt and, if it's taken, executes `opcode` as supplied by the bus during
+    /// the acknowledge cycle. Taking an interrupt disables further ones until the next EI.
+    ///
+    /// Returns the T-states `opcode` took if the interrupt was taken right away. If not, it's
+    /// latched like the board's interrupt line and taken once interrupts are enabled again,
+    /// replacing any interrupt that was already pending. Only one-byte instructions can be
+    /// supplied; operands would be read from memory at PC.
+    pub fn interrupt_with(&mut self, opcode: u8) -> Result<Option<u32>> {
+        if self.interrupt_status != InterruptStatus::Enabled {
+            self.pending_interrupt = Some(opcode);
+            return Ok(None);
+        }
+        if opcodes::undocumented(opcode) && self.undefined_opcodes == UndefinedOpcodePolicy::Error {
+            return Err(Error::UnimplementedOpcode { opcode });
+        }
+        self.halted = false;
+        self.interrupt_status = InterruptStatus::Disabled;
+        let pc = self.pc;
+        self.instruction_pc = pc;
+        self.in_interrupt = true;
+        let result = self.execute_opcode(opcode).and_then(|cycles| self.check_faults(pc).map(|_| cycles));
+        self.in_interrupt = false;
+        if result.is_err() {
+            self.pc = pc;
         }
+        result.map(Some)
     }
 
+    /// Executes one instruction and returns how many T-states it took. An interrupt latched
+    /// before interrupts were enabled is taken instead, as a step of its own. While halted,
+    /// nothing is fetched and each call idles for 4 T-states until an interrupt is accepted.
     pub fn step(&mut self) -> Result<u32> {
+        if self.interrupt_status == InterruptStatus::Enabled {
+            if let Some(opcode) = self.pending_interrupt.take() {
+                return self.interrupt_with(opcode).map(|cycles| cycles.unwrap_or(0));
+            }
+        }
+        if self.halted {
+            return Ok(HALT_IDLE_CYCLES);
+        }
+        if let Some(hook) = self.pre_exec_hook {
+            match hook(self, self.pc, self.memory[self.pc]) {
+                HookAction::Continue => {}
+                HookAction::Skip => {
+                    let len = opcodes::OPCODES[self.memory[self.pc] as usize].len;
+                    self.pc = self.pc.wrapping_add(len as u16);
+                    return Ok(HOOK_SKIP_CYCLES);
+                }
+                HookAction::Halt => {
+                    self.events.push_back(Event::Halt);
+                    return Ok(0);
+                }
+            }
+        }
+        let pc = self.pc;
+        self.instruction_pc = pc;
+        #[cfg(feature = "coverage")]
+        if let Some(coverage) = &mut self.coverage {
+            coverage.mark(pc, opcodes::OPCODES[self.memory[pc] as usize].len);
+        }
         let opcode = self.read_pc();
+        let result = self.execute_opcode(opcode).and_then(|cycles| self.check_faults(pc).map(|_| cycles));
+        if result.is_err() {
+            self.pc = pc;
+        }
+        #[cfg(feature = "profiler")]
+        if let (Some(profiler), Ok(cycles)) = (&mut self.profiler, &result) {
+            profiler.record(pc, opcode, *cycles);
+        }
+        result
+    }
 
-        macro_rules! mvi {
-            ($to:expr,$cycles:expr) => {
-                {
-                    $to = self.read_pc();
-                    $cycles
-                }
-            };
-            ($to:expr) => { mvi!($to, 2) };
+    fn check_faults(&mut self, pc: u16) -> Result<()> {
+        if let Some(address) = self.memory_fault.take() {
+            (self.stack_fault, self.rom_write) = (None, None);
+            return Err(Error::MemoryFault { pc, address });
         }
+        if let Some(address) = self.rom_write.take() {
+            self.stack_fault = None;
+            return Err(Error::RomWriteViolation { pc, address });
+        }
+        match self.stack_fault.take() {
+            Some(sp) => Err(Error::StackFault { sp, pc }),
+            None => Ok(()),
+        }
+    }
 
-        macro_rules! ret {
-            () => {
-                {
-                    self.pc = self.stack_pop_u16();
-                    3
-                }
-            };
-            (!$flag:expr) => {
-                if self.flag($flag) == 0 { ret!() } else { 1 }
-            };
-            ($flag:expr) => {
-                if self.flag($flag) != 0 { ret!() } else { 1 }
-            };
-        }
-
-        macro_rules! push {
-            ($hi:expr,$lo:expr) => {
-                {
-                    self.stack_push($hi);
-                    self.stack_push($lo);
-                    3
-                }
-            };
+    /// Decodes the instruction at `address` without executing it.
+    pub fn decode_at(&self, address: u16) -> Instruction {
+        Instruction::decode([0, 1, 2].map(|i| self.memory[address.wrapping_add(i)]))
+    }
+
+    /// Executes `instruction` as if it had been fetched from PC, so PC moves past it first, and
+    /// returns how many T-states it took. Unlike [`CPU::step`], pending interrupts, the pre-exec
+    /// hook and the undefined opcode policy don't apply.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<u32> {
+        let pc = self.pc;
+        self.instruction_pc = pc;
+        self.pc = pc.wrapping_add(instruction.size() as u16);
+        let cycles = self.execute_decoded(instruction);
+        if let Err(e) = self.check_faults(pc) {
+            self.pc = pc;
+            return Err(e);
         }
+        Ok(cycles)
+    }
 
-        macro_rules! pop {
-            ($hi:expr,$lo:expr) => {
-                {
-                    $lo = self.stack_pop();
-                    $hi = self.stack_pop();
-                    3
-                }
-            };
+    /// Executes `opcode`, already fetched, with its operands read from PC on.
+    fn execute_opcode(&mut self, opcode: u8) -> Result<u32> {
+        if opcodes::undocumented(opcode) {
+            match self.undefined_opcodes {
+                UndefinedOpcodePolicy::TreatAsDocumented => {}
+                UndefinedOpcodePolicy::Nop => return Ok(4),
+                UndefinedOpcodePolicy::Error => return Err(Error::UnimplementedOpcode { opcode }),
+            }
         }
+        Ok(DISPATCH[opcode as usize](self))
+    }
 
-        Ok(match opcode {
+    #[inline(always)]
+    fn execute_decoded(&mut self, instruction: Instruction) -> u32 {
+        let enable_pending = self.interrupt_status == InterruptStatus::EnablePending;
+
+        let cycles = match instruction {
             // Misc/control instructions
-            0x00 | 0x10 | 0x20 | 0x30 | 0x08 | 0x18 | 0x28 | 0x38 => 1, // NOP
-            0x76 => {                                                   // HLT
-                self.event = Some(Event::Halt);
-                1
-            }
-            0xD3 => {                                                   // OUT   d8
-                let port = self.read_pc();
-                self.event = Some(Event::PortWrite(port, self.a));
-                3
-            }
-            0xDB => {                                                   // IN    d8
-                let port = self.read_pc();
-                self.event = Some(Event::PortRead(port));
-                3
-            }
-            0xF3 => {                                                   // DI
+            Instruction::Nop => 4,
+            Instruction::Hlt => {
+                self.halted = true;
+                if self.interrupt_status == InterruptStatus::Disabled {
+                    self.events.push_back(Event::Halt);
+                }
+                7
+            }
+            Instruction::Out(port) => {
+                self.events.push_back(Event::PortWrite(port, self.a));
+                10
+            }
+            Instruction::In(port) => {
+                self.events.push_back(Event::PortRead(port));
+                10
+            }
+            Instruction::Di => {
                 self.interrupt_status = InterruptStatus::Disabled;
-                1
+                4
             }
-            0xFB => {                                                   // EI
-                self.interrupt_status = InterruptStatus::Enabled;
-                1
+            Instruction::Ei => {
+                self.interrupt_status = InterruptStatus::EnablePending;
+                4
             }
 
             // Jumps/calls
-            0xC0 => ret!(!ZERO_FLAG),                         // RNZ
-            0xD0 => ret!(!CARRY_FLAG),                        // RNC
-            0xE0 => ret!(!PARITY_FLAG),                       // RPO
-            0xF0 => ret!(!SIGN_FLAG),                         // RP
-            0xC2 => self.jmp_if_not(ZERO_FLAG),                         // JNZ   a16
-            0xD2 => self.jmp_if_not(CARRY_FLAG),                        // JNC   a16
-            0xE2 => self.jmp_if_not(PARITY_FLAG),                       // JPO   a16
-            0xF2 => self.jmp_if_not(SIGN_FLAG),                         // JP    a16
-            0xC3 | 0xCB => {                                            // JMP   a16
-                self.pc = self.read_pc_u16();
-                3
-            }
-            0xC4 => self.call_if_not(ZERO_FLAG),                        // CNZ   a16
-            0xD4 => self.call_if_not(CARRY_FLAG),                       // CNC   a16
-            0xE4 => self.call_if_not(PARITY_FLAG),                      // CPO   a16
-            0xF4 => self.call_if_not(SIGN_FLAG),                        // CP    a16
-            0xC7 => self.rst(0),                                        // RST   0
-            0xCF => self.rst(1),                                        // RST   1
-            0xD7 => self.rst(2),                                        // RST   2
-            0xDF => self.rst(3),                                        // RST   3
-            0xE7 => self.rst(4),                                        // RST   4
-            0xEF => self.rst(5),                                        // RST   5
-            0xF7 => self.rst(6),                                        // RST   6
-            0xFF => self.rst(7),                                        // RST   7
-            0xC8 => ret!(ZERO_FLAG),                             // RZ
-            0xD8 => ret!(CARRY_FLAG),                            // RC
-            0xE8 => ret!(PARITY_FLAG),                           // RPE
-            0xF8 => ret!(SIGN_FLAG),                             // RM
-            0xC9 | 0xD9 => ret!(),                                  // RET
-            0xE9 => {                                                   // PCHL
-                self.pc = concat_u16!(self.h, self.l);
-                1
+            Instruction::Jmp(adr) => {
+                self.pc = adr;
+                10
+            }
+            Instruction::JmpIf(condition, adr) => {
+                if self.holds(condition) { self.pc = adr; }
+                10
+            }
+            Instruction::Call(adr) => self.call(adr),
+            Instruction::CallIf(condition, adr) => if self.holds(condition) { self.call(adr) } else { 11 },
+            Instruction::Ret => {
+                self.ret();
+                10
+            }
+            // A taken conditional return takes one cycle more than RET to check the flag
+            Instruction::RetIf(condition) => {
+                if self.holds(condition) {
+                    self.ret();
+                    11
+                } else {
+                    5
+                }
             }
-            0xCA => self.jmp_if(ZERO_FLAG),                             // JZ    a16
-            0xDA => self.jmp_if(CARRY_FLAG),                            // JC    a16
-            0xEA => self.jmp_if(PARITY_FLAG),                           // JPE   a16
-            0xFA => self.jmp_if(SIGN_FLAG),                             // JM    a16
-            0xCC => self.call_if(ZERO_FLAG),                            // CZ    a16
-            0xDC => self.call_if(CARRY_FLAG),                           // CC    a16
-            0xEC => self.call_if(PARITY_FLAG),                          // CPE   a16
-            0xFC => self.call_if(SIGN_FLAG),                            // CM    a16
-            0xCD | 0xDD | 0xED | 0xFD => {                              // CALL  a16
-                let adr = self.read_pc_u16();
-                self.call(adr)
+            Instruction::Rst(n) => {
+                self.enter(CallKind::Rst, (n as u16) << 3);
+                11
+            }
+            Instruction::Pchl => {
+                self.pc = concat_u16!(self.h, self.l);
+                5
             }
 
             // 8-bit load/store/move instructions
-            0x12 => {                                                   // STAX  D
-                *self.de_val_mut() = self.a;
-                2
+            Instruction::Mov(to, from) => {
+                let val = self.operand(from);
+                self.set_operand(to, val);
+                if to == Operand::M || from == Operand::M { 7 } else { 5 }
             }
-            0x02 => {                                                   // STAX  B
-                *self.bc_val_mut() = self.a;
-                2
+            Instruction::Mvi(to, val) => {
+                self.set_operand(to, val);
+                if to == Operand::M { 10 } else { 7 }
             }
-            0x32 => {                                                   // STA   a16
-                let adr = self.read_pc_u16();
-                self.memory[adr] = self.a;
-                4
+            Instruction::Stax(pair) => {
+                self.write(self.pair_val(pair), self.a);
+                7
             }
-            0x06 => mvi!(self.b),                                                   // MVI   B,d8
-            0x0E => mvi!(self.c),                                                   // MVI   C,d8
-            0x16 => mvi!(self.d),                                                   // MVI   D,d8
-            0x1E => mvi!(self.e),                                                   // MVI   E,d8
-            0x26 => mvi!(self.h),                                                   // MVI   H,d8
-            0x2E => mvi!(self.l),                                                   // MVI   L,d8
-            0x36 => mvi!(*self.m_val_mut(), 3),                                     // MVI   M,d8
-            0x3E => mvi!(self.a),                                                   // MVI   A,d8
-            0x0A => {                                                   // LDAX  B
-                self.a = self.bc_val();
-                2
-            }
-            0x1A => {                                                   // LDAX  D
-                self.a = self.de_val();
-                2
-            }
-            0x3A => {                                                   // LDA   a16
-                let adr = self.read_pc_u16();
-                self.a = self.memory[adr];
-                4
+            Instruction::Ldax(pair) => {
+                self.a = self.read(self.pair_val(pair));
+                7
             }
-            0x40 => mov!(self.b, self.b),                     // MOV   B,B
-            0x41 => mov!(self.c, self.b),                     // MOV   B,C
-            0x42 => mov!(self.d, self.b),                     // MOV   B,D
-            0x43 => mov!(self.e, self.b),                     // MOV   B,E
-            0x44 => mov!(self.h, self.b),                     // MOV   B,H
-            0x45 => mov!(self.l, self.b),                     // MOV   B,L
-            0x46 => mov!(self.m_val(), self.b, 2),             // MOV   B,M
-            0x47 => mov!(self.a, self.b),                     // MOV   B,A
-            0x48 => mov!(self.b, self.c),                     // MOV   C,B
-            0x49 => mov!(self.c, self.c),                     // MOV   C,C
-            0x4A => mov!(self.d, self.c),                     // MOV   C,D
-            0x4B => mov!(self.e, self.c),                     // MOV   C,E
-            0x4C => mov!(self.h, self.c),                     // MOV   C,H
-            0x4D => mov!(self.l, self.c),                     // MOV   C,L
-            0x4E => mov!(self.m_val(), self.c, 2),             // MOV   C,M
-            0x4F => mov!(self.a, self.c),                     // MOV   C,A
-            0x50 => mov!(self.b, self.d),                     // MOV   D,B
-            0x51 => mov!(self.c, self.d),                     // MOV   D,C
-            0x52 => mov!(self.d, self.d),                     // MOV   D,D
-            0x53 => mov!(self.e, self.d),                     // MOV   D,E
-            0x54 => mov!(self.h, self.d),                     // MOV   D,H
-            0x55 => mov!(self.l, self.d),                     // MOV   D,L
-            0x56 => mov!(self.m_val(), self.d, 2),             // MOV   D,M
-            0x57 => mov!(self.a, self.d),                     // MOV   D,A
-            0x58 => mov!(self.b, self.e),                     // MOV   E,B
-            0x59 => mov!(self.c, self.e),                     // MOV   E,C
-            0x5A => mov!(self.d, self.e),                     // MOV   E,D
-            0x5B => mov!(self.e, self.e),                     // MOV   E,E
-            0x5C => mov!(self.h, self.e),                     // MOV   E,H
-            0x5D => mov!(self.l, self.e),                     // MOV   E,L
-            0x5E => mov!(self.m_val(), self.e, 2),             // MOV   E,M
-            0x5F => mov!(self.a, self.e),                     // MOV   E,A
-            0x60 => mov!(self.b, self.h),                     // MOV   H,B
-            0x61 => mov!(self.c, self.h),                     // MOV   H,C
-            0x62 => mov!(self.d, self.h),                     // MOV   H,D
-            0x63 => mov!(self.e, self.h),                     // MOV   H,E
-            0x64 => mov!(self.h, self.h),                     // MOV   H,H
-            0x65 => mov!(self.l, self.h),                     // MOV   H,L
-            0x66 => mov!(self.m_val(), self.h, 2),             // MOV   H,M
-            0x67 => mov!(self.a, self.h),                     // MOV   H,A
-            0x68 => mov!(self.b, self.l),                     // MOV   L,B
-            0x69 => mov!(self.c, self.l),                     // MOV   L,C
-            0x6A => mov!(self.d, self.l),                     // MOV   L,D
-            0x6B => mov!(self.e, self.l),                     // MOV   L,E
-            0x6C => mov!(self.h, self.l),                     // MOV   L,H
-            0x6D => mov!(self.l, self.l),                     // MOV   L,L
-            0x6E => mov!(self.m_val(), self.l, 2),             // MOV   L,M
-            0x6F => mov!(self.a, self.l),                     // MOV   L,A
-            0x70 => mov!(self.b, *self.m_val_mut(), 2),         // MOV   M,B
-            0x71 => mov!(self.c, *self.m_val_mut(), 2),         // MOV   M,C
-            0x72 => mov!(self.d, *self.m_val_mut(), 2),         // MOV   M,D
-            0x73 => mov!(self.e, *self.m_val_mut(), 2),         // MOV   M,E
-            0x74 => mov!(self.h, *self.m_val_mut(), 2),         // MOV   M,H
-            0x75 => mov!(self.l, *self.m_val_mut(), 2),         // MOV   M,L
-            0x77 => mov!(self.a, *self.m_val_mut(), 2),         // MOV   M,A
-            0x78 => mov!(self.b, self.a),                     // MOV   A,B
-            0x79 => mov!(self.c, self.a),                     // MOV   A,C
-            0x7A => mov!(self.d, self.a),                     // MOV   A,D
-            0x7B => mov!(self.e, self.a),                     // MOV   A,E
-            0x7C => mov!(self.h, self.a),                     // MOV   A,H
-            0x7D => mov!(self.l, self.a),                     // MOV   A,L
-            0x7E => mov!(self.m_val(), self.a, 2),             // MOV   A,M
-            0x7F => mov!(self.a, self.a),                   // MOV   A,A
-
-            // 16-bit load/store/move instructions
-            0x01 => {                                                   // LXI   B,d16
-                self.c = self.read_pc();
-                self.b = self.read_pc();
-                3
-            }
-            0x11 => {                                                   // LXI   D,d16
-                self.e = self.read_pc();
-                self.d = self.read_pc();
-                3
-            }
-            0x21 => {                                                   // LXI   H,d16
-                self.l = self.read_pc();
-                self.h = self.read_pc();
-                3
-            }
-            0x31 => {                                                   // LXI   SP,d16
-                self.sp = self.read_pc_u16();
-                3
-            }
-            0x22 => {                                                   // SHLD
-                let adr = self.read_pc_u16();
-                self.memory[adr] = self.l;
-                self.memory[adr + 1] = self.h;
-                5
+            Instruction::Sta(adr) => {
+                self.write(adr, self.a);
+                13
             }
-            0x2A => {                                                   // LHLD
-                let adr = self.read_pc_u16();
-                self.l = self.memory[adr];
-                self.h = self.memory[adr + 1];
-                5
-            }
-            0xC1 => pop!(self.b, self.c),                                                   // POP  B
-            0xD1 => pop!(self.d, self.e),                                                   // POP  D
-            0xE1 => pop!(self.h, self.l),                                                   // POP  H
-            0xF1 => pop!(self.a, self.flags),                                               // POP  PSW
-            0xC5 => push!(self.b, self.c),                                                   // PUSH  B
-            0xD5 => push!(self.d, self.e),                                                   // PUSH  D
-            0xE5 => push!(self.h, self.l),                                                   // PUSH  H
-            0xF5 => push!(self.a, self.flags),                                               // PUSH  PSW
-            0xE3 => {                                                   // XTHL
-                mem::swap(&mut self.h, &mut self.memory[self.sp + 1]);
-                mem::swap(&mut self.l, &mut self.memory[self.sp]);
-                5
+            Instruction::Lda(adr) => {
+                self.a = self.read(adr);
+                13
             }
-            0xF9 => {                                                   // SPHL
+
+            // 16-bit load/store/move instructions
+            Instruction::Lxi(pair, val) => {
+                self.set_pair_val(pair, val);
+                10
+            }
+            Instruction::Shld(adr) => {
+                self.write(adr, self.l);
+                self.write(adr.wrapping_add(1), self.h);
+                16
+            }
+            Instruction::Lhld(adr) => {
+                self.l = self.read(adr);
+                self.h = self.read(adr.wrapping_add(1));
+                16
+            }
+            Instruction::Push(pair) => {
+                let [hi, lo] = self.pair(pair).to_be_bytes();
+                self.stack_push(hi);
+                self.stack_push(lo);
+                11
+            }
+            Instruction::Pop(pair) => {
+                let lo = self.stack_pop();
+                let hi = self.stack_pop();
+                self.set_pair(pair, concat_u16!(hi, lo));
+                10
+            }
+            Instruction::Xthl => {
+                let (lo, hi) = (self.read(self.sp), self.read(self.sp.wrapping_add(1)));
+                self.write(self.sp.wrapping_add(1), self.h);
+                self.write(self.sp, self.l);
+                (self.l, self.h) = (lo, hi);
+                18
+            }
+            Instruction::Sphl => {
                 self.sp = self.m();
-                1
+                5
             }
-            0xEB => {                                                   // XCHG
+            Instruction::Xchg => {
                 mem::swap(&mut self.h, &mut self.d);
                 mem::swap(&mut self.l, &mut self.e);
-                1
+                4
             }
 
             // 8-bit arithmetic/logical instructions
-            0x04 => {                                                   // INR   B
-                self.b = self.inr(self.b);
-                1
-            }
-            0x0C => {                                                   // INR   C
-                self.c = self.inr(self.c);
-                1
-            }
-            0x14 => {                                                   // INR   D
-                self.d = self.inr(self.d);
-                1
-            }
-            0x1C => {                                                   // INR   E
-                self.e = self.inr(self.e);
-                1
-            }
-            0x24 => {                                                   // INR   H
-                self.h = self.inr(self.h);
-                1
-            }
-            0x2C => {                                                   // INR   L
-                self.l = self.inr(self.l);
-                1
-            }
-            0x34 => {                                                   // INR   M
-                *self.m_val_mut() = self.inr(self.m_val());
-                3
-            }
-            0x3C => {                                                   // INR   A
-                self.a = self.inr(self.a);
-                1
-            }
-            0x05 => {                                                   // DCR   B
-                self.b = self.dcr(self.b);
-                1
-            }
-            0x0D => {                                                   // DCR   C
-                self.c = self.dcr(self.c);
-                1
-            }
-            0x15 => {                                                   // DCR   D
-                self.d = self.dcr(self.d);
-                1
-            }
-            0x1D => {                                                   // DCR   E
-                self.e = self.dcr(self.e);
-                1
-            }
-            0x25 => {                                                   // DCR   H
-                self.h = self.dcr(self.h);
-                1
-            }
-            0x2D => {                                                   // DCR   L
-                self.l = self.dcr(self.l);
-                1
-            }
-            0x35 => {                                                   // DCR   M
-                *self.m_val_mut() = self.dcr(self.m_val());
-                3
-            }
-            0x3D => {                                                   // DCR   A
-                self.a = self.dcr(self.a);
-                1
-            }
-            0x07 => {                                                   // RLC
-                self.set_flag(CARRY_FLAG, self.a & (1 << 7));
+            Instruction::Inr(operand) => {
+                let val = self.operand(operand);
+                let val = self.inr(val);
+                self.set_operand(operand, val);
+                if operand == Operand::M { 10 } else { 5 }
+            }
+            Instruction::Dcr(operand) => {
+                let val = self.operand(operand);
+                let val = self.dcr(val);
+                self.set_operand(operand, val);
+                if operand == Operand::M { 10 } else { 5 }
+            }
+            Instruction::Rlc => {
+                self.set_flag(Flags::CARRY, self.a & (1 << 7));
                 self.a = self.a.rotate_left(1);
-                1
+                4
             }
-            0x0F => {                                                   // RRC
-                self.set_flag(CARRY_FLAG, self.a & 1);
+            Instruction::Rrc => {
+                self.set_flag(Flags::CARRY, self.a & 1);
                 self.a = self.a.rotate_right(1);
-                1
+                4
             }
-            0x17 => {                                                   // RAL
+            Instruction::Ral => {
                 let carry = self.a & (1 << 7);
-                self.a = (self.a << 1) | self.flag(CARRY_FLAG);
-                self.set_flag(CARRY_FLAG, carry);
-                1
+                self.a = (self.a << 1) | self.flag(Flags::CARRY);
+                self.set_flag(Flags::CARRY, carry);
+                4
             }
-            0x1F => {                                                   // RAR
+            Instruction::Rar => {
                 let carry = self.a & 1;
-                self.a = (self.a >> 1) | (self.flag(CARRY_FLAG) << 7);
-                self.set_flag(CARRY_FLAG, carry);
-                1
+                self.a = (self.a >> 1) | (self.flag(Flags::CARRY) << 7);
+                self.set_flag(Flags::CARRY, carry);
+                4
             }
-            0x27 => {                                                   // DAA
-                if self.a & 0x0F > 9 {
-                    self.a += 6;
+            Instruction::Daa => {
+                let mut correction = 0;
+                let mut carry = self.flag(Flags::CARRY);
+                if self.a & 0x0F > 9 || self.flag(Flags::AUX_CARRY) != 0 {
+                    correction |= 0x06;
                 }
-
-                if self.a & 0xF0 > 0x90 {
-                    let (result, carry) = self.a.overflowing_add(0x60);
-                    self.set_flags(self.a, carry as u8);
-                    self.a = result;
+                if self.a > 0x99 || carry != 0 {
+                    correction |= 0x60;
+                    carry = 1;
                 }
 
-                1
+                let result = self.a.wrapping_add(correction);
+                self.set_flag(Flags::AUX_CARRY, half_carry(self.a, correction, 0));
+                self.set_result_flags(result, carry);
+                self.a = result;
+                4
             }
-            0x37 => {                                                   // STC
-                self.set_flag(CARRY_FLAG, 1);
-                1
+            Instruction::Stc => {
+                self.set_flag(Flags::CARRY, 1);
+                4
             }
-            0x2F => {                                                   // CMA
+            Instruction::Cma => {
                 self.a = !self.a;
-                1
-            }
-            0x3F => {                                                   // CMC
-                self.flags ^= CARRY_FLAG;
-                1
-            }
-            0x80 => self.add_a(self.b),                                 // ADD   B
-            0x81 => self.add_a(self.c),                                 // ADD   C
-            0x82 => self.add_a(self.d),                                 // ADD   D
-            0x83 => self.add_a(self.e),                                 // ADD   E
-            0x84 => self.add_a(self.h),                                 // ADD   H
-            0x85 => self.add_a(self.l),                                 // ADD   L
-            0x86 => {                                                         // ADD   M
-                self.add_a(self.m_val());
-                2
-            }
-            0x87 => self.add_a(self.a),                                 // ADD   A
-            0x88 => self.add_a(self.b + self.flag(CARRY_FLAG)),         // ADC   B
-            0x89 => self.add_a(self.c + self.flag(CARRY_FLAG)),         // ADC   C
-            0x8A => self.add_a(self.d + self.flag(CARRY_FLAG)),         // ADC   D
-            0x8B => self.add_a(self.e + self.flag(CARRY_FLAG)),         // ADC   E
-            0x8C => self.add_a(self.h + self.flag(CARRY_FLAG)),         // ADC   H
-            0x8D => self.add_a(self.l + self.flag(CARRY_FLAG)),         // ADC   L
-            0x8E => {                                                   // ADC   M
-                self.add_a(self.m_val() + self.flag(CARRY_FLAG));
-                2
-            }
-            0x8F => self.add_a(self.a + self.flag(CARRY_FLAG)),         // ADC   A
-            0x90 => self.sub_a(self.b),                                 // SUB   B
-            0x91 => self.sub_a(self.c),                                 // SUB   C
-            0x92 => self.sub_a(self.d),                                 // SUB   D
-            0x93 => self.sub_a(self.e),                                 // SUB   E
-            0x94 => self.sub_a(self.h),                                 // SUB   H
-            0x95 => self.sub_a(self.l),                                 // SUB   L
-            0x96 => {                                                   // SUB   M
-                self.sub_a(self.m_val());
-                2
-            }
-            0x97 => self.sub_a(self.a),                                 // SUB   A
-            0x98 => self.sub_a(self.b + self.flag(CARRY_FLAG)),         // SBB   B
-            0x99 => self.sub_a(self.c + self.flag(CARRY_FLAG)),         // SBB   C
-            0x9A => self.sub_a(self.d + self.flag(CARRY_FLAG)),         // SBB   D
-            0x9B => self.sub_a(self.e + self.flag(CARRY_FLAG)),         // SBB   E
-            0x9C => self.sub_a(self.h + self.flag(CARRY_FLAG)),         // SBB   H
-            0x9D => self.sub_a(self.l + self.flag(CARRY_FLAG)),         // SBB   L
-            0x9E => {                                                   // SBB   M
-                self.sub_a(self.m_val() + self.flag(CARRY_FLAG));
-                2
-            }
-            0x9F => self.sub_a(self.a + self.flag(CARRY_FLAG)),         // SBB   A
-            0xA0 => self.and_a(self.b),                                 // ANA   B
-            0xA1 => self.and_a(self.c),                                 // ANA   C
-            0xA2 => self.and_a(self.d),                                 // ANA   D
-            0xA3 => self.and_a(self.e),                                 // ANA   E
-            0xA4 => self.and_a(self.h),                                 // ANA   H
-            0xA5 => self.and_a(self.l),                                 // ANA   L
-            0xA6 => {                                                   // ANA   M
-                self.and_a(self.m_val());
-                2
-            }
-            0xA7 => self.and_a(self.a),                                 // ANA   A
-            0xA8 => self.xor_a(self.b),                                 // XRA   B
-            0xA9 => self.xor_a(self.c),                                 // XRA   C
-            0xAA => self.xor_a(self.d),                                 // XRA   D
-            0xAB => self.xor_a(self.e),                                 // XRA   E
-            0xAC => self.xor_a(self.h),                                 // XRA   H
-            0xAD => self.xor_a(self.l),                                 // XRA   L
-            0xAE => {                                                   // XRA   M
-                self.xor_a(self.m_val());
-                2
-            }
-            0xAF => self.xor_a(self.a),                                 // XRA   A
-            0xB0 => self.or_a(self.b),                                  // ORA   B
-            0xB1 => self.or_a(self.c),                                  // ORA   C
-            0xB2 => self.or_a(self.d),                                  // ORA   D
-            0xB3 => self.or_a(self.e),                                  // ORA   E
-            0xB4 => self.or_a(self.h),                                  // ORA   H
-            0xB5 => self.or_a(self.l),                                  // ORA   L
-            0xB6 => {                                                   // ORA   M
-                self.or_a(self.m_val());
-                2
-            }
-            0xB7 => self.or_a(self.a),                                  // ORA   A
-            0xB8 => self.cmp_a(self.b),                                 // CMP   B
-            0xB9 => self.cmp_a(self.c),                                 // CMP   C
-            0xBA => self.cmp_a(self.d),                                 // CMP   D
-            0xBB => self.cmp_a(self.e),                                 // CMP   E
-            0xBC => self.cmp_a(self.h),                                 // CMP   H
-            0xBD => self.cmp_a(self.l),                                 // CMP   L
-            0xBE => {                                                   // CMP   M
-                self.cmp_a(self.m_val());
-                2
-            }
-            0xBF => self.cmp_a(self.a),                                 // CMP   A
-            0xC6 => {                                                   // ADI   d8
-                let d8 = self.read_pc();
-                self.add_a(d8);
-                2
-            }
-            0xD6 => {                                                   // SUI   d8
-                let d8 = self.read_pc();
-                self.sub_a(d8);
-                2
-            }
-            0xE6 => {                                                   // ANI   d8
-                let d8 = self.read_pc();
-                self.and_a(d8);
-                2
-            }
-            0xF6 => {                                                   // ORI   d8
-                let d8 = self.read_pc();
-                self.or_a(d8);
-                2
-            }
-            0xCE => {                                                   // ACI   d8
-                let d8 = self.read_pc();
-                self.add_a(d8 + self.flag(CARRY_FLAG));
-                2
-            }
-            0xDE => {                                                   // SBI   d8
-                let d8 = self.read_pc();
-                self.sub_a(d8 + self.flag(CARRY_FLAG));
-                2
-            }
-            0xEE => {                                                   // XRI   d8
-                let d8 = self.read_pc();
-                self.xor_a(d8);
-                2
-            }
-            0xFE => {                                                   // CPI   d8
-                let d8 = self.read_pc();
-                self.cmp_a(d8);
-                2
+                4
             }
+            Instruction::Cmc => {
+                self.flags.toggle(Flags::CARRY);
+                4
+            }
+            Instruction::Add(operand) => self.alu(operand, Self::add_a),
+            Instruction::Adc(operand) => self.alu(operand, Self::adc_a),
+            Instruction::Sub(operand) => self.alu(operand, Self::sub_a),
+            Instruction::Sbb(operand) => self.alu(operand, Self::sbb_a),
+            Instruction::Ana(operand) => self.alu(operand, Self::and_a),
+            Instruction::Xra(operand) => self.alu(operand, Self::xor_a),
+            Instruction::Ora(operand) => self.alu(operand, Self::or_a),
+            Instruction::Cmp(operand) => self.alu(operand, Self::cmp_a),
+            Instruction::Adi(d8) => self.add_a(d8) + 3,
+            Instruction::Aci(d8) => self.adc_a(d8) + 3,
+            Instruction::Sui(d8) => self.sub_a(d8) + 3,
+            Instruction::Sbi(d8) => self.sbb_a(d8) + 3,
+            Instruction::Ani(d8) => self.and_a(d8) + 3,
+            Instruction::Xri(d8) => self.xor_a(d8) + 3,
+            Instruction::Ori(d8) => self.or_a(d8) + 3,
+            Instruction::Cpi(d8) => self.cmp_a(d8) + 3,
 
             // 16-bit arithmetic/logical instructions
-            0x03 => Self::inx(&mut self.b, &mut self.c),                // INX   B
-            0x13 => Self::inx(&mut self.d, &mut self.e),                // INX   D
-            0x23 => Self::inx(&mut self.h, &mut self.l),                // INX   H
-            0x33 => {                                                   // INX   SP
-                self.sp = self.sp.wrapping_add(1);
-                1
-            }
-            0x09 => self.dad(self.b, self.c),                           // DAD   B
-            0x19 => self.dad(self.d, self.e),                           // DAD   D
-            0x29 => self.dad(self.h, self.l),                           // DAD   H
-            0x39 => self.dad((self.sp >> 8) as u8, self.sp as u8),      // DAD   SP
-            0x0B => Self::dcx(&mut self.b, &mut self.c),                // DCX   B
-            0x1B => Self::dcx(&mut self.d, &mut self.e),                // DCX   D
-            0x2B => Self::dcx(&mut self.h, &mut self.l),                // DCX   H
-            0x3B => {                                                   // DCX   SP
-                self.sp = self.sp.wrapping_sub(1);
-                1
+            Instruction::Inx(pair) => {
+                self.set_pair_val(pair, self.pair_val(pair).wrapping_add(1));
+                5
             }
-        })
+            Instruction::Dcx(pair) => {
+                self.set_pair_val(pair, self.pair_val(pair).wrapping_sub(1));
+                5
+            }
+            Instruction::Dad(pair) => {
+                let (result, carry) = self.m().overflowing_add(self.pair_val(pair));
+                (self.h, self.l) = ((result >> 8) as u8, result as u8);
+                self.set_flag(Flags::CARRY, carry as u8);
+                10
+            }
+        };
+
+        // A DI or another EI in between overrides the pending enable
+        if enable_pending && instruction != Instruction::Ei && self.interrupt_status == InterruptStatus::EnablePending {
+            self.interrupt_status = InterruptStatus::Enabled;
+        }
+
+        cycles
     }
 
+    /// Takes the oldest event not taken yet. Events queue up, so polling after every step is
+    /// only needed to answer a port read before the next instruction uses the value.
     pub fn event(&mut self) -> Option<Event> {
-        mem::replace(&mut self.event, None)
+        self.events.pop_front()
     }
 
-    pub fn port_in(&mut self, val: u8) {
-        self.a = val;
+    /// Takes every event not taken yet, oldest first.
+    pub fn drain_events(&mut self) -> Drain<'_, Event> {
+        self.events.drain(..)
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp;
+    }
+
+    pub fn reg(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::E => self.e,
+            Register::H => self.h,
+            Register::L => self.l,
+        }
+    }
+
+    pub fn set_reg(&mut self, register: Register, val: u8) {
+        *match register {
+            Register::A => &mut self.a,
+            Register::B => &mut self.b,
+            Register::C => &mut self.c,
+            Register::D => &mut self.d,
+            Register::E => &mut self.e,
+            Register::H => &mut self.h,
+            Register::L => &mut self.l,
+        } = val;
+    }
+
+    pub fn pair(&self, pair: RegisterPair) -> u16 {
+        match pair {
+            RegisterPair::BC => concat_u16!(self.b, self.c),
+            RegisterPair::DE => concat_u16!(self.d, self.e),
+            RegisterPair::HL => concat_u16!(self.h, self.l),
+            RegisterPair::PSW => concat_u16!(self.a, self.flags.to_psw()),
+        }
+    }
+
+    /// Setting `PSW` discards the flags bits that don't exist, like `POP PSW`.
+    pub fn set_pair(&mut self, pair: RegisterPair, val: u16) {
+        let [hi, lo] = val.to_be_bytes();
+        match pair {
+            RegisterPair::BC => (self.b, self.c) = (hi, lo),
+            RegisterPair::DE => (self.d, self.e) = (hi, lo),
+            RegisterPair::HL => (self.h, self.l) = (hi, lo),
+            RegisterPair::PSW => (self.a, self.flags) = (hi, Flags::from_psw(lo)),
+        }
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn undefined_opcode_policy(&self) -> UndefinedOpcodePolicy {
+        self.undefined_opcodes
+    }
+
+    pub fn set_undefined_opcode_policy(&mut self, policy: UndefinedOpcodePolicy) {
+        self.undefined_opcodes = policy;
+    }
+
+    pub fn rom_write_policy(&self) -> RomWritePolicy {
+        self.rom_writes
+    }
+
+    pub fn set_rom_write_policy(&mut self, policy: RomWritePolicy) {
+        self.rom_writes = policy;
+    }
+
+    /// Sets the function called before every instruction, or with `None` removes it. Costs a
+    /// single check per instruction while unset.
+    pub fn set_pre_exec_hook(&mut self, hook: Option<PreExecHook>) {
+        self.pre_exec_hook = hook;
+    }
+
+    pub fn stack_guard(&self) -> Option<StackGuard> {
+        self.stack_guard
+    }
+
+    /// With a guard set, a push or pop outside of it fails the instruction with
+    /// [`Error::StackFault`] instead of writing over whatever is there. The faulting access is
+    /// skipped and PC is left on the instruction, but the rest of it may already have run.
+    pub fn set_stack_guard(&mut self, guard: Option<StackGuard>) {
+        self.stack_guard = guard;
+    }
+
+    /// Starts or stops recording calls and returns in a shadow call stack, for backtraces.
+    /// Stopping discards the frames recorded so far.
+    pub fn set_call_stack_tracking(&mut self, enabled: bool) {
+        self.call_stack = enabled.then(CallStack::new);
+    }
+
+    /// The shadow call stack, if tracking is on.
+    pub fn call_stack(&self) -> Option<&CallStack> {
+        self.call_stack.as_ref()
+    }
+
+    pub fn call_stack_mut(&mut self) -> Option<&mut CallStack> {
+        self.call_stack.as_mut()
+    }
+
+    /// Starts marking the bytes fetched as instructions in `coverage`, or stops with `None`. The
+    /// coverage is kept across resets.
+    #[cfg(feature = "coverage")]
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    #[cfg(feature = "coverage")]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    #[cfg(feature = "coverage")]
+    pub fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        self.coverage.as_mut()
+    }
+
+    /// Starts counting the instructions executed in `profiler`, or stops with `None`. The counts
+    /// are kept across resets.
+    #[cfg(feature = "profiler")]
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    #[cfg(feature = "profiler")]
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    #[cfg(feature = "profiler")]
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    /// Takes the first watched access made since the last call, see [`Memory::add_watchpoint`].
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    /// Instruction of the interrupt waiting for interrupts to be enabled, if any.
+    pub fn pending_interrupt(&self) -> Option<u8> {
+        self.pending_interrupt
+    }
+
+    pub fn clear_pending_interrupt(&mut self) {
+        self.pending_interrupt = None;
+    }
+
+    /// Whether an interrupt would be accepted now, which right after EI it isn't yet.
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupt_status == InterruptStatus::Enabled
+    }
+
+    pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+        self.interrupt_status = if enabled { InterruptStatus::Enabled } else { InterruptStatus::Disabled };
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            flags: self.flags,
+        }
+    }
+
+    /// Overwrites every register with `registers`, e.g. to restore a [`CPU::registers`] snapshot.
+    pub fn set_registers(&mut self, registers: Registers) {
+        let Registers { pc, sp, a, b, c, d, e, h, l, flags } = registers;
+        (self.pc, self.sp, self.flags) = (pc, sp, flags);
+        (self.a, self.b, self.c, self.d, self.e, self.h, self.l) = (a, b, c, d, e, h, l);
     }
 
-    fn jmp_if(&mut self, flag: u8) -> u32 {
-        let adr = self.read_pc_u16();
-        if self.flag(flag) != 0 { self.pc = adr; }
-        3
+    pub(crate) fn debug_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\n",
+                "    \"pc\": \"0x{:04X}\",\n",
+                "    \"sp\": \"0x{:04X}\",\n",
+                "    \"a\": \"0x{:02X}\",\n",
+                "    \"b\": \"0x{:02X}\",\n",
+                "    \"c\": \"0x{:02X}\",\n",
+                "    \"d\": \"0x{:02X}\",\n",
+                "    \"e\": \"0x{:02X}\",\n",
+                "    \"h\": \"0x{:02X}\",\n",
+                "    \"l\": \"0x{:02X}\",\n",
+                "    \"flags\": \"{}\",\n",
+                "    \"interrupts_enabled\": {}\n",
+                "  }}",
+            ),
+            self.pc, self.sp, self.a, self.b, self.c, self.d, self.e, self.h, self.l,
+            self.flags, self.interrupt_status == InterruptStatus::Enabled,
+        )
     }
 
-    fn jmp_if_not(&mut self, flag: u8) -> u32 {
-        let adr = self.read_pc_u16();
-        if self.flag(flag) == 0 { self.pc = adr; }
-        3
+    pub(crate) fn write_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.sp.to_le_bytes());
+        out.extend_from_slice(&[self.a, self.b, self.c, self.d, self.e, self.h, self.l]);
+        out.push(self.flags.to_psw());
+        out.push(match self.interrupt_status {
+            InterruptStatus::Disabled => 0,
+            InterruptStatus::Enabled => 1,
+            InterruptStatus::EnablePending => 2,
+        });
+        out.push(self.halted as u8);
+        out.extend_from_slice(&[self.pending_interrupt.is_some() as u8, self.pending_interrupt.unwrap_or(0)]);
     }
 
-    fn rst(&mut self, val: u8) -> u32 {
-        self.call((val as u16) << 3)
+    pub(crate) fn read_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.pc = reader.u16()?;
+        self.sp = reader.u16()?;
+        self.a = reader.u8()?;
+        self.b = reader.u8()?;
+        self.c = reader.u8()?;
+        self.d = reader.u8()?;
+        self.e = reader.u8()?;
+        self.h = reader.u8()?;
+        self.l = reader.u8()?;
+        self.flags = Flags::from_psw(reader.u8()?);
+        self.interrupt_status = match reader.u8()? {
+            0 => InterruptStatus::Disabled,
+            2 => InterruptStatus::EnablePending,
+            _ => InterruptStatus::Enabled,
+        };
+        self.halted = reader.u8()? != 0;
+        let pending = reader.u8()? != 0;
+        let opcode = reader.u8()?;
+        self.pending_interrupt = pending.then_some(opcode);
+        self.events.clear();
+        Ok(())
+    }
+
+    pub fn port_in(&mut self, val: u8) {
+        self.a = val;
     }
 
     fn call(&mut self, adr: u16) -> u32 {
+        self.enter(CallKind::Call, adr);
+        17
+    }
+
+    /// Pushes the return address and jumps to `adr`, recording the call if tracking is on.
+    fn enter(&mut self, kind: CallKind, adr: u16) {
         self.stack_push_u16(self.pc);
+        if let Some(stack) = &mut self.call_stack {
+            let kind = if self.in_interrupt { CallKind::Interrupt } else { kind };
+            stack.call(CallFrame { kind, call_site: self.instruction_pc, target: adr, return_address: self.pc, sp: self.sp });
+        }
         self.pc = adr;
-        5
     }
 
-    fn call_if(&mut self, flag: u8) -> u32 {
-        let adr = self.read_pc_u16();
-        if self.flag(flag) != 0 { self.call(adr) } else { 3 }
+    fn ret(&mut self) {
+        let sp = self.sp;
+        self.pc = self.stack_pop_u16();
+        if let Some(stack) = &mut self.call_stack {
+            stack.ret(self.instruction_pc, sp, self.pc);
+        }
+    }
+
+    #[inline(always)]
+    fn holds(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::NotZero => self.flag(Flags::ZERO) == 0,
+            Condition::Zero => self.flag(Flags::ZERO) != 0,
+            Condition::NoCarry => self.flag(Flags::CARRY) == 0,
+            Condition::Carry => self.flag(Flags::CARRY) != 0,
+            Condition::ParityOdd => self.flag(Flags::PARITY) == 0,
+            Condition::ParityEven => self.flag(Flags::PARITY) != 0,
+            Condition::Plus => self.flag(Flags::SIGN) == 0,
+            Condition::Minus => self.flag(Flags::SIGN) != 0,
+        }
     }
 
-    fn call_if_not(&mut self, flag: u8) -> u32 {
-        let adr = self.read_pc_u16();
-        if self.flag(flag) == 0 { self.call(adr) } else { 3 }
+    /// Runs an ALU operation on A and `operand`, which takes 3 T-states more from memory.
+    #[inline(always)]
+    fn alu(&mut self, operand: Operand, op: fn(&mut Self, u8) -> u32) -> u32 {
+        let val = self.operand(operand);
+        op(self, val) + if operand == Operand::M { 3 } else { 0 }
     }
 
     fn inr(&mut self, val: u8) -> u8 {
         let result = val.wrapping_add(1);
-        self.set_flags(result, self.flag(CARRY_FLAG));
+        self.set_flag(Flags::AUX_CARRY, half_carry(val, 1, 0));
+        self.set_result_flags(result, self.flag(Flags::CARRY));
         result
     }
 
     fn dcr(&mut self, val: u8) -> u8 {
         let result = val.wrapping_sub(1);
-        self.set_flags(result, self.flag(CARRY_FLAG));
+        // Subtraction adds the complement, so the auxiliary carry is set when there's no borrow
+        self.set_flag(Flags::AUX_CARRY, half_carry(val, !1, 1));
+        self.set_result_flags(result, self.flag(Flags::CARRY));
         result
     }
 
     fn add_a(&mut self, right: u8) -> u32 {
         let (result, overflow) = self.a.overflowing_add(right);
-        self.set_flags(result, overflow as u8);
+        self.set_flag(Flags::AUX_CARRY, half_carry(self.a, right, 0));
+        self.set_result_flags(result, overflow as u8);
         self.a = result;
-        1
+        4
+    }
+
+    fn adc_a(&mut self, right: u8) -> u32 {
+        let carry = self.flag(Flags::CARRY);
+        let result = self.a as u16 + right as u16 + carry as u16;
+        self.set_flag(Flags::AUX_CARRY, half_carry(self.a, right, carry));
+        self.set_result_flags(result as u8, (result > 0xFF) as u8);
+        self.a = result as u8;
+        4
     }
 
     fn sub_a(&mut self, val: u8) -> u32 {
         let (result, underflow) = self.a.overflowing_sub(val);
-        self.set_flags(result, underflow as u8);
+        self.set_flag(Flags::AUX_CARRY, half_carry(self.a, !val, 1));
+        self.set_result_flags(result, underflow as u8);
         self.a = result;
-        1
+        4
+    }
+
+    fn sbb_a(&mut self, val: u8) -> u32 {
+        let borrow = self.flag(Flags::CARRY);
+        // Borrowing out of bit 7 wraps the result above 0xFF
+        let result = (self.a as u16).wrapping_sub(val as u16 + borrow as u16);
+        self.set_flag(Flags::AUX_CARRY, half_carry(self.a, !val, 1 - borrow));
+        self.set_result_flags(result as u8, (result > 0xFF) as u8);
+        self.a = result as u8;
+        4
     }
 
     fn and_a(&mut self, val: u8) -> u32 {
+        // The 8080 sets the auxiliary carry from bit 3 of either operand
+        self.set_flag(Flags::AUX_CARRY, (self.a | val) & 0x08);
         self.a &= val;
-        self.set_flags(self.a, 0);
-        1
+        self.set_result_flags(self.a, 0);
+        4
     }
 
     fn xor_a(&mut self, val: u8) -> u32 {
         self.a ^= val;
-        self.set_flags(self.a, 0);
-        1
+        self.set_flag(Flags::AUX_CARRY, 0);
+        self.set_result_flags(self.a, 0);
+        4
     }
 
     fn or_a(&mut self, val: u8) -> u32 {
         self.a |= val;
-        self.set_flags(self.a, 0);
-        1
+        self.set_flag(Flags::AUX_CARRY, 0);
+        self.set_result_flags(self.a, 0);
+        4
     }
 
     fn cmp_a(&mut self, val: u8) -> u32 {
         let (result, underflow) = self.a.overflowing_sub(val);
-        self.set_flags(result, underflow as u8);
-        1
-    }
-
-    fn inx(hi: &mut u8, lo: &mut u8) -> u32 {
-        let (result_lo, carry) = lo.overflowing_add(1);
-        *lo = result_lo;
-        *hi = hi.wrapping_add(carry as u8);
-        1
-    }
-
-    fn dad(&mut self, hi: u8, lo: u8) -> u32 {
-        let val = concat_u16!(hi, lo);
-        let hl = concat_u16!(self.h, self.l);
-
-        let (result, carry) = hl.overflowing_add(val);
-        self.h = (result >> 8) as u8;
-        self.l = (result & 0xFF) as u8;
-        self.set_flag(CARRY_FLAG, carry as u8);
-        3
-    }
-
-    fn dcx(hi: &mut u8, lo: &mut u8) -> u32 {
-        let (result_lo, carry) = lo.overflowing_sub(1);
-        *lo = result_lo;
-        *hi = hi.wrapping_sub(carry as u8);
-        1
+        self.set_flag(Flags::AUX_CARRY, half_carry(self.a, !val, 1));
+        self.set_result_flags(result, underflow as u8);
+        4
     }
 
     fn stack_push(&mut self, val: u8) {
-        self.sp -= 1;
-        self.memory[self.sp] = val;
+        let sp = self.sp.wrapping_sub(1);
+        if self.stack_guard.is_some_and(|guard| !guard.contains(sp)) {
+            self.stack_fault.get_or_insert(self.sp);
+            return;
+        }
+        self.sp = sp;
+        self.write(sp, val);
     }
 
     fn stack_push_u16(&mut self, val: u16) {
@@ -756,8 +1025,12 @@ impl CPU {
     }
 
     fn stack_pop(&mut self) -> u8 {
-        let val = self.memory[self.sp];
-        self.sp += 1;
+        if self.stack_guard.is_some_and(|guard| !guard.contains(self.sp)) {
+            self.stack_fault.get_or_insert(self.sp);
+            return 0;
+        }
+        let val = self.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
         val
     }
 
@@ -767,61 +1040,681 @@ impl CPU {
         (hi << 8) | lo
     }
 
-    fn set_flags(&mut self, val: u8, carry: u8) {
-        self.set_flag(CARRY_FLAG, carry);
-        self.set_flag(PARITY_FLAG, crate::even_parity(val) as u8);
-        self.set_flag(ZERO_FLAG, (val == 0) as u8);
-        self.set_flag(SIGN_FLAG, val & (1 << 7));
+    fn set_result_flags(&mut self, val: u8, carry: u8) {
+        self.set_flag(Flags::CARRY, carry);
+        self.set_flag(Flags::PARITY, crate::even_parity(val) as u8);
+        self.set_flag(Flags::ZERO, (val == 0) as u8);
+        self.set_flag(Flags::SIGN, val & (1 << 7));
     }
 
     fn read_pc(&mut self) -> u8 {
-        let val = self.memory[self.pc];
-        self.pc += 1;
+        let val = self.fetch(self.pc);
+        self.pc = self.pc.wrapping_add(1);
         val
     }
 
-    fn read_pc_u16(&mut self) -> u16 {
-        let val = concat_u16!(self.memory[self.pc + 1], self.memory[self.pc]);
-        self.pc += 2;
+    fn flag(&self, flag: Flags) -> u8 {
+        self.flags.contains(flag).into()
+    }
+
+    fn set_flag(&mut self, flag: Flags, value: u8) {
+        self.flags.set(flag, value != 0);
+    }
+
+    fn m(&self) -> u16 { concat_u16!(self.h, self.l) }
+
+    #[inline(always)]
+    fn operand(&mut self, operand: Operand) -> u8 {
+        match operand {
+            Operand::B => self.b,
+            Operand::C => self.c,
+            Operand::D => self.d,
+            Operand::E => self.e,
+            Operand::H => self.h,
+            Operand::L => self.l,
+            Operand::M => self.read(self.m()),
+            Operand::A => self.a,
+        }
+    }
+
+    #[inline(always)]
+    fn set_operand(&mut self, operand: Operand, val: u8) {
+        match operand {
+            Operand::B => self.b = val,
+            Operand::C => self.c = val,
+            Operand::D => self.d = val,
+            Operand::E => self.e = val,
+            Operand::H => self.h = val,
+            Operand::L => self.l = val,
+            Operand::M => self.write(self.m(), val),
+            Operand::A => self.a = val,
+        }
+    }
+
+    fn pair_val(&self, pair: Pair) -> u16 {
+        match pair {
+            Pair::BC => concat_u16!(self.b, self.c),
+            Pair::DE => concat_u16!(self.d, self.e),
+            Pair::HL => concat_u16!(self.h, self.l),
+            Pair::SP => self.sp,
+        }
+    }
+
+    fn set_pair_val(&mut self, pair: Pair, val: u16) {
+        let [hi, lo] = val.to_be_bytes();
+        match pair {
+            Pair::BC => (self.b, self.c) = (hi, lo),
+            Pair::DE => (self.d, self.e) = (hi, lo),
+            Pair::HL => (self.h, self.l) = (hi, lo),
+            Pair::SP => self.sp = val,
+        }
+    }
+
+    /// Data read by an instruction, reported if a watchpoint covers it.
+    fn read(&mut self, address: u16) -> u8 {
+        let val = self.fetch(address);
+        if self.memory.watched(address, Access::Read) {
+            self.watch(address, Access::Read, val, val);
+        }
         val
     }
 
-    fn flag(&self, flag: u8) -> u8 {
-        (self.flags & flag != 0).into()
+    /// Data write by an instruction, reported if a watchpoint covers it.
+    fn write(&mut self, address: u16, val: u8) {
+        if self.memory.watched(address, Access::Write) {
+            self.watch(address, Access::Write, self.memory[address], val);
+        }
+        if !self.memory.write(address, val) {
+            if !self.memory.mapped(address) {
+                self.memory_fault.get_or_insert(address);
+            } else if self.rom_writes == RomWritePolicy::Error {
+                self.rom_write.get_or_insert(address);
+            }
+        }
     }
 
-    fn set_flag(&mut self, flag: u8, value: u8) {
-        if value != 0 {
-            self.flags |= flag;
-        } else {
-            self.flags &= !flag;
+    /// Any read by an instruction, recording a fault for unmapped addresses.
+    #[inline(always)]
+    fn fetch(&mut self, address: u16) -> u8 {
+        self.memory.read(address).unwrap_or_else(|| {
+            self.memory_fault.get_or_insert(address);
+            OPEN_BUS
+        })
+    }
+
+    fn watch(&mut self, address: u16, access: Access, old: u8, new: u8) {
+        let pc = self.instruction_pc;
+        self.watch_hit.get_or_insert(WatchHit { pc, address, access, old, new });
+    }
+}
+
+/// Where translated code finds the registers it accesses directly.
+#[cfg(feature = "jit")]
+impl CPU {
+    pub(crate) const PC_OFFSET: usize = mem::offset_of!(CPU, pc);
+    pub(crate) const SP_OFFSET: usize = mem::offset_of!(CPU, sp);
+
+    /// Offset of the register `operand` names, `None` for M.
+    pub(crate) fn register_offset(operand: Operand) -> Option<usize> {
+        Some(match operand {
+            Operand::B => mem::offset_of!(CPU, b),
+            Operand::C => mem::offset_of!(CPU, c),
+            Operand::D => mem::offset_of!(CPU, d),
+            Operand::E => mem::offset_of!(CPU, e),
+            Operand::H => mem::offset_of!(CPU, h),
+            Operand::L => mem::offset_of!(CPU, l),
+            Operand::A => mem::offset_of!(CPU, a),
+            Operand::M => return None,
+        })
+    }
+
+    /// Whether translated blocks can run: nothing needs the checks [`CPU::step`] makes before
+    /// or after every instruction, and no interrupt is about to be taken.
+    pub(crate) fn jit_ready(&self) -> bool {
+        let ready = !self.halted
+            && self.interrupt_status != InterruptStatus::EnablePending
+            && (self.interrupt_status == InterruptStatus::Disabled || self.pending_interrupt.is_none())
+            && self.pre_exec_hook.is_none()
+            && self.stack_guard.is_none()
+            && self.rom_writes == RomWritePolicy::Ignore
+            && self.memory.fixed()
+            && self.call_stack.is_none()
+            && self.memory.watchpoints().is_empty();
+        #[cfg(feature = "coverage")]
+        let ready = ready && self.coverage.is_none();
+        #[cfg(feature = "profiler")]
+        let ready = ready && self.profiler.is_none();
+        ready
+    }
+}
+
+/// The `RST interrupt_num` the Space Invaders board puts on the data bus to raise an interrupt.
+pub(crate) fn rst(interrupt_num: u8) -> u8 {
+    0xC7 | ((interrupt_num & 7) << 3)
+}
+
+/// Whether adding `a`, `b` and `carry` carries out of bit 3, which is what the auxiliary carry
+/// flag holds. Subtractions go through here as additions of the complement.
+fn half_carry(a: u8, b: u8, carry: u8) -> u8 {
+    ((a & 0x0F) + (b & 0x0F) + carry > 0x0F) as u8
+}
+
+type Handler = fn(&mut CPU) -> u32;
+#[cfg(feature = "jit")]
+pub(crate) type JitHandler = unsafe extern "C" fn(*mut CPU) -> u64;
+
+/// Array of `$handler::<OPCODE>` for every opcode, as `$ty`.
+macro_rules! opcode_table {
+    ($handler:ident as $ty:ty) => {
+        opcode_table!(@ $handler $ty;
+            0x00 0x01 0x02 0x03 0x04 0x05 0x06 0x07 0x08 0x09 0x0A 0x0B 0x0C 0x0D 0x0E 0x0F
+            0x10 0x11 0x12 0x13 0x14 0x15 0x16 0x17 0x18 0x19 0x1A 0x1B 0x1C 0x1D 0x1E 0x1F
+            0x20 0x21 0x22 0x23 0x24 0x25 0x26 0x27 0x28 0x29 0x2A 0x2B 0x2C 0x2D 0x2E 0x2F
+            0x30 0x31 0x32 0x33 0x34 0x35 0x36 0x37 0x38 0x39 0x3A 0x3B 0x3C 0x3D 0x3E 0x3F
+            0x40 0x41 0x42 0x43 0x44 0x45 0x46 0x47 0x48 0x49 0x4A 0x4B 0x4C 0x4D 0x4E 0x4F
+            0x50 0x51 0x52 0x53 0x54 0x55 0x56 0x57 0x58 0x59 0x5A 0x5B 0x5C 0x5D 0x5E 0x5F
+            0x60 0x61 0x62 0x63 0x64 0x65 0x66 0x67 0x68 0x69 0x6A 0x6B 0x6C 0x6D 0x6E 0x6F
+            0x70 0x71 0x72 0x73 0x74 0x75 0x76 0x77 0x78 0x79 0x7A 0x7B 0x7C 0x7D 0x7E 0x7F
+            0x80 0x81 0x82 0x83 0x84 0x85 0x86 0x87 0x88 0x89 0x8A 0x8B 0x8C 0x8D 0x8E 0x8F
+            0x90 0x91 0x92 0x93 0x94 0x95 0x96 0x97 0x98 0x99 0x9A 0x9B 0x9C 0x9D 0x9E 0x9F
+            0xA0 0xA1 0xA2 0xA3 0xA4 0xA5 0xA6 0xA7 0xA8 0xA9 0xAA 0xAB 0xAC 0xAD 0xAE 0xAF
+            0xB0 0xB1 0xB2 0xB3 0xB4 0xB5 0xB6 0xB7 0xB8 0xB9 0xBA 0xBB 0xBC 0xBD 0xBE 0xBF
+            0xC0 0xC1 0xC2 0xC3 0xC4 0xC5 0xC6 0xC7 0xC8 0xC9 0xCA 0xCB 0xCC 0xCD 0xCE 0xCF
+            0xD0 0xD1 0xD2 0xD3 0xD4 0xD5 0xD6 0xD7 0xD8 0xD9 0xDA 0xDB 0xDC 0xDD 0xDE 0xDF
+            0xE0 0xE1 0xE2 0xE3 0xE4 0xE5 0xE6 0xE7 0xE8 0xE9 0xEA 0xEB 0xEC 0xED 0xEE 0xEF
+            0xF0 0xF1 0xF2 0xF3 0xF4 0xF5 0xF6 0xF7 0xF8 0xF9 0xFA 0xFB 0xFC 0xFD 0xFE 0xFF
+        )
+    };
+    (@ $handler:ident $ty:ty; $($opcode:literal)*) => { [$($handler::<$opcode> as $ty),*] };
+}
+
+/// Handler of every opcode, indexed like [`opcodes::OPCODES`], which holds their length, timing
+/// and mnemonic.
+static DISPATCH: [Handler; 256] = opcode_table!(handler as Handler);
+
+/// [`DISPATCH`] for code translated by the JIT.
+#[cfg(feature = "jit")]
+pub(crate) static JIT_DISPATCH: [JitHandler; 256] = opcode_table!(jit_handler as JitHandler);
+
+/// Executes `OPCODE`, already fetched, with its operands read from PC on. Each opcode gets its
+/// own copy, so decoding folds away at compile time and only the instruction's work is left.
+fn handler<const OPCODE: u8>(cpu: &mut CPU) -> u32 {
+    let len = opcodes::OPCODES[OPCODE as usize].len;
+    // Fetched like the opcode, so operands running into unmapped memory fault too
+    let operand = |cpu: &mut CPU, i: u16| if i < len as u16 { cpu.fetch(cpu.pc.wrapping_add(i - 1)) } else { 0 };
+    let instruction = Instruction::decode([OPCODE, operand(cpu, 1), operand(cpu, 2)]);
+    cpu.pc = cpu.pc.wrapping_add(len as u16 - 1);
+    cpu.execute_decoded(instruction)
+}
+
+/// [`handler`] called from translated code, with PC just past the opcode. Returns the T-states
+/// in the low half and whether the instruction wrote to translated code in the high half.
+///
+/// # Safety
+/// `cpu` must be valid and not otherwise borrowed.
+#[cfg(feature = "jit")]
+unsafe extern "C" fn jit_handler<const OPCODE: u8>(cpu: *mut CPU) -> u64 {
+    let cpu = &mut *cpu;
+    let cycles = handler::<OPCODE>(cpu);
+    cycles as u64 | (cpu.memory.code_written() as u64) << 32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{MemoryMap, Watchpoint};
+
+    /// Runs `program` up to its last instruction and returns the CPU.
+    fn run(program: &[u8], steps: usize) -> CPU {
+        let mut cpu = CPU::new(program);
+        for _ in 0..steps {
+            cpu.step().unwrap();
         }
+        cpu
     }
 
-    fn bc(&self) -> u16 { concat_u16!(self.b, self.c) }
+    #[test]
+    fn test_daa() {
+        // MVI A,a; ADI b; DAA, for BCD a + b
+        let cases = [
+            (0x08, 0x08, 0x16, false),
+            (0x19, 0x28, 0x47, false),
+            (0x45, 0x55, 0x00, true),
+            (0x99, 0x01, 0x00, true),
+            (0x50, 0x50, 0x00, true),
+            (0x99, 0x99, 0x98, true),
+        ];
+
+        for (a, b, sum, carry) in cases {
+            let cpu = run(&[0x3E, a, 0xC6, b, 0x27], 3);
+            assert_eq!(cpu.a, sum, "{:02X} + {:02X}", a, b);
+            assert_eq!(cpu.flag(Flags::CARRY) != 0, carry, "{:02X} + {:02X} carry", a, b);
+            assert_eq!(cpu.flag(Flags::ZERO) != 0, sum == 0);
+        }
+    }
 
-    fn bc_val(&self) -> u8 { self.memory[self.bc()] }
+    #[test]
+    fn test_daa_flags() {
+        // MVI A,$9B; DAA: both digits adjust, no overflow panic
+        let cpu = run(&[0x3E, 0x9B, 0x27], 2);
+        assert_eq!(cpu.a, 0x01);
+        assert_eq!(cpu.flags, Flags::CARRY | Flags::AUX_CARRY);
 
-    fn bc_val_mut(&mut self) -> &mut u8 {
-        let adr = self.bc();
-        &mut self.memory[adr]
+        // MVI A,$99; STC; DAA: the incoming carry forces the high digit adjustment
+        let cpu = run(&[0x3E, 0x99, 0x37, 0x27], 3);
+        assert_eq!(cpu.a, 0xF9);
+        assert_eq!(cpu.flags, Flags::CARRY | Flags::SIGN | Flags::PARITY);
     }
 
-    fn de(&self) -> u16 { concat_u16!(self.d, self.e) }
+    #[test]
+    fn test_aux_carry() {
+        // MVI A,$0F; INR A
+        assert_ne!(run(&[0x3E, 0x0F, 0x3C], 2).flag(Flags::AUX_CARRY), 0);
+        // MVI A,$10; DCR A: borrows from bit 4
+        assert_eq!(run(&[0x3E, 0x10, 0x3D], 2).flag(Flags::AUX_CARRY), 0);
+        // MVI A,$11; SUI $01: no borrow
+        assert_ne!(run(&[0x3E, 0x11, 0xD6, 0x01], 2).flag(Flags::AUX_CARRY), 0);
+        // MVI A,$08; ANI $00: bit 3 of either operand
+        assert_ne!(run(&[0x3E, 0x08, 0xE6, 0x00], 2).flag(Flags::AUX_CARRY), 0);
+        // MVI A,$0F; ADI $01; ORI $00
+        assert_eq!(run(&[0x3E, 0x0F, 0xC6, 0x01, 0xF6, 0x00], 3).flag(Flags::AUX_CARRY), 0);
+    }
 
-    fn de_val(&self) -> u8 { self.memory[self.de()] }
+    #[test]
+    fn test_conditional_cycles() {
+        // LXI SP,$2400; XRA A; CNZ $0100; CZ $000B; NOP; RNZ; RZ
+        let program = [0x31, 0x00, 0x24, 0xAF, 0xC4, 0x00, 0x01, 0xCC, 0x0B, 0x00, 0x00, 0xC0, 0xC8];
+        let mut cpu = CPU::new(&program);
+        let cycles: Vec<u32> = (0..6).map(|_| cpu.step().unwrap()).collect();
 
-    fn de_val_mut(&mut self) -> &mut u8 {
-        let adr = self.de();
-        &mut self.memory[adr]
+        assert_eq!(cycles, [10, 4, 11, 17, 5, 11]);
+        assert_eq!(cpu.pc, 0x000A);
     }
 
-    fn m(&self) -> u16 { concat_u16!(self.h, self.l) }
+    #[test]
+    fn test_opcode_table() {
+        for opcode in 0..=0xFF {
+            let info = crate::opcodes::OPCODES[opcode as usize];
+            let branch = matches!(opcode & 0xC7, 0xC0 | 0xC2 | 0xC4 | 0xC7)
+                || matches!(opcode, 0xC3 | 0xC9 | 0xCB | 0xCD | 0xD9 | 0xDD | 0xE9 | 0xED | 0xFD);
+
+            // Once with every condition false and once with every condition true
+            for flags in [Flags::empty(), Flags::all()] {
+                // Operands point at $2412, and all register pairs into RAM
+                let mut cpu = CPU::new(&[opcode, 0x12, 0x24]);
+                cpu.flags = flags;
+                cpu.sp = 0x2400;
+                (cpu.b, cpu.d, cpu.h) = (0x24, 0x24, 0x24);
+
+                let cycles = cpu.step().unwrap();
+                if !branch {
+                    assert_eq!(cpu.pc, info.len as u16, "length of {:02X} {}", opcode, info.mnemonic);
+                }
+                let expected = if cpu.pc == info.len as u16 { info.cycles } else { info.cycles_taken };
+                assert_eq!(cycles, expected as u32, "cycles of {:02X} {}", opcode, info.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn test_register_accessors() {
+        let mut cpu = CPU::new(&[]);
+        cpu.set_pair(RegisterPair::BC, 0x1234);
+        cpu.set_reg(Register::E, 0x56);
+        cpu.set_pair(RegisterPair::PSW, 0x42FF);
+
+        assert_eq!((cpu.reg(Register::B), cpu.reg(Register::C)), (0x12, 0x34));
+        assert_eq!(cpu.pair(RegisterPair::DE), 0x0056);
+        assert_eq!(cpu.reg(Register::A), 0x42);
+        assert_eq!(cpu.flags(), Flags::all());
+        // Bit 1 always reads as set and bits 3 and 5 as clear
+        assert_eq!(cpu.pair(RegisterPair::PSW), 0x42D7);
+
+        let snapshot = cpu.registers();
+        cpu.set_registers(Registers::default());
+        assert_eq!(cpu.pair(RegisterPair::BC), 0);
+        cpu.set_registers(snapshot);
+        assert_eq!(cpu.registers(), snapshot);
+    }
+
+    #[test]
+    fn test_carry_in() {
+        // STC; MVI A,a; MVI B,b; ADC B or SBB B
+        let adc = |a, b| run(&[0x37, 0x3E, a, 0x06, b, 0x88], 4);
+        let sbb = |a, b| run(&[0x37, 0x3E, a, 0x06, b, 0x98], 4);
+
+        let cpu = adc(0x00, 0xFF);
+        assert_eq!((cpu.a, cpu.flag(Flags::CARRY), cpu.flag(Flags::ZERO)), (0x00, 1, 1));
+        assert_ne!(cpu.flag(Flags::AUX_CARRY), 0);
+        let cpu = adc(0x12, 0x34);
+        assert_eq!((cpu.a, cpu.flag(Flags::CARRY)), (0x47, 0));
+
+        let cpu = sbb(0x00, 0xFF);
+        assert_eq!((cpu.a, cpu.flag(Flags::CARRY)), (0x00, 1));
+        // The auxiliary carry is set when the low nibble doesn't borrow
+        let cpu = sbb(0x10, 0x0F);
+        assert_eq!((cpu.a, cpu.flag(Flags::CARRY), cpu.flag(Flags::AUX_CARRY)), (0x00, 0, 0));
+        let cpu = sbb(0x1F, 0x0E);
+        assert_eq!((cpu.a, cpu.flag(Flags::CARRY)), (0x10, 0));
+        assert_ne!(cpu.flag(Flags::AUX_CARRY), 0);
+        let cpu = sbb(0x05, 0x05);
+        assert_eq!((cpu.a, cpu.flag(Flags::CARRY)), (0xFF, 1));
+    }
+
+    #[test]
+    fn test_ei_delay() {
+        // LXI SP,$2400; DI; EI; NOP; NOP
+        let mut cpu = run(&[0x31, 0x00, 0x24, 0xF3, 0xFB, 0x00, 0x00], 3);
+        assert!(!cpu.interrupt(1), "accepted right after EI");
+        assert_eq!(cpu.pc, 0x0005);
+
+        cpu.step().unwrap();
+        assert!(cpu.interrupt(1), "rejected after the instruction following EI");
+        assert_eq!(cpu.pc, 0x0008);
+    }
+
+    #[test]
+    fn test_ei_ret() {
+        // LXI SP,$2400; CALL $0008; HLT; NOP; EI; RET
+        let mut cpu = run(&[0x31, 0x00, 0x24, 0xCD, 0x08, 0x00, 0x76, 0x00, 0xFB, 0xC9], 3);
+        assert!(!cpu.interrupt(1));
+
+        // The handler returns before the next interrupt can nest inside it
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0006);
+        assert!(cpu.interrupt(1));
+        assert_eq!(cpu.sp, 0x23FE);
+    }
+
+    #[test]
+    fn test_halt_until_interrupt() {
+        // LXI SP,$2400; EI; HLT; MVI A,$42
+        let mut cpu = run(&[0x31, 0x00, 0x24, 0xFB, 0x76, 0x3E, 0x42], 3);
+        assert!(cpu.halted());
+        assert!(cpu.event().is_none());
+
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.pc, 0x0005);
+
+        // The interrupt returns to the instruction after HLT
+        assert!(cpu.interrupt(1));
+        assert!(!cpu.halted());
+        assert_eq!(cpu.stack_pop_u16(), 0x0005);
+    }
+
+    #[test]
+    fn test_events_queue_up() {
+        // MVI A,$42; OUT 3; IN 1; OUT 5
+        let mut cpu = run(&[0x3E, 0x42, 0xD3, 0x03, 0xDB, 0x01, 0xD3, 0x05], 4);
+        let events: Vec<Event> = cpu.drain_events().collect();
+        assert!(matches!(events[..], [Event::PortWrite(3, 0x42), Event::PortRead(1), Event::PortWrite(5, 0x42)]));
+        assert!(cpu.event().is_none());
+    }
+
+    #[test]
+    fn test_halt_with_interrupts_disabled() {
+        // DI; HLT
+        let mut cpu = run(&[0xF3, 0x76], 2);
+        assert!(matches!(cpu.event(), Some(Event::Halt)));
+        assert!(!cpu.interrupt(1));
+        assert!(cpu.halted());
+    }
+
+    #[test]
+    fn test_interrupt_with() {
+        let mut cpu = CPU::new(&[0x00]);
+
+        // INR A instead of an RST runs in place without touching PC
+        assert_eq!(cpu.interrupt_with(0x3C).unwrap(), Some(5));
+        assert_eq!((cpu.a, cpu.pc), (1, 0));
+
+        // Taking it disabled interrupts, so the next one has to be raised again after EI
+        assert_eq!(cpu.interrupt_with(0x3C).unwrap(), None);
+        assert!(!cpu.interrupts_enabled());
+        assert_eq!(cpu.a, 1);
+    }
+
+    #[test]
+    fn test_pending_interrupt() {
+        // LXI SP,$2400; DI; NOP; EI; NOP; NOP
+        let mut cpu = run(&[0x31, 0x00, 0x24, 0xF3, 0x00, 0xFB, 0x00, 0x00], 2);
+        assert!(!cpu.interrupt(2));
+        assert_eq!(cpu.pending_interrupt(), Some(0xD7));
+
+        // Still held through EI and the instruction after it
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x0007);
+
+        // Taking it is the whole step
+        assert_eq!(cpu.step().unwrap(), 11);
+        assert_eq!(cpu.pending_interrupt(), None);
+        assert_eq!(cpu.stack_pop_u16(), 0x0007);
+        assert_eq!(cpu.pc, 0x0010);
+    }
+
+    #[test]
+    fn test_pending_interrupt_wakes_halt() {
+        // LXI SP,$2400; DI; NOP; EI; HLT; MVI A,$42
+        let mut cpu = run(&[0x31, 0x00, 0x24, 0xF3, 0x00, 0xFB, 0x76, 0x3E, 0x42], 3);
+        assert!(!cpu.interrupt(1));
+
+        // EI; HLT, then the latched interrupt is taken instead of idling
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.halted());
+        assert_eq!(cpu.step().unwrap(), 11);
+        assert!(!cpu.halted());
+        assert_eq!(cpu.pending_interrupt(), None);
+        assert_eq!(cpu.pc, 0x0008);
+        assert_eq!(cpu.stack_pop_u16(), 0x0007);
+    }
+
+    #[test]
+    fn test_clear_pending_interrupt() {
+        // DI; EI; NOP; NOP
+        let mut cpu = run(&[0xF3, 0xFB, 0x00, 0x00], 1);
+        cpu.interrupt(1);
+        cpu.clear_pending_interrupt();
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x0004);
+    }
+
+    #[test]
+    fn test_undefined_opcode_policy() {
+        // JMP $0010 through the undocumented 0xCB
+        let program = [0xCB, 0x10, 0x00];
+        let with_policy = |policy| {
+            let mut cpu = CPU::new(&program);
+            cpu.set_undefined_opcode_policy(policy);
+            let result = cpu.step();
+            (result.ok(), cpu.pc)
+        };
+
+        assert_eq!(with_policy(UndefinedOpcodePolicy::TreatAsDocumented), (Some(10), 0x0010));
+        assert_eq!(with_policy(UndefinedOpcodePolicy::Nop), (Some(4), 0x0001));
+        assert_eq!(with_policy(UndefinedOpcodePolicy::Error), (None, 0x0000));
+    }
+
+    #[test]
+    fn test_psw_stack_round_trip() {
+        // Flags byte popped by POP PSW -> byte pushed back by PUSH PSW
+        let images = [(0xFF, 0xD7), (0x00, 0x02), (0x28, 0x02), (0x02, 0x02), (0x55, 0x57), (0xAA, 0x82)];
+
+        for (popped, pushed) in images {
+            // LXI SP,$2400; LXI B,$42xx; PUSH B; POP PSW; PUSH PSW; POP D
+            let program = [0x31, 0x00, 0x24, 0x01, popped, 0x42, 0xC5, 0xF1, 0xF5, 0xD1];
+            let cpu = run(&program, 6);
+            assert_eq!(cpu.a, 0x42);
+            assert_eq!((cpu.d, cpu.e), (0x42, pushed), "POP PSW of {:02X}", popped);
+        }
+    }
+
+    #[test]
+    fn test_address_wrap_around() {
+        let flat = |code: &[(u16, u8)]| {
+            let mut cpu = CPU::with_memory(Memory::flat());
+            for &(address, byte) in code {
+                cpu.memory.write(address, byte);
+            }
+            cpu
+        };
+
+        // LXI B,$1234 with its operand split across the top of memory
+        let mut cpu = flat(&[(0xFFFE, 0x01), (0xFFFF, 0x34), (0x0000, 0x12)]);
+        cpu.pc = 0xFFFE;
+        cpu.step().unwrap();
+        assert_eq!((cpu.pair(RegisterPair::BC), cpu.pc), (0x1234, 0x0001));
+
+        // SHLD $FFFF; LHLD $FFFF
+        let mut cpu = flat(&[(0, 0x22), (1, 0xFF), (2, 0xFF), (3, 0x2A), (4, 0xFF), (5, 0xFF)]);
+        cpu.set_pair(RegisterPair::HL, 0xABCD);
+        cpu.step().unwrap();
+        assert_eq!((cpu.memory[0xFFFF], cpu.memory[0x0000]), (0xCD, 0xAB));
+        cpu.set_pair(RegisterPair::HL, 0);
+        cpu.memory.write(0x0000, 0xAB);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pair(RegisterPair::HL), 0xABCD);
+
+        // XTHL with the stack top at $FFFF
+        let mut cpu = flat(&[(0x0100, 0xE3), (0xFFFF, 0x34), (0x0000, 0x12)]);
+        cpu.pc = 0x0100;
+        cpu.sp = 0xFFFF;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pair(RegisterPair::HL), 0x1234);
+
+        // PUSH B; POP D across SP = 0
+        let mut cpu = flat(&[(0x0100, 0xC5), (0x0101, 0xD1)]);
+        cpu.pc = 0x0100;
+        cpu.sp = 0x0001;
+        cpu.set_pair(RegisterPair::BC, 0x5678);
+        cpu.step().unwrap();
+        assert_eq!(cpu.sp, 0xFFFF);
+        cpu.step().unwrap();
+        assert_eq!((cpu.pair(RegisterPair::DE), cpu.sp), (0x5678, 0x0001));
+    }
+
+    #[test]
+    fn test_rom_write_policy() {
+        // LXI H,$0010; MVI M,$42
+        let program = [0x21, 0x10, 0x00, 0x36, 0x42];
+        let mut cpu = run(&program, 1);
+        let mut strict = cpu.clone();
+
+        cpu.step().unwrap();
+        assert_eq!((cpu.memory[0x0010], cpu.pc), (0x00, 0x0005));
+
+        strict.set_rom_write_policy(RomWritePolicy::Error);
+        assert!(matches!(strict.step(), Err(Error::RomWriteViolation { pc: 0x0003, address: 0x0010 })));
+        assert_eq!((strict.memory[0x0010], strict.pc), (0x00, 0x0003));
+    }
+
+    #[test]
+    fn test_memory_fault() {
+        // LDA $4800; JMP $4800
+        let mut rom = [0; 0x100];
+        rom[..6].copy_from_slice(&[0x3A, 0x00, 0x48, 0xC3, 0x00, 0x48]);
+        let mut cpu = CPU::with_memory(MemoryMap::new().rom(0x0000, &rom).ram(0x2000..=0x3FFF).build().unwrap());
+        assert!(matches!(cpu.step(), Err(Error::MemoryFault { pc: 0x0000, address: 0x4800 })));
+        assert_eq!(cpu.pc, 0x0000);
+
+        cpu.pc = 0x0003;
+        cpu.step().unwrap();
+        assert!(matches!(cpu.step(), Err(Error::MemoryFault { pc: 0x4800, address: 0x4800 })));
+
+        // JMP with its address bytes past the end of RAM
+        cpu.memory.write(0x3FFF, 0xC3);
+        cpu.pc = 0x3FFF;
+        assert!(matches!(cpu.step(), Err(Error::MemoryFault { pc: 0x3FFF, address: 0x4000 })));
+        assert_eq!(cpu.pc, 0x3FFF);
+    }
+
+    #[test]
+    fn test_stack_guard() {
+        // LXI SP,$2302; PUSH B; PUSH B; POP B; POP B
+        let program = [0x31, 0x02, 0x23, 0xC5, 0xC5, 0xC1, 0xC1];
+        let mut cpu = run(&program, 2);
+        cpu.set_stack_guard(Some(StackGuard { low: 0x2300, top: 0x2302 }));
+
+        // The second push would write below the low-water mark
+        assert!(matches!(cpu.step(), Err(Error::StackFault { sp: 0x2300, pc: 0x0004 })));
+        assert_eq!((cpu.pc, cpu.sp), (0x0004, 0x2300));
+
+        // Skip it, pop what the first one pushed, then pop past the top
+        cpu.pc = 0x0005;
+        cpu.step().unwrap();
+        assert!(matches!(cpu.step(), Err(Error::StackFault { sp: 0x2302, pc: 0x0006 })));
+        assert_eq!(cpu.pc, 0x0006);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // LXI H,$2000; MVI M,$42; MOV A,M; INR M; LXI SP,$2002; PUSH B
+        let program = [0x21, 0x00, 0x20, 0x36, 0x42, 0x7E, 0x34, 0x31, 0x02, 0x20, 0xC5];
+        let mut cpu = run(&program, 1);
+        cpu.memory.add_watchpoint(Watchpoint::write(0x2000..=0x2001));
+
+        cpu.step().unwrap();
+        let write = WatchHit { pc: 0x0003, address: 0x2000, access: Access::Write, old: 0x00, new: 0x42 };
+        assert_eq!(cpu.take_watch_hit(), Some(write));
+        cpu.step().unwrap();
+        assert_eq!(cpu.take_watch_hit(), None);
+
+        // INR M reads then writes, only the write is watched
+        cpu.step().unwrap();
+        assert_eq!(cpu.take_watch_hit().map(|hit| (hit.old, hit.new)), Some((0x42, 0x43)));
+
+        // The first of the two pushed bytes is reported
+        cpu.memory.add_watchpoint(Watchpoint::read(0x2000..=0x2000));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.take_watch_hit().map(|hit| (hit.pc, hit.address)), Some((0x000A, 0x2001)));
+
+        assert!(cpu.memory.remove_watchpoint(&Watchpoint::write(0x2000..=0x2001)));
+        assert_eq!(cpu.memory.watchpoints(), [Watchpoint::read(0x2000..=0x2000)]);
+    }
+
+    #[test]
+    fn test_pre_exec_hook() {
+        // MVI A,$01; MVI B,$02; INR A
+        let mut cpu = CPU::new(&[0x3E, 0x01, 0x06, 0x02, 0x3C]);
+        cpu.set_pre_exec_hook(Some(|_, _, opcode| match opcode {
+            0x06 => HookAction::Skip,
+            0x3C => HookAction::Halt,
+            _ => HookAction::Continue,
+        }));
+
+        assert_eq!(cpu.step().unwrap(), 7);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!((cpu.b, cpu.pc), (0, 0x0004));
+
+        cpu.step().unwrap();
+        assert!(matches!(cpu.event(), Some(Event::Halt)));
+        assert_eq!((cpu.a, cpu.pc), (1, 0x0004));
+
+        cpu.set_pre_exec_hook(None);
+        cpu.step().unwrap();
+        assert_eq!(cpu.a, 2);
+    }
+
+    #[test]
+    fn test_decode_and_execute() {
+        use crate::{Condition, Operand, Pair};
 
-    fn m_val(&self) -> u8 { self.memory[self.m()] }
+        // LXI H,$2000; JNZ $0010
+        let mut cpu = CPU::new(&[0x21, 0x00, 0x20, 0xC2, 0x10, 0x00]);
+        assert_eq!(cpu.decode_at(0x0000), Instruction::Lxi(Pair::HL, 0x2000));
+        assert_eq!(cpu.decode_at(0x0003), Instruction::JmpIf(Condition::NotZero, 0x0010));
 
-    fn m_val_mut(&mut self) -> &mut u8 {
-        let adr = self.m();
-        &mut self.memory[adr]
+        assert_eq!(cpu.execute(Instruction::Mvi(Operand::A, 0x42)).unwrap(), 7);
+        assert_eq!((cpu.a, cpu.pc), (0x42, 0x0002));
+        cpu.set_pair(RegisterPair::HL, 0x2000);
+        assert_eq!(cpu.execute(Instruction::Mov(Operand::M, Operand::A)).unwrap(), 7);
+        assert_eq!(cpu.memory[0x2000], 0x42);
+        assert_eq!(cpu.execute(Instruction::Jmp(0x0100)).unwrap(), 10);
+        assert_eq!(cpu.pc, 0x0100);
     }
 }
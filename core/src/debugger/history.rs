@@ -0,0 +1,140 @@
+//! Execution history for stepping backwards.
+//!
+//! The core is deterministic, so any past instruction can be reached again by restoring an earlier
+//! snapshot and re-executing from there. Snapshots are taken every `interval` instructions, and
+//! also whenever something outside the CPU changed the machine (an interrupt or an input port)
+//! since the last recorded instruction, so that re-execution between two snapshots never has to
+//! replay external input.
+
+use alloc::collections::VecDeque;
+use crate::{Emulator, ExecutionStatus, Result};
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    instruction: u64,
+    emulator: Emulator,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    interval: u64,
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+    last_interrupts: u64,
+    last_inputs: [u8; 2],
+}
+
+impl History {
+    /// Keeps at most `capacity` snapshots, taken at least every `interval` instructions.
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+            last_interrupts: 0,
+            last_inputs: [0; 2],
+        }
+    }
+
+    /// Must be called before every instruction executed while the history is in use.
+    pub fn record(&mut self, emulator: &Emulator) {
+        let instruction = emulator.stats().instructions;
+        let interrupts = emulator.stats().interrupts;
+        let inputs = emulator.input_ports();
+
+        let external_change = interrupts != self.last_interrupts || inputs != self.last_inputs;
+        self.last_interrupts = interrupts;
+        self.last_inputs = inputs;
+
+        let due = match self.snapshots.back() {
+            Some(last) => external_change || instruction.saturating_sub(last.instruction) >= self.interval,
+            None => true,
+        };
+        if !due {
+            return;
+        }
+
+        // A snapshot at the same instruction replaces the older one, it has the newer external state
+        if self.snapshots.back().is_some_and(|last| last.instruction >= instruction) {
+            self.snapshots.pop_back();
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot { instruction, emulator: emulator.clone() });
+    }
+
+    /// Rewinds the emulator by one instruction.
+    /// Returns `false` if the previous instruction is older than every snapshot kept.
+    pub fn step_back(&mut self, emulator: &mut Emulator) -> Result<bool> {
+        let Some(target) = emulator.stats().instructions.checked_sub(1) else { return Ok(false) };
+
+        while self.snapshots.back().is_some_and(|s| s.instruction > target) {
+            self.snapshots.pop_back();
+        }
+        let Some(snapshot) = self.snapshots.back() else { return Ok(false) };
+
+        let mut restored = snapshot.emulator.clone();
+        while restored.stats().instructions < target {
+            if restored.step()? == ExecutionStatus::Halt {
+                break;
+            }
+        }
+        // Events were already delivered the first time these instructions ran
+        while restored.event().is_some() {}
+
+        self.last_interrupts = restored.stats().interrupts;
+        self.last_inputs = restored.input_ports();
+        *emulator = restored;
+        Ok(true)
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../../../frontend/assets/invaders");
+
+    #[test]
+    fn test_step_back() {
+        let mut emulator = Emulator::new(ROM);
+        let mut history = History::new(1000, 16);
+        let mut pcs = Vec::new();
+
+        for i in 0..5000 {
+            if i == 2500 {
+                emulator.interrupt(1);
+            }
+            history.record(&emulator);
+            pcs.push(emulator.cpu().pc());
+            emulator.step().unwrap();
+        }
+
+        for expected in pcs.iter().rev().take(3000) {
+            assert!(history.step_back(&mut emulator).unwrap());
+            assert_eq!(emulator.cpu().pc(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_limited_by_capacity() {
+        let mut emulator = Emulator::new(ROM);
+        let mut history = History::new(10, 2);
+
+        for _ in 0..100 {
+            history.record(&emulator);
+            emulator.step().unwrap();
+        }
+
+        for _ in 0..20 {
+            assert!(history.step_back(&mut emulator).unwrap());
+        }
+        assert!(!history.step_back(&mut emulator).unwrap());
+        assert_eq!(emulator.stats().instructions, 80);
+    }
+}
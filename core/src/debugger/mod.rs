@@ -0,0 +1,254 @@
+//! Breakpoints with attached actions, layered on top of [`Emulator::step`].
+//!
+//! Each breakpoint runs its actions whenever execution reaches its address, then either stops
+//! or, with `auto_continue`, keeps running. That makes it possible to trace a routine (log its
+//! arguments, dump the buffer it fills, call into embedder code) without manual stepping.
+//! With a [`History`] attached, the debugger can also step backwards.
+
+mod history;
+
+pub use history::History;
+
+use core::fmt::{Debug, Formatter};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::format;
+use crate::{Emulator, ExecutionStatus, Result};
+
+/// Embedder-provided function that breakpoints can call by name, e.g. a script callback.
+/// Lines pushed to the output vector are appended to the debugger output.
+pub type DebugFunction = Box<dyn FnMut(&mut Emulator, &mut Vec<String>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BreakpointAction {
+    /// Logs a message. `{pc}`, `{sp}`, `{a}`..`{l}`, `{bc}`, `{de}`, `{hl}` and `{flags}` are
+    /// replaced with the current register values.
+    Log(String),
+    DumpMemory { address: u16, length: u16 },
+    /// Calls a function registered with [`Debugger::register_function`].
+    Call(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Breakpoint {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub actions: Vec<BreakpointAction>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_continue: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepResult {
+    Executed(ExecutionStatus),
+    /// Execution stopped before the instruction at this address.
+    Break(u16),
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    functions: BTreeMap<String, DebugFunction>,
+    output: Vec<String>,
+    resume_from: Option<u16>,
+    history: Option<History>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_breakpoint(&mut self, address: u16, breakpoint: Breakpoint) {
+        self.breakpoints.insert(address, breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> Option<Breakpoint> {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (u16, &Breakpoint)> {
+        self.breakpoints.iter().map(|(&address, bp)| (address, bp))
+    }
+
+    pub fn register_function(&mut self, name: &str, function: DebugFunction) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    /// Starts recording execution so [`Debugger::step_back`] can be used.
+    pub fn enable_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    /// Drains the lines produced by breakpoint actions.
+    pub fn take_output(&mut self) -> Vec<String> {
+        core::mem::take(&mut self.output)
+    }
+
+    /// Runs the actions of the breakpoint at the current PC, if any, and returns whether execution
+    /// should stop. Also records the execution history, so it must be called before every
+    /// instruction.
+    pub fn check(&mut self, emulator: &mut Emulator) -> bool {
+        self.record(emulator);
+        self.run_breakpoint(emulator)
+    }
+
+    fn record(&mut self, emulator: &Emulator) {
+        if let Some(history) = &mut self.history {
+            history.record(emulator);
+        }
+    }
+
+    fn run_breakpoint(&mut self, emulator: &mut Emulator) -> bool {
+        let pc = emulator.cpu().pc();
+        let Some(breakpoint) = self.breakpoints.get(&pc) else { return false };
+
+        for action in &breakpoint.actions {
+            match action {
+                BreakpointAction::Log(message) => self.output.push(format_message(message, emulator)),
+                BreakpointAction::DumpMemory { address, length } => {
+                    self.output.extend(dump_memory(emulator, *address, *length));
+                }
+                BreakpointAction::Call(name) => match self.functions.get_mut(name) {
+                    Some(function) => function(emulator, &mut self.output),
+                    None => self.output.push(format!("breakpoint at ${:04X}: unknown function {}", pc, name)),
+                },
+            }
+        }
+
+        !breakpoint.auto_continue
+    }
+
+    /// Executes one instruction unless a breakpoint stops execution first.
+    /// Stepping again after a break executes the instruction the break happened on.
+    pub fn step(&mut self, emulator: &mut Emulator) -> Result<StepResult> {
+        let pc = emulator.cpu().pc();
+        self.record(emulator);
+
+        if self.resume_from.take() != Some(pc) && self.run_breakpoint(emulator) {
+            self.resume_from = Some(pc);
+            return Ok(StepResult::Break(pc));
+        }
+
+        Ok(StepResult::Executed(emulator.step()?))
+    }
+
+    /// Rewinds the emulator by one instruction, without triggering the breakpoint it lands on.
+    /// Returns `false` if history is disabled or doesn't reach back far enough.
+    pub fn step_back(&mut self, emulator: &mut Emulator) -> Result<bool> {
+        let Some(history) = &mut self.history else { return Ok(false) };
+
+        let stepped = history.step_back(emulator)?;
+        if stepped {
+            self.resume_from = Some(emulator.cpu().pc());
+        }
+        Ok(stepped)
+    }
+}
+
+impl Debug for Debugger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.breakpoints)
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("output", &self.output)
+            .field("history", &self.history.is_some())
+            .finish()
+    }
+}
+
+fn format_message(message: &str, emulator: &Emulator) -> String {
+    let r = emulator.cpu().registers();
+    let replacements = [
+        ("{pc}", format!("{:04X}", r.pc)),
+        ("{sp}", format!("{:04X}", r.sp)),
+        ("{a}", format!("{:02X}", r.a)),
+        ("{b}", format!("{:02X}", r.b)),
+        ("{c}", format!("{:02X}", r.c)),
+        ("{d}", format!("{:02X}", r.d)),
+        ("{e}", format!("{:02X}", r.e)),
+        ("{h}", format!("{:02X}", r.h)),
+        ("{l}", format!("{:02X}", r.l)),
+        ("{bc}", format!("{:02X}{:02X}", r.b, r.c)),
+        ("{de}", format!("{:02X}{:02X}", r.d, r.e)),
+        ("{hl}", format!("{:02X}{:02X}", r.h, r.l)),
+        ("{flags}", r.flags.to_string()),
+    ];
+
+    replacements.iter().fold(message.to_string(), |msg, (key, val)| msg.replace(key, val))
+}
+
+fn dump_memory(emulator: &Emulator, address: u16, length: u16) -> Vec<String> {
+    let memory = &emulator.cpu().memory;
+
+    (0..length).step_by(16).map(|offset| {
+        let line_address = address.wrapping_add(offset);
+        let bytes: Vec<_> = (0..16.min(length - offset))
+            .map(|i| format!("{:02X}", memory[line_address.wrapping_add(i)]))
+            .collect();
+        format!("{:04X}: {}", line_address, bytes.join(" "))
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // MVI A,42h / INR A / JMP 0000h
+    const PROGRAM: &[u8] = &[0x3E, 0x42, 0x3C, 0xC3, 0x00, 0x00];
+
+    #[test]
+    fn test_break_and_resume() {
+        let mut emulator = Emulator::new(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.set_breakpoint(0x0002, Breakpoint::default());
+
+        assert!(matches!(debugger.step(&mut emulator).unwrap(), StepResult::Executed(_)));
+        assert_eq!(debugger.step(&mut emulator).unwrap(), StepResult::Break(0x0002));
+        assert!(matches!(debugger.step(&mut emulator).unwrap(), StepResult::Executed(_)));
+        assert_eq!(emulator.cpu().registers().a, 0x43);
+    }
+
+    #[test]
+    fn test_actions_with_auto_continue() {
+        let mut emulator = Emulator::new(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.register_function("count", Box::new(|_, out| out.push("called".to_string())));
+        debugger.set_breakpoint(0x0002, Breakpoint {
+            actions: vec![
+                BreakpointAction::Log("A={a} at {pc}".to_string()),
+                BreakpointAction::DumpMemory { address: 0x0000, length: 4 },
+                BreakpointAction::Call("count".to_string()),
+            ],
+            auto_continue: true,
+        });
+
+        for _ in 0..3 {
+            assert!(matches!(debugger.step(&mut emulator).unwrap(), StepResult::Executed(_)));
+        }
+        assert_eq!(debugger.take_output(), ["A=42 at 0002", "0000: 3E 42 3C C3", "called"]);
+    }
+
+    #[test]
+    fn test_step_back_over_breakpoint() {
+        let mut emulator = Emulator::new(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.enable_history(History::new(100, 4));
+        debugger.set_breakpoint(0x0002, Breakpoint::default());
+
+        debugger.step(&mut emulator).unwrap();
+        debugger.step(&mut emulator).unwrap();
+        debugger.step(&mut emulator).unwrap();
+        assert_eq!(emulator.cpu().registers().a, 0x43);
+
+        assert!(debugger.step_back(&mut emulator).unwrap());
+        assert_eq!(emulator.cpu().pc(), 0x0002);
+        assert_eq!(emulator.cpu().registers().a, 0x42);
+        assert!(matches!(debugger.step(&mut emulator).unwrap(), StepResult::Executed(_)));
+        assert_eq!(emulator.cpu().registers().a, 0x43);
+    }
+}
@@ -0,0 +1,106 @@
+//! 8080 disassembler for debugging tools and ROM listings.
+//!
+//! Instructions format as their bytes followed by the assembly, e.g. `CD 18 1A  CALL $1A18`.
+//! They're decoded by [`crate::Instruction::decode`], the same decoder the CPU executes from.
+
+use core::fmt::{Display, Formatter};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::format;
+use crate::opcodes::OPCODES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instruction {
+    pub address: u16,
+    /// The opcode followed by its operand bytes, padded with 0 past `len`.
+    pub bytes: [u8; 3],
+    pub len: u8,
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Mnemonic with operand placeholders, e.g. `MVI B,d8`.
+    pub fn mnemonic(&self) -> &'static str {
+        OPCODES[self.opcode() as usize].mnemonic
+    }
+
+    /// Immediate value or address following the opcode, if it has one.
+    pub fn operand(&self) -> Option<u16> {
+        match self.len {
+            2 => Some(self.bytes[1] as u16),
+            3 => Some(u16::from_le_bytes([self.bytes[1], self.bytes[2]])),
+            _ => None,
+        }
+    }
+
+    /// What the instruction does, as executed by the CPU.
+    pub fn decoded(&self) -> crate::Instruction {
+        crate::Instruction::decode(self.bytes)
+    }
+
+    /// Address of the instruction after this one.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.len as u16)
+    }
+
+    /// The assembly alone, e.g. `CALL $1A18`.
+    pub fn text(&self) -> String {
+        self.decoded().to_string()
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let bytes: Vec<String> = self.bytes[..self.len as usize].iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:<8}  {}", bytes.join(" "), self.text())
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, which was read from `address`.
+/// Operand bytes missing from the end of `bytes` read as 0.
+pub fn disassemble(address: u16, bytes: &[u8]) -> Instruction {
+    let mut padded = [0; 3];
+    for (i, byte) in bytes.iter().take(3).enumerate() {
+        padded[i] = *byte;
+    }
+    let len = crate::Instruction::decode(padded).size();
+    padded[len as usize..].fill(0);
+    Instruction { address, bytes: padded, len }
+}
+
+/// Decodes `bytes` from start to end as consecutive instructions, `bytes[0]` being at `start`.
+pub fn disassemble_all(start: u16, bytes: &[u8]) -> impl Iterator<Item = Instruction> + '_ {
+    let mut offset = 0;
+    core::iter::from_fn(move || {
+        let rest = bytes.get(offset..).filter(|rest| !rest.is_empty())?;
+        let instruction = disassemble(start.wrapping_add(offset as u16), rest);
+        offset += instruction.len as usize;
+        Some(instruction)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(disassemble(0, &[0xCD, 0x18, 0x1A]).to_string(), "CD 18 1A  CALL $1A18");
+        assert_eq!(disassemble(0, &[0x06, 0x42]).to_string(), "06 42     MVI B,$42");
+        assert_eq!(disassemble(0, &[0x11, 0x00, 0x24]).to_string(), "11 00 24  LXI D,$2400");
+        assert_eq!(disassemble(0, &[0x76]).to_string(), "76        HLT");
+        assert_eq!(disassemble(0, &[0xC3]).text(), "JMP $0000");
+    }
+
+    #[test]
+    fn test_disassemble_all() {
+        // NOP; MVI A,$01; JMP $0000
+        let program = [0x00, 0x3E, 0x01, 0xC3, 0x00, 0x00];
+        let addresses: Vec<u16> = disassemble_all(0x1000, &program).map(|i| i.address).collect();
+        assert_eq!(addresses, [0x1000, 0x1001, 0x1003]);
+    }
+}
@@ -1,32 +1,177 @@
-use std::mem;
-use crate::{concat_u16, Result, Error, CPU, CPUEvent, Button};
-
-macro_rules! check_sound_events {
-    ( $last_port:expr, $val:expr, $ev:expr, $(($msk:expr,$snd:expr)),* ) => {
-        $(
-            if $val & $msk != 0 && $last_port & $msk == 0 {
-                $ev = Some(Event::PlaySound($snd));
-            } else if $val & $msk == 0 && $last_port & $msk != 0 {
-                $ev = Some(Event::StopSound($snd))
-            }
-        )*
-    };
-}
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::collections::vec_deque::Drain;
+use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use crate::{cpu, Result, Error, CPU, CPUEvent, Button, Buttons, Cheat, CheatKind, EmulatorStats, PcHistory, Quirks, Access, Memory, WatchHit};
+use crate::expr::Expr;
+use crate::checksum::{fnv1a64, fnv1a64_continue};
+use crate::stats::IpsMeter;
+#[cfg(feature = "tracer")]
+use crate::tracer::Tracer;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::savestate::{self, SaveStateError, StateWriter};
+use crate::machine::{Machine, SpaceInvadersMachine};
+use crate::io::{IoBus, IoDevice};
+use crate::video::DirtyLines;
+use crate::raster::{BYTES_PER_LINE, CYCLES_PER_FRAME, MID_SCREEN_CYCLE, MID_SCREEN_LINE, VISIBLE_LINES};
 
-#[derive(Debug, Clone)]
+pub const DIP_SWITCH_MASK: u8 = 0b1000_1011;
+const NOP_CYCLES: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExecutionStatus {
     Continue(u32),
+    /// HLT ran with interrupts disabled or the CPU's pre-exec hook halted. A HLT that an
+    /// interrupt can end just idles instead.
     Halt,
+    /// Execution reached a breakpoint set with [`Emulator::add_breakpoint`]. The instruction at the
+    /// address hasn't run yet, the next step runs it.
+    Breakpoint(u16),
+    /// An IN or OUT matching [`Emulator::add_port_break`] ran, taking `cycles` T-states.
+    PortBreak { access: PortAccess, cycles: u32 },
+}
+
+/// How a call to [`Emulator::run_frame`] ended. Every way but [`FrameStatus::Complete`] leaves
+/// the frame unfinished, and the next call picks it up where it stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameStatus {
+    /// The frame ran to the end of its cycle budget.
+    Complete,
+    /// Stopped before running the instruction at a breakpoint set with [`Emulator::add_breakpoint`].
+    Breakpoint(u16),
+    /// Stopped after an IN or OUT matching [`Emulator::add_port_break`].
+    PortBreak(PortAccess),
+    /// Stopped after the instruction that hit a watchpoint, see [`crate::Memory::add_watchpoint`].
+    Watchpoint(WatchHit),
+    /// [`FrameSink::stop`] asked to stop.
+    Stopped,
+    /// HLT ran with interrupts disabled, so the CPU can never run again and no VBlank is raised.
+    Halted,
+}
+
+/// Receives what happens during [`Emulator::run_frame`] as it happens. `()` discards everything,
+/// and any `FnMut(&mut Emulator, Event)` closure gets the events.
+pub trait FrameSink {
+    /// Every event the frame raises, in order, up to and including [`Event::FrameComplete`].
+    fn event(&mut self, emulator: &mut Emulator, event: Event);
+
+    /// Checked after every instruction, stopping the frame with [`FrameStatus::Stopped`] when it
+    /// returns `true`, e.g. for breakpoints a frontend keeps itself.
+    fn stop(&mut self, _emulator: &mut Emulator) -> bool {
+        false
+    }
+
+    /// Whether a watchpoint hit stops the frame with [`FrameStatus::Watchpoint`]. Sinks that
+    /// watch memory for their own purposes, like scripts, let the frame go on for theirs.
+    fn stop_at_watchpoint(&mut self, _emulator: &mut Emulator, _hit: &WatchHit) -> bool {
+        true
+    }
+}
+
+impl FrameSink for () {
+    fn event(&mut self, _emulator: &mut Emulator, _event: Event) {}
+}
+
+impl<F: FnMut(&mut Emulator, Event)> FrameSink for F {
+    fn event(&mut self, emulator: &mut Emulator, event: Event) {
+        self(emulator, event)
+    }
+}
+
+/// How [`Emulator::step`] executes instructions, see [`Emulator::with_backend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+    /// One instruction per step.
+    #[default]
+    Interpreter,
+    /// Hot blocks of code translated to native code and run a whole block per step. Needs the
+    /// `jit` feature.
+    ///
+    /// Each instruction has the same effect and timing as in the interpreter, but interrupts
+    /// raised between steps land after a block instead of a single instruction, so a run
+    /// drifts from the interpreter's by a few cycles per interrupt. Breakpoints, tracing,
+    /// watchpoints and the other per-instruction debugging aids switch back to the
+    /// interpreter while they're in use, and so do the instructions the JIT leaves to it.
+    Jit,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
     PlaySound(Sound),
     StopSound(Sound),
     Debug(u8),
+    VBlank,
+    FrameComplete,
+    CoinInserted,
+    GameStarted,
+    GameOver,
+    PlayerDied,
+    /// The game stopped writing to port 6 and the watchdog reset the board, see
+    /// [`Emulator::set_watchdog`].
+    WatchdogReset,
+    /// An unimplemented opcode was executed as a NOP, see
+    /// [`Emulator::set_skip_unimplemented_opcodes`].
+    SkippedOpcode { pc: u16, opcode: u8 },
+    /// An IN or OUT instruction ran, see [`Emulator::set_port_logging`].
+    PortAccess(PortAccess),
+    /// The CPU accessed memory covered by a watchpoint, see [`crate::Memory::add_watchpoint`].
+    /// Only the first watched access of an instruction is reported.
+    Watchpoint(WatchHit),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortAccess {
+    /// Total cycles executed when the instruction finished.
+    pub cycle: u64,
+    /// Address of the IN or OUT instruction.
+    pub pc: u16,
+    pub port: u8,
+    pub value: u8,
+    pub write: bool,
+}
+
+impl PortAccess {
+    pub fn kind(&self) -> Access {
+        if self.write { Access::Write } else { Access::Read }
+    }
+
+    /// What the Space Invaders board does with the port.
+    pub fn port_name(&self) -> &'static str {
+        match (self.write, self.port) {
+            (false, 1) => "inputs 1",
+            (false, 2) => "inputs 2",
+            (false, 3) => "shift result",
+            (true, 2) => "shift amount",
+            (true, 3) => "sound 1",
+            (true, 4) => "shift data",
+            (true, 5) => "sound 2",
+            (true, 6) => "watchdog",
+            _ => "unmapped",
+        }
+    }
+}
+
+impl Display for PortAccess {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let direction = if self.write { "OUT" } else { "IN " };
+        write!(f, "{:>12} ${:04X} {} {} 0x{:02X} ({})", self.cycle, self.pc, direction, self.port, self.value, self.port_name())
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Sound {
     UFO,
     Shoot,
@@ -39,131 +184,945 @@ pub enum Sound {
     UFOExplode,
 }
 
+impl Sound {
+    /// Output port and bit that turn the sound on.
+    pub(crate) fn port_bit(&self) -> (u8, u8) {
+        match self {
+            Self::UFO => (3, 0x01),
+            Self::Shoot => (3, 0x02),
+            Self::PlayerDie => (3, 0x04),
+            Self::InvaderDie => (3, 0x08),
+            Self::Bomp1 => (5, 0x01),
+            Self::Bomp2 => (5, 0x02),
+            Self::Bomp3 => (5, 0x04),
+            Self::Bomp4 => (5, 0x08),
+            Self::UFOExplode => (5, 0x10),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Emulator {
     cpu: CPU,
-    shift_lo: u8,
-    shift_hi: u8,
-    shift_offset: u8,
-    input_1: u8,
-    input_2: u8,
-    last_port_3: u8,
-    last_port_5: u8,
-    event: Option<Event>,
+    io: IoBus,
+    /// What this game's board adds to the shared hardware, see [`Emulator::with_machine`].
+    machine: Box<dyn Machine>,
+    events: VecDeque<Event>,
+    stats: EmulatorStats,
+    ips_meter: IpsMeter,
+    pc_history: PcHistory,
+    skip_opcode_limit: u32,
+    skipped_opcodes: u32,
+    cheats: Vec<Cheat>,
+    /// Address and original byte of every ROM byte a cheat has patched.
+    rom_patches: Vec<(u16, u8)>,
+    /// CRC32 of the unpatched ROM, which save states are tied to.
+    rom_crc: u32,
+    log_ports: bool,
+    quirks: Quirks,
+    /// Interrupt raised during the current instruction, see [`Quirks::interrupt_latency`].
+    pending_interrupt: Option<u8>,
+    /// Breakpoint addresses and the condition each one only stops on, if any.
+    breakpoints: BTreeMap<u16, Option<Expr>>,
+    /// Breakpoint execution last stopped at, stepped over by the next step.
+    resume_from: Option<u16>,
+    port_breaks: BTreeSet<(u8, Access)>,
+    /// Video RAM as the beam read it, see [`Emulator::screen`].
+    screen: Vec<u8>,
+    /// Lines of `screen` changed since [`Emulator::take_dirty_lines`] was last called.
+    dirty_lines: DirtyLines,
+    /// NVRAM to write once the game has booted, see [`Emulator::restore_nvram`].
+    pending_nvram: Option<Vec<u8>>,
+    /// Whether NVRAM is put back after resets, set once it's been restored.
+    keep_nvram: bool,
+    /// Whether a frame has ended since the last reset, by when the game has set up RAM.
+    booted: bool,
+    /// Whether [`Emulator::run_frame`] stopped partway through a frame.
+    mid_frame: bool,
+    /// T-states run in that frame.
+    frame_cycles: u32,
+    /// Whether that frame's mid-screen interrupt has been raised.
+    mid_screen_done: bool,
+    #[cfg(feature = "tracer")]
+    tracer: Option<Tracer>,
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
 }
 
 impl Emulator {
+    /// An emulator running `program` on the Space Invaders board.
     pub fn new(program: &[u8]) -> Self {
-        Self {
-            cpu: CPU::new(program),
-            shift_lo: 0,
-            shift_hi: 0,
-            shift_offset: 0,
-            input_1: 1,
-            input_2: 0,
-            last_port_3: 0,
-            last_port_5: 0,
-            event: None,
+        Self::with_machine(program, Box::new(SpaceInvadersMachine::new()))
+    }
+
+    /// An emulator running `program` on another board, e.g. one picked at runtime with
+    /// [`MachineKind::machine`](crate::MachineKind::machine).
+    pub fn with_machine(program: &[u8], machine: Box<dyn Machine>) -> Self {
+        Self::with_memory(CPU::new(program).memory, machine)
+    }
+
+    /// An emulator running on `machine` with memory laid out by a
+    /// [`MemoryMap`](crate::MemoryMap), for boards with more ROM or RAM than Space Invaders. The
+    /// screen is still read from video RAM at 0x2400-0x3FFF, so the map needs RAM there.
+    pub fn with_memory(memory: Memory, machine: Box<dyn Machine>) -> Self {
+        let rom_crc = crate::crc32(memory.rom());
+        let mut emulator = Self {
+            cpu: CPU::with_memory(memory),
+            io: IoBus::new(Quirks::FAST),
+            machine: machine.clone(),
+            events: VecDeque::new(),
+            stats: EmulatorStats::default(),
+            ips_meter: IpsMeter::new(),
+            pc_history: PcHistory::new(),
+            skip_opcode_limit: 0,
+            skipped_opcodes: 0,
+            cheats: Vec::new(),
+            rom_patches: Vec::new(),
+            rom_crc,
+            log_ports: false,
+            quirks: Quirks::FAST,
+            pending_interrupt: None,
+            breakpoints: BTreeMap::new(),
+            resume_from: None,
+            port_breaks: BTreeSet::new(),
+            screen: vec![0; VISIBLE_LINES as usize * BYTES_PER_LINE],
+            dirty_lines: DirtyLines::all(),
+            pending_nvram: None,
+            keep_nvram: false,
+            booted: false,
+            mid_frame: false,
+            frame_cycles: 0,
+            mid_screen_done: false,
+            #[cfg(feature = "tracer")]
+            tracer: None,
+            #[cfg(feature = "jit")]
+            jit: None,
+        };
+        machine.attach_devices(&mut emulator);
+        emulator
+    }
+
+    pub fn machine(&self) -> &dyn Machine {
+        &*self.machine
+    }
+
+    /// The RAM the game keeps between plays, see [`Machine::nvram`], for frontends to save when
+    /// they exit. Empty if the machine has none.
+    pub fn nvram(&self) -> &[u8] {
+        self.machine.nvram().and_then(|range| self.cpu.memory.get(range)).unwrap_or_default()
+    }
+
+    /// Puts back NVRAM saved from [`Emulator::nvram`], like the high score from the last run.
+    /// The game clears RAM as it boots, so a new emulator holds on to it until the end of its
+    /// first frame, and from then on it's put back after every reset too.
+    pub fn restore_nvram(&mut self, data: &[u8]) {
+        self.keep_nvram = true;
+        match self.booted {
+            true => self.write_nvram(data),
+            false => self.pending_nvram = Some(data.to_vec()),
+        }
+    }
+
+    fn write_nvram(&mut self, data: &[u8]) {
+        let Some(range) = self.machine.nvram() else { return };
+        for (address, &value) in range.zip(data) {
+            self.cpu.memory.write(address, value);
         }
     }
 
+    /// Executes instructions with `backend`, see [`Emulator::set_backend`].
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.set_backend(backend);
+        self
+    }
+
+    /// Executes instructions with `backend` from now on. [`Backend::Jit`] falls back to the
+    /// interpreter when the `jit` feature is off or no native code can be generated for this
+    /// machine; [`Emulator::backend`] tells which one is in use.
+    pub fn set_backend(&mut self, backend: Backend) {
+        #[cfg(feature = "jit")]
+        {
+            self.jit = match backend {
+                Backend::Jit => Jit::new(),
+                Backend::Interpreter => None,
+            };
+            self.cpu.memory.clear_code();
+        }
+        #[cfg(not(feature = "jit"))]
+        let _ = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        #[cfg(feature = "jit")]
+        if self.jit.is_some() {
+            return Backend::Jit;
+        }
+        Backend::Interpreter
+    }
+
     pub fn step(&mut self) -> Result<ExecutionStatus> {
-        let cycles = self.cpu.step()?;
+        let pc = self.cpu.pc();
+        if self.resume_from.take() != Some(pc) && self.breakpoint_hit(pc) {
+            self.resume_from = Some(pc);
+            return Ok(ExecutionStatus::Breakpoint(pc));
+        }
+        #[cfg(feature = "jit")]
+        if let Some(cycles) = self.step_jit() {
+            self.pc_history.push(pc);
+            return Ok(ExecutionStatus::Continue(cycles));
+        }
+
+        self.pc_history.push(pc);
+        let latched_interrupt = self.cpu.pending_interrupt().is_some();
+        #[cfg(feature = "tracer")]
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(&self.cpu, self.stats.cycles);
+        }
 
-        if let Some(event) = self.cpu.event() {
-            match event {
-                CPUEvent::Halt => return Ok(ExecutionStatus::Halt),
-                CPUEvent::PortWrite(port, val) => self.write_port(port, val)?,
+        let cycles = match self.cpu.step() {
+            Err(Error::UnimplementedOpcode { opcode }) if self.skipped_opcodes < self.skip_opcode_limit => {
+                self.skipped_opcodes += 1;
+                self.cpu.set_pc(pc.wrapping_add(1));
+                self.events.push_back(Event::SkippedOpcode { pc, opcode });
+                NOP_CYCLES
+            }
+            result => result?,
+        };
+        self.stats.instructions += 1;
+        self.stats.cycles += cycles as u64;
+        if latched_interrupt && self.cpu.pending_interrupt().is_none() {
+            self.stats.interrupts += 1;
+        }
+        self.report_watch_hit();
+
+        let mut halted = false;
+        let mut port_break = None;
+        while let Some(event) = self.cpu.event() {
+            let access = match event {
+                CPUEvent::Halt => {
+                    halted = true;
+                    continue;
+                }
+                CPUEvent::PortWrite(port, value) => {
+                    self.write_port(port, value)?;
+                    PortAccess { cycle: self.stats.cycles, pc, port, value, write: true }
+                }
                 CPUEvent::PortRead(port) => {
-                    let val = self.read_port(port)?;
-                    self.cpu.port_in(val);
+                    let value = self.read_port(port)?;
+                    self.cpu.port_in(value);
+                    PortAccess { cycle: self.stats.cycles, pc, port, value, write: false }
                 }
+            };
+            if self.log_ports {
+                self.events.push_back(Event::PortAccess(access));
+            }
+            if self.port_breaks.contains(&(access.port, access.kind())) {
+                port_break = Some(access);
             }
         }
+        if halted {
+            return Ok(ExecutionStatus::Halt);
+        }
+
+        let cycles = match self.pending_interrupt.take() {
+            Some(interrupt_num) => cycles + self.take_interrupt(interrupt_num),
+            None => cycles,
+        };
 
-        Ok(ExecutionStatus::Continue(cycles))
+        match port_break {
+            Some(access) => Ok(ExecutionStatus::PortBreak { access, cycles }),
+            None => Ok(ExecutionStatus::Continue(cycles)),
+        }
+    }
+
+    /// Runs a translated block if the JIT is on and nothing needs the interpreter, returning
+    /// the T-states it took.
+    #[cfg(feature = "jit")]
+    fn step_jit(&mut self) -> Option<u32> {
+        let jit = self.jit.as_mut()?;
+        #[cfg(feature = "tracer")]
+        if self.tracer.is_some() {
+            return None;
+        }
+        if !self.breakpoints.is_empty() || self.pending_interrupt.is_some() || !self.cpu.jit_ready() {
+            return None;
+        }
+
+        let (instructions, cycles) = jit.run(&mut self.cpu)?;
+        self.stats.instructions += instructions as u64;
+        self.stats.cycles += cycles as u64;
+        Some(cycles)
+    }
+
+    /// Whether the game has `sound` turned on. Loading a state raises no sound events, so this is
+    /// how a frontend picks looping sounds like the UFO back up afterwards.
+    pub fn sound_on(&self, sound: Sound) -> bool {
+        self.io.sound.sound_on(sound)
+    }
+
+    /// Byte at `address` as the CPU would read it, open bus where nothing is mapped. Unlike a CPU
+    /// read this never trips a watchpoint, so memory viewers can call it every frame.
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.cpu.memory[address]
+    }
+
+    /// Pokes `value` into `address`, returning whether it was written: like the CPU, ROM and
+    /// unmapped addresses can't be. Video RAM writes show up on [`Emulator::screen`] right away
+    /// rather than when the beam next gets there, so edits made while paused can be seen.
+    pub fn write_memory(&mut self, address: u16, value: u8) -> bool {
+        if !self.cpu.memory.write(address, value) {
+            return false;
+        }
+
+        let vram = self.cpu.memory.ram_offset(0x2400);
+        let offset = self.cpu.memory.ram_offset(address).zip(vram).and_then(|(offset, vram)| offset.checked_sub(vram));
+        if let Some(line) = offset.map(|offset| (offset / BYTES_PER_LINE) as u32).filter(|&line| line < VISIBLE_LINES) {
+            self.latch_lines(line..line + 1);
+        }
+        true
     }
 
     pub fn video_ram(&self) -> &[u8] {
         &self.cpu.memory[0x2400..0x4000]
     }
 
+    /// Video RAM as the beam drew it during the last frame: the lines above the middle of the
+    /// picture as they were at RST 1, the rest as they were at RST 2. The game only moves objects
+    /// in the half the beam isn't drawing, so unlike [`Emulator::video_ram`] at the end of a frame
+    /// this never shows a sprite torn between two positions.
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    /// Lines of [`Emulator::screen`] that changed since the last call, all of them the first time,
+    /// so frontends only have to redraw those.
+    pub fn take_dirty_lines(&mut self) -> DirtyLines {
+        core::mem::take(&mut self.dirty_lines)
+    }
+
+    /// Copies `lines` of video RAM to [`Emulator::screen`], as the beam just drew them, marking
+    /// the ones that changed dirty.
+    fn latch_lines(&mut self, lines: Range<u32>) {
+        let vram = &self.cpu.memory[0x2400..0x4000];
+        for line in lines {
+            let bytes = line as usize * BYTES_PER_LINE..(line as usize + 1) * BYTES_PER_LINE;
+            if self.screen[bytes.clone()] != vram[bytes.clone()] {
+                self.screen[bytes.clone()].copy_from_slice(&vram[bytes]);
+                self.dirty_lines.insert(line);
+            }
+        }
+    }
+
+    /// Hash of video RAM as it is now, meant to be taken after [`Emulator::run_frame`] and
+    /// chained with [`FrameHashChain`](crate::FrameHashChain). That's the framebuffer as the game
+    /// draws it, one bit per pixel and on its side, not the picture a frontend shows: frames
+    /// that look the same always hash the same, whatever the overlay or renderer.
+    pub fn frame_hash(&self) -> u64 {
+        fnv1a64(self.video_ram())
+    }
+
     pub fn reset(&mut self) {
+        if self.keep_nvram {
+            self.pending_nvram = Some(self.nvram().to_vec());
+        }
+        self.booted = false;
         self.cpu.reset();
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.flush_ram(&mut self.cpu.memory);
+        }
+        self.machine.reset();
+        self.io.watchdog.frames = 0;
+        self.pending_interrupt = None;
+        (self.mid_frame, self.frame_cycles, self.mid_screen_done) = (false, 0, false);
+        self.events.clear();
+        self.pc_history.clear();
+        self.latch_lines(0..VISIBLE_LINES);
     }
 
-    pub fn button_press(&mut self, button: Button) {
-        let mask = button.mask();
-        match button {
-            Button::Coin => self.input_1 &= !mask,
-            Button::Tilt | Button::P2Shoot | Button::P2Left | Button::P2Right => self.input_2 |= mask,
-            _ => self.input_1 |= mask,
+    pub fn interrupt(&mut self, interrupt_num: u8) {
+        match interrupt_num {
+            1 => self.latch_lines(0..MID_SCREEN_LINE),
+            2 => {
+                self.latch_lines(MID_SCREEN_LINE..VISIBLE_LINES);
+                self.events.push_back(Event::VBlank);
+            }
+            _ => {}
+        }
+
+        if self.quirks.interrupt_latency {
+            self.pending_interrupt = Some(interrupt_num);
+        } else {
+            self.frame_cycles += self.take_interrupt(interrupt_num);
+        }
+    }
+
+    /// Raises the VBlank interrupt and emits the events derived from this frame's RAM and port
+    /// activity.
+    pub fn end_frame(&mut self) {
+        if self.io.watchdog.tick() {
+            self.reset();
+            self.events.push_back(Event::WatchdogReset);
+        } else if !self.booted {
+            self.booted = true;
+            if let Some(data) = self.pending_nvram.take() {
+                self.write_nvram(&data);
+            }
         }
+
+        self.apply_cheats();
+        self.interrupt(2);
+        self.check_game_events();
+
+        self.stats.frames += 1;
+        self.ips_meter.update(&mut self.stats);
+        self.events.push_back(Event::FrameComplete);
+    }
+
+    /// Runs one frame of the board's CRT timing, see [`crate::raster`]: [`CYCLES_PER_FRAME`]
+    /// T-states from the start of VBlank, with RST 1 when the beam reaches line 96 and then
+    /// [`Emulator::end_frame`] for RST 2 at line 224, handing every event to `sink` as it's raised.
+    /// The 11 T-states each RST takes to acknowledge count toward the frame like any instruction.
+    ///
+    /// A frame that stops early, at a breakpoint for instance, keeps its place: the next call
+    /// finishes it, with the interrupts at the same cycles as if it had never stopped, so
+    /// debugging doesn't change how the game runs.
+    pub fn run_frame(&mut self, sink: &mut impl FrameSink) -> Result<FrameStatus> {
+        self.mid_frame = true;
+        while self.frame_cycles < CYCLES_PER_FRAME {
+            let mut status = FrameStatus::Complete;
+            match self.step()? {
+                ExecutionStatus::Continue(cycles) => self.frame_cycles += cycles,
+                ExecutionStatus::Halt => return Ok(FrameStatus::Halted),
+                ExecutionStatus::Breakpoint(address) => return Ok(FrameStatus::Breakpoint(address)),
+                ExecutionStatus::PortBreak { access, cycles } => {
+                    self.frame_cycles += cycles;
+                    status = FrameStatus::PortBreak(access);
+                }
+            }
+
+            while let Some(event) = self.event() {
+                if let Event::Watchpoint(hit) = event {
+                    if sink.stop_at_watchpoint(self, &hit) {
+                        status = FrameStatus::Watchpoint(hit);
+                    }
+                }
+                sink.event(self, event);
+            }
+
+            if !self.mid_screen_done && self.frame_cycles >= MID_SCREEN_CYCLE {
+                self.interrupt(1);
+                self.mid_screen_done = true;
+            }
+            if status == FrameStatus::Complete && sink.stop(self) {
+                status = FrameStatus::Stopped;
+            }
+            if status != FrameStatus::Complete {
+                return Ok(status);
+            }
+        }
+
+        (self.mid_frame, self.frame_cycles, self.mid_screen_done) = (false, 0, false);
+        self.end_frame();
+        while let Some(event) = self.event() {
+            sink.event(self, event);
+        }
+        Ok(FrameStatus::Complete)
+    }
+
+    /// Whether [`Emulator::run_frame`] stopped partway through a frame, which the next call
+    /// finishes. Frontends doing something once per frame, like recording input, wait for it.
+    pub fn mid_frame(&self) -> bool {
+        self.mid_frame
+    }
+
+    /// T-states already run in the frame [`Emulator::run_frame`] stopped in, 0 between frames.
+    pub fn frame_cycles(&self) -> u32 {
+        self.frame_cycles
+    }
+
+    /// Addresses of the most recently executed instructions, ending with the one that faulted if
+    /// [`Emulator::step`] returned an error.
+    pub fn pc_history(&self) -> &PcHistory {
+        &self.pc_history
+    }
+
+    /// Makes [`Emulator::step`] stop with [`ExecutionStatus::Breakpoint`] before running the
+    /// instruction at `address`. Replaces the breakpoint already there, if any.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, None);
+    }
+
+    /// Like [`Emulator::add_breakpoint`], but only stops when `condition` holds. `addr` in the
+    /// condition is `address`.
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: Expr) {
+        self.breakpoints.insert(address, Some(condition));
+    }
+
+    /// Makes [`Emulator::step`] report [`ExecutionStatus::PortBreak`] after every IN from `port`
+    /// ([`Access::Read`]) or OUT to it ([`Access::Write`]).
+    pub fn add_port_break(&mut self, port: u8, access: Access) {
+        self.port_breaks.insert((port, access));
+    }
+
+    /// Returns whether the port break was set.
+    pub fn remove_port_break(&mut self, port: u8, access: Access) -> bool {
+        self.port_breaks.remove(&(port, access))
+    }
+
+    pub fn clear_port_breaks(&mut self) {
+        self.port_breaks.clear();
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    /// Condition of the breakpoint at `address`, `None` if there's no breakpoint or it always stops.
+    pub fn breakpoint_condition(&self, address: u16) -> Option<&Expr> {
+        self.breakpoints.get(&address)?.as_ref()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Breakpoint addresses in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    fn breakpoint_hit(&self, pc: u16) -> bool {
+        match self.breakpoints.get(&pc) {
+            Some(condition) => !self.cpu.halted() && condition.as_ref().is_none_or(|c| c.is_true(&self.cpu, pc)),
+            None => false,
+        }
+    }
+
+    /// Attaches a tracer recording every instruction from now on, or detaches it with `None`.
+    #[cfg(feature = "tracer")]
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    #[cfg(feature = "tracer")]
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    #[cfg(feature = "tracer")]
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn stats(&self) -> EmulatorStats {
+        self.stats
+    }
+
+    /// Dumps the machine state as human-readable JSON, one field per line so two dumps can be diffed.
+    /// RAM (including video RAM) is included as base64.
+    pub fn to_debug_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\n",
+                "  \"cpu\": {},\n",
+                "  \"shift_register\": {{\n",
+                "    \"lo\": \"0x{:02X}\",\n",
+                "    \"hi\": \"0x{:02X}\",\n",
+                "    \"offset\": {}\n",
+                "  }},\n",
+                "  \"ports\": {{\n",
+                "    \"input_1\": \"0x{:02X}\",\n",
+                "    \"input_2\": \"0x{:02X}\",\n",
+                "    \"sound_1\": \"0x{:02X}\",\n",
+                "    \"sound_2\": \"0x{:02X}\"\n",
+                "  }},\n",
+                "  \"ram\": \"{}\"\n",
+                "}}\n",
+            ),
+            self.cpu.debug_json(),
+            self.io.shifter.lo, self.io.shifter.hi, self.io.shifter.offset,
+            self.io.inputs.ports[0], self.io.inputs.ports[1], self.io.sound.ports[0], self.io.sound.ports[1],
+            crate::base64::encode(self.cpu.memory.ram()),
+        )
+    }
+
+    /// Serializes the machine state in the [`savestate`] format.
+    pub fn save_state_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+
+        let mut cpu = Vec::new();
+        self.cpu.write_state(&mut cpu);
+        writer.section(b"CPU ", &cpu);
+        writer.section(b"RAM ", self.cpu.memory.ram());
+        if self.cpu.memory.banks().next().is_some() {
+            writer.section(b"BANK", &self.bank_state());
+        }
+        writer.section(b"IO  ", &self.io_state());
+        writer.section(b"EVNT", &self.event_state());
+
+        let stats: Vec<u8> = [self.stats.instructions, self.stats.cycles, self.stats.frames, self.stats.interrupts]
+            .iter()
+            .flat_map(|n| n.to_le_bytes())
+            .collect();
+        writer.section(b"STAT", &stats);
+        writer.section(b"INTR", &self.interrupt_state());
+        writer.section(b"FRAM", &self.frame_state());
+
+        writer.finish(self.machine.kind().id(), self.rom_crc)
+    }
+
+    /// FNV-1a hash of everything that decides how emulation goes on from here: the CPU, RAM, the
+    /// selected banks, the port devices, the driver, the watchdog, a pending interrupt and where
+    /// a stopped frame is at. Unlike a save state it leaves out [`Emulator::stats`], so the same
+    /// state reached after a different number of frames hashes the same. Regression tests compare
+    /// it frame by frame to catch any change in behavior.
+    pub fn state_hash(&self) -> u64 {
+        let mut cpu = Vec::new();
+        self.cpu.write_state(&mut cpu);
+
+        // Between frames there's no position to hash, which keeps hashes taken then as they were
+        let frame: &[u8] = if self.mid_frame { &self.frame_state() } else { &[] };
+        let parts: [&[u8]; 7] = [&cpu, self.cpu.memory.ram(), &self.bank_state(), &self.io_state(), &self.event_state(), &self.interrupt_state(), frame];
+        parts.into_iter()
+            .fold(fnv1a64(&[]), fnv1a64_continue)
+    }
+
+    fn bank_state(&self) -> Vec<u8> {
+        self.cpu.memory.banks().map(|(_, bank)| bank as u8).collect()
+    }
+
+    fn io_state(&self) -> [u8; 7] {
+        [
+            self.io.shifter.lo,
+            self.io.shifter.hi,
+            self.io.shifter.offset,
+            self.io.inputs.ports[0],
+            self.io.inputs.ports[1],
+            self.io.sound.ports[0],
+            self.io.sound.ports[1],
+        ]
+    }
+
+    /// The driver's state, then the watchdog's.
+    fn event_state(&self) -> Vec<u8> {
+        let mut events = self.machine.save_state();
+        events.extend_from_slice(&self.io.watchdog.frames.to_le_bytes());
+        events
+    }
+
+    fn interrupt_state(&self) -> [u8; 2] {
+        [self.pending_interrupt.is_some() as u8, self.pending_interrupt.unwrap_or(0)]
+    }
+
+    /// Position in a stopped frame: whether there is one, T-states run, then whether RST 1 has
+    /// been raised.
+    fn frame_state(&self) -> [u8; 6] {
+        let [a, b, c, d] = self.frame_cycles.to_le_bytes();
+        [self.mid_frame as u8, a, b, c, d, self.mid_screen_done as u8]
+    }
+
+    /// Restores a state created by [`Emulator::save_state_bytes`]. The emulator is left untouched
+    /// on error.
+    pub fn load_state_bytes(&mut self, data: &[u8]) -> Result<()> {
+        let (header, sections) = savestate::parse(data)?;
+
+        let machine = self.machine.kind().id();
+        if header.machine != machine {
+            return Err(SaveStateError::MachineMismatch { expected: machine, found: header.machine }.into());
+        }
+        let rom_crc = self.rom_crc;
+        if header.rom_crc != rom_crc {
+            return Err(SaveStateError::RomMismatch { expected: rom_crc, found: header.rom_crc }.into());
+        }
+
+        let mut loaded = self.clone();
+        // The state has the game's RAM as it was, booted or not
+        (loaded.pending_nvram, loaded.booted) = (None, true);
+        loaded.cpu.read_state(&mut savestate::find(&sections, b"CPU ")?)?;
+
+        let ram = savestate::find(&sections, b"RAM ")?.bytes(self.cpu.memory.ram().len())?;
+        loaded.cpu.memory.ram_mut().copy_from_slice(ram);
+        if let Ok(mut banks) = savestate::find(&sections, b"BANK") {
+            let starts: Vec<u16> = self.cpu.memory.banks().map(|(start, _)| start).collect();
+            for start in starts {
+                loaded.cpu.memory.select_bank(start, banks.u8()? as usize);
+            }
+        }
+
+        let mut io = savestate::find(&sections, b"IO  ")?;
+        loaded.io.shifter.lo = io.u8()?;
+        loaded.io.shifter.hi = io.u8()?;
+        loaded.io.shifter.offset = io.u8()?;
+        loaded.io.inputs.ports[0] = io.u8()?;
+        loaded.io.inputs.ports[1] = io.u8()?;
+        loaded.io.sound.ports[0] = io.u8()?;
+        loaded.io.sound.ports[1] = io.u8()?;
+
+        // The driver's state, then the watchdog's
+        let events = savestate::find(&sections, b"EVNT")?.rest();
+        let (machine, watchdog) = events.split_at_checked(events.len().wrapping_sub(4)).ok_or(SaveStateError::Truncated)?;
+        loaded.machine.load_state(machine)?;
+        loaded.io.watchdog.frames = u32::from_le_bytes(watchdog.try_into().unwrap());
+        loaded.events.clear();
+
+        let mut stats = savestate::find(&sections, b"STAT")?;
+        loaded.stats.instructions = stats.u64()?;
+        loaded.stats.cycles = stats.u64()?;
+        loaded.stats.frames = stats.u64()?;
+        loaded.stats.interrupts = stats.u64()?;
+
+        let mut interrupt = savestate::find(&sections, b"INTR")?;
+        let pending = interrupt.u8()? != 0;
+        let interrupt_num = interrupt.u8()?;
+        loaded.pending_interrupt = pending.then_some(interrupt_num);
+
+        let mut frame = savestate::find(&sections, b"FRAM")?;
+        loaded.mid_frame = frame.u8()? != 0;
+        loaded.frame_cycles = frame.u32()?;
+        loaded.mid_screen_done = frame.u8()? != 0;
+        loaded.latch_lines(0..VISIBLE_LINES);
+
+        // Keep the blocks already translated from ROM rather than the clone's empty JIT
+        #[cfg(feature = "jit")]
+        if let Some(mut jit) = self.jit.take() {
+            jit.flush_ram(&mut loaded.cpu.memory);
+            loaded.jit = Some(jit);
+        }
+        *self = loaded;
+        Ok(())
+    }
+
+    /// Writes the machine state to `writer` in the [`savestate`] format.
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(&self.save_state_bytes())?;
+        Ok(())
+    }
+
+    /// Reads a state written by [`Emulator::save_state`] from `reader` up to its end. Files from
+    /// older versions are upgraded, while corrupted, truncated or foreign ones fail with an
+    /// [`Error::SaveState`] and leave the emulator untouched.
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, reader: &mut impl Read) -> Result<()> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.load_state_bytes(&data)
+    }
+
+    pub fn button_press(&mut self, button: Button) {
+        self.set_button(button, true);
     }
 
     pub fn button_release(&mut self, button: Button) {
-        let mask = button.mask();
-        match button {
-            Button::Coin => self.input_1 |= mask,
-            Button::Tilt | Button::P2Shoot | Button::P2Left | Button::P2Right => self.input_2 &= !mask,
-            _ => self.input_1 &= !mask,
+        self.set_button(button, false);
+    }
+
+    /// Presses or releases `button`, setting or clearing its bit of the input ports.
+    pub fn set_button(&mut self, button: Button, held: bool) {
+        self.io.inputs.set(button, held);
+    }
+
+    pub fn button_held(&self, button: Button) -> bool {
+        self.io.inputs.held(button)
+    }
+
+    /// Every button held right now.
+    pub fn buttons(&self) -> Buttons {
+        Button::ALL.into_iter().filter(|&button| self.button_held(button)).collect()
+    }
+
+    /// Holds exactly the buttons in `buttons`, releasing all others.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        for button in Button::ALL {
+            self.set_button(button, buttons.contains(button.into()));
         }
     }
 
+    /// DIP switch bits of input port 2: ships per game (bits 0-1), extra ship at 1000 instead of
+    /// 1500 points (bit 3) and hiding the coin info on the demo screen (bit 7).
+    pub fn dip_switches(&self) -> u8 {
+        self.io.inputs.ports[1] & DIP_SWITCH_MASK
+    }
+
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        let port = &mut self.io.inputs.ports[1];
+        *port = (*port & !DIP_SWITCH_MASK) | (dip_switches & DIP_SWITCH_MASK);
+    }
+
+    /// Executes up to `limit` unimplemented opcodes as NOPs, reporting each one with
+    /// [`Event::SkippedOpcode`], before [`Emulator::step`] starts failing on them again.
+    /// Keeps slightly broken ROM hacks playable. The count isn't cleared by [`Emulator::reset`].
+    pub fn set_skip_unimplemented_opcodes(&mut self, limit: u32) {
+        self.skip_opcode_limit = limit;
+    }
+
+    pub fn skipped_opcodes(&self) -> u32 {
+        self.skipped_opcodes
+    }
+
+    /// Queues a memory patch, applied from the end of the next frame [`Emulator::run_frame`] runs
+    /// on. Cheats aren't part of save states and survive [`Emulator::reset`]. Only
+    /// [`CheatKind::RomPatch`] cheats change ROM; the others skip it.
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    /// Removes every cheat patching `address`, putting back the ROM byte if one patched it.
+    pub fn remove_cheats(&mut self, address: u16) {
+        self.cheats.retain(|cheat| cheat.address != address);
+        self.unpatch_rom(|patched| patched == address);
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+        self.unpatch_rom(|_| true);
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Board behaviors being emulated, see [`Quirks`].
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.io.set_quirks(quirks);
+        if !quirks.interrupt_latency {
+            if let Some(interrupt_num) = self.pending_interrupt.take() {
+                self.frame_cycles += self.take_interrupt(interrupt_num);
+            }
+        }
+    }
+
+    /// Queues an [`Event::PortAccess`] for every IN and OUT instruction while enabled.
+    pub fn set_port_logging(&mut self, enabled: bool) {
+        self.log_ports = enabled;
+    }
+
+    /// Resets the board like the real watchdog does when the game goes
+    /// [`WATCHDOG_FRAMES`](crate::io::WATCHDOG_FRAMES) frames without writing to port 6, raising
+    /// [`Event::WatchdogReset`]. Off by default; a game that hangs usually means an emulation bug.
+    pub fn set_watchdog(&mut self, enabled: bool) {
+        self.io.watchdog.enabled = enabled;
+        self.io.watchdog.frames = 0;
+    }
+
+    pub fn watchdog_enabled(&self) -> bool {
+        self.io.watchdog.enabled
+    }
+
+    /// Puts `device` on the board, serving every IN from a port in `reads` and every OUT to one
+    /// in `writes` in place of what was there, or turning an unused port into a working one.
+    /// Attached devices aren't part of save states.
+    pub fn attach_device(&mut self, device: impl IoDevice + 'static, reads: &[u8], writes: &[u8]) {
+        self.io.attach(Box::new(device), reads, writes);
+    }
+
+    /// Raw values of input ports 1 and 2.
+    pub fn input_ports(&self) -> [u8; 2] {
+        self.io.inputs.ports
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
     pub fn cpu_mut(&mut self) -> &mut CPU {
         &mut self.cpu
     }
 
+    /// Takes the oldest event not taken yet. Events queue up until taken, so they can be handled
+    /// after every step, once per frame with [`Emulator::drain_events`], or as they're raised
+    /// with [`Emulator::run_frame`].
     pub fn event(&mut self) -> Option<Event> {
-        mem::replace(&mut self.event, None)
+        self.events.pop_front()
     }
 
-    fn write_port(&mut self, port: u8, val: u8) -> Result<()> {
-        match port {
-            2 => self.shift_offset = val & 0x7,
-            3 => {
-                if val != self.last_port_3 {
-                    check_sound_events!(self.last_port_3, val, self.event,
-                        (0x01, Sound::UFO),
-                        (0x02, Sound::Shoot),
-                        (0x04, Sound::PlayerDie),
-                        (0x08, Sound::InvaderDie)
-                    );
-                    self.last_port_3 = val;
-                }
-            }
-            4 => {
-                self.shift_lo = self.shift_hi;
-                self.shift_hi = val;
+    /// Takes every event not taken yet, oldest first.
+    pub fn drain_events(&mut self) -> Drain<'_, Event> {
+        self.events.drain(..)
+    }
+
+    /// Acknowledges an interrupt right away, returning the T-states its RST took, 0 if it was
+    /// latched instead.
+    fn take_interrupt(&mut self, interrupt_num: u8) -> u32 {
+        let cycles = match self.cpu.interrupt_with(cpu::rst(interrupt_num)) {
+            Ok(Some(cycles)) => {
+                self.stats.interrupts += 1;
+                self.stats.cycles += cycles as u64;
+                cycles
             }
-            5 => {
-                if val != self.last_port_5 {
-                    check_sound_events!(self.last_port_5, val, self.event,
-                        (0x01, Sound::Bomp1),
-                        (0x02, Sound::Bomp2),
-                        (0x04, Sound::Bomp3),
-                        (0x08, Sound::Bomp4),
-                        (0x10, Sound::UFOExplode)
-                    );
-                    self.last_port_5 = val;
+            _ => 0,
+        };
+        self.report_watch_hit();
+        cycles
+    }
+
+    fn report_watch_hit(&mut self) {
+        if let Some(hit) = self.cpu.take_watch_hit() {
+            self.events.push_back(Event::Watchpoint(hit));
+        }
+    }
+
+    fn apply_cheats(&mut self) {
+        let mut patched = false;
+        for cheat in &self.cheats {
+            if cheat.kind != CheatKind::RomPatch {
+                self.cpu.memory.write(cheat.address, cheat.value);
+            } else if !self.rom_patches.iter().any(|&(address, _)| address == cheat.address) {
+                if let Some(original) = self.cpu.memory.patch_rom(cheat.address, cheat.value) {
+                    self.rom_patches.push((cheat.address, original));
+                    patched = true;
                 }
             }
-            6 => self.event = Some(Event::Debug(val)),
-            _ => return Err(Error::InvalidWritePort { port })
         }
+        self.cheats.retain(|cheat| cheat.kind != CheatKind::Once);
+        if patched {
+            self.flush_translations();
+        }
+    }
+
+    /// Puts back the original ROM bytes at the addresses `remove` picks.
+    fn unpatch_rom(&mut self, remove: impl Fn(u16) -> bool) {
+        let len = self.rom_patches.len();
+        for &(address, original) in self.rom_patches.iter().filter(|&&(address, _)| remove(address)) {
+            self.cpu.memory.patch_rom(address, original);
+        }
+        self.rom_patches.retain(|&(address, _)| !remove(address));
+        if self.rom_patches.len() != len {
+            self.flush_translations();
+        }
+    }
+
+    /// Throws away translated code after ROM changed under it.
+    fn flush_translations(&mut self) {
+        #[cfg(feature = "jit")]
+        if let Some(jit) = &mut self.jit {
+            jit.clear(&mut self.cpu.memory);
+        }
+    }
+
+    fn check_game_events(&mut self) {
+        let events = &mut self.events;
+        self.machine.frame_events(&self.cpu.memory, &mut |event| events.push_back(event));
+    }
 
+    fn write_port(&mut self, port: u8, val: u8) -> Result<()> {
+        if !self.io.write(port, val) {
+            return Err(Error::InvalidWritePort { port });
+        }
+        self.io.take_events(&mut self.events);
         Ok(())
     }
 
     fn read_port(&mut self, port: u8) -> Result<u8> {
-        Ok(match port {
-            1 => self.input_1,
-            2 => self.input_2,
-            3 => {
-                let shift_val = concat_u16!(self.shift_hi, self.shift_lo);
-                ((shift_val >> (8 - self.shift_offset)) & 0xFF) as u8
-            }
-            _ => return Err(Error::InvalidReadPort { port })
-        })
+        let value = self.io.read(port).ok_or(Error::InvalidReadPort { port })?;
+        self.io.take_events(&mut self.events);
+        Ok(value)
     }
 }
\ No newline at end of file
@@ -0,0 +1,242 @@
+//! Gym-style reinforcement learning environment over a headless [`Emulator`]: [`Env::reset`]
+//! starts a one-player game and [`Env::step`] plays an [`Action`] for a few frames, returning what
+//! the agent sees, the points scored and whether the game is over.
+//!
+//! Every episode starts from the same frame, the first one of the game, so runs are reproducible.
+
+use alloc::vec::Vec;
+use crate::game::SpaceInvadersState;
+use crate::video::{Renderer, WHITE};
+use crate::{Button, Buttons, Emulator, Error, Result};
+
+/// Frames [`Env::reset`] gives the game to take a coin and start, about ten seconds.
+const START_FRAMES: u32 = 600;
+/// Frames the coin switch stays closed, and then open. The game ignores a coin held down.
+const COIN_FRAMES: u32 = 5;
+/// Scores have four BCD digits and roll over to 0 after 9999.
+const SCORE_ROLLOVER: u32 = 10_000;
+
+/// What player 1 does during a step, the same six actions as the Atari 2600 version in ALE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Action {
+    #[default]
+    Noop,
+    Fire,
+    Left,
+    Right,
+    LeftFire,
+    RightFire,
+}
+
+impl Action {
+    /// Every action, so a discrete action space can index into it.
+    pub const ALL: [Action; 6] = [
+        Action::Noop,
+        Action::Fire,
+        Action::Left,
+        Action::Right,
+        Action::LeftFire,
+        Action::RightFire,
+    ];
+
+    pub fn buttons(self) -> Buttons {
+        match self {
+            Self::Noop => Buttons::empty(),
+            Self::Fire => Buttons::P1_SHOOT,
+            Self::Left => Buttons::P1_LEFT,
+            Self::Right => Buttons::P1_RIGHT,
+            Self::LeftFire => Buttons::P1_LEFT | Buttons::P1_SHOOT,
+            Self::RightFire => Buttons::P1_RIGHT | Buttons::P1_SHOOT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObservationKind {
+    /// [`Emulator::screen`] drawn by the renderer. Overlays aren't applied, lit pixels are white.
+    Screen(Renderer),
+    /// The game state decoded from RAM.
+    State,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observation {
+    Screen(Vec<u8>),
+    State(SpaceInvadersState),
+}
+
+#[derive(Clone)]
+pub struct Env {
+    emulator: Emulator,
+    observation: ObservationKind,
+    frame_skip: u32,
+    /// Save state of the first frame of the game, taken by the first reset.
+    start: Option<Vec<u8>>,
+    score: u32,
+    done: bool,
+}
+
+impl Env {
+    /// An environment observing the decoded game state every frame.
+    pub fn new(program: &[u8]) -> Self {
+        Self::with_emulator(Emulator::new(program))
+    }
+
+    /// An environment over an emulator set up by the caller, e.g. with different DIP switches for
+    /// more ships.
+    pub fn with_emulator(emulator: Emulator) -> Self {
+        Self {
+            emulator,
+            observation: ObservationKind::State,
+            frame_skip: 1,
+            start: None,
+            score: 0,
+            done: true,
+        }
+    }
+
+    pub fn with_observation(mut self, observation: ObservationKind) -> Self {
+        self.observation = observation;
+        self
+    }
+
+    /// Frames each [`Env::step`] plays its action for, at least 1.
+    pub fn with_frame_skip(mut self, frames: u32) -> Self {
+        self.frame_skip = frames.max(1);
+        self
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    /// Player 1's score in the current episode.
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// Starts a new one-player game and returns the first observation. The first call boots the
+    /// machine, inserts a coin and presses start, later ones go back to where that left off.
+    pub fn reset(&mut self) -> Result<Observation> {
+        match &self.start {
+            Some(start) => self.emulator.load_state_bytes(start)?,
+            None => {
+                self.emulator.reset();
+                self.start_game()?;
+                self.start = Some(self.emulator.save_state_bytes());
+            }
+        }
+
+        let state = self.state();
+        self.score = state.scores[0];
+        self.done = false;
+        Ok(self.observe(state))
+    }
+
+    /// Holds the buttons of `action` for the frame skip and returns the observation after it, the
+    /// points scored meanwhile and whether the game is over. Stepping a finished game runs the
+    /// attract mode without rewards until the next [`Env::reset`].
+    pub fn step(&mut self, action: Action) -> Result<(Observation, i32, bool)> {
+        self.emulator.set_buttons(action.buttons());
+        for _ in 0..self.frame_skip {
+            self.emulator.run_frame(&mut ())?;
+        }
+
+        let state = self.state();
+        let mut reward = 0;
+        if !self.done {
+            let score = state.scores[0];
+            reward = ((score + SCORE_ROLLOVER - self.score) % SCORE_ROLLOVER) as i32;
+            self.score = score;
+            self.done = !state.in_game;
+        }
+        Ok((self.observe(state), reward, self.done))
+    }
+
+    /// Inserts a coin, then presses player 1 start until the game begins.
+    fn start_game(&mut self) -> Result<()> {
+        let mut credited = false;
+        for frame in 0..START_FRAMES {
+            let state = self.state();
+            if state.in_game {
+                self.emulator.set_buttons(Buttons::empty());
+                return Ok(());
+            }
+            credited |= state.credits > 0;
+
+            if credited {
+                self.emulator.set_buttons(Button::P1Start.into());
+            } else {
+                self.emulator.set_button(Button::Coin, (frame / COIN_FRAMES).is_multiple_of(2));
+            }
+            self.emulator.run_frame(&mut ())?;
+        }
+
+        Err(Error::GameNotStarted { frames: START_FRAMES })
+    }
+
+    fn state(&self) -> SpaceInvadersState {
+        SpaceInvadersState::from_memory(&self.emulator.cpu().memory)
+    }
+
+    fn observe(&self, state: SpaceInvadersState) -> Observation {
+        match self.observation {
+            ObservationKind::Screen(renderer) => {
+                let mut image = renderer.buffer();
+                renderer.render(self.emulator.screen(), &mut image, |_, _| WHITE);
+                Observation::Screen(image)
+            }
+            ObservationKind::State => Observation::State(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::video::{Orientation, PixelFormat, HEIGHT, WIDTH};
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    #[test]
+    fn test_episode() {
+        let mut env = Env::new(ROM).with_frame_skip(4);
+        let Observation::State(state) = env.reset().unwrap() else { panic!("expected the game state") };
+        assert!(state.in_game);
+        assert_eq!(state.scores[0], 0);
+
+        // Firing from the start position without moving eventually loses every ship
+        let mut total = 0;
+        let mut steps = 0;
+        loop {
+            let (_, reward, done) = env.step(Action::ALL[steps % 2]).unwrap();
+            total += reward;
+            steps += 1;
+            if done {
+                break;
+            }
+            assert!(steps < 10_000, "the game never ended");
+        }
+        assert!(total > 0);
+        assert_eq!(total as u32, env.score());
+
+        // Every episode starts from the same frame
+        let first = env.reset().unwrap();
+        env.step(Action::Left).unwrap();
+        assert_eq!(env.reset().unwrap(), first);
+        assert_eq!(env.score(), 0);
+    }
+
+    #[test]
+    fn test_screen_observation() {
+        let renderer = Renderer::new(PixelFormat::Indexed, Orientation::Upright);
+        let mut env = Env::new(ROM).with_observation(ObservationKind::Screen(renderer));
+        env.reset().unwrap();
+        let (Observation::Screen(image), ..) = env.step(Action::Noop).unwrap() else {
+            panic!("expected a screen")
+        };
+        assert_eq!(image.len(), (WIDTH * HEIGHT) as usize);
+        assert!(image.contains(&1));
+    }
+}
@@ -1,22 +1,62 @@
-use std::fmt::{Debug, Display, Formatter};
+use core::fmt::{Debug, Display, Formatter};
+use crate::{MemoryMapError, MovieError, SaveStateError};
+use crate::netplay::NetplayError;
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
     UnimplementedOpcode { opcode: u8 },
     InvalidReadPort { port: u8 },
     InvalidWritePort { port: u8 },
+    /// A push or pop left the [`StackGuard`](crate::StackGuard) of the instruction at `pc`.
+    StackFault { sp: u16, pc: u16 },
+    /// The instruction at `pc` wrote to ROM while
+    /// [`RomWritePolicy::Error`](crate::RomWritePolicy::Error) was set.
+    RomWriteViolation { pc: u16, address: u16 },
+    /// The instruction at `pc` accessed `address`, where no memory is mapped.
+    MemoryFault { pc: u16, address: u16 },
+    /// [`Env::reset`](crate::env::Env::reset) inserted a coin and pressed start, but no game
+    /// began within `frames` frames.
+    GameNotStarted { frames: u32 },
+    SaveState(SaveStateError),
+    Movie(MovieError),
+    MemoryMap(MemoryMapError),
+    Netplay(NetplayError),
+    #[cfg(feature = "scripting")]
+    Script(crate::scripting::ScriptError),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
 }
 
 impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::UnimplementedOpcode { opcode } => write!(f, "unimplemented opcode: 0x{:02X}", opcode),
             Self::InvalidWritePort { port } => write!(f, "invalid write port: {}", port),
             Self::InvalidReadPort { port } => write!(f, "invalid read port: {}", port),
+            Self::StackFault { sp, pc } => write!(f, "stack fault at PC ${:04X} with SP ${:04X}", pc, sp),
+            Self::MemoryFault { pc, address } => write!(f, "memory fault at PC ${:04X} accessing ${:04X}", pc, address),
+            Self::RomWriteViolation { pc, address } => write!(f, "ROM write to ${:04X} at PC ${:04X}", address, pc),
+            Self::GameNotStarted { frames } => write!(f, "no game started within {} frames", frames),
+            Self::SaveState(e) => write!(f, "could not load save state: {}", e),
+            Self::Movie(e) => write!(f, "could not load movie: {}", e),
+            Self::MemoryMap(e) => write!(f, "invalid memory map: {}", e),
+            Self::Netplay(e) => write!(f, "netplay error: {}", e),
+            #[cfg(feature = "scripting")]
+            Self::Script(e) => write!(f, "script error: {}", e),
+            #[cfg(feature = "std")]
+            Self::Io(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
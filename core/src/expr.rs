@@ -0,0 +1,340 @@
+//! Small expression language for breakpoint conditions, e.g. `A == 0x20 && [HL] != 0`.
+//!
+//! Operands are numbers (decimal, `0x20` or `$20`), the registers `A`..`L`, `F` (the flags byte
+//! pushed by `PUSH PSW`), `PC`, `SP`, the pairs `BC`, `DE`, `HL`, `addr` (the address the
+//! breakpoint is on), and `[expr]` for the byte in memory at an address. Names are case
+//! insensitive. From loosest to tightest binding, the operators are `||`, `&&`, the comparisons
+//! `== != < <= > >=`, then `+ - & | ^`, which evaluate left to right, and finally unary `!`.
+//! Values are unsigned 16-bit, arithmetic wraps, and anything but 0 is true.
+
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use alloc::string::{String, ToString};
+use alloc::boxed::Box;
+use alloc::format;
+use crate::CPU;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    /// Byte offset into the source the error was found at.
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    F,
+    Pc,
+    Sp,
+    Bc,
+    De,
+    Hl,
+    Addr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Const(u16),
+    Operand(Operand),
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+/// Parsed condition, evaluated against the CPU state before the instruction at a breakpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser { source, pos: 0 };
+        let root = parser.or()?;
+        parser.skip_whitespace();
+        if parser.pos < source.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(Self { source: source.to_string(), root })
+    }
+
+    /// Value of the expression with `addr` standing for `address`.
+    pub fn eval(&self, cpu: &CPU, address: u16) -> u16 {
+        eval(&self.root, cpu, address)
+    }
+
+    pub fn is_true(&self, cpu: &CPU, address: u16) -> bool {
+        self.eval(cpu, address) != 0
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn eval(node: &Node, cpu: &CPU, address: u16) -> u16 {
+    match node {
+        Node::Const(val) => *val,
+        Node::Operand(operand) => {
+            let r = cpu.registers();
+            match operand {
+                Operand::A => r.a as u16,
+                Operand::B => r.b as u16,
+                Operand::C => r.c as u16,
+                Operand::D => r.d as u16,
+                Operand::E => r.e as u16,
+                Operand::H => r.h as u16,
+                Operand::L => r.l as u16,
+                Operand::F => r.flags.to_psw() as u16,
+                Operand::Pc => r.pc,
+                Operand::Sp => r.sp,
+                Operand::Bc => u16::from_be_bytes([r.b, r.c]),
+                Operand::De => u16::from_be_bytes([r.d, r.e]),
+                Operand::Hl => u16::from_be_bytes([r.h, r.l]),
+                Operand::Addr => address,
+            }
+        }
+        Node::Memory(node) => cpu.memory[eval(node, cpu, address)] as u16,
+        Node::Not(node) => (eval(node, cpu, address) == 0) as u16,
+        Node::Binary(BinaryOp::Or, lhs, rhs) => (eval(lhs, cpu, address) != 0 || eval(rhs, cpu, address) != 0) as u16,
+        Node::Binary(BinaryOp::And, lhs, rhs) => (eval(lhs, cpu, address) != 0 && eval(rhs, cpu, address) != 0) as u16,
+        Node::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, cpu, address), eval(rhs, cpu, address));
+            match op {
+                BinaryOp::Eq => (lhs == rhs) as u16,
+                BinaryOp::Ne => (lhs != rhs) as u16,
+                BinaryOp::Lt => (lhs < rhs) as u16,
+                BinaryOp::Le => (lhs <= rhs) as u16,
+                BinaryOp::Gt => (lhs > rhs) as u16,
+                BinaryOp::Ge => (lhs >= rhs) as u16,
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                BinaryOp::BitAnd => lhs & rhs,
+                BinaryOp::BitOr => lhs | rhs,
+                BinaryOp::BitXor => lhs ^ rhs,
+                BinaryOp::Or | BinaryOp::And => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Recursive descent parser, one method per precedence level.
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.and()?;
+        while self.eat("||") {
+            node = Node::Binary(BinaryOp::Or, Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.comparison()?;
+        while self.eat("&&") {
+            node = Node::Binary(BinaryOp::And, Box::new(node), Box::new(self.comparison()?));
+        }
+        Ok(node)
+    }
+
+    fn comparison(&mut self) -> Result<Node, ExprError> {
+        let lhs = self.term()?;
+        // Two-character operators first, so `<=` isn't read as `<`
+        let ops = [
+            ("==", BinaryOp::Eq), ("!=", BinaryOp::Ne), ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge), ("<", BinaryOp::Lt), (">", BinaryOp::Gt),
+        ];
+        match ops.into_iter().find(|(text, _)| self.eat(text)) {
+            Some((_, op)) => Ok(Node::Binary(op, Box::new(lhs), Box::new(self.term()?))),
+            None => Ok(lhs),
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            let rest = &self.source[self.pos..];
+            let op = match rest.chars().next() {
+                Some('+') => BinaryOp::Add,
+                Some('-') => BinaryOp::Sub,
+                Some('^') => BinaryOp::BitXor,
+                Some('&') if !rest.starts_with("&&") => BinaryOp::BitAnd,
+                Some('|') if !rest.starts_with("||") => BinaryOp::BitOr,
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        self.skip_whitespace();
+        if self.source[self.pos..].starts_with("!=") {
+            return Err(self.error("expected a value"));
+        }
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let node = self.or()?;
+            self.expect(")")?;
+            return Ok(node);
+        }
+        if self.eat("[") {
+            let node = self.or()?;
+            self.expect("]")?;
+            return Ok(Node::Memory(Box::new(node)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Node, ExprError> {
+        let start = self.pos;
+        let len = self.source[start..].find(|c: char| !c.is_ascii_alphanumeric() && c != '$' && c != '_')
+            .unwrap_or(self.source.len() - start);
+        let word = &self.source[start..start + len];
+        if word.is_empty() {
+            return Err(self.error("expected a value"));
+        }
+
+        let number = if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix('$')) {
+            Some(u16::from_str_radix(hex, 16))
+        } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+            Some(word.parse())
+        } else {
+            None
+        };
+        let node = match number {
+            Some(Ok(val)) => Node::Const(val),
+            Some(Err(_)) => return Err(self.error(&format!("invalid number {}", word))),
+            None => Node::Operand(match word.to_ascii_lowercase().as_str() {
+                "a" => Operand::A,
+                "b" => Operand::B,
+                "c" => Operand::C,
+                "d" => Operand::D,
+                "e" => Operand::E,
+                "h" => Operand::H,
+                "l" => Operand::L,
+                "f" => Operand::F,
+                "pc" => Operand::Pc,
+                "sp" => Operand::Sp,
+                "bc" => Operand::Bc,
+                "de" => Operand::De,
+                "hl" => Operand::Hl,
+                "addr" => Operand::Addr,
+                _ => return Err(self.error(&format!("unknown name {}", word))),
+            }),
+        };
+        self.pos += len;
+        Ok(node)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.source[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ExprError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", token)))
+        }
+    }
+
+    fn error(&self, message: &str) -> ExprError {
+        ExprError { position: self.pos, message: message.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Registers;
+
+    fn cpu() -> CPU {
+        let mut cpu = CPU::new(&[]);
+        cpu.set_registers(Registers { a: 0x20, h: 0x20, l: 0xF8, ..Registers::default() });
+        cpu.memory.write(0x20F8, 0x05);
+        cpu
+    }
+
+    fn eval(source: &str) -> u16 {
+        Expr::parse(source).unwrap().eval(&cpu(), 0x1A32)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("A == 0x20 && HL == $20F8"), 1);
+        assert_eq!(eval("a == 32 && addr == 0x1A33"), 0);
+        assert_eq!(eval("[hl] + 1"), 6);
+        assert_eq!(eval("[HL - 0x20F8 + $20F8] & 4 != 0"), 1);
+        assert_eq!(eval("!(a == 0) || b"), 1);
+        assert_eq!(eval("b - 1"), 0xFFFF);
+        assert_eq!(eval("1 || 0 && 0"), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Expr::parse("A ==").unwrap_err(), ExprError { position: 4, message: "expected a value".to_string() });
+        assert_eq!(Expr::parse("X == 1").unwrap_err().message, "unknown name X");
+        assert_eq!(Expr::parse("[hl").unwrap_err().message, "expected ]");
+        assert_eq!(Expr::parse("0x10000").unwrap_err().message, "invalid number 0x10000");
+        assert_eq!(Expr::parse("a 1").unwrap_err().position, 2);
+    }
+}
@@ -0,0 +1,67 @@
+use core::fmt::{Display, Formatter};
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Flags: u8 {
+        const SIGN = 1 << 7;
+        const ZERO = 1 << 6;
+        const AUX_CARRY = 1 << 4;
+        const PARITY = 1 << 2;
+        const CARRY = 1 << 0;
+    }
+}
+
+impl Flags {
+    /// Bit 1 of the flags byte always reads as 1 on a real 8080.
+    const PSW_ALWAYS_SET: u8 = 1 << 1;
+
+    /// Packs the flags into the byte stored by `PUSH PSW`.
+    pub fn to_psw(self) -> u8 {
+        self.bits() | Self::PSW_ALWAYS_SET
+    }
+
+    /// Unpacks a byte loaded by `POP PSW`, discarding the fixed bits 1, 3 and 5.
+    pub fn from_psw(psw: u8) -> Self {
+        Self::from_bits_truncate(psw)
+    }
+}
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let names = [
+            (Self::SIGN, 'S'),
+            (Self::ZERO, 'Z'),
+            (Self::AUX_CARRY, 'A'),
+            (Self::PARITY, 'P'),
+            (Self::CARRY, 'C'),
+        ];
+
+        for (i, (flag, name)) in names.into_iter().enumerate() {
+            if i != 0 { write!(f, " ")?; }
+            write!(f, "{}", if self.contains(flag) { name } else { '-' })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_psw_round_trip() {
+        assert_eq!(Flags::empty().to_psw(), 0b0000_0010);
+        assert_eq!(Flags::all().to_psw(), 0b1101_0111);
+        assert_eq!(Flags::from_psw(0xFF), Flags::all());
+        assert_eq!(Flags::from_psw(0b0010_1010), Flags::empty());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Flags::all().to_string(), "S Z A P C");
+        assert_eq!((Flags::ZERO | Flags::CARRY).to_string(), "- Z - - C");
+    }
+}
@@ -0,0 +1,72 @@
+//! Per-frame hashes of video RAM chained over a whole run, so two replays of the same inputs can
+//! be compared frame by frame and the first frame where they diverge pinpointed. Video RAM is
+//! hashed as the game wrote it rather than decoded into a picture, see
+//! [`Emulator::frame_hash`](crate::Emulator::frame_hash).
+
+use crate::checksum::fnv1a64_continue;
+
+/// Running hash over the frame hashes of a run. Two runs have the same chain value after frame N
+/// only if every frame up to N looked the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameHashChain {
+    value: u64,
+    frames: u64,
+}
+
+impl FrameHashChain {
+    pub fn new() -> Self {
+        Self { value: 0, frames: 0 }
+    }
+
+    /// Chains the hash of the next frame, see [`Emulator::frame_hash`](crate::Emulator::frame_hash),
+    /// and returns the new chain value.
+    pub fn push(&mut self, frame_hash: u64) -> u64 {
+        self.value = fnv1a64_continue(self.value, &frame_hash.to_le_bytes());
+        self.frames += 1;
+        self.value
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+impl Default for FrameHashChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Index of the first frame whose hash differs between two runs, or where one of them ends early.
+pub fn first_divergence(expected: &[u64], actual: &[u64]) -> Option<usize> {
+    expected.iter().zip(actual).position(|(a, b)| a != b)
+        .or_else(|| (expected.len() != actual.len()).then(|| expected.len().min(actual.len())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        let mut a = FrameHashChain::new();
+        let mut b = FrameHashChain::new();
+        assert_eq!(a.push(1), b.push(1));
+        assert_ne!(a.push(2), b.push(3));
+        // Once diverged, the chain never matches again
+        assert_ne!(a.push(4), b.push(4));
+        assert_eq!(a.frames(), 3);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        assert_eq!(first_divergence(&[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(first_divergence(&[1, 2, 3], &[1, 5, 3]), Some(1));
+        assert_eq!(first_divergence(&[1, 2, 3], &[1, 2]), Some(2));
+    }
+}
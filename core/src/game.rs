@@ -0,0 +1,201 @@
+//! Typed view of the Space Invaders game state, decoded from RAM.
+//!
+//! Coordinates are in the game's own units: pixels from the left (x) and bottom (y) edges of the
+//! upright screen.
+
+use crate::Memory;
+
+const RAM_RACK_DELTA_X: u16 = 0x2008;
+const RAM_RACK_Y: u16 = 0x2009;
+const RAM_RACK_X: u16 = 0x200A;
+const RAM_PLAYER_ALIVE: u16 = 0x2015;
+const RAM_PLAYER_X: u16 = 0x201B;
+const RAM_PLAYER_SHOT_STATUS: u16 = 0x2025;
+const RAM_PLAYER_SHOT_Y: u16 = 0x2029;
+const RAM_PLAYER_SHOT_X: u16 = 0x202A;
+/// Rolling, plunger and squiggly shots, each a 16-byte object.
+const RAM_ALIEN_SHOTS: [u16; 3] = [0x2030, 0x2040, 0x2050];
+const SHOT_STATUS: u16 = 0x05;
+const SHOT_Y: u16 = 0x0D;
+const SHOT_X: u16 = 0x0E;
+const SHOT_ACTIVE: u8 = 0x80;
+const RAM_CURRENT_PLAYER: u16 = 0x2067;
+const RAM_NUM_ALIENS: u16 = 0x2082;
+const RAM_UFO_ACTIVE: u16 = 0x2084;
+const RAM_UFO_HIT: u16 = 0x2085;
+const RAM_UFO_Y: u16 = 0x2089;
+const RAM_UFO_X: u16 = 0x208A;
+const RAM_UFO_DELTA_X: u16 = 0x208C;
+const RAM_NUM_COINS: u16 = 0x20EB;
+const RAM_GAME_MODE: u16 = 0x20EF;
+const RAM_HIGH_SCORE: u16 = 0x20F4;
+const RAM_SCORES: [u16; 2] = [0x20F8, 0x20FC];
+/// Per-player data pages: alien grid at the start, rack count and ships at the end.
+const RAM_PLAYER_DATA: [u16; 2] = [0x2100, 0x2200];
+const RACK_COUNT: u16 = 0xFE;
+const SHIPS: u16 = 0xFF;
+
+pub const ALIEN_ROWS: usize = 5;
+pub const ALIEN_COLUMNS: usize = 11;
+/// Distance between neighbouring aliens in the rack.
+pub const ALIEN_SPACING: u8 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub x: u8,
+    pub y: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ufo {
+    pub position: Position,
+    /// Moving right.
+    pub rightwards: bool,
+    /// Exploding or showing its score.
+    pub hit: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpaceInvadersState {
+    /// A game is in progress, as opposed to the attract mode.
+    pub in_game: bool,
+    /// Player whose turn it is, 0 or 1.
+    pub current_player: usize,
+    pub player_x: u8,
+    pub player_alive: bool,
+    /// In flight or exploding.
+    pub player_shot: Option<Position>,
+    /// Live aliens of the current player, bit `row * ALIEN_COLUMNS + column` with row 0 at the
+    /// bottom and column 0 on the left.
+    pub aliens: u64,
+    pub num_aliens: u8,
+    /// Position of the bottom-left alien, whether or not it is alive.
+    pub rack: Position,
+    pub rack_rightwards: bool,
+    /// Rolling, plunger and squiggly shots.
+    pub bombs: [Option<Position>; 3],
+    pub ufo: Option<Ufo>,
+    pub scores: [u32; 2],
+    pub high_score: u32,
+    /// Ships left for each player, not counting the one in play.
+    pub ships: [u8; 2],
+    /// Waves cleared by each player, wrapping from 8 back to 1.
+    pub rack_counts: [u8; 2],
+    pub credits: u32,
+}
+
+impl SpaceInvadersState {
+    pub fn from_memory(memory: &Memory) -> Self {
+        let current_player = if memory[RAM_CURRENT_PLAYER] == (RAM_PLAYER_DATA[1] >> 8) as u8 { 1 } else { 0 };
+        let grid = RAM_PLAYER_DATA[current_player];
+        let aliens = (0..(ALIEN_ROWS * ALIEN_COLUMNS) as u16)
+            .filter(|&i| memory[grid + i] != 0)
+            .fold(0, |aliens, i| aliens | 1 << i);
+
+        let position = |y: u16, x: u16| Position { x: memory[x], y: memory[y] };
+        let bombs = RAM_ALIEN_SHOTS.map(|shot| {
+            (memory[shot + SHOT_STATUS] & SHOT_ACTIVE != 0).then(|| position(shot + SHOT_Y, shot + SHOT_X))
+        });
+        let ufo = (memory[RAM_UFO_ACTIVE] != 0).then(|| Ufo {
+            position: position(RAM_UFO_Y, RAM_UFO_X),
+            rightwards: (memory[RAM_UFO_DELTA_X] as i8) > 0,
+            hit: memory[RAM_UFO_HIT] != 0,
+        });
+
+        Self {
+            in_game: memory[RAM_GAME_MODE] & 1 != 0,
+            current_player,
+            player_x: memory[RAM_PLAYER_X],
+            player_alive: memory[RAM_PLAYER_ALIVE] == 0xFF,
+            player_shot: (memory[RAM_PLAYER_SHOT_STATUS] != 0).then(|| position(RAM_PLAYER_SHOT_Y, RAM_PLAYER_SHOT_X)),
+            aliens,
+            num_aliens: memory[RAM_NUM_ALIENS],
+            rack: position(RAM_RACK_Y, RAM_RACK_X),
+            rack_rightwards: (memory[RAM_RACK_DELTA_X] as i8) > 0,
+            bombs,
+            ufo,
+            scores: RAM_SCORES.map(|address| bcd16(memory, address)),
+            high_score: bcd16(memory, RAM_HIGH_SCORE),
+            ships: RAM_PLAYER_DATA.map(|page| memory[page + SHIPS]),
+            rack_counts: RAM_PLAYER_DATA.map(|page| memory[page + RACK_COUNT]),
+            credits: bcd(memory[RAM_NUM_COINS]),
+        }
+    }
+
+    /// Score of the player whose turn it is.
+    pub fn score(&self) -> u32 {
+        self.scores[self.current_player]
+    }
+
+    /// Ships the player whose turn it is has left, not counting the one in play.
+    pub fn lives(&self) -> u8 {
+        self.ships[self.current_player]
+    }
+
+    pub fn alien_alive(&self, row: usize, column: usize) -> bool {
+        row < ALIEN_ROWS && column < ALIEN_COLUMNS && self.aliens & 1 << (row * ALIEN_COLUMNS + column) != 0
+    }
+
+    /// Where the alien at `row` and `column` is, or would be if it was alive.
+    pub fn alien_position(&self, row: usize, column: usize) -> Position {
+        Position {
+            x: self.rack.x.wrapping_add(column as u8 * ALIEN_SPACING),
+            y: self.rack.y.wrapping_add(row as u8 * ALIEN_SPACING),
+        }
+    }
+}
+
+fn bcd(byte: u8) -> u32 {
+    (byte >> 4) as u32 * 10 + (byte & 0x0F) as u32
+}
+
+/// Four BCD digits, least significant byte first.
+fn bcd16(memory: &Memory, address: u16) -> u32 {
+    bcd(memory[address + 1]) * 100 + bcd(memory[address])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_memory() {
+        let mut memory = Memory::new([0; 0x2000]);
+        memory.write(RAM_GAME_MODE, 1);
+        memory.write(RAM_CURRENT_PLAYER, 0x21);
+        memory.write(RAM_PLAYER_ALIVE, 0xFF);
+        memory.write(RAM_PLAYER_X, 0x30);
+        memory.write(RAM_RACK_DELTA_X, 0xFE);
+        memory.write(RAM_RACK_Y, 0x78);
+        memory.write(RAM_RACK_X, 0x20);
+        memory.write(0x2100, 1);
+        memory.write(0x2100 + 12, 1);
+        memory.write(0x2050 + SHOT_STATUS, 0x80);
+        memory.write(0x2050 + SHOT_Y, 0x6E);
+        memory.write(0x2050 + SHOT_X, 0xE5);
+        memory.write(RAM_SCORES[0], 0x70);
+        memory.write(RAM_SCORES[0] + 1, 0x03);
+        memory.write(RAM_HIGH_SCORE + 1, 0x15);
+        memory.write(0x21FF, 2);
+        memory.write(RAM_NUM_COINS, 0x12);
+
+        let state = SpaceInvadersState::from_memory(&memory);
+        assert!(state.in_game && state.player_alive);
+        assert_eq!(state.current_player, 0);
+        assert_eq!(state.player_shot, None);
+        assert_eq!(state.aliens, 1 | 1 << 12);
+        assert!(state.alien_alive(1, 1) && !state.alien_alive(0, 1));
+        assert_eq!(state.alien_position(1, 1), Position { x: 0x30, y: 0x88 });
+        assert!(!state.rack_rightwards);
+        assert_eq!(state.bombs, [None, None, Some(Position { x: 0xE5, y: 0x6E })]);
+        assert_eq!(state.ufo, None);
+        assert_eq!(state.scores, [370, 0]);
+        assert_eq!(state.high_score, 1500);
+        assert_eq!(state.ships, [2, 0]);
+        assert_eq!((state.score(), state.lives()), (370, 2));
+        assert_eq!(state.credits, 12);
+    }
+}
@@ -0,0 +1,293 @@
+//! Known ROM images, used to tell a good dump apart from a bad or unknown one and to pick the
+//! machine profile a ROM runs on.
+
+use core::fmt::{Display, Formatter};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::vec;
+use crate::checksum::crc32;
+use crate::savestate::MACHINE_SPACE_INVADERS;
+use crate::Quirks;
+use crate::machine::{Machine, SpaceInvadersMachine};
+
+/// Size of the ROM area every supported machine maps at 0x0000.
+pub const ROM_SIZE: usize = 0x2000;
+
+/// Hardware variant a game runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MachineKind {
+    SpaceInvaders,
+}
+
+impl MachineKind {
+    /// A fresh driver for the machine, see [`Emulator::with_machine`](crate::Emulator::with_machine).
+    pub fn machine(self) -> Box<dyn Machine> {
+        match self {
+            Self::SpaceInvaders => Box::new(SpaceInvadersMachine::new()),
+        }
+    }
+
+    /// ID stored in save states.
+    pub fn id(self) -> u16 {
+        match self {
+            Self::SpaceInvaders => MACHINE_SPACE_INVADERS,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SpaceInvaders => "Space Invaders (Midway 8080)",
+        }
+    }
+}
+
+/// Colors laid over the monochrome screen by the cabinet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Overlay {
+    /// Midway's cellophane strips: red across the top, green across the bottom.
+    Gel,
+    Monochrome,
+}
+
+/// Settings a set runs with unless the user overrides them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub machine: MachineKind,
+    /// See [`Emulator::dip_switches`](crate::Emulator::dip_switches).
+    pub dip_switches: u8,
+    pub overlay: Overlay,
+    pub quirks: Quirks,
+}
+
+const MIDWAY: Profile = Profile { machine: MachineKind::SpaceInvaders, dip_switches: 0, overlay: Overlay::Gel, quirks: Quirks::ACCURATE };
+const TAITO: Profile = Profile { machine: MachineKind::SpaceInvaders, dip_switches: 0, overlay: Overlay::Monochrome, quirks: Quirks::ACCURATE };
+
+/// A MAME ROM set, recognized by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetInfo {
+    pub name: &'static str,
+    pub title: &'static str,
+    /// `None` for sets that need hardware which isn't emulated.
+    pub profile: Option<Profile>,
+}
+
+pub const SETS: &[SetInfo] = &[
+    SetInfo { name: "invaders", title: "Space Invaders / Space Invaders M", profile: Some(MIDWAY) },
+    SetInfo { name: "sisv", title: "Space Invaders (SV Version rev 4)", profile: Some(TAITO) },
+    SetInfo { name: "sisv3", title: "Space Invaders (SV Version rev 3)", profile: Some(TAITO) },
+    SetInfo { name: "sisv2", title: "Space Invaders (SV Version rev 2)", profile: Some(TAITO) },
+    SetInfo { name: "sitv", title: "Space Invaders (TV Version rev 2)", profile: Some(TAITO) },
+    SetInfo { name: "sitv1", title: "Space Invaders (TV Version rev 1)", profile: Some(TAITO) },
+    SetInfo { name: "invadrmr", title: "Space Invaders (Model Racing)", profile: Some(MIDWAY) },
+    SetInfo { name: "invaderl", title: "Space Invaders (Logitec)", profile: Some(MIDWAY) },
+    SetInfo { name: "spaceatt", title: "Space Attack", profile: Some(MIDWAY) },
+    SetInfo { name: "invadpt2", title: "Space Invaders Part II (Taito)", profile: None },
+    SetInfo { name: "invaddlx", title: "Space Invaders Deluxe", profile: None },
+];
+
+pub fn find_set(name: &str) -> Option<&'static SetInfo> {
+    SETS.iter().find(|set| set.name.eq_ignore_ascii_case(name))
+}
+
+/// Picks the set that names (zip file name, files inside it) most likely refer to.
+/// Directories and extensions are ignored, so `roms/sisv.zip` and `sisv/sv01.36` both give `sisv`.
+pub fn recognize<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<&'static SetInfo> {
+    names.into_iter().find_map(|name| {
+        let file = file_name(name);
+        let stem = file.split('.').next().unwrap_or(file);
+        find_set(stem).or_else(|| name.split(['/', '\\']).next().and_then(find_set))
+    })
+}
+
+/// One ROM chip of a game, as found in MAME-style dumps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomPart {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub crc32: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameInfo {
+    /// MAME set name.
+    pub name: &'static str,
+    pub title: &'static str,
+    pub machine: MachineKind,
+    /// CRC32 of the assembled ROM image.
+    pub crc32: u32,
+    pub parts: &'static [RomPart],
+}
+
+impl GameInfo {
+    pub fn set(&self) -> Option<&'static SetInfo> {
+        find_set(self.name)
+    }
+
+    /// Names of the parts of `rom` that don't match this game, e.g. to report a bad chip dump.
+    pub fn bad_parts(&self, rom: &[u8]) -> Vec<&'static str> {
+        self.parts.iter()
+            .filter(|part| rom.get(part.offset..part.offset + part.size).map(crc32) != Some(part.crc32))
+            .map(|part| part.name)
+            .collect()
+    }
+}
+
+pub const GAMES: &[GameInfo] = &[
+    GameInfo {
+        name: "invaders",
+        title: "Space Invaders",
+        machine: MachineKind::SpaceInvaders,
+        crc32: 0xB64C_A815,
+        parts: &[
+            RomPart { name: "invaders.h", offset: 0x0000, size: 0x800, crc32: 0x734F_5AD8 },
+            RomPart { name: "invaders.g", offset: 0x0800, size: 0x800, crc32: 0x6BFA_CA4A },
+            RomPart { name: "invaders.f", offset: 0x1000, size: 0x800, crc32: 0x0CCE_AD96 },
+            RomPart { name: "invaders.e", offset: 0x1800, size: 0x800, crc32: 0x14E5_38B0 },
+        ],
+    },
+];
+
+/// ROM image assembled from the separate chip dumps of a set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomSet {
+    pub game: &'static GameInfo,
+    pub rom: Vec<u8>,
+    /// Parts whose CRC32 doesn't match the known good dump.
+    pub bad_parts: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomSetError {
+    UnknownSet,
+    MissingParts { game: &'static str, parts: Vec<&'static str> },
+    WrongSize { part: &'static str, expected: usize, found: usize },
+}
+
+impl Display for RomSetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownSet => write!(f, "no known ROM set matches these files"),
+            Self::MissingParts { game, parts } => write!(f, "{} set is missing {}", game, parts.join(", ")),
+            Self::WrongSize { part, expected, found } => write!(f, "{} should be {} bytes, found {}", part, expected, found),
+        }
+    }
+}
+
+/// Places the parts of a split ROM set (e.g. the contents of a MAME zip) at their addresses.
+/// Parts are matched by file name, ignoring case and directories, or else by CRC32.
+/// The set with the most parts present is picked.
+pub fn assemble<'a>(files: &[(&'a str, &'a [u8])]) -> Result<RomSet, RomSetError> {
+    let find = |part: &RomPart| {
+        files.iter()
+            .find(|(name, _)| file_name(name).eq_ignore_ascii_case(part.name))
+            .or_else(|| files.iter().find(|(_, data)| crc32(data) == part.crc32))
+            .map(|&(_, data)| data)
+    };
+
+    let (game, found) = GAMES.iter()
+        .map(|game| (game, game.parts.iter().map(find).collect::<Vec<_>>()))
+        .max_by_key(|(_, found)| found.iter().flatten().count())
+        .filter(|(_, found)| found.iter().any(Option::is_some))
+        .ok_or(RomSetError::UnknownSet)?;
+
+    let missing: Vec<_> = game.parts.iter().zip(&found).filter(|(_, data)| data.is_none()).map(|(part, _)| part.name).collect();
+    if !missing.is_empty() {
+        return Err(RomSetError::MissingParts { game: game.name, parts: missing });
+    }
+
+    let mut rom = vec![0; game.parts.iter().map(|part| part.offset + part.size).max().unwrap_or(0)];
+    let mut bad_parts = Vec::new();
+    for (part, data) in game.parts.iter().zip(found.into_iter().flatten()) {
+        if data.len() != part.size {
+            return Err(RomSetError::WrongSize { part: part.name, expected: part.size, found: data.len() });
+        }
+        if crc32(data) != part.crc32 {
+            bad_parts.push(part.name);
+        }
+        rom[part.offset..part.offset + part.size].copy_from_slice(data);
+    }
+
+    Ok(RomSet { game, rom, bad_parts })
+}
+
+/// The game and part a chip dump's file name belongs to, e.g. `invaders.h`.
+pub fn find_part(path: &str) -> Option<(&'static GameInfo, &'static RomPart)> {
+    GAMES.iter()
+        .flat_map(|game| game.parts.iter().map(move |part| (game, part)))
+        .find(|(_, part)| file_name(path).eq_ignore_ascii_case(part.name))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Looks up an assembled ROM image by its hash.
+pub fn identify(rom: &[u8]) -> Option<&'static GameInfo> {
+    let crc = crc32(rom);
+    GAMES.iter().find(|game| game.crc32 == crc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    #[test]
+    fn test_find_part() {
+        let (game, part) = find_part("roms/INVADERS.G").unwrap();
+        assert_eq!(game.name, "invaders");
+        assert_eq!(part.offset, 0x0800);
+        assert!(find_part("invaders").is_none());
+    }
+
+    #[test]
+    fn test_identify() {
+        let game = identify(ROM).unwrap();
+        assert_eq!(game.name, "invaders");
+        assert_eq!(game.machine, MachineKind::SpaceInvaders);
+        assert!(game.bad_parts(ROM).is_empty());
+        assert!(identify(&ROM[..0x1000]).is_none());
+    }
+
+    #[test]
+    fn test_bad_parts() {
+        let mut rom = ROM.to_vec();
+        rom[0x1234] ^= 0xFF;
+
+        assert!(identify(&rom).is_none());
+        assert_eq!(GAMES[0].bad_parts(&rom), ["invaders.f"]);
+    }
+
+    #[test]
+    fn test_assemble() {
+        let parts = |names: [&'static str; 4]| -> Vec<(&'static str, &'static [u8])> {
+            let chunks = [&ROM[0x0000..0x0800], &ROM[0x0800..0x1000], &ROM[0x1000..0x1800], &ROM[0x1800..0x2000]];
+            names.into_iter().zip(chunks).rev().collect()
+        };
+
+        let set = assemble(&parts(["invaders/INVADERS.H", "invaders.g", "invaders.f", "invaders.e"])).unwrap();
+        assert_eq!(set.game.name, "invaders");
+        assert_eq!(set.rom, ROM);
+        assert!(set.bad_parts.is_empty());
+
+        // Renamed files are still found by their CRC
+        let set = assemble(&parts(["a.bin", "b.bin", "c.bin", "d.bin"])).unwrap();
+        assert_eq!(set.rom, ROM);
+
+        let mut files = parts(["invaders.h", "invaders.g", "invaders.f", "invaders.e"]);
+        files.remove(0);
+        assert_eq!(assemble(&files), Err(RomSetError::MissingParts { game: "invaders", parts: vec!["invaders.e"] }));
+        assert_eq!(assemble(&[("readme.txt", b"hello")]), Err(RomSetError::UnknownSet));
+    }
+
+    #[test]
+    fn test_recognize() {
+        assert_eq!(GAMES[0].set().unwrap().name, "invaders");
+        assert_eq!(recognize(["roms/SISV.zip"]).unwrap().name, "sisv");
+        assert_eq!(recognize(["readme.txt", "invadpt2/pv01"]).unwrap().name, "invadpt2");
+        assert!(recognize(["invadpt2.h"]).unwrap().profile.is_none());
+        assert!(recognize(["roms/galaxian.zip", "7f.bin"]).is_none());
+    }
+}
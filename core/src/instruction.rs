@@ -0,0 +1,357 @@
+//! 8080 instructions decoded from their bytes, shared by [`CPU::step`], the disassembler and
+//! anything else that needs to know what an instruction does.
+//!
+//! Undocumented opcodes decode to the instruction the 8080 actually executes for them, so
+//! `0xCB` is a `JMP`. Whether they run at all is up to the CPU's
+//! [`UndefinedOpcodePolicy`](crate::UndefinedOpcodePolicy).
+//!
+//! [`CPU::step`]: crate::CPU::step
+
+use core::fmt::{Display, Formatter};
+use crate::RegisterPair;
+
+/// 8-bit operand: a register, or `M`, the byte at the address in HL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operand {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    M,
+    A,
+}
+
+/// 16-bit operand of `LXI`, `INX`, `DCX`, `DAD`, `LDAX` and `STAX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pair {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+/// Flag test of a conditional jump, call or return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Condition {
+    NotZero,
+    Zero,
+    NoCarry,
+    Carry,
+    ParityOdd,
+    ParityEven,
+    Plus,
+    Minus,
+}
+
+/// One instruction with its operands. `MOV` and `MVI` take the destination first, like the
+/// assembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Instruction {
+    Nop,
+    Hlt,
+    Out(u8),
+    In(u8),
+    Di,
+    Ei,
+
+    Jmp(u16),
+    JmpIf(Condition, u16),
+    Call(u16),
+    CallIf(Condition, u16),
+    Ret,
+    RetIf(Condition),
+    Rst(u8),
+    Pchl,
+
+    Mov(Operand, Operand),
+    Mvi(Operand, u8),
+    Lxi(Pair, u16),
+    Stax(Pair),
+    Ldax(Pair),
+    Sta(u16),
+    Lda(u16),
+    Shld(u16),
+    Lhld(u16),
+    Push(RegisterPair),
+    Pop(RegisterPair),
+    Xthl,
+    Sphl,
+    Xchg,
+
+    Inr(Operand),
+    Dcr(Operand),
+    Inx(Pair),
+    Dcx(Pair),
+    Dad(Pair),
+    Add(Operand),
+    Adc(Operand),
+    Sub(Operand),
+    Sbb(Operand),
+    Ana(Operand),
+    Xra(Operand),
+    Ora(Operand),
+    Cmp(Operand),
+    Adi(u8),
+    Aci(u8),
+    Sui(u8),
+    Sbi(u8),
+    Ani(u8),
+    Xri(u8),
+    Ori(u8),
+    Cpi(u8),
+    Rlc,
+    Rrc,
+    Ral,
+    Rar,
+    Daa,
+    Stc,
+    Cma,
+    Cmc,
+}
+
+const OPERANDS: [Operand; 8] = [Operand::B, Operand::C, Operand::D, Operand::E, Operand::H, Operand::L, Operand::M, Operand::A];
+const PAIRS: [Pair; 4] = [Pair::BC, Pair::DE, Pair::HL, Pair::SP];
+const STACK_PAIRS: [RegisterPair; 4] = [RegisterPair::BC, RegisterPair::DE, RegisterPair::HL, RegisterPair::PSW];
+const CONDITIONS: [Condition; 8] = [
+    Condition::NotZero, Condition::Zero, Condition::NoCarry, Condition::Carry,
+    Condition::ParityOdd, Condition::ParityEven, Condition::Plus, Condition::Minus,
+];
+const ALU: [fn(Operand) -> Instruction; 8] = [
+    Instruction::Add, Instruction::Adc, Instruction::Sub, Instruction::Sbb,
+    Instruction::Ana, Instruction::Xra, Instruction::Ora, Instruction::Cmp,
+];
+const ALU_IMMEDIATE: [fn(u8) -> Instruction; 8] = [
+    Instruction::Adi, Instruction::Aci, Instruction::Sui, Instruction::Sbi,
+    Instruction::Ani, Instruction::Xri, Instruction::Ori, Instruction::Cpi,
+];
+
+impl Instruction {
+    /// Decodes the opcode in `bytes[0]`, taking whatever operand it has from the bytes after it.
+    #[inline(always)]
+    pub fn decode(bytes: [u8; 3]) -> Self {
+        let [opcode, lo, hi] = bytes;
+        let d16 = u16::from_le_bytes([lo, hi]);
+        // Fields of the opcode, as laid out in the 8080 manual
+        let ddd = OPERANDS[(opcode >> 3 & 7) as usize];
+        let sss = OPERANDS[(opcode & 7) as usize];
+        let rp = PAIRS[(opcode >> 4 & 3) as usize];
+        let condition = CONDITIONS[(opcode >> 3 & 7) as usize];
+
+        match opcode {
+            0x76 => Self::Hlt,
+            0x40..=0x7F => Self::Mov(ddd, sss),
+            0x80..=0xBF => ALU[(opcode >> 3 & 7) as usize](sss),
+
+            0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => Self::Nop,
+            0x02 | 0x12 => Self::Stax(rp),
+            0x0A | 0x1A => Self::Ldax(rp),
+            0x22 => Self::Shld(d16),
+            0x2A => Self::Lhld(d16),
+            0x32 => Self::Sta(d16),
+            0x3A => Self::Lda(d16),
+            0x07 => Self::Rlc,
+            0x0F => Self::Rrc,
+            0x17 => Self::Ral,
+            0x1F => Self::Rar,
+            0x27 => Self::Daa,
+            0x2F => Self::Cma,
+            0x37 => Self::Stc,
+            0x3F => Self::Cmc,
+            _ if opcode < 0x40 => match opcode & 0x0F {
+                0x01 => Self::Lxi(rp, d16),
+                0x03 => Self::Inx(rp),
+                0x09 => Self::Dad(rp),
+                0x0B => Self::Dcx(rp),
+                _ => match opcode & 7 {
+                    4 => Self::Inr(ddd),
+                    5 => Self::Dcr(ddd),
+                    _ => Self::Mvi(ddd, lo),
+                },
+            },
+
+            0xC3 | 0xCB => Self::Jmp(d16),
+            0xC9 | 0xD9 => Self::Ret,
+            0xCD | 0xDD | 0xED | 0xFD => Self::Call(d16),
+            0xD3 => Self::Out(lo),
+            0xDB => Self::In(lo),
+            0xE3 => Self::Xthl,
+            0xE9 => Self::Pchl,
+            0xEB => Self::Xchg,
+            0xF3 => Self::Di,
+            0xF9 => Self::Sphl,
+            0xFB => Self::Ei,
+            _ => match opcode & 7 {
+                0 => Self::RetIf(condition),
+                1 => Self::Pop(STACK_PAIRS[(opcode >> 4 & 3) as usize]),
+                2 => Self::JmpIf(condition, d16),
+                4 => Self::CallIf(condition, d16),
+                5 => Self::Push(STACK_PAIRS[(opcode >> 4 & 3) as usize]),
+                6 => ALU_IMMEDIATE[(opcode >> 3 & 7) as usize](lo),
+                _ => Self::Rst(opcode >> 3 & 7),
+            },
+        }
+    }
+
+    /// Bytes the instruction takes up, opcode included.
+    #[inline]
+    pub fn size(&self) -> u8 {
+        match self {
+            Self::Out(_) | Self::In(_) | Self::Mvi(..) | Self::Adi(_) | Self::Aci(_) | Self::Sui(_)
+            | Self::Sbi(_) | Self::Ani(_) | Self::Xri(_) | Self::Ori(_) | Self::Cpi(_) => 2,
+            Self::Jmp(_) | Self::JmpIf(..) | Self::Call(_) | Self::CallIf(..) | Self::Lxi(..)
+            | Self::Sta(_) | Self::Lda(_) | Self::Shld(_) | Self::Lhld(_) => 3,
+            _ => 1,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            Self::B => "B",
+            Self::C => "C",
+            Self::D => "D",
+            Self::E => "E",
+            Self::H => "H",
+            Self::L => "L",
+            Self::M => "M",
+            Self::A => "A",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for Pair {
+    /// Pairs are named by their high register in the assembly, e.g. `LXI B`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            Self::BC => "B",
+            Self::DE => "D",
+            Self::HL => "H",
+            Self::SP => "SP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let suffix = match self {
+            Self::NotZero => "NZ",
+            Self::Zero => "Z",
+            Self::NoCarry => "NC",
+            Self::Carry => "C",
+            Self::ParityOdd => "PO",
+            Self::ParityEven => "PE",
+            Self::Plus => "P",
+            Self::Minus => "M",
+        };
+        write!(f, "{}", suffix)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let stack_pair = |pair: &RegisterPair| match pair {
+            RegisterPair::BC => "B",
+            RegisterPair::DE => "D",
+            RegisterPair::HL => "H",
+            RegisterPair::PSW => "PSW",
+        };
+
+        match self {
+            Self::Nop => write!(f, "NOP"),
+            Self::Hlt => write!(f, "HLT"),
+            Self::Out(port) => write!(f, "OUT ${:02X}", port),
+            Self::In(port) => write!(f, "IN ${:02X}", port),
+            Self::Di => write!(f, "DI"),
+            Self::Ei => write!(f, "EI"),
+            Self::Jmp(address) => write!(f, "JMP ${:04X}", address),
+            Self::JmpIf(condition, address) => write!(f, "J{} ${:04X}", condition, address),
+            Self::Call(address) => write!(f, "CALL ${:04X}", address),
+            Self::CallIf(condition, address) => write!(f, "C{} ${:04X}", condition, address),
+            Self::Ret => write!(f, "RET"),
+            Self::RetIf(condition) => write!(f, "R{}", condition),
+            Self::Rst(n) => write!(f, "RST {}", n),
+            Self::Pchl => write!(f, "PCHL"),
+            Self::Mov(to, from) => write!(f, "MOV {},{}", to, from),
+            Self::Mvi(to, value) => write!(f, "MVI {},${:02X}", to, value),
+            Self::Lxi(pair, value) => write!(f, "LXI {},${:04X}", pair, value),
+            Self::Stax(pair) => write!(f, "STAX {}", pair),
+            Self::Ldax(pair) => write!(f, "LDAX {}", pair),
+            Self::Sta(address) => write!(f, "STA ${:04X}", address),
+            Self::Lda(address) => write!(f, "LDA ${:04X}", address),
+            Self::Shld(address) => write!(f, "SHLD ${:04X}", address),
+            Self::Lhld(address) => write!(f, "LHLD ${:04X}", address),
+            Self::Push(pair) => write!(f, "PUSH {}", stack_pair(pair)),
+            Self::Pop(pair) => write!(f, "POP {}", stack_pair(pair)),
+            Self::Xthl => write!(f, "XTHL"),
+            Self::Sphl => write!(f, "SPHL"),
+            Self::Xchg => write!(f, "XCHG"),
+            Self::Inr(operand) => write!(f, "INR {}", operand),
+            Self::Dcr(operand) => write!(f, "DCR {}", operand),
+            Self::Inx(pair) => write!(f, "INX {}", pair),
+            Self::Dcx(pair) => write!(f, "DCX {}", pair),
+            Self::Dad(pair) => write!(f, "DAD {}", pair),
+            Self::Add(operand) => write!(f, "ADD {}", operand),
+            Self::Adc(operand) => write!(f, "ADC {}", operand),
+            Self::Sub(operand) => write!(f, "SUB {}", operand),
+            Self::Sbb(operand) => write!(f, "SBB {}", operand),
+            Self::Ana(operand) => write!(f, "ANA {}", operand),
+            Self::Xra(operand) => write!(f, "XRA {}", operand),
+            Self::Ora(operand) => write!(f, "ORA {}", operand),
+            Self::Cmp(operand) => write!(f, "CMP {}", operand),
+            Self::Adi(value) => write!(f, "ADI ${:02X}", value),
+            Self::Aci(value) => write!(f, "ACI ${:02X}", value),
+            Self::Sui(value) => write!(f, "SUI ${:02X}", value),
+            Self::Sbi(value) => write!(f, "SBI ${:02X}", value),
+            Self::Ani(value) => write!(f, "ANI ${:02X}", value),
+            Self::Xri(value) => write!(f, "XRI ${:02X}", value),
+            Self::Ori(value) => write!(f, "ORI ${:02X}", value),
+            Self::Cpi(value) => write!(f, "CPI ${:02X}", value),
+            Self::Rlc => write!(f, "RLC"),
+            Self::Rrc => write!(f, "RRC"),
+            Self::Ral => write!(f, "RAL"),
+            Self::Rar => write!(f, "RAR"),
+            Self::Daa => write!(f, "DAA"),
+            Self::Stc => write!(f, "STC"),
+            Self::Cma => write!(f, "CMA"),
+            Self::Cmc => write!(f, "CMC"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opcodes::OPCODES;
+
+    #[test]
+    fn test_matches_opcode_table() {
+        for opcode in 0..=0xFF {
+            let info = OPCODES[opcode as usize];
+            let instruction = Instruction::decode([opcode, 0x34, 0x12]);
+            let text = info.mnemonic.replace("d16", "$1234").replace("a16", "$1234").replace("d8", "$34");
+
+            assert_eq!(instruction.size(), info.len, "length of {:02X} {}", opcode, info.mnemonic);
+            assert_eq!(instruction.to_string(), text, "text of {:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Instruction::decode([0x7E, 0, 0]), Instruction::Mov(Operand::A, Operand::M));
+        assert_eq!(Instruction::decode([0x36, 0x42, 0]), Instruction::Mvi(Operand::M, 0x42));
+        assert_eq!(Instruction::decode([0x31, 0x00, 0x24]), Instruction::Lxi(Pair::SP, 0x2400));
+        assert_eq!(Instruction::decode([0xF5, 0, 0]), Instruction::Push(RegisterPair::PSW));
+        assert_eq!(Instruction::decode([0xEC, 0x18, 0x1A]), Instruction::CallIf(Condition::ParityEven, 0x1A18));
+        assert_eq!(Instruction::decode([0xDF, 0, 0]), Instruction::Rst(3));
+        assert_eq!(Instruction::decode([0xCB, 0x10, 0x00]), Instruction::Jmp(0x0010));
+    }
+}
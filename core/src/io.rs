@@ -0,0 +1,299 @@
+//! The board's I/O ports, each served by an [`IoDevice`].
+//!
+//! The Space Invaders board decodes the port number of every IN and OUT to one of a handful of
+//! devices: the input ports with the buttons and DIP switches, the external shift register, the
+//! two sound latches and the watchdog. The shift register itself is in [`shifter`](crate::shifter).
+//! The emulator keeps one device per port and direction, and
+//! [`Emulator::attach_device`](crate::Emulator::attach_device) can put custom peripherals on any
+//! of them, replacing what the board had there.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use crate::{Button, EmulatorEvent as Event, Quirks, Sound};
+use crate::shifter::ShiftRegister;
+
+macro_rules! check_sound_events {
+    ( $last_port:expr, $val:expr, $ev:expr, $(($msk:expr,$snd:expr)),* ) => {
+        $(
+            if $val & $msk != 0 && $last_port & $msk == 0 {
+                $ev.push_back(Event::PlaySound($snd));
+            } else if $val & $msk == 0 && $last_port & $msk != 0 {
+                $ev.push_back(Event::StopSound($snd))
+            }
+        )*
+    };
+}
+
+/// Something on the other end of the IN and OUT instructions for some ports.
+pub trait IoDevice: CloneDevice + Debug + Send {
+    /// Value an IN from `port` reads.
+    fn port_in(&mut self, port: u8) -> u8;
+
+    fn port_out(&mut self, port: u8, val: u8);
+
+    /// Takes the oldest event the device raised, e.g. a sound starting. The emulator takes them
+    /// all after every access, so they're queued with the CPU's own in order.
+    fn event(&mut self) -> Option<Event> {
+        None
+    }
+}
+
+/// Lets emulators holding attached devices be cloned, implemented for every `Clone` device.
+pub trait CloneDevice {
+    fn clone_device(&self) -> Box<dyn IoDevice>;
+}
+
+impl<T: IoDevice + Clone + 'static> CloneDevice for T {
+    fn clone_device(&self) -> Box<dyn IoDevice> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn IoDevice> {
+    fn clone(&self) -> Self {
+        self.clone_device()
+    }
+}
+
+/// Input ports 0 to 2: the buttons, the tilt switch and the DIP switches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputPorts {
+    /// Ports 1 and 2. Coin is active-low, everything else active-high.
+    pub(crate) ports: [u8; 2],
+    /// Port 0, unused by the game and only read by the self test.
+    pub(crate) port0: u8,
+}
+
+impl InputPorts {
+    fn new() -> Self {
+        Self { ports: [0x01, 0x00], port0: 0x00 }
+    }
+
+    pub(crate) fn set_quirks(&mut self, quirks: Quirks) {
+        self.port0 = if quirks.port0_unused_high { 0x0E } else { 0x00 };
+    }
+
+    pub(crate) fn set(&mut self, button: Button, held: bool) {
+        let (port, mask) = button.port_bit();
+        let port = &mut self.ports[port as usize - 1];
+        if held != (button == Button::Coin) {
+            *port |= mask;
+        } else {
+            *port &= !mask;
+        }
+    }
+
+    pub(crate) fn held(&self, button: Button) -> bool {
+        let (port, mask) = button.port_bit();
+        (self.ports[port as usize - 1] & mask != 0) != (button == Button::Coin)
+    }
+}
+
+impl IoDevice for InputPorts {
+    fn port_in(&mut self, port: u8) -> u8 {
+        match port {
+            0 => self.port0,
+            1 => self.ports[0],
+            _ => self.ports[1],
+        }
+    }
+
+    fn port_out(&mut self, _port: u8, _val: u8) {}
+}
+
+/// Sound latches on ports 3 and 5, raising [`Event::PlaySound`] and [`Event::StopSound`] when a
+/// sound's bit changes.
+#[derive(Debug, Clone)]
+pub struct SoundLatches {
+    /// Last values written to ports 3 and 5.
+    pub(crate) ports: [u8; 2],
+    events: VecDeque<Event>,
+}
+
+impl SoundLatches {
+    fn new() -> Self {
+        Self { ports: [0; 2], events: VecDeque::new() }
+    }
+
+    pub(crate) fn sound_on(&self, sound: Sound) -> bool {
+        let (port, bit) = sound.port_bit();
+        self.ports[(port == 5) as usize] & bit != 0
+    }
+}
+
+impl IoDevice for SoundLatches {
+    fn port_in(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn port_out(&mut self, port: u8, val: u8) {
+        let latch = &mut self.ports[(port == 5) as usize];
+        if port == 3 {
+            check_sound_events!(*latch, val, self.events,
+                (0x01, Sound::UFO),
+                (0x02, Sound::Shoot),
+                (0x04, Sound::PlayerDie),
+                (0x08, Sound::InvaderDie)
+            );
+        } else {
+            check_sound_events!(*latch, val, self.events,
+                (0x01, Sound::Bomp1),
+                (0x02, Sound::Bomp2),
+                (0x04, Sound::Bomp3),
+                (0x08, Sound::Bomp4),
+                (0x10, Sound::UFOExplode)
+            );
+        }
+        *latch = val;
+    }
+
+    fn event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+}
+
+/// Frames the game may go without writing to port 6 before the watchdog resets the board, about
+/// 4.3 seconds.
+pub const WATCHDOG_FRAMES: u32 = 255;
+
+/// Watchdog on port 6, which the game writes to every frame. The written value shows up as
+/// [`Event::Debug`]. It's off unless enabled, see
+/// [`Emulator::set_watchdog`](crate::Emulator::set_watchdog).
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    /// Frames since the last write.
+    pub(crate) frames: u32,
+    pub(crate) enabled: bool,
+    events: VecDeque<Event>,
+}
+
+impl Watchdog {
+    fn new() -> Self {
+        Self { frames: 0, enabled: false, events: VecDeque::new() }
+    }
+
+    /// Counts a frame, returning whether the game has now gone [`WATCHDOG_FRAMES`] of them
+    /// without a write and the board resets.
+    pub(crate) fn tick(&mut self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.frames += 1;
+        self.frames >= WATCHDOG_FRAMES
+    }
+}
+
+impl IoDevice for Watchdog {
+    fn port_in(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn port_out(&mut self, _port: u8, val: u8) {
+        self.frames = 0;
+        self.events.push_back(Event::Debug(val));
+    }
+
+    fn event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+}
+
+/// Device serving a port in one direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Inputs,
+    Shifter,
+    Sound,
+    Watchdog,
+    /// Index into [`IoBus::attached`].
+    Attached(usize),
+}
+
+/// The devices of the board and the port map routing every IN and OUT to one of them.
+#[derive(Debug, Clone)]
+pub(crate) struct IoBus {
+    pub(crate) inputs: InputPorts,
+    pub(crate) shifter: ShiftRegister,
+    pub(crate) sound: SoundLatches,
+    pub(crate) watchdog: Watchdog,
+    attached: Vec<Box<dyn IoDevice>>,
+    reads: [Option<Slot>; 256],
+    writes: [Option<Slot>; 256],
+}
+
+impl IoBus {
+    /// The Space Invaders port map.
+    pub(crate) fn new(quirks: Quirks) -> Self {
+        let mut reads = [None; 256];
+        let mut writes = [None; 256];
+        reads[0..=2].fill(Some(Slot::Inputs));
+        reads[3] = Some(Slot::Shifter);
+        writes[2] = Some(Slot::Shifter);
+        writes[4] = Some(Slot::Shifter);
+        writes[3] = Some(Slot::Sound);
+        writes[5] = Some(Slot::Sound);
+        writes[6] = Some(Slot::Watchdog);
+
+        let mut bus = Self {
+            inputs: InputPorts::new(),
+            shifter: ShiftRegister::new(),
+            sound: SoundLatches::new(),
+            watchdog: Watchdog::new(),
+            attached: Vec::new(),
+            reads,
+            writes,
+        };
+        bus.set_quirks(quirks);
+        bus
+    }
+
+    pub(crate) fn set_quirks(&mut self, quirks: Quirks) {
+        self.inputs.set_quirks(quirks);
+        self.shifter.set_quirks(quirks);
+    }
+
+    /// Routes INs from `reads` and OUTs to `writes` to `device` from now on.
+    pub(crate) fn attach(&mut self, device: Box<dyn IoDevice>, reads: &[u8], writes: &[u8]) {
+        let slot = Some(Slot::Attached(self.attached.len()));
+        self.attached.push(device);
+        for &port in reads {
+            self.reads[port as usize] = slot;
+        }
+        for &port in writes {
+            self.writes[port as usize] = slot;
+        }
+    }
+
+    /// Value an IN from `port` reads, or `None` if nothing serves it.
+    pub(crate) fn read(&mut self, port: u8) -> Option<u8> {
+        let slot = self.reads[port as usize]?;
+        Some(self.device(slot).port_in(port))
+    }
+
+    /// Hands an OUT to the device serving `port`, returning `false` if there's none.
+    pub(crate) fn write(&mut self, port: u8, val: u8) -> bool {
+        let Some(slot) = self.writes[port as usize] else { return false };
+        self.device(slot).port_out(port, val);
+        true
+    }
+
+    /// Moves the events every device raised to `events`.
+    pub(crate) fn take_events(&mut self, events: &mut VecDeque<Event>) {
+        let devices = [&mut self.inputs as &mut dyn IoDevice, &mut self.shifter, &mut self.sound, &mut self.watchdog];
+        for device in devices.into_iter().chain(self.attached.iter_mut().map(|device| &mut **device)) {
+            events.extend(core::iter::from_fn(|| device.event()));
+        }
+    }
+
+    fn device(&mut self, slot: Slot) -> &mut dyn IoDevice {
+        match slot {
+            Slot::Inputs => &mut self.inputs,
+            Slot::Shifter => &mut self.shifter,
+            Slot::Sound => &mut self.sound,
+            Slot::Watchdog => &mut self.watchdog,
+            Slot::Attached(index) => &mut *self.attached[index],
+        }
+    }
+}
@@ -0,0 +1,642 @@
+//! Translates blocks of 8080 code to native code with Cranelift, see [`Backend::Jit`].
+//!
+//! A block runs from an address up to the first jump, call, return, RST or EI, and stops
+//! short of IN, OUT and HLT, which the emulator has to handle, and of undocumented opcodes,
+//! which follow the CPU's [`UndefinedOpcodePolicy`]. Moves, loads and stores are translated to
+//! native code; every other instruction calls the interpreter's handler for its opcode, so the
+//! flags and timing come out exactly the same on both backends. A block is only translated
+//! once execution has reached it [`HOT_THRESHOLD`] times, so code that runs once isn't.
+//!
+//! Memory records writes to the RAM bytes of translated blocks. A block ends right after an
+//! instruction that wrote to translated code, and every block covering the written byte is
+//! thrown away. Code that modified itself once likely will again, so those addresses are left
+//! to the interpreter from then on.
+//!
+//! [`Backend::Jit`]: crate::Backend::Jit
+//! [`UndefinedOpcodePolicy`]: crate::UndefinedOpcodePolicy
+
+use std::fmt::{Debug, Formatter};
+use std::mem;
+
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, SigRef, Signature, Type, Value};
+use cranelift_codegen::isa::OwnedTargetIsa;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::cpu::JIT_DISPATCH;
+use crate::{opcodes, Instruction, Memory, Operand, Pair, CPU};
+
+/// Times execution has to reach an address before the block there is translated.
+const HOT_THRESHOLD: u16 = 16;
+const MAX_BLOCK_INSTRUCTIONS: u32 = 32;
+/// Blocks translated before all generated code is thrown away and translation starts over.
+/// Cranelift can't free single functions, so this bounds the memory used by blocks that were
+/// invalidated or flushed.
+const MAX_TRANSLATED: usize = 0x4000;
+
+/// Runs a block, returning the instructions executed in the high half and T-states in the low.
+type BlockFn = unsafe extern "C" fn(*mut CPU) -> u64;
+
+#[derive(Clone, Copy)]
+enum Entry {
+    /// Not translated yet, reached this many times.
+    Cold(u16),
+    Translated { run: BlockFn, len: u16 },
+    /// Left to the interpreter, either because the instruction there can't be translated or
+    /// because a block there was overwritten.
+    Interpreted,
+}
+
+pub(crate) struct Jit {
+    isa: OwnedTargetIsa,
+    /// Created with the first block.
+    module: Option<JITModule>,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+    /// What to do at every address, empty until the first block runs.
+    entries: Vec<Entry>,
+    /// Addresses of the blocks in `entries` that are translated.
+    translated: Vec<u16>,
+    /// Blocks translated by `module`, including the ones thrown away since.
+    module_blocks: usize,
+    threshold: u16,
+}
+
+impl Jit {
+    /// `None` if Cranelift can't generate code for this machine.
+    pub fn new() -> Option<Self> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+        let isa = cranelift_native::builder().ok()?.finish(settings::Flags::new(flags)).ok()?;
+        Some(Self::with_isa(isa))
+    }
+
+    fn with_isa(isa: OwnedTargetIsa) -> Self {
+        Self {
+            isa,
+            module: None,
+            context: Context::new(),
+            builder_context: FunctionBuilderContext::new(),
+            entries: Vec::new(),
+            translated: Vec::new(),
+            module_blocks: 0,
+            threshold: HOT_THRESHOLD,
+        }
+    }
+
+    /// Runs the block at PC, translating it first if it has become hot. Returns how many
+    /// instructions ran and the T-states they took, or `None` if the instruction at PC is left
+    /// to the interpreter this time. `cpu` must be [`CPU::jit_ready`].
+    pub fn run(&mut self, cpu: &mut CPU) -> Option<(u32, u32)> {
+        if self.entries.is_empty() {
+            self.entries = vec![Entry::Cold(0); 0x10000];
+        }
+        let pc = cpu.pc();
+        let run = match self.entries[pc as usize] {
+            Entry::Translated { run, .. } => run,
+            Entry::Interpreted => return None,
+            Entry::Cold(count) if count < self.threshold => {
+                self.entries[pc as usize] = Entry::Cold(count + 1);
+                return None;
+            }
+            Entry::Cold(_) => self.translate(&mut cpu.memory, pc)?,
+        };
+
+        // SAFETY: blocks only touch the CPU through its register offsets and handlers, and the
+        // module holding their code lives as long as `self`.
+        let result = unsafe { run(cpu) };
+        if cpu.memory.code_written() {
+            self.invalidate(&mut cpu.memory);
+        }
+        Some(((result >> 32) as u32, result as u32))
+    }
+
+    /// Throws away the blocks in RAM, for when it was replaced as a whole.
+    pub fn flush_ram(&mut self, memory: &mut Memory) {
+        let entries = &mut self.entries;
+        self.translated.retain(|&start| {
+            let in_ram = block_addresses(entries[start as usize], start).any(|address| memory.ram_offset(address).is_some());
+            if in_ram {
+                entries[start as usize] = Entry::Cold(0);
+            }
+            !in_ram
+        });
+        memory.clear_code();
+    }
+
+    /// Throws away every block and the code generated for them.
+    pub fn clear(&mut self, memory: &mut Memory) {
+        self.free_module();
+        self.entries.fill(Entry::Cold(0));
+        self.translated.clear();
+        memory.clear_code();
+    }
+
+    fn free_module(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: the entries pointing into the module are dropped or overwritten with it.
+            unsafe { module.free_memory() };
+        }
+        self.module_blocks = 0;
+    }
+
+    /// Throws away the blocks covering translated bytes that were written and leaves their
+    /// addresses to the interpreter.
+    fn invalidate(&mut self, memory: &mut Memory) {
+        let writes = memory.take_code_writes();
+        let entries = &mut self.entries;
+        self.translated.retain(|&start| {
+            let written = block_addresses(entries[start as usize], start)
+                .filter_map(|address| memory.ram_offset(address))
+                .any(|offset| writes.contains(&(offset as u16)));
+            if written {
+                entries[start as usize] = Entry::Interpreted;
+            }
+            !written
+        });
+    }
+
+    fn translate(&mut self, memory: &mut Memory, start: u16) -> Option<BlockFn> {
+        if self.module_blocks >= MAX_TRANSLATED {
+            self.clear(memory);
+        }
+        match self.compile(memory, start) {
+            Some((run, len)) => {
+                self.entries[start as usize] = Entry::Translated { run, len };
+                self.translated.push(start);
+                for i in 0..len {
+                    memory.mark_code(start.wrapping_add(i));
+                }
+                Some(run)
+            }
+            None => {
+                self.entries[start as usize] = Entry::Interpreted;
+                None
+            }
+        }
+    }
+
+    /// Generates the code of the block at `start`, returning it and the block's length in bytes.
+    fn compile(&mut self, memory: &Memory, start: u16) -> Option<(BlockFn, u16)> {
+        if !translatable(memory, start) {
+            return None;
+        }
+        let isa = &self.isa;
+        let module = self.module.get_or_insert_with(|| JITModule::new(JITBuilder::with_isa(isa.clone(), default_libcall_names())));
+        let pointer = module.target_config().pointer_type();
+
+        self.context.func.signature = signature(module, &[pointer], types::I64);
+        let handler = signature(module, &[pointer], types::I64);
+        let read = signature(module, &[pointer, types::I32], types::I32);
+        let write = signature(module, &[pointer, types::I32, types::I32], types::I32);
+
+        let mut builder = FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let cpu = builder.block_params(entry)[0];
+        let cycles = builder.ins().iconst(types::I64, 0);
+        let mut translator = Translator {
+            handler: builder.import_signature(handler),
+            read: builder.import_signature(read),
+            write: builder.import_signature(write),
+            builder,
+            pointer,
+            cpu,
+            cycles,
+            instructions: 0,
+        };
+        let end = translator.translate(memory, start);
+        translator.builder.finalize();
+
+        let id = module.declare_anonymous_function(&self.context.func.signature).ok();
+        let defined = id.is_some_and(|id| module.define_function(id, &mut self.context).is_ok());
+        module.clear_context(&mut self.context);
+        let id = id.filter(|_| defined)?;
+        module.finalize_definitions().ok()?;
+        self.module_blocks += 1;
+
+        // SAFETY: the function was declared with the signature of `BlockFn`.
+        let run = unsafe { mem::transmute::<*const u8, BlockFn>(module.get_finalized_function(id)) };
+        Some((run, end.wrapping_sub(start)))
+    }
+}
+
+impl Clone for Jit {
+    /// Starts with no blocks translated, since the generated code can't be shared.
+    fn clone(&self) -> Self {
+        let mut jit = Self::with_isa(self.isa.clone());
+        jit.threshold = self.threshold;
+        jit
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        self.free_module();
+    }
+}
+
+impl Debug for Jit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jit").field("translated", &self.translated.len()).finish_non_exhaustive()
+    }
+}
+
+/// Generates the code of one block.
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    pointer: Type,
+    /// Pointer to the CPU, the block's only parameter.
+    cpu: Value,
+    /// T-states taken so far.
+    cycles: Value,
+    instructions: u32,
+    handler: SigRef,
+    read: SigRef,
+    write: SigRef,
+}
+
+impl Translator<'_> {
+    /// Translates the block at `start` and returns the address just past it.
+    fn translate(&mut self, memory: &Memory, start: u16) -> u16 {
+        let mut address = start;
+        while self.instructions < MAX_BLOCK_INSTRUCTIONS && translatable(memory, address) {
+            let opcode = memory[address];
+            let instruction = Instruction::decode([0, 1, 2].map(|i| memory[address.wrapping_add(i)]));
+            let next = address.wrapping_add(instruction.size() as u16);
+            self.instructions += 1;
+
+            let written = match instruction {
+                Instruction::Jmp(target) => {
+                    self.add_cycles(opcode);
+                    self.exit(Some(target));
+                    return next;
+                }
+                Instruction::Nop => None,
+                Instruction::Mov(to, from) => {
+                    let value = self.operand(from);
+                    self.set_operand(to, value)
+                }
+                Instruction::Mvi(to, value) => {
+                    let value = self.builder.ins().iconst(types::I32, value as i64);
+                    self.set_operand(to, value)
+                }
+                Instruction::Lxi(pair, value) => {
+                    let value = self.builder.ins().iconst(types::I32, value as i64);
+                    self.set_pair(pair, value);
+                    None
+                }
+                Instruction::Inx(pair) | Instruction::Dcx(pair) => {
+                    let delta = if matches!(instruction, Instruction::Inx(_)) { 1 } else { -1 };
+                    let value = self.pair(pair);
+                    let value = self.builder.ins().iadd_imm(value, delta);
+                    self.set_pair(pair, value);
+                    None
+                }
+                Instruction::Xchg => {
+                    let (de, hl) = (self.pair(Pair::DE), self.pair(Pair::HL));
+                    self.set_pair(Pair::DE, hl);
+                    self.set_pair(Pair::HL, de);
+                    None
+                }
+                Instruction::Sphl => {
+                    let hl = self.pair(Pair::HL);
+                    self.set_pair(Pair::SP, hl);
+                    None
+                }
+                Instruction::Lda(adr) => {
+                    let adr = self.builder.ins().iconst(types::I32, adr as i64);
+                    let value = self.read(adr);
+                    self.set_operand(Operand::A, value)
+                }
+                Instruction::Sta(adr) => {
+                    let adr = self.builder.ins().iconst(types::I32, adr as i64);
+                    let a = self.operand(Operand::A);
+                    Some(self.write(adr, a))
+                }
+                Instruction::Ldax(pair) => {
+                    let adr = self.pair(pair);
+                    let value = self.read(adr);
+                    self.set_operand(Operand::A, value)
+                }
+                Instruction::Stax(pair) => {
+                    let adr = self.pair(pair);
+                    let a = self.operand(Operand::A);
+                    Some(self.write(adr, a))
+                }
+                Instruction::Lhld(adr) => {
+                    let lo = self.builder.ins().iconst(types::I32, adr as i64);
+                    let hi = self.builder.ins().iconst(types::I32, adr.wrapping_add(1) as i64);
+                    let (lo, hi) = (self.read(lo), self.read(hi));
+                    self.set_operand(Operand::L, lo);
+                    self.set_operand(Operand::H, hi);
+                    None
+                }
+                Instruction::Shld(adr) => {
+                    let lo = self.builder.ins().iconst(types::I32, adr as i64);
+                    let hi = self.builder.ins().iconst(types::I32, adr.wrapping_add(1) as i64);
+                    let (l, h) = (self.operand(Operand::L), self.operand(Operand::H));
+                    let lo = self.write(lo, l);
+                    let hi = self.write(hi, h);
+                    Some(self.builder.ins().bor(lo, hi))
+                }
+                _ => {
+                    let written = self.call_handler(address, opcode);
+                    if ends_block(instruction) {
+                        self.exit(None);
+                        return next;
+                    }
+                    Some(written)
+                }
+            };
+            if translated_directly(instruction) {
+                self.add_cycles(opcode);
+            }
+            if let Some(written) = written {
+                self.exit_if(written, next);
+            }
+            address = next;
+        }
+        self.exit(Some(address));
+        address
+    }
+
+    fn add_cycles(&mut self, opcode: u8) {
+        let cycles = opcodes::OPCODES[opcode as usize].cycles;
+        self.cycles = self.builder.ins().iadd_imm(self.cycles, cycles as i64);
+    }
+
+    /// Calls the interpreter's handler for the instruction at `address` and returns whether it
+    /// wrote to translated code.
+    fn call_handler(&mut self, address: u16, opcode: u8) -> Value {
+        self.set_pc(address.wrapping_add(1));
+        let handler = JIT_DISPATCH[opcode as usize];
+        let result = self.call(self.handler, handler as *const u8, &[self.cpu]);
+        let cycles = self.builder.ins().band_imm(result, 0xFFFF_FFFF);
+        self.cycles = self.builder.ins().iadd(self.cycles, cycles);
+        let written = self.builder.ins().ushr_imm(result, 32);
+        self.builder.ins().ireduce(types::I32, written)
+    }
+
+    /// Returns from the block if `written` is set, with PC at `next`.
+    fn exit_if(&mut self, written: Value, next: u16) {
+        let exit = self.builder.create_block();
+        let resume = self.builder.create_block();
+        self.builder.ins().brif(written, exit, &[], resume, &[]);
+        self.builder.seal_block(exit);
+        self.builder.seal_block(resume);
+
+        self.builder.switch_to_block(exit);
+        self.exit(Some(next));
+        self.builder.switch_to_block(resume);
+    }
+
+    /// Returns from the block, first setting PC to `pc` unless a handler already did.
+    fn exit(&mut self, pc: Option<u16>) {
+        if let Some(pc) = pc {
+            self.set_pc(pc);
+        }
+        let result = self.builder.ins().bor_imm(self.cycles, (self.instructions as i64) << 32);
+        self.builder.ins().return_(&[result]);
+    }
+
+    fn set_pc(&mut self, pc: u16) {
+        let pc = self.builder.ins().iconst(types::I32, pc as i64);
+        self.builder.ins().istore16(MemFlags::trusted(), pc, self.cpu, CPU::PC_OFFSET as i32);
+    }
+
+    /// Value of a register or M, zero-extended to 32 bits.
+    fn operand(&mut self, operand: Operand) -> Value {
+        match CPU::register_offset(operand) {
+            Some(offset) => self.builder.ins().uload8(types::I32, MemFlags::trusted(), self.cpu, offset as i32),
+            None => {
+                let hl = self.pair(Pair::HL);
+                self.read(hl)
+            }
+        }
+    }
+
+    /// Stores the low byte of `value` in a register, or at HL for M, returning whether a write
+    /// to M hit translated code.
+    fn set_operand(&mut self, operand: Operand, value: Value) -> Option<Value> {
+        match CPU::register_offset(operand) {
+            Some(offset) => {
+                self.builder.ins().istore8(MemFlags::trusted(), value, self.cpu, offset as i32);
+                None
+            }
+            None => {
+                let hl = self.pair(Pair::HL);
+                Some(self.write(hl, value))
+            }
+        }
+    }
+
+    /// Value of a register pair, zero-extended to 32 bits.
+    fn pair(&mut self, pair: Pair) -> Value {
+        match pair_operands(pair) {
+            Some((hi, lo)) => {
+                let (hi, lo) = (self.operand(hi), self.operand(lo));
+                let hi = self.builder.ins().ishl_imm(hi, 8);
+                self.builder.ins().bor(hi, lo)
+            }
+            None => self.builder.ins().uload16(types::I32, MemFlags::trusted(), self.cpu, CPU::SP_OFFSET as i32),
+        }
+    }
+
+    /// Stores the low 16 bits of `value` in a register pair.
+    fn set_pair(&mut self, pair: Pair, value: Value) {
+        match pair_operands(pair) {
+            Some((hi, lo)) => {
+                let high_byte = self.builder.ins().ushr_imm(value, 8);
+                self.set_operand(hi, high_byte);
+                self.set_operand(lo, value);
+            }
+            None => {
+                self.builder.ins().istore16(MemFlags::trusted(), value, self.cpu, CPU::SP_OFFSET as i32);
+            }
+        }
+    }
+
+    fn read(&mut self, address: Value) -> Value {
+        self.call(self.read, read as *const u8, &[self.cpu, address])
+    }
+
+    /// Writes the low byte of `value` and returns whether it hit translated code.
+    fn write(&mut self, address: Value, value: Value) -> Value {
+        self.call(self.write, write as *const u8, &[self.cpu, address, value])
+    }
+
+    fn call(&mut self, signature: SigRef, function: *const u8, args: &[Value]) -> Value {
+        let callee = self.builder.ins().iconst(self.pointer, function as i64);
+        let call = self.builder.ins().call_indirect(signature, callee, args);
+        self.builder.inst_results(call)[0]
+    }
+}
+
+/// Memory read from translated code.
+unsafe extern "C" fn read(cpu: *mut CPU, address: u32) -> u32 {
+    let memory = &(*cpu).memory;
+    memory[address as u16] as u32
+}
+
+/// Memory write from translated code, returning whether it hit translated code.
+unsafe extern "C" fn write(cpu: *mut CPU, address: u32, value: u32) -> u32 {
+    let memory = &mut (*cpu).memory;
+    memory.write(address as u16, value as u8);
+    memory.code_written() as u32
+}
+
+fn signature(module: &JITModule, params: &[Type], returns: Type) -> Signature {
+    let mut signature = module.make_signature();
+    signature.params.extend(params.iter().map(|&param| AbiParam::new(param)));
+    signature.returns.push(AbiParam::new(returns));
+    signature
+}
+
+/// Whether the instruction at `address` can be part of a block.
+fn translatable(memory: &Memory, address: u16) -> bool {
+    let opcode = memory[address];
+    !opcodes::undocumented(opcode) && !matches!(Instruction::decode([opcode, 0, 0]), Instruction::Hlt | Instruction::In(_) | Instruction::Out(_))
+}
+
+/// Whether `instruction` is one of the ones generated directly instead of calling its handler.
+fn translated_directly(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Nop | Instruction::Mov(..) | Instruction::Mvi(..) | Instruction::Lxi(..) | Instruction::Inx(_)
+            | Instruction::Dcx(_) | Instruction::Xchg | Instruction::Sphl | Instruction::Lda(_) | Instruction::Sta(_)
+            | Instruction::Ldax(_) | Instruction::Stax(_) | Instruction::Lhld(_) | Instruction::Shld(_)
+    )
+}
+
+/// Whether `instruction` may change PC or enable interrupts, which ends a block.
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Jmp(_) | Instruction::JmpIf(..) | Instruction::Call(_) | Instruction::CallIf(..) | Instruction::Ret
+            | Instruction::RetIf(_) | Instruction::Rst(_) | Instruction::Pchl | Instruction::Ei
+    )
+}
+
+/// High and low register of a pair, `None` for SP.
+fn pair_operands(pair: Pair) -> Option<(Operand, Operand)> {
+    match pair {
+        Pair::BC => Some((Operand::B, Operand::C)),
+        Pair::DE => Some((Operand::D, Operand::E)),
+        Pair::HL => Some((Operand::H, Operand::L)),
+        Pair::SP => None,
+    }
+}
+
+/// Addresses of the bytes of the block `entry` at `start`, none if it isn't translated.
+fn block_addresses(entry: Entry, start: u16) -> impl Iterator<Item = u16> {
+    let len = match entry {
+        Entry::Translated { len, .. } => len,
+        _ => 0,
+    };
+    (0..len).map(move |i| start.wrapping_add(i))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Backend, Emulator, Registers};
+
+    fn jit() -> Jit {
+        let mut jit = Jit::new().expect("no native code generation for this machine");
+        jit.threshold = 0;
+        jit
+    }
+
+    /// Runs `jit` side by side with the interpreter from `cpu`, stepping the interpreter over as
+    /// many instructions as every block ran, and checks that both end up in the same state.
+    fn run_side_by_side(jit: &mut Jit, cpu: CPU, blocks: usize) -> CPU {
+        let (mut translated, mut interpreted) = (cpu.clone(), cpu);
+        for _ in 0..blocks {
+            let block = if translated.jit_ready() { jit.run(&mut translated) } else { None };
+            let (instructions, cycles) = block.unwrap_or_else(|| (1, translated.step().unwrap()));
+            let expected: u32 = (0..instructions).map(|_| interpreted.step().unwrap()).sum();
+
+            assert_eq!(cycles, expected);
+            assert_eq!(translated.registers(), interpreted.registers());
+        }
+        assert_eq!(translated.memory.ram(), interpreted.memory.ram());
+        translated
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        // Random code in flat RAM, without HLT so the CPU never stops for good
+        let mut seed = 0x2545_F491_4F6C_DD1D_u64;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        };
+        let mut cpu = CPU::with_memory(Memory::flat());
+        for address in 0..=0xFFFF {
+            let byte = match random() {
+                0x76 => 0x00,
+                byte => byte,
+            };
+            cpu.memory.write(address, byte);
+        }
+        let [pc_hi, pc_lo, sp_hi, sp_lo, a, b, c, d, e, h, l] = [(); 11].map(|_| random());
+        cpu.set_registers(Registers {
+            pc: u16::from_be_bytes([pc_hi, pc_lo]),
+            sp: u16::from_be_bytes([sp_hi, sp_lo]),
+            a, b, c, d, e, h, l,
+            flags: Default::default(),
+        });
+
+        run_side_by_side(&mut jit(), cpu, 20_000);
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // 0100: MVI B,$00; LXI H,$0107; INR M; MVI A,$00; JMP $0100
+        let mut cpu = CPU::with_memory(Memory::flat());
+        for (i, byte) in [0x06, 0x00, 0x21, 0x07, 0x01, 0x34, 0x3E, 0x00, 0xC3, 0x00, 0x01].into_iter().enumerate() {
+            cpu.memory.write(0x0100 + i as u16, byte);
+        }
+        cpu.set_pc(0x0100);
+
+        let mut jit = jit();
+        let cpu = run_side_by_side(&mut jit, cpu, 100);
+        // INR M patches the operand of MVI A, so the block stops after it and is left to the
+        // interpreter, which picks up every new operand
+        assert!(matches!(jit.entries[0x0100], Entry::Interpreted));
+        assert!(cpu.memory[0x0107] > 10);
+        assert_eq!(cpu.registers().a, cpu.memory[0x0107]);
+    }
+
+    #[test]
+    fn test_emulator_backend() {
+        const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+        let mut emulator = Emulator::new(ROM).with_backend(Backend::Jit);
+        assert_eq!(emulator.backend(), Backend::Jit);
+
+        let mut steps = 0;
+        while emulator.stats().cycles < 2_000_000 {
+            emulator.step().unwrap();
+            steps += 1;
+        }
+        assert!(emulator.stats().instructions > 2 * steps);
+
+        // Breakpoints need every instruction interpreted
+        emulator.add_breakpoint(0x0000);
+        let instructions = emulator.stats().instructions;
+        emulator.step().unwrap();
+        assert_eq!(emulator.stats().instructions, instructions + 1);
+
+        let emulator = emulator.with_backend(Backend::Interpreter);
+        assert_eq!(emulator.backend(), Backend::Interpreter);
+    }
+}
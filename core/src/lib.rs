@@ -1,44 +1,78 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod cpu;
+mod flags;
+mod buttons;
 mod memory;
 mod error;
 mod macros;
 mod emulator;
+mod stats;
+mod checksum;
+mod base64;
+mod pc_history;
+mod call_stack;
+mod frame_hash;
+mod opcodes;
+pub mod savestate;
+pub mod games;
+pub mod conditions;
+pub mod achievements;
+pub mod cheats;
+pub mod game;
+pub mod batch;
+pub mod env;
+pub mod netplay;
+pub mod movie;
+pub mod rewind;
+pub mod raster;
+pub mod video;
+pub mod io;
+pub mod machine;
+pub mod shifter;
+pub mod quirks;
+pub mod disasm;
+pub mod instruction;
+pub mod expr;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "tracer")]
+pub mod tracer;
+#[cfg(feature = "coverage")]
+pub mod coverage;
+#[cfg(feature = "profiler")]
+pub mod profiler;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "cpm")]
+pub mod cpm;
+#[cfg(feature = "jit")]
+mod jit;
 
 pub use error::{Result, Error};
-pub use cpu::{CPU, Event as CPUEvent};
-pub use emulator::{Emulator, ExecutionStatus, Event as EmulatorEvent, Sound};
-pub use memory::Memory;
-
-#[derive(Debug, Clone)]
-pub enum Button {
-    P1Start,
-    P2Start,
-    P1Shoot,
-    P2Shoot,
-    P1Left,
-    P2Left,
-    P1Right,
-    P2Right,
-    Tilt,
-    Coin,
-}
-
-impl Button {
-    fn mask(&self) -> u8 {
-        match self {
-            Self::Coin => 0b0000_0001,
-            Self::P2Start => 0b0000_0010,
-            Self::P1Start => 0b0000_0100,
-            Self::P1Shoot => 0b0001_0000,
-            Self::P1Left => 0b0010_0000,
-            Self::P1Right => 0b0100_0000,
-            Self::Tilt => 0b0000_0100,
-            Self::P2Shoot => 0b0001_0000,
-            Self::P2Left => 0b0010_0000,
-            Self::P2Right => 0b0100_0000,
-        }
-    }
-}
+pub use cpu::{CPU, Event as CPUEvent, HookAction, PreExecHook, Register, RegisterPair, Registers, RomWritePolicy, StackGuard, UndefinedOpcodePolicy};
+pub use flags::Flags;
+pub use instruction::{Condition, Instruction, Operand, Pair};
+pub use emulator::{Backend, Emulator, ExecutionStatus, Event as EmulatorEvent, FrameSink, FrameStatus, PortAccess, Sound};
+pub use raster::CYCLES_PER_FRAME;
+pub use io::IoDevice;
+pub use memory::{Access, Memory, MemoryMap, MemoryMapError, WatchHit, Watchpoint};
+pub use stats::EmulatorStats;
+pub use savestate::SaveStateError;
+pub use checksum::{crc32, fnv1a64};
+pub use achievements::{Achievement, AchievementTracker};
+pub use pc_history::{PcHistory, PC_HISTORY_LEN};
+pub use call_stack::{CallFrame, CallKind, CallStack, ReturnMismatch, CALL_STACK_LEN};
+pub use cheats::{Cheat, CheatKind, NamedCheat};
+pub use game::SpaceInvadersState;
+pub use frame_hash::{first_divergence, FrameHashChain};
+pub use movie::{Movie, MovieError};
+pub use rewind::Rewind;
+pub use quirks::Quirks;
+pub use buttons::{Button, Buttons};
+pub use games::{GameInfo, MachineKind, Overlay, Profile, RomSet, RomSetError, SetInfo};
 
 pub fn even_parity(mut n: u8) -> bool {
     let mut parity = true;
@@ -56,6 +90,7 @@ mod test {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_even_parity() {
         assert_eq!(super::even_parity(0b1101), false);
         assert_eq!(super::even_parity(0b0101_1101), false);
@@ -63,10 +98,392 @@ mod test {
         assert_eq!(super::even_parity(0b1100_1111), true);
     }
 
+    #[test]
+    fn test_debug_json() {
+        let json = Emulator::new(&[0x3E, 0x42]).to_debug_json();
+
+        assert!(json.contains("\"pc\": \"0x0000\""));
+        assert!(json.contains("\"flags\": \"- - - - -\""));
+        assert!(json.contains("\"offset\": 0"));
+        assert!(json.contains(&format!("\"ram\": \"{}\"", "A".repeat(0x2000 / 3 * 4) + "AAA=")));
+    }
+
+    #[test]
+    fn test_cheats() {
+        // LXI SP,$2400 so the VBlank interrupt has a stack
+        let mut emulator = Emulator::new(&[0x31, 0x00, 0x24]);
+        emulator.step().unwrap();
+        emulator.add_cheat(Cheat::once(0x2000, 0x12));
+        emulator.add_cheat(Cheat::freeze(0x2001, 0x34));
+        emulator.add_cheat(Cheat::freeze(0x0010, 0x56));
+
+        emulator.end_frame();
+        assert_eq!(emulator.cpu().memory[0x2000], 0x12);
+        assert_eq!(emulator.cpu().memory[0x2001], 0x34);
+        assert_eq!(emulator.cpu().memory[0x0010], 0x00);
+        assert_eq!(emulator.cheats().len(), 2);
+
+        emulator.cpu_mut().memory.write(0x2000, 0);
+        emulator.cpu_mut().memory.write(0x2001, 0);
+        emulator.end_frame();
+        assert_eq!(emulator.cpu().memory[0x2000], 0x00);
+        assert_eq!(emulator.cpu().memory[0x2001], 0x34);
+
+        emulator.remove_cheats(0x2001);
+        emulator.remove_cheats(0x0010);
+        assert!(emulator.cheats().is_empty());
+    }
+
+    #[test]
+    fn test_rom_patch_cheat() {
+        // LXI SP,$2400; MVI A,$42
+        let mut emulator = Emulator::new(&[0x31, 0x00, 0x24, 0x3E, 0x42]);
+        let state = emulator.save_state_bytes();
+        emulator.step().unwrap();
+        emulator.add_cheat(Cheat::rom_patch(0x0004, 0x99));
+        emulator.add_cheat(Cheat::rom_patch(0x2000, 0x12));
+        emulator.end_frame();
+        assert_eq!(emulator.cpu().memory[0x0004], 0x99);
+        assert_eq!(emulator.cpu().memory[0x2000], 0x00);
+
+        // Still the same ROM as far as save states go
+        emulator.load_state_bytes(&state).unwrap();
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu().registers().a, 0x99);
+
+        emulator.clear_cheats();
+        assert_eq!(emulator.cpu().memory[0x0004], 0x42);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // MVI A,$42; INR A; JMP $0000
+        let mut emulator = Emulator::new(&[0x3E, 0x42, 0x3C, 0xC3, 0x00, 0x00]);
+        emulator.add_breakpoint(0x0002);
+
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Continue(7));
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Breakpoint(0x0002));
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Continue(5));
+        assert_eq!(emulator.cpu().registers().a, 0x43);
+
+        // Hit again on the next pass, until removed
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Breakpoint(0x0002));
+        assert!(emulator.remove_breakpoint(0x0002));
+        assert!(!emulator.remove_breakpoint(0x0002));
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Continue(5));
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        // MVI A,$42; INR A; JMP $0002
+        let mut emulator = Emulator::new(&[0x3E, 0x42, 0x3C, 0xC3, 0x02, 0x00]);
+        emulator.add_conditional_breakpoint(0x0003, "A == 0x44 && addr == 3".parse().unwrap());
+
+        // A is 0x43 on the first pass
+        for _ in 0..4 {
+            assert!(matches!(emulator.step().unwrap(), ExecutionStatus::Continue(_)));
+        }
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Breakpoint(0x0003));
+        assert_eq!(emulator.breakpoint_condition(0x0003).unwrap().to_string(), "A == 0x44 && addr == 3");
+    }
+
+    #[test]
+    fn test_port_breaks() {
+        // MVI A,$42; OUT 4; OUT 3; IN 3
+        let mut emulator = Emulator::new(&[0x3E, 0x42, 0xD3, 0x04, 0xD3, 0x03, 0xDB, 0x03]);
+        emulator.add_port_break(3, Access::Write);
+
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Continue(7));
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Continue(10));
+        let access = PortAccess { cycle: 27, pc: 0x0004, port: 3, value: 0x42, write: true };
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::PortBreak { access, cycles: 10 });
+        assert_eq!(emulator.step().unwrap(), ExecutionStatus::Continue(10));
+
+        assert!(emulator.remove_port_break(3, Access::Write));
+        assert!(!emulator.remove_port_break(3, Access::Write));
+    }
+
+    #[test]
+    fn test_drain_events() {
+        // LXI SP,$2400; MVI A,$03; OUT 3; OUT 5
+        let mut emulator = Emulator::new(&[0x31, 0x00, 0x24, 0x3E, 0x03, 0xD3, 0x03, 0xD3, 0x05]);
+        emulator.set_port_logging(true);
+        for _ in 0..4 {
+            emulator.step().unwrap();
+        }
+
+        let events: Vec<EmulatorEvent> = emulator.drain_events().collect();
+        assert!(matches!(events[..], [
+            EmulatorEvent::PlaySound(Sound::UFO),
+            EmulatorEvent::PlaySound(Sound::Shoot),
+            EmulatorEvent::PortAccess(_),
+            EmulatorEvent::PlaySound(Sound::Bomp1),
+            EmulatorEvent::PlaySound(Sound::Bomp2),
+            EmulatorEvent::PortAccess(_),
+        ]));
+        assert!(emulator.event().is_none());
+    }
+
+    #[test]
+    fn test_run_frame() {
+        // LXI SP,$2400; MVI A,$01; OUT 3; JMP $0007
+        let rom = [0x31, 0x00, 0x24, 0x3E, 0x01, 0xD3, 0x03, 0xC3, 0x07, 0x00];
+        let mut emulator = Emulator::new(&rom);
+        let mut events = Vec::new();
+        let status = emulator.run_frame(&mut |_: &mut Emulator, event| events.push(event)).unwrap();
+
+        assert_eq!(status, FrameStatus::Complete);
+        assert!(emulator.stats().cycles >= CYCLES_PER_FRAME as u64);
+        assert_eq!(emulator.stats().frames, 1);
+        assert!(matches!(events[0], EmulatorEvent::PlaySound(Sound::UFO)));
+        assert!(matches!(events.last(), Some(EmulatorEvent::FrameComplete)));
+        assert!(emulator.event().is_none());
+
+        // Stopping leaves the frame unfinished until the next call
+        let mut emulator = Emulator::new(&rom);
+        emulator.add_breakpoint(0x0007);
+        assert_eq!(emulator.run_frame(&mut ()).unwrap(), FrameStatus::Breakpoint(0x0007));
+        assert_eq!(emulator.stats().frames, 0);
+        assert_eq!(emulator.frame_cycles(), 27);
+        emulator.remove_breakpoint(0x0007);
+        assert_eq!(emulator.run_frame(&mut ()).unwrap(), FrameStatus::Complete);
+        assert_eq!(emulator.stats().frames, 1);
+        assert_eq!(emulator.frame_cycles(), 0);
+
+        // DI; HLT
+        let mut emulator = Emulator::new(&[0xF3, 0x76]);
+        assert_eq!(emulator.run_frame(&mut ()).unwrap(), FrameStatus::Halted);
+        assert_eq!(emulator.stats().frames, 0);
+    }
+
+    #[test]
+    fn test_breakpoint_resume() {
+        let rom = include_bytes!("../../frontend/assets/invaders");
+        let mut plain = Emulator::new(rom);
+        let mut debugged = Emulator::new(rom);
+        // The mid-screen interrupt handler, hit partway through every frame once the game is up
+        debugged.add_breakpoint(0x0008);
+
+        let mut stops = 0;
+        for _ in 0..120 {
+            plain.run_frame(&mut ()).unwrap();
+            while let FrameStatus::Breakpoint(_) = debugged.run_frame(&mut ()).unwrap() {
+                stops += 1;
+                // A state saved mid-frame carries on the same way
+                if stops == 60 {
+                    let state = debugged.save_state_bytes();
+                    let mut loaded = Emulator::new(rom);
+                    loaded.load_state_bytes(&state).unwrap();
+                    assert_eq!(loaded.state_hash(), debugged.state_hash());
+                    debugged = loaded;
+                    debugged.add_breakpoint(0x0008);
+                }
+            }
+            assert_eq!(debugged.state_hash(), plain.state_hash());
+        }
+        assert!(stops >= 120);
+        assert_eq!(debugged.stats().frames, 120);
+    }
+
     #[test]
     fn test_concat_u16() {
         assert_eq!(concat_u16!(0xF6, 0x78), 0xF678);
         assert_eq!(concat_u16!(0xD1, 0x4A), 0xD14A);
         assert_eq!(concat_u16!(0x00, 0x20), 0x0020);
     }
+
+    #[test]
+    fn test_sound_on() {
+        // MVI A,$01; OUT 3
+        let mut emulator = Emulator::new(&[0x3E, 0x01, 0xD3, 0x03]);
+        emulator.step().unwrap();
+        assert!(!emulator.sound_on(Sound::UFO));
+        emulator.step().unwrap();
+        assert!(emulator.sound_on(Sound::UFO));
+        assert!(!emulator.sound_on(Sound::Shoot));
+        assert!(!emulator.sound_on(Sound::Bomp1));
+
+        let mut loaded = Emulator::new(&[0x3E, 0x01, 0xD3, 0x03]);
+        loaded.load_state_bytes(&emulator.save_state_bytes()).unwrap();
+        assert!(loaded.sound_on(Sound::UFO));
+        assert!(loaded.event().is_none());
+    }
+
+    #[test]
+    fn test_port_logging() {
+        // MVI A,$42; OUT 4; IN 3
+        let mut emulator = Emulator::new(&[0x3E, 0x42, 0xD3, 0x04, 0xDB, 0x03]);
+        emulator.set_port_logging(true);
+        for _ in 0..3 {
+            emulator.step().unwrap();
+        }
+
+        let accesses: Vec<PortAccess> = core::iter::from_fn(|| emulator.event())
+            .filter_map(|event| match event {
+                EmulatorEvent::PortAccess(access) => Some(access),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(accesses, [
+            PortAccess { cycle: 17, pc: 0x0002, port: 4, value: 0x42, write: true },
+            PortAccess { cycle: 27, pc: 0x0004, port: 3, value: 0x42, write: false },
+        ]);
+        assert_eq!(accesses[0].port_name(), "shift data");
+    }
+
+    #[test]
+    fn test_attach_device() {
+        #[derive(Debug, Clone)]
+        struct Latch(u8);
+
+        impl IoDevice for Latch {
+            fn port_in(&mut self, _port: u8) -> u8 {
+                self.0.wrapping_add(1)
+            }
+
+            fn port_out(&mut self, _port: u8, val: u8) {
+                self.0 = val;
+            }
+        }
+
+        // MVI A,$41; OUT 7; IN 7; IN 3
+        let rom = [0x3E, 0x41, 0xD3, 0x07, 0xDB, 0x07, 0xDB, 0x03];
+        let mut emulator = Emulator::new(&rom);
+        emulator.step().unwrap();
+        assert!(emulator.step().is_err());
+
+        let mut emulator = Emulator::new(&rom);
+        emulator.attach_device(Latch(0), &[3, 7], &[7]);
+        for _ in 0..3 {
+            emulator.step().unwrap();
+        }
+        assert_eq!(emulator.cpu().registers().a, 0x42);
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu().registers().a, 0x42);
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let resets = |emulator: &mut Emulator, frames: u32| {
+            let mut resets = 0;
+            for _ in 0..frames {
+                emulator.run_frame(&mut |_: &mut Emulator, event| {
+                    if let EmulatorEvent::WatchdogReset = event {
+                        resets += 1;
+                    }
+                }).unwrap();
+            }
+            resets
+        };
+
+        // JMP $0003; JMP $0003
+        let mut emulator = Emulator::new(&[0xC3, 0x03, 0x00, 0xC3, 0x03, 0x00]);
+        assert_eq!(resets(&mut emulator, io::WATCHDOG_FRAMES), 0);
+
+        emulator.set_watchdog(true);
+        assert_eq!(resets(&mut emulator, io::WATCHDOG_FRAMES - 1), 0);
+        assert_eq!(resets(&mut emulator, 1), 1);
+        assert_eq!(emulator.stats().frames, io::WATCHDOG_FRAMES as u64 * 2);
+
+        // OUT 6; JMP $0000
+        let mut emulator = Emulator::new(&[0xD3, 0x06, 0xC3, 0x00, 0x00]);
+        emulator.set_watchdog(true);
+        assert_eq!(resets(&mut emulator, io::WATCHDOG_FRAMES * 2), 0);
+    }
+
+    #[test]
+    fn test_with_memory() {
+        // LDA $8000; JMP $0003
+        let mut rom = [0; 0x100];
+        rom[..6].copy_from_slice(&[0x3A, 0x00, 0x80, 0xC3, 0x03, 0x00]);
+        let banks: Vec<u8> = [1, 2].iter().flat_map(|&bank| [bank; 0x100]).collect();
+        let memory = MemoryMap::new()
+            .rom(0x0000, &rom)
+            .ram(0x2000..=0x5FFF)
+            .banked_rom(0x8000..=0x80FF, &banks)
+            .build()
+            .unwrap();
+        let mut emulator = Emulator::with_memory(memory, Box::new(machine::SpaceInvadersMachine::new()));
+        emulator.cpu_mut().memory.select_bank(0x8000, 1);
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu().registers().a, 2);
+
+        emulator.cpu_mut().memory.write(0x5FFF, 0x42);
+        let state = emulator.save_state_bytes();
+        emulator.reset();
+        assert_eq!(emulator.cpu().memory.banks().collect::<Vec<_>>(), [(0x8000, 0)]);
+        emulator.load_state_bytes(&state).unwrap();
+        assert_eq!(emulator.cpu().memory.banks().collect::<Vec<_>>(), [(0x8000, 1)]);
+        assert_eq!(emulator.cpu().memory[0x5FFF], 0x42);
+    }
+
+    #[test]
+    fn test_restore_nvram() {
+        let mut emulator = Emulator::new(include_bytes!("../../frontend/assets/invaders"));
+        let run = |emulator: &mut Emulator, frames| (0..frames).for_each(|_| { emulator.run_frame(&mut ()).unwrap(); });
+        assert_eq!(emulator.nvram(), [0, 0]);
+
+        // Held until the game has cleared RAM on boot
+        emulator.restore_nvram(&[0x50, 0x12]);
+        run(&mut emulator, 60);
+        assert_eq!(emulator.nvram(), [0x50, 0x12]);
+
+        emulator.reset();
+        run(&mut emulator, 60);
+        assert_eq!(emulator.nvram(), [0x50, 0x12]);
+    }
+
+    #[test]
+    fn test_set_button() {
+        // IN 1; IN 2
+        let mut emulator = Emulator::new(&[0xDB, 0x01, 0xDB, 0x02]);
+        emulator.set_button(Button::Coin, true);
+        emulator.set_button(Button::P2Shoot, true);
+        emulator.set_button(Button::Tilt, true);
+        assert_eq!(emulator.buttons(), Buttons::COIN | Buttons::P2_SHOOT | Buttons::TILT);
+
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu().registers().a, 0x00);
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu().registers().a, 0x14);
+
+        emulator.set_buttons(Buttons::P1_LEFT);
+        assert!(!emulator.button_held(Button::Coin));
+        assert!(emulator.button_held(Button::P1Left));
+        assert_eq!(emulator.input_ports(), [0x21, 0x00]);
+    }
+
+    #[test]
+    fn test_dirty_lines() {
+        // LXI SP,$2400; MVI A,$FF; STA $24A0; JMP $0008
+        let mut emulator = Emulator::new(&[0x31, 0x00, 0x24, 0x3E, 0xFF, 0x32, 0xA0, 0x24, 0xC3, 0x08, 0x00]);
+        assert_eq!(emulator.take_dirty_lines(), video::DirtyLines::all());
+
+        emulator.run_frame(&mut ()).unwrap();
+        assert_eq!(emulator.take_dirty_lines().iter().collect::<Vec<_>>(), [5]);
+        emulator.run_frame(&mut ()).unwrap();
+        assert!(emulator.take_dirty_lines().is_empty());
+    }
+
+    #[test]
+    fn test_write_memory() {
+        // MVI A,$42
+        let mut emulator = Emulator::new(&[0x3E, 0x42]);
+        emulator.take_dirty_lines();
+
+        assert!(emulator.write_memory(0x2010, 0x99));
+        assert_eq!(emulator.read_memory(0x2010), 0x99);
+        assert!(emulator.take_dirty_lines().is_empty());
+
+        assert!(!emulator.write_memory(0x0001, 0x99));
+        assert_eq!(emulator.read_memory(0x0001), 0x42);
+
+        // On screen without running a frame
+        assert!(emulator.write_memory(0x24A0, 0xFF));
+        assert_eq!(emulator.screen()[0xA0], 0xFF);
+        assert_eq!(emulator.take_dirty_lines().iter().collect::<Vec<_>>(), [5]);
+    }
 }
@@ -0,0 +1,163 @@
+//! Drivers for the games on Midway's 8080 black and white board.
+//!
+//! Space Invaders, Lunar Rescue, Balloon Bomber, Space Invaders Part II and Galaxy Wars all run on
+//! the same CPU, video RAM and CRT timing; what tells them apart is the devices on some I/O ports
+//! and where each game keeps its state in RAM. A [`Machine`] holds those parts, and the
+//! [`Emulator`] everything else, so a driver picked at runtime with
+//! [`MachineKind::machine`](crate::MachineKind::machine) is all another game needs.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::ops::Range;
+use crate::savestate::SaveStateError;
+use crate::{Emulator, EmulatorEvent as Event, MachineKind, Memory, Result};
+
+/// The parts of a game's board that differ from the others, see the [module](self) docs.
+pub trait Machine: CloneMachine + Debug + Send {
+    fn kind(&self) -> MachineKind;
+
+    /// Puts the board's own devices on the I/O ports, with
+    /// [`Emulator::attach_device`], in place of the Space Invaders port map every board starts
+    /// with. Called once, when the emulator is created.
+    fn attach_devices(&self, _emulator: &mut Emulator) {}
+
+    /// Reads the game's RAM at the end of every frame, calling `emit` with the events it finds,
+    /// such as a coin going in or the game ending.
+    fn frame_events(&mut self, _memory: &Memory, _emit: &mut dyn FnMut(Event)) {}
+
+    fn reset(&mut self) {}
+
+    /// RAM holding what the game keeps between plays, like the high score, which frontends
+    /// can save across runs the way MAME keeps NVRAM. See [`Emulator::nvram`].
+    fn nvram(&self) -> Option<Range<u16>> {
+        None
+    }
+
+    /// State of the driver itself kept in save states, e.g. what `frame_events` compares with.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Lets emulators be cloned with their machine, implemented for every `Clone` machine.
+pub trait CloneMachine {
+    fn clone_machine(&self) -> Box<dyn Machine>;
+}
+
+impl<T: Machine + Clone + 'static> CloneMachine for T {
+    fn clone_machine(&self) -> Box<dyn Machine> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Machine> {
+    fn clone(&self) -> Self {
+        self.clone_machine()
+    }
+}
+
+// Well-known Space Invaders RAM locations
+const RAM_PLAYER_ALIVE: u16 = 0x2015;
+const RAM_NUM_COINS: u16 = 0x20EB;
+const RAM_GAME_MODE: u16 = 0x20EF;
+const RAM_HIGH_SCORE: u16 = 0x20F4;
+
+/// Midway's Space Invaders, what [`Emulator::new`] runs. The board's port map is the default
+/// one, so all it adds is game events from RAM and the high score as NVRAM.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpaceInvadersMachine {
+    last_coins: u8,
+    last_game_mode: u8,
+    last_player_alive: u8,
+}
+
+impl SpaceInvadersMachine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Machine for SpaceInvadersMachine {
+    fn kind(&self) -> MachineKind {
+        MachineKind::SpaceInvaders
+    }
+
+    fn frame_events(&mut self, memory: &Memory, emit: &mut dyn FnMut(Event)) {
+        let coins = memory[RAM_NUM_COINS];
+        let game_mode = memory[RAM_GAME_MODE];
+        let player_alive = memory[RAM_PLAYER_ALIVE];
+
+        if coins > self.last_coins {
+            emit(Event::CoinInserted);
+        }
+        if game_mode != 0 && self.last_game_mode == 0 {
+            emit(Event::GameStarted);
+        }
+        if game_mode == 0 && self.last_game_mode != 0 {
+            emit(Event::GameOver);
+        }
+        if game_mode != 0 && player_alive != 0xFF && self.last_player_alive == 0xFF {
+            emit(Event::PlayerDied);
+        }
+
+        self.last_coins = coins;
+        self.last_game_mode = game_mode;
+        self.last_player_alive = player_alive;
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// The high score, two BCD bytes, least significant first.
+    fn nvram(&self) -> Option<Range<u16>> {
+        Some(RAM_HIGH_SCORE..RAM_HIGH_SCORE + 2)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        Vec::from([self.last_coins, self.last_game_mode, self.last_player_alive])
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let &[coins, game_mode, player_alive] = data else { return Err(SaveStateError::Truncated.into()) };
+        self.last_coins = coins;
+        self.last_game_mode = game_mode;
+        self.last_player_alive = player_alive;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_space_invaders_events() {
+        let mut memory = Memory::new([0; 0x2000]);
+        let mut machine = SpaceInvadersMachine::new();
+        let frame = |memory: &Memory, machine: &mut SpaceInvadersMachine| {
+            let mut events = vec![];
+            machine.frame_events(memory, &mut |event| events.push(event));
+            events.len()
+        };
+
+        memory.write(RAM_PLAYER_ALIVE, 0xFF);
+        assert_eq!(frame(&memory, &mut machine), 0);
+        memory.write(RAM_NUM_COINS, 1);
+        memory.write(RAM_GAME_MODE, 1);
+        assert_eq!(frame(&memory, &mut machine), 2);
+        memory.write(RAM_PLAYER_ALIVE, 0);
+        assert_eq!(frame(&memory, &mut machine), 1);
+
+        let mut loaded = SpaceInvadersMachine::new();
+        loaded.load_state(&machine.save_state()).unwrap();
+        assert_eq!(loaded, machine);
+        assert!(loaded.load_state(&[1]).is_err());
+    }
+}
@@ -1,62 +1,616 @@
-use std::ops::{Index, IndexMut, Range};
+use core::fmt::{Display, Formatter};
+use core::ops::{Index, Range, RangeInclusive};
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::vec;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Range of addresses whose reads, writes or both are reported by the CPU, see
+/// [`Memory::add_watchpoint`]. Only data accesses are watched, not instruction fetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Watchpoint {
+    pub start: u16,
+    /// Last address watched, inclusive.
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    pub fn read(range: RangeInclusive<u16>) -> Self {
+        Self { start: *range.start(), end: *range.end(), read: true, write: false }
+    }
+
+    pub fn write(range: RangeInclusive<u16>) -> Self {
+        Self { start: *range.start(), end: *range.end(), read: false, write: true }
+    }
+
+    pub fn read_write(range: RangeInclusive<u16>) -> Self {
+        Self { start: *range.start(), end: *range.end(), read: true, write: true }
+    }
+
+    pub(crate) fn matches(&self, address: u16, access: Access) -> bool {
+        let kind = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+        kind && (self.start..=self.end).contains(&address)
+    }
+}
+
+/// A watched access, reported once the instruction that made it has run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchHit {
+    /// Address of the instruction, or PC when an interrupt was taken for pushes by its RST.
+    pub pc: u16,
+    pub address: u16,
+    pub access: Access,
+    /// Value before the access. Same as `new` for reads.
+    pub old: u8,
+    pub new: u8,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self.access {
+            Access::Read => write!(f, "${:04X} read ${:02X} from ${:04X}", self.pc, self.new, self.address),
+            Access::Write => write!(f, "${:04X} wrote ${:02X} to ${:04X} (was ${:02X})", self.pc, self.new, self.address, self.old),
+        }
+    }
+}
+
+/// Size of the blocks regions of a [`MemoryMap`] are made of. Regions start and end on a page
+/// boundary.
+pub const PAGE_SIZE: usize = 0x100;
+const PAGES: usize = 0x10000 / PAGE_SIZE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryMapError {
+    /// The region starting at `start` doesn't start or end on a [`PAGE_SIZE`] boundary, or is empty.
+    Unaligned { start: u32 },
+    /// The region starting at `start` runs past 0xFFFF.
+    OutOfRange { start: u32 },
+    /// The region starting at `start` covers `address`, which an earlier one already maps.
+    Overlap { start: u32, address: u32 },
+    /// The mirror starting at `start` copies unmapped or banked addresses.
+    BadMirror { start: u32 },
+    /// The banked region starting at `start` wasn't given a whole number of banks.
+    BankSize { start: u32 },
+}
+
+impl Display for MemoryMapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unaligned { start } => write!(f, "region at ${:04X} isn't a whole number of {}-byte pages", start, PAGE_SIZE),
+            Self::OutOfRange { start } => write!(f, "region at ${:04X} runs past $FFFF", start),
+            Self::Overlap { start, address } => write!(f, "region at ${:04X} overlaps another one at ${:04X}", start, address),
+            Self::BadMirror { start } => write!(f, "mirror at ${:04X} copies unmapped or banked memory", start),
+            Self::BankSize { start } => write!(f, "banked region at ${:04X} isn't a whole number of banks", start),
+        }
+    }
+}
+
+impl From<MemoryMapError> for crate::Error {
+    fn from(e: MemoryMapError) -> Self {
+        crate::Error::MemoryMap(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Region {
+    Rom { start: u32, data: Vec<u8> },
+    Ram { start: u32, len: usize },
+    Mirror { start: u32, len: usize, source: u32, source_len: usize },
+    BankedRom { start: u32, len: usize, banks: Vec<u8> },
+}
+
+impl Region {
+    fn span(&self) -> (u32, usize) {
+        match self {
+            Self::Rom { start, data } => (*start, data.len()),
+            Self::Ram { start, len } | Self::Mirror { start, len, .. } | Self::BankedRom { start, len, .. } => (*start, *len),
+        }
+    }
+}
+
+/// Layout of the address space, built up a region at a time and turned into [`Memory`] with
+/// [`MemoryMap::build`]. Addresses no region covers are unmapped.
+///
+/// ```ignore
+/// // Space Invaders Part II: the usual board plus 2K of ROM at 0x4000
+/// let memory = MemoryMap::new()
+///     .rom(0x0000, &program[..0x2000])
+///     .ram(0x2000..=0x3FFF)
+///     .rom(0x4000, &program[0x2000..])
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `data` at `start`, read-only.
+    pub fn rom(mut self, start: u16, data: &[u8]) -> Self {
+        self.regions.push(Region::Rom { start: start as u32, data: data.to_vec() });
+        self
+    }
+
+    /// RAM covering `range`, cleared on reset.
+    pub fn ram(mut self, range: RangeInclusive<u16>) -> Self {
+        self.regions.push(Region::Ram { start: *range.start() as u32, len: range.len() });
+        self
+    }
+
+    /// Makes `range` repeat the memory in `source`, which earlier regions must map. Address
+    /// lines the board doesn't decode do this, like the ones that put copies of Space Invaders'
+    /// RAM all the way up to 0xFFFF.
+    pub fn mirror(mut self, range: RangeInclusive<u16>, source: RangeInclusive<u16>) -> Self {
+        self.regions.push(Region::Mirror {
+            start: *range.start() as u32,
+            len: range.len(),
+            source: *source.start() as u32,
+            source_len: source.len(),
+        });
+        self
+    }
+
+    /// ROM covering `range` that shows one of the banks `banks` is split into at a time, the
+    /// first one until another is picked with [`Memory::select_bank`].
+    pub fn banked_rom(mut self, range: RangeInclusive<u16>, banks: &[u8]) -> Self {
+        self.regions.push(Region::BankedRom { start: *range.start() as u32, len: range.len(), banks: banks.to_vec() });
+        self
+    }
+
+    pub fn build(&self) -> Result<Memory, MemoryMapError> {
+        let mut pages = vec![Page::Unmapped; PAGES];
+        let mut rom = Vec::new();
+        let mut ram_len = 0;
+        let mut banks: Vec<Bank> = Vec::new();
+
+        for region in &self.regions {
+            let (start, len) = region.span();
+            if !(start as usize).is_multiple_of(PAGE_SIZE) || !len.is_multiple_of(PAGE_SIZE) || len == 0 {
+                return Err(MemoryMapError::Unaligned { start });
+            }
+            if start as usize + len > 0x10000 {
+                return Err(MemoryMapError::OutOfRange { start });
+            }
+            let first = start as usize / PAGE_SIZE;
+            let count = len / PAGE_SIZE;
+            if let Some(page) = (first..first + count).find(|&page| pages[page] != Page::Unmapped) {
+                return Err(MemoryMapError::Overlap { start, address: (page * PAGE_SIZE) as u32 });
+            }
+
+            match region {
+                Region::Rom { data, .. } => {
+                    (0..count).for_each(|i| pages[first + i] = Page::Rom(rom.len() + i * PAGE_SIZE));
+                    rom.extend_from_slice(data);
+                }
+                Region::Ram { .. } => {
+                    (0..count).for_each(|i| pages[first + i] = Page::Ram(ram_len + i * PAGE_SIZE));
+                    ram_len += len;
+                }
+                Region::Mirror { source, source_len, .. } => {
+                    let source_first = *source as usize / PAGE_SIZE;
+                    let source_count = source_len / PAGE_SIZE;
+                    let source_pages = source_first..source_first + source_count;
+                    let banked = banks.iter().any(|bank| bank.pages().any(|page| source_pages.contains(&page)));
+                    if !(*source as usize).is_multiple_of(PAGE_SIZE) || !source_len.is_multiple_of(PAGE_SIZE) || source_count == 0 || banked
+                        || source_pages.clone().any(|page| pages[page] == Page::Unmapped)
+                    {
+                        return Err(MemoryMapError::BadMirror { start });
+                    }
+                    (0..count).for_each(|i| pages[first + i] = pages[source_first + i % source_count]);
+                }
+                Region::BankedRom { banks: data, .. } => {
+                    if data.is_empty() || !data.len().is_multiple_of(len) {
+                        return Err(MemoryMapError::BankSize { start });
+                    }
+                    (0..count).for_each(|i| pages[first + i] = Page::Rom(rom.len() + i * PAGE_SIZE));
+                    banks.push(Bank { start: start as u16, len, offset: rom.len(), count: data.len() / len, selected: 0 });
+                    rom.extend_from_slice(data);
+                }
+            }
+        }
+
+        Ok(Memory {
+            rom: rom.into_boxed_slice(),
+            ram: vec![0; ram_len].into_boxed_slice(),
+            pages: pages.into_boxed_slice(),
+            banks,
+            watchpoints: Vec::new(),
+            #[cfg(feature = "jit")]
+            code: Vec::new(),
+            #[cfg(feature = "jit")]
+            code_writes: Vec::new(),
+        })
+    }
+}
+
+/// Where the addresses of a page are, as the offset of its first byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Unmapped,
+    Rom(usize),
+    Ram(usize),
+}
+
+impl Page {
+    /// The page `pages` pages further into the same region.
+    fn advance(self, pages: usize) -> Self {
+        match self {
+            Self::Rom(offset) => Self::Rom(offset + pages * PAGE_SIZE),
+            Self::Ram(offset) => Self::Ram(offset + pages * PAGE_SIZE),
+            Self::Unmapped => Self::Unmapped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bank {
+    start: u16,
+    len: usize,
+    /// Offset in ROM of the first bank.
+    offset: usize,
+    count: usize,
+    selected: usize,
+}
+
+impl Bank {
+    fn pages(&self) -> Range<usize> {
+        self.start as usize / PAGE_SIZE..(self.start as usize + self.len) / PAGE_SIZE
+    }
+}
+
+/// The address space, laid out by a [`MemoryMap`]. On the Space Invaders board that's 8K of ROM
+/// followed by 8K of RAM, mirrored up to 0xFFFF, which puts copies of RAM and video RAM at
+/// 0x4000, 0x6000 and so on. Some code reads and writes through them, so addresses in the mirrors
+/// reach the same bytes as the ones at 0x2000.
+///
+/// Indexing reads unmapped addresses as 0xFF, what the data bus floats to, and panics on writes
+/// to them or to ROM. [`Memory::read`], [`Memory::get`] and [`Memory::write`] tell those apart
+/// instead, which is how the CPU turns them into [`Error::MemoryFault`](crate::Error::MemoryFault).
 #[derive(Debug, Clone)]
 pub struct Memory {
-    rom: [u8; 0x2000],
-    ram: [u8; 0x2000],
+    /// Every ROM region and bank, in the order they were mapped.
+    rom: Box<[u8]>,
+    /// Every RAM region, in the order they were mapped.
+    ram: Box<[u8]>,
+    pages: Box<[Page]>,
+    banks: Vec<Bank>,
+    watchpoints: Vec<Watchpoint>,
+    /// One bit per RAM byte translated by the JIT, empty until a block in RAM is translated.
+    #[cfg(feature = "jit")]
+    code: Vec<u64>,
+    /// RAM offsets of translated bytes written since the JIT last looked.
+    #[cfg(feature = "jit")]
+    code_writes: Vec<u16>,
 }
 
 impl Memory {
+    /// The Space Invaders layout: 8K of ROM followed by 8K of RAM.
     pub fn new(rom: [u8; 0x2000]) -> Self {
-        Self {
-            rom,
-            ram: [0; 0x2000],
-        }
+        MemoryMap::new()
+            .rom(0x0000, &rom)
+            .ram(0x2000..=0x3FFF)
+            .mirror(0x4000..=0xFFFF, 0x2000..=0x3FFF)
+            .build()
+            .expect("the Space Invaders map is valid")
+    }
+
+    /// 64K of RAM and no ROM, for programs written for other 8080 machines.
+    pub fn flat() -> Self {
+        MemoryMap::new().ram(0x0000..=0xFFFF).build().expect("a flat map is valid")
     }
 
     pub fn reset_ram(&mut self) {
         self.ram.fill(0);
     }
-}
 
-impl Index<u16> for Memory {
-    type Output = u8;
+    /// Clears RAM and switches every banked region back to its first bank, like a reset of the
+    /// board.
+    pub fn reset(&mut self) {
+        self.reset_ram();
+        let starts: Vec<u16> = self.banks.iter().map(|bank| bank.start).collect();
+        starts.into_iter().for_each(|start| { self.select_bank(start, 0); });
+    }
 
-    fn index(&self, index: u16) -> &Self::Output {
-        let rom_len = self.rom.len();
-        let index = index as usize;
+    /// Every ROM region, banks included, in the order they were mapped.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Every RAM region, in the order they were mapped.
+    pub(crate) fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub(crate) fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    /// Start address and selected bank of every banked region.
+    pub fn banks(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.banks.iter().map(|bank| (bank.start, bank.selected))
+    }
+
+    /// Shows `bank` in the banked region starting at `start`. Returns whether there's such a
+    /// region with that many banks.
+    pub fn select_bank(&mut self, start: u16, bank: usize) -> bool {
+        let Some(region) = self.banks.iter_mut().find(|region| region.start == start && bank < region.count) else {
+            return false;
+        };
+        region.selected = bank;
+        let offset = region.offset + bank * region.len;
+        for (i, page) in region.pages().enumerate() {
+            self.pages[page] = Page::Rom(offset + i * PAGE_SIZE);
+        }
+        true
+    }
+
+    /// Overwrites the ROM byte at `address`, returning what was there, or `None` if `address`
+    /// isn't in ROM. Only for cheats, which put the original back when they're removed.
+    pub(crate) fn patch_rom(&mut self, address: u16, value: u8) -> Option<u8> {
+        let Page::Rom(offset) = self.pages[address as usize / PAGE_SIZE] else { return None };
+        let byte = &mut self.rom[offset + address as usize % PAGE_SIZE];
+        Some(core::mem::replace(byte, value))
+    }
+
+    /// Byte at `address`, `None` if nothing is mapped there.
+    #[inline]
+    pub fn read(&self, address: u16) -> Option<u8> {
+        let low = address as usize % PAGE_SIZE;
+        match self.pages[address as usize / PAGE_SIZE] {
+            Page::Ram(offset) => Some(self.ram[offset + low]),
+            Page::Rom(offset) => Some(self.rom[offset + low]),
+            Page::Unmapped => None,
+        }
+    }
+
+    /// Bytes in `range`, `None` unless they're all mapped and next to each other in one region
+    /// or copy of it.
+    pub fn get(&self, range: Range<u16>) -> Option<&[u8]> {
+        if range.is_empty() {
+            return Some(&[]);
+        }
+        let first = range.start as usize / PAGE_SIZE;
+        let last = (range.end as usize - 1) / PAGE_SIZE;
+        if !(first..=last).all(|page| self.pages[page] == self.pages[first].advance(page - first)) {
+            return None;
+        }
+        let offset = range.start as usize % PAGE_SIZE;
+        match self.pages[first] {
+            Page::Rom(base) => Some(&self.rom[base + offset..base + offset + range.len()]),
+            Page::Ram(base) => Some(&self.ram[base + offset..base + offset + range.len()]),
+            Page::Unmapped => None,
+        }
+    }
+
+    /// Writes `value` to `address`, unless it's in ROM, which can't be written just like on the
+    /// board, or unmapped. Returns whether it was written.
+    #[inline]
+    pub fn write(&mut self, address: u16, value: u8) -> bool {
+        let Some(offset) = self.ram_offset(address) else { return false };
+        #[cfg(feature = "jit")]
+        if !self.code.is_empty() && self.code[offset / 64] & (1 << (offset % 64)) != 0 {
+            self.code_writes.push(offset as u16);
+        }
+        self.ram[offset] = value;
+        true
+    }
+
+    /// Whether `address` is RAM, so [`Memory::write`] would write it.
+    pub fn writable(&self, address: u16) -> bool {
+        self.ram_offset(address).is_some()
+    }
+
+    /// Whether anything is mapped at `address`.
+    pub fn mapped(&self, address: u16) -> bool {
+        self.pages[address as usize / PAGE_SIZE] != Page::Unmapped
+    }
+
+    /// Whether every address is mapped and no bank can be switched, which translated code
+    /// relies on.
+    #[cfg(feature = "jit")]
+    pub(crate) fn fixed(&self) -> bool {
+        self.banks.is_empty() && !self.pages.contains(&Page::Unmapped)
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Returns whether the watchpoint was set.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
 
-        if index < rom_len {
-            &self.rom[index]
-        } else {
-            &self.ram[(index - rom_len) % self.ram.len()]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Marks `address` as translated by the JIT, so writes to it are recorded. ROM can't be
+    /// written and isn't tracked.
+    #[cfg(feature = "jit")]
+    pub(crate) fn mark_code(&mut self, address: u16) {
+        let Some(offset) = self.ram_offset(address) else { return };
+        if self.code.is_empty() {
+            self.code = vec![0; self.ram.len().div_ceil(64)];
+        }
+        self.code[offset / 64] |= 1 << (offset % 64);
+    }
+
+    /// Forgets every translated byte and write to one.
+    #[cfg(feature = "jit")]
+    pub(crate) fn clear_code(&mut self) {
+        self.code.clear();
+        self.code_writes.clear();
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn code_written(&self) -> bool {
+        !self.code_writes.is_empty()
+    }
+
+    /// RAM offsets of translated bytes written since the last call.
+    #[cfg(feature = "jit")]
+    pub(crate) fn take_code_writes(&mut self) -> Vec<u16> {
+        core::mem::take(&mut self.code_writes)
+    }
+
+    /// Offset in RAM `address` or its mirror maps to, `None` for ROM and unmapped addresses.
+    #[inline]
+    pub(crate) fn ram_offset(&self, address: u16) -> Option<usize> {
+        match self.pages[address as usize / PAGE_SIZE] {
+            Page::Ram(offset) => Some(offset + address as usize % PAGE_SIZE),
+            _ => None,
         }
     }
+
+    /// Whether the CPU should report `access` to `address`. Kept cheap for the common case of
+    /// no watchpoints, since it runs on every data access.
+    #[inline]
+    pub(crate) fn watched(&self, address: u16, access: Access) -> bool {
+        !self.watchpoints.is_empty() && self.watchpoints.iter().any(|w| w.matches(address, access))
+    }
 }
 
-impl IndexMut<u16> for Memory {
-    fn index_mut(&mut self, index: u16) -> &mut Self::Output {
-        let rom_len = self.rom.len();
-        let index = index as usize;
+/// What reads of unmapped addresses see.
+pub(crate) const OPEN_BUS: u8 = 0xFF;
 
-        if index < rom_len { panic!("cannot write to ROM"); }
+impl Index<u16> for Memory {
+    type Output = u8;
 
-        &mut self.ram[(index - rom_len) % self.ram.len()]
+    fn index(&self, index: u16) -> &Self::Output {
+        let low = index as usize % PAGE_SIZE;
+        match self.pages[index as usize / PAGE_SIZE] {
+            Page::Ram(offset) => &self.ram[offset + low],
+            Page::Rom(offset) => &self.rom[offset + low],
+            Page::Unmapped => &OPEN_BUS,
+        }
     }
 }
 
 impl Index<Range<u16>> for Memory {
     type Output = [u8];
 
+    /// Ranges in a mirror are read from what it copies, but can't run past the end of the copy.
+    /// See [`Memory::get`] for a version that doesn't panic.
     fn index(&self, range: Range<u16>) -> &Self::Output {
-        let start = range.start as usize;
-        let end = range.end as usize;
-        let rom_len = self.rom.len();
+        self.get(range.clone()).unwrap_or_else(|| panic!("no contiguous memory at ${:04X}..${:04X}", range.start, range.end))
+    }
+}
 
-        if start >= rom_len {
-            &self.ram[start - rom_len..end - rom_len]
-        } else {
-            &self.rom[start..end]
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ram_mirror() {
+        let mut memory = Memory::new([0x76; 0x2000]);
+        memory.write(0x4000, 0x12);
+        memory.write(0x5FFF, 0x34);
+        memory.write(0x6400, 0x56);
+        assert_eq!(memory[0x2000], 0x12);
+        assert_eq!(memory[0x3FFF], 0x34);
+        assert_eq!(memory[0x2400], 0x56);
+        assert_eq!(memory[0xE400], 0x56);
+
+        memory.write(0x2001, 0x78);
+        assert_eq!(memory[0x4001], 0x78);
+        assert_eq!(memory[0x4000..0x4002], [0x12, 0x78]);
+        assert_eq!(memory[0x4400..0x6000], memory[0x2400..0x4000]);
+        assert_eq!(memory[0x1FFF], 0x76);
+    }
+
+    #[test]
+    fn test_fallible_access() {
+        let memory = Memory::new([0x76; 0x2000]);
+        assert_eq!(memory.read(0x8000), Some(0));
+        assert_eq!(memory.get(0x1FFE..0x2000), Some(&[0x76, 0x76][..]));
+        assert_eq!(memory.get(0x1FFF..0x2001), None);
+        assert_eq!(memory.get(0x3FFF..0x4001), None);
+        assert!((0..=0xFFFF).all(|address| memory.mapped(address)));
+    }
+
+    #[test]
+    fn test_memory_map() {
+        // Space Invaders Part II
+        let mut memory = MemoryMap::new()
+            .rom(0x0000, &[0x11; 0x2000])
+            .ram(0x2000..=0x3FFF)
+            .rom(0x4000, &[0x22; 0x800])
+            .build()
+            .unwrap();
+        assert_eq!((memory[0x1FFF], memory[0x2000], memory[0x47FF]), (0x11, 0x00, 0x22));
+        assert_eq!(memory.rom().len(), 0x2800);
+        assert_eq!(memory.read(0x4800), None);
+        assert_eq!(memory[0x4800], OPEN_BUS);
+        assert!(!memory.mapped(0xFFFF) && !memory.write(0x4800, 1));
+        assert!(memory.write(0x3FFF, 1));
+
+        let map = MemoryMap::new().ram(0x0000..=0x3FFF);
+        assert_eq!(map.clone().ram(0x3000..=0x4FFF).build().unwrap_err(), MemoryMapError::Overlap { start: 0x3000, address: 0x3000 });
+        assert_eq!(map.clone().ram(0x4000..=0x4010).build().unwrap_err(), MemoryMapError::Unaligned { start: 0x4000 });
+        assert_eq!(map.clone().rom(0xFF00, &[0; 0x200]).build().unwrap_err(), MemoryMapError::OutOfRange { start: 0xFF00 });
+        assert_eq!(map.clone().mirror(0x8000..=0xFFFF, 0x4000..=0x7FFF).build().unwrap_err(), MemoryMapError::BadMirror { start: 0x8000 });
+        assert_eq!(map.banked_rom(0x4000..=0x47FF, &[0; 0x1000 + 1]).build().unwrap_err(), MemoryMapError::BankSize { start: 0x4000 });
+    }
+
+    #[test]
+    fn test_banks() {
+        let banks: Vec<u8> = [1, 2, 3].iter().flat_map(|&bank| [bank; 0x1000]).collect();
+        let mut memory = MemoryMap::new().ram(0x0000..=0x0FFF).banked_rom(0x8000..=0x8FFF, &banks).build().unwrap();
+        assert_eq!(memory[0x8000], 1);
+        assert!(memory.select_bank(0x8000, 2));
+        assert_eq!(memory.get(0x8000..0x9000), Some(&banks[0x2000..]));
+        assert_eq!(memory.banks().collect::<Vec<_>>(), [(0x8000, 2)]);
+        assert!(!memory.select_bank(0x8000, 3) && !memory.select_bank(0x9000, 0));
+
+        memory.reset();
+        assert_eq!(memory[0x8FFF], 1);
+    }
+
+    #[test]
+    fn test_rom_write() {
+        let mut memory = Memory::new([0; 0x2000]);
+        assert!(!memory.write(0x1000, 1));
+        assert!(!memory.writable(0x1000));
+        assert_eq!(memory[0x1000], 0);
+    }
+
+    #[test]
+    fn test_ram_write() {
+        let mut memory = Memory::new([0; 0x2000]);
+        assert!(memory.write(0x3000, 1));
+        assert!(memory.writable(0x3000));
+        assert_eq!(memory[0x3000], 1);
+    }
+
+    #[test]
+    fn test_unmapped_write() {
+        let mut memory = MemoryMap::new().ram(0x2000..=0x3FFF).build().unwrap();
+        assert!(!memory.write(0x8000, 1));
+        assert!(!memory.writable(0x8000));
+        assert_eq!(memory[0x8000], OPEN_BUS);
     }
 }
\ No newline at end of file
@@ -0,0 +1,597 @@
+//! Input movie file format, plus conversion to and from the BizHawk `.bk2` layout so runs can be
+//! shared with other tools.
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic "SIMV"
+//! 4       2     format version (little endian, like every other integer)
+//! 6       2     flags: bit 0 starts from a save state, bit 1 has frame hashes
+//! 8       4     CRC32 of the ROM the movie was recorded with
+//! 12      4     rerecord count
+//! 16      4     frame count
+//! 20      1     DIP switches, see Emulator::dip_switches
+//! 21      1     emulator version length N
+//! 22      N     emulator version, UTF-8
+//! ..      1     author length M
+//! ..      M     author, UTF-8
+//! ..      4     save state length S, only with flag bit 0
+//! ..      S     save state the movie starts from, power-on otherwise
+//! ..      ..    one record per frame: u16 buttons held, followed by the u64 video RAM hash
+//!               after the frame with flag bit 1
+//! ```
+//!
+//! Button bits follow [`BUTTONS`]: bit 0 is `Coin`, bit 9 is `Tilt`.
+//!
+//! [`Recorder`] takes a movie of an emulator from its current state, and [`Player`] drives an
+//! emulator through one. The core is deterministic, so playing back a movie with the ROM it was
+//! recorded with reproduces every frame exactly, which the recorded frame hashes confirm.
+
+use core::fmt::{Display, Formatter};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::format;
+use crate::{Button, Buttons, Emulator, Error, Result};
+
+pub const MAGIC: [u8; 4] = *b"SIMV";
+pub const VERSION: u16 = 1;
+
+const FLAG_START_STATE: u16 = 1 << 0;
+const FLAG_FRAME_HASHES: u16 = 1 << 1;
+
+/// Button of each bit of a frame, and column of each one in a `.bk2` input log.
+pub const BUTTONS: [Button; 10] = Button::ALL;
+
+/// `.bk2` log key and mnemonics, in [`BUTTONS`] order.
+const LOG_KEY: &str = "#Coin|P1 Start|P2 Start|P1 Left|P1 Right|P1 Fire|P2 Left|P2 Right|P2 Fire|Tilt|";
+const MNEMONICS: [char; 10] = ['C', '1', '2', 'L', 'R', 'F', 'l', 'r', 'f', 'T'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion { version: u16 },
+    Truncated,
+    InvalidText,
+    /// A `.bk2` input log line that isn't a valid frame.
+    InvalidInputLog { line: usize },
+    InvalidFrameHashes { line: usize },
+    /// An input script line that isn't a frame number followed by button names.
+    InvalidScript { line: usize },
+    RomMismatch { expected: u32, found: u32 },
+    /// The framebuffer after a frame of playback differs from the recorded one.
+    Desync { frame: usize },
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a movie file"),
+            Self::UnsupportedVersion { version } => write!(f, "unsupported movie version {} (newest supported is {})", version, VERSION),
+            Self::Truncated => write!(f, "movie is truncated"),
+            Self::InvalidText => write!(f, "movie header text is not valid UTF-8"),
+            Self::InvalidInputLog { line } => write!(f, "invalid input log at line {}", line),
+            Self::InvalidFrameHashes { line } => write!(f, "invalid frame hash at line {}", line),
+            Self::InvalidScript { line } => write!(f, "invalid input script at line {}", line),
+            Self::RomMismatch { expected, found } => write!(f, "movie was recorded with a different ROM (CRC32 {:08X}, loaded ROM is {:08X})", found, expected),
+            Self::Desync { frame } => write!(f, "playback desynced at frame {}", frame),
+        }
+    }
+}
+
+impl From<MovieError> for Error {
+    fn from(e: MovieError) -> Self {
+        Error::Movie(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// CRC32 of the ROM, 0 when converted from a movie that didn't record it.
+    pub rom_crc: u32,
+    pub dip_switches: u8,
+    /// Version of the emulator the movie was recorded with.
+    pub emulator_version: String,
+    pub author: String,
+    pub rerecords: u32,
+    /// Save state the movie starts from, power-on when `None`.
+    pub start_state: Option<Vec<u8>>,
+    /// Buttons held on each frame, see [`buttons`].
+    pub frames: Vec<u16>,
+    /// Video RAM hash after each frame, see [`Emulator::frame_hash`](crate::Emulator::frame_hash),
+    /// or empty if they weren't recorded.
+    pub frame_hashes: Vec<u64>,
+}
+
+/// Files of a `.bk2` archive, before zipping or after unzipping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bk2 {
+    /// `Header.txt`
+    pub header: String,
+    /// `Input Log.txt`
+    pub input_log: String,
+    /// `Core.bin`, the save state the movie starts from.
+    pub core_state: Option<Vec<u8>>,
+    /// `FrameHashes.txt`, one hex hash per frame. Not part of the BizHawk layout, so other tools
+    /// ignore it.
+    pub frame_hashes: Option<String>,
+}
+
+impl Movie {
+    /// Empty power-on movie for the ROM with checksum `rom_crc`, tagged with this emulator version.
+    pub fn new(rom_crc: u32, dip_switches: u8) -> Self {
+        Self {
+            rom_crc,
+            dip_switches,
+            emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+            author: String::new(),
+            rerecords: 0,
+            start_state: None,
+            frames: Vec::new(),
+            frame_hashes: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let has_hashes = !self.frame_hashes.is_empty();
+        let mut flags = 0;
+        if self.start_state.is_some() {
+            flags |= FLAG_START_STATE;
+        }
+        if has_hashes {
+            flags |= FLAG_FRAME_HASHES;
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&self.rom_crc.to_le_bytes());
+        out.extend_from_slice(&self.rerecords.to_le_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        out.push(self.dip_switches);
+        write_text(&mut out, &self.emulator_version);
+        write_text(&mut out, &self.author);
+
+        if let Some(state) = &self.start_state {
+            out.extend_from_slice(&(state.len() as u32).to_le_bytes());
+            out.extend_from_slice(state);
+        }
+
+        for (i, buttons) in self.frames.iter().enumerate() {
+            out.extend_from_slice(&buttons.to_le_bytes());
+            if has_hashes {
+                out.extend_from_slice(&self.frame_hashes.get(i).copied().unwrap_or(0).to_le_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.bytes(4)? != MAGIC {
+            return Err(MovieError::BadMagic.into());
+        }
+        let version = reader.u16()?;
+        if version == 0 || version > VERSION {
+            return Err(MovieError::UnsupportedVersion { version }.into());
+        }
+
+        let flags = reader.u16()?;
+        let rom_crc = reader.u32()?;
+        let rerecords = reader.u32()?;
+        let frame_count = reader.u32()? as usize;
+        let dip_switches = reader.u8()?;
+        let emulator_version = reader.text()?;
+        let author = reader.text()?;
+
+        let start_state = if flags & FLAG_START_STATE != 0 {
+            let len = reader.u32()? as usize;
+            Some(reader.bytes(len)?.to_vec())
+        } else {
+            None
+        };
+
+        let has_hashes = flags & FLAG_FRAME_HASHES != 0;
+        let mut frames = Vec::new();
+        let mut frame_hashes = Vec::new();
+        for _ in 0..frame_count {
+            frames.push(reader.u16()?);
+            if has_hashes {
+                frame_hashes.push(reader.u64()?);
+            }
+        }
+
+        Ok(Self { rom_crc, dip_switches, emulator_version, author, rerecords, start_state, frames, frame_hashes })
+    }
+
+    pub fn to_bk2(&self) -> Bk2 {
+        let mut header = String::new();
+        header += "MovieVersion BizHawk v2.0.0\n";
+        header += &format!("Author {}\n", self.author);
+        header += &format!("emuVersion space-invaders {}\n", self.emulator_version);
+        header += "Platform Arcade\n";
+        header += "GameName Space Invaders\n";
+        header += "Core space-invaders\n";
+        header += &format!("rerecordCount {}\n", self.rerecords);
+        header += &format!("RomCrc32 {:08X}\n", self.rom_crc);
+        header += &format!("DipSwitches {}\n", self.dip_switches);
+        if self.start_state.is_some() {
+            header += "StartsFromSavestate True\n";
+        }
+
+        let mut input_log = format!("[Input]\nLogKey:{}\n", LOG_KEY);
+        for &buttons in &self.frames {
+            let line: String = MNEMONICS.iter().enumerate()
+                .map(|(bit, &c)| if buttons & 1 << bit != 0 { c } else { '.' })
+                .collect();
+            input_log += &format!("|{}|\n", line);
+        }
+        input_log += "[/Input]\n";
+
+        let frame_hashes = (!self.frame_hashes.is_empty())
+            .then(|| self.frame_hashes.iter().map(|hash| format!("{:016X}\n", hash)).collect());
+
+        Bk2 { header, input_log, core_state: self.start_state.clone(), frame_hashes }
+    }
+
+    /// Reads a `.bk2` movie. Header keys other tools don't write keep their defaults, and any
+    /// non-`.` input log character counts as a pressed button.
+    pub fn from_bk2(bk2: &Bk2) -> Result<Self> {
+        let mut movie = Self::new(0, 0);
+        movie.emulator_version.clear();
+
+        for line in bk2.header.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "Author" => movie.author = value.to_string(),
+                "emuVersion" => movie.emulator_version = value.strip_prefix("space-invaders ").unwrap_or(value).to_string(),
+                "rerecordCount" => movie.rerecords = value.parse().unwrap_or(0),
+                "RomCrc32" => movie.rom_crc = u32::from_str_radix(value, 16).unwrap_or(0),
+                "DipSwitches" => movie.dip_switches = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+
+        for (i, line) in bk2.input_log.lines().enumerate() {
+            let Some(line) = line.strip_prefix('|') else { continue };
+            let buttons: Vec<char> = line.split('|').next().unwrap_or("").chars().collect();
+            if buttons.len() != BUTTONS.len() {
+                return Err(MovieError::InvalidInputLog { line: i + 1 }.into());
+            }
+            movie.frames.push(buttons.iter().enumerate()
+                .filter(|(_, &c)| c != '.' && c != ' ')
+                .fold(0, |mask, (bit, _)| mask | 1 << bit));
+        }
+
+        movie.start_state = bk2.core_state.clone();
+        if let Some(hashes) = &bk2.frame_hashes {
+            for (i, line) in hashes.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+                let hash = u64::from_str_radix(line.trim(), 16).map_err(|_| MovieError::InvalidFrameHashes { line: i + 1 })?;
+                movie.frame_hashes.push(hash);
+            }
+        }
+
+        Ok(movie)
+    }
+
+    /// Builds a power-on movie for any ROM from an input script, which sets the buttons held from
+    /// a frame on with one `<frame> [button...]` line each. Buttons are named as in
+    /// [`Button::from_name`], and `#` starts a comment. The movie ends with the frame of the last
+    /// line, so a last line without buttons sets its length:
+    ///
+    /// ```text
+    /// 100 coin
+    /// 105
+    /// 200 p1start p1shoot
+    /// 600
+    /// ```
+    pub fn from_script(script: &str) -> Result<Self> {
+        let mut changes = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let invalid = || MovieError::InvalidScript { line: i + 1 };
+            let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+            let Some(frame) = words.next() else { continue };
+            let frame: usize = frame.parse().map_err(|_| invalid())?;
+            let buttons = words
+                .map(|name| Button::from_name(name).ok_or_else(invalid))
+                .collect::<core::result::Result<Buttons, _>>()?;
+            changes.push((frame, buttons.bits()));
+        }
+        changes.sort_by_key(|&(frame, _)| frame);
+
+        let mut movie = Self::new(0, 0);
+        if let Some(&(_, last)) = changes.last() {
+            let mut held = 0;
+            for (frame, mask) in changes {
+                movie.frames.resize(frame, held);
+                held = mask;
+            }
+            movie.frames.push(last);
+        }
+        Ok(movie)
+    }
+}
+
+/// Records the buttons held on every frame of an emulator into a [`Movie`].
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    movie: Movie,
+    /// Frames the emulator had run when recording started.
+    start_frame: u64,
+}
+
+impl Recorder {
+    /// Starts a movie from the current state of `emulator`.
+    pub fn start(emulator: &Emulator) -> Self {
+        let mut movie = Movie::new(crate::crc32(emulator.cpu().memory.rom()), emulator.dip_switches());
+        movie.start_state = Some(emulator.save_state_bytes());
+        Self { movie, start_frame: emulator.stats().frames }
+    }
+
+    /// Records the frame `emulator` just ran, meant to be called every time
+    /// [`Emulator::run_frame`] completes one. If an earlier state was loaded in between, the frames
+    /// after it are dropped and counted as a rerecord.
+    pub fn record_frame(&mut self, emulator: &Emulator) {
+        let frame = emulator.stats().frames.saturating_sub(self.start_frame + 1) as usize;
+        if frame < self.movie.frames.len() {
+            self.movie.frames.truncate(frame);
+            self.movie.frame_hashes.truncate(frame);
+            self.movie.rerecords += 1;
+        }
+
+        self.movie.frames.push(emulator.buttons().bits());
+        self.movie.frame_hashes.push(emulator.frame_hash());
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Plays a [`Movie`] back by holding its buttons on an emulator frame by frame.
+#[derive(Debug, Clone)]
+pub struct Player {
+    movie: Movie,
+    frame: usize,
+}
+
+impl Player {
+    /// Puts `emulator` at the start of `movie`: its start state, or a reset with its DIP switches.
+    /// Fails, leaving the emulator untouched, if the movie was recorded with another ROM.
+    pub fn start(movie: Movie, emulator: &mut Emulator) -> Result<Self> {
+        let rom_crc = crate::crc32(emulator.cpu().memory.rom());
+        if movie.rom_crc != 0 && movie.rom_crc != rom_crc {
+            return Err(MovieError::RomMismatch { expected: rom_crc, found: movie.rom_crc }.into());
+        }
+
+        match &movie.start_state {
+            Some(state) => emulator.load_state_bytes(state)?,
+            None => {
+                emulator.reset();
+                emulator.set_dip_switches(movie.dip_switches);
+            }
+        }
+        Ok(Self { movie, frame: 0 })
+    }
+
+    /// Holds the buttons of the next frame, meant to be called before running it. Returns `false`
+    /// once every frame was played.
+    pub fn next_frame(&mut self, emulator: &mut Emulator) -> bool {
+        let Some(&mask) = self.movie.frames.get(self.frame) else { return false };
+        emulator.set_buttons(Buttons::from_bits_truncate(mask));
+        self.frame += 1;
+        true
+    }
+
+    /// Compares the frame just run with its recorded framebuffer hash, if the movie has one.
+    pub fn check_frame(&self, emulator: &Emulator) -> Result<()> {
+        let Some(frame) = self.frame.checked_sub(1) else { return Ok(()) };
+        match self.movie.frame_hashes.get(frame) {
+            Some(&hash) if hash != emulator.frame_hash() => Err(MovieError::Desync { frame }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+/// Frame bitmask of a set of held buttons, the bits of [`Buttons`].
+pub fn mask(buttons: &[Button]) -> u16 {
+    buttons.iter().copied().collect::<Buttons>().bits()
+}
+
+/// Buttons held in a frame bitmask.
+pub fn buttons(mask: u16) -> Vec<Button> {
+    Buttons::from_bits_truncate(mask).buttons().collect()
+}
+
+/// Length-prefixed string, cut to 255 bytes on a character boundary.
+fn write_text(out: &mut Vec<u8>, text: &str) {
+    let mut len = text.len().min(u8::MAX as usize);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(&text.as_bytes()[..len]);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len()).ok_or(MovieError::Truncated)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn text(&mut self) -> Result<String> {
+        let len = self.u8()? as usize;
+        let bytes = self.bytes(len)?;
+        Ok(core::str::from_utf8(bytes).map_err(|_| MovieError::InvalidText)?.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn movie() -> Movie {
+        let mut movie = Movie::new(0x1234_5678, 0b1000_0001);
+        movie.author = "someone".to_string();
+        movie.rerecords = 42;
+        movie.frames = vec![0, mask(&[Button::Coin]), mask(&[Button::P1Start, Button::P1Shoot]), mask(&[Button::Tilt])];
+        movie.frame_hashes = vec![1, 2, 3, u64::MAX];
+        movie
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut movie = movie();
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+
+        movie.start_state = Some(vec![1, 2, 3]);
+        movie.frame_hashes.clear();
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        let bytes = movie().to_bytes();
+        assert!(matches!(Movie::from_bytes(b"SIST"), Err(Error::Movie(MovieError::BadMagic))));
+        assert!(matches!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(Error::Movie(MovieError::Truncated))));
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(Movie::from_bytes(&newer), Err(Error::Movie(MovieError::UnsupportedVersion { .. }))));
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+        let inputs = |frame: usize| match frame {
+            10..=12 => vec![Button::Coin],
+            40..=42 => vec![Button::P1Start],
+            60.. => vec![if frame % 40 < 20 { Button::P1Left } else { Button::P1Right }, Button::P1Shoot],
+            _ => vec![],
+        };
+
+        let mut emulator = Emulator::new(ROM);
+        emulator.run_frame(&mut ()).unwrap();
+        let mut recorder = Recorder::start(&emulator);
+        for frame in 0..120 {
+            for button in BUTTONS {
+                emulator.button_release(button);
+            }
+            for button in inputs(frame) {
+                emulator.button_press(button);
+            }
+            emulator.run_frame(&mut ()).unwrap();
+            recorder.record_frame(&emulator);
+        }
+        let movie = recorder.finish();
+        assert_eq!(movie.frames.len(), 120);
+        assert_eq!(buttons(movie.frames[11]).len(), 1);
+
+        let mut replay = Emulator::new(ROM);
+        let mut player = Player::start(movie.clone(), &mut replay).unwrap();
+        while player.next_frame(&mut replay) {
+            replay.run_frame(&mut ()).unwrap();
+            player.check_frame(&replay).unwrap();
+        }
+        assert_eq!(replay.save_state_bytes(), emulator.save_state_bytes());
+
+        let mut other = Emulator::new(&[0; 16]);
+        assert!(matches!(Player::start(movie, &mut other), Err(Error::Movie(MovieError::RomMismatch { .. }))));
+    }
+
+    #[test]
+    fn test_rerecord() {
+        let mut emulator = Emulator::new(&[0x31, 0x00, 0x24, 0xC3, 0x03, 0x00]);
+        let mut recorder = Recorder::start(&emulator);
+        emulator.run_frame(&mut ()).unwrap();
+        recorder.record_frame(&emulator);
+        let state = emulator.save_state_bytes();
+        emulator.run_frame(&mut ()).unwrap();
+        recorder.record_frame(&emulator);
+
+        emulator.load_state_bytes(&state).unwrap();
+        emulator.button_press(Button::P1Shoot);
+        emulator.run_frame(&mut ()).unwrap();
+        recorder.record_frame(&emulator);
+        assert_eq!(recorder.movie().frames, [0, mask(&[Button::P1Shoot])]);
+        assert_eq!(recorder.movie().rerecords, 1);
+    }
+
+    #[test]
+    fn test_bk2_round_trip() {
+        let mut movie = movie();
+        movie.start_state = Some(vec![4, 5, 6]);
+        let bk2 = movie.to_bk2();
+
+        assert!(bk2.header.contains("rerecordCount 42\n"));
+        assert!(bk2.input_log.contains("|.1...F....|\n"));
+        assert_eq!(Movie::from_bk2(&bk2).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_bk2_from_other_tools() {
+        let bk2 = Bk2 {
+            header: "MovieVersion BizHawk v2.0.0\nrerecordCount 7\n".to_string(),
+            input_log: format!("[Input]\nLogKey:{}\n|C.........|\n|.1...F....|\n[/Input]\n", LOG_KEY),
+            core_state: None,
+            frame_hashes: None,
+        };
+        let movie = Movie::from_bk2(&bk2).unwrap();
+        assert_eq!(movie.rerecords, 7);
+        assert_eq!(movie.rom_crc, 0);
+        assert_eq!(buttons(movie.frames[1]).len(), 2);
+
+        let bad = Bk2 { input_log: "[Input]\n|C..|\n".to_string(), ..bk2 };
+        assert!(matches!(Movie::from_bk2(&bad), Err(Error::Movie(MovieError::InvalidInputLog { line: 2 }))));
+    }
+
+    #[test]
+    fn test_from_script() {
+        let movie = Movie::from_script("# insert a coin\n2 Coin\n\n4   # release\n3 p1left P1SHOOT\n5\n").unwrap();
+        assert_eq!(movie.frames, [0, 0, mask(&[Button::Coin]), mask(&[Button::P1Left, Button::P1Shoot]), 0, 0]);
+
+        assert!(matches!(Movie::from_script("10 coin\nsoon p1start\n"), Err(Error::Movie(MovieError::InvalidScript { line: 2 }))));
+        assert!(matches!(Movie::from_script("10 jump\n"), Err(Error::Movie(MovieError::InvalidScript { line: 1 }))));
+    }
+}
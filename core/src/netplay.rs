@@ -0,0 +1,399 @@
+//! Lockstep netplay: two emulators on different machines run the same frames with the same inputs,
+//! so they stay in sync without ever sending more than buttons. The host plays player 1 and the
+//! guest player 2, each with the player 1 controls of their own keyboard.
+//!
+//! The host starts a [`Session`] from its current state and sends the guest a [`Message::Hello`]
+//! with it. From then on each side sends its buttons for the frame [`Session::delay`] frames
+//! ahead, which hides the round trip as long as it's shorter than the delay, and only runs a frame
+//! once both inputs for it are in. Every [`HASH_INTERVAL`] frames both send their
+//! [`Emulator::state_hash`], and the first mismatch is reported as a desync.
+//!
+//! Sessions don't do any I/O. Messages go over whatever reliable, ordered transport the caller
+//! likes, encoded like this:
+//!
+//! ```text
+//! offset  size  field
+//! 0       1     kind: 0 hello, 1 input, 2 hash
+//! hello:
+//! 1       2     protocol version (little endian, like every other integer)
+//! 3       4     CRC32 of the host's ROM
+//! 7       1     input delay in frames
+//! 8       ..    save state the session starts from, up to the end
+//! input:
+//! 1       8     frame the buttons are for
+//! 9       2     buttons held, the bits of Buttons
+//! hash:
+//! 1       8     frames run
+//! 9       8     state hash after them
+//! ```
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use crate::{Buttons, Emulator, Error, Result};
+
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Frames between state hash checks, one second.
+pub const HASH_INTERVAL: u64 = 60;
+
+const KIND_HELLO: u8 = 0;
+const KIND_INPUT: u8 = 1;
+const KIND_HASH: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetplayError {
+    /// A message that doesn't decode.
+    Malformed,
+    UnsupportedVersion { version: u16 },
+    RomMismatch { expected: u32, found: u32 },
+    /// A message that makes no sense at this point of the session, like a second hello.
+    Unexpected,
+    /// Inputs arrived for another frame than the next one missing.
+    OutOfOrder { expected: u64, found: u64 },
+    /// The two emulators' states differ after `frame` frames.
+    Desync { frame: u64 },
+}
+
+impl Display for NetplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "malformed netplay message"),
+            Self::UnsupportedVersion { version } => write!(f, "unsupported netplay protocol version {} (expected {})", version, PROTOCOL_VERSION),
+            Self::RomMismatch { expected, found } => write!(f, "the host runs a different ROM (CRC32 {:08X}, loaded ROM is {:08X})", found, expected),
+            Self::Unexpected => write!(f, "unexpected netplay message"),
+            Self::OutOfOrder { expected, found } => write!(f, "got inputs for frame {} while waiting for frame {}", found, expected),
+            Self::Desync { frame } => write!(f, "desynced at frame {}", frame),
+        }
+    }
+}
+
+impl From<NetplayError> for Error {
+    fn from(e: NetplayError) -> Self {
+        Error::Netplay(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Plays player 1 and decides the start state and input delay.
+    Host,
+    /// Plays player 2.
+    Guest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Hello { version: u16, rom_crc: u32, delay: u8, state: Vec<u8> },
+    Input { frame: u64, buttons: Buttons },
+    Hash { frame: u64, hash: u64 },
+}
+
+impl Message {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Self::Hello { version, rom_crc, delay, state } => {
+                out.push(KIND_HELLO);
+                out.extend_from_slice(&version.to_le_bytes());
+                out.extend_from_slice(&rom_crc.to_le_bytes());
+                out.push(*delay);
+                out.extend_from_slice(state);
+            }
+            Self::Input { frame, buttons } => {
+                out.push(KIND_INPUT);
+                out.extend_from_slice(&frame.to_le_bytes());
+                out.extend_from_slice(&buttons.bits().to_le_bytes());
+            }
+            Self::Hash { frame, hash } => {
+                out.push(KIND_HASH);
+                out.extend_from_slice(&frame.to_le_bytes());
+                out.extend_from_slice(&hash.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let field = |range: core::ops::Range<usize>| data.get(range).ok_or(NetplayError::Malformed);
+        let u64_at = |at: usize| field(at..at + 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+
+        let message = match data.first() {
+            Some(&KIND_HELLO) => Self::Hello {
+                version: u16::from_le_bytes(field(1..3)?.try_into().unwrap()),
+                rom_crc: u32::from_le_bytes(field(3..7)?.try_into().unwrap()),
+                delay: field(7..8)?[0],
+                state: data[8..].to_vec(),
+            },
+            Some(&KIND_INPUT) if data.len() == 11 => Self::Input {
+                frame: u64_at(1)?,
+                buttons: Buttons::from_bits_truncate(u16::from_le_bytes(field(9..11)?.try_into().unwrap())),
+            },
+            Some(&KIND_HASH) if data.len() == 17 => Self::Hash { frame: u64_at(1)?, hash: u64_at(9)? },
+            _ => return Err(NetplayError::Malformed.into()),
+        };
+        Ok(message)
+    }
+}
+
+/// One side of a lockstep session.
+#[derive(Debug, Clone)]
+pub struct Session {
+    side: Side,
+    delay: u32,
+    /// Frames run so far.
+    frame: u64,
+    /// Buttons of each side for the frames from `frame` on.
+    local: VecDeque<Buttons>,
+    remote: VecDeque<Buttons>,
+    /// State hashes of each side not compared yet, oldest first.
+    local_hashes: VecDeque<(u64, u64)>,
+    remote_hashes: VecDeque<(u64, u64)>,
+}
+
+impl Session {
+    /// Hosts a session starting from the current state of `emulator`, returning the hello to
+    /// send to the guest.
+    pub fn host(emulator: &Emulator, delay: u8) -> (Self, Message) {
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            rom_crc: crate::crc32(emulator.cpu().memory.rom()),
+            delay,
+            state: emulator.save_state_bytes(),
+        };
+        (Self::new(Side::Host, delay as u32), hello)
+    }
+
+    /// Joins the session the host sent `hello` for, loading its start state into `emulator`.
+    /// Fails, leaving the emulator untouched, if the host runs another ROM.
+    pub fn join(hello: &Message, emulator: &mut Emulator) -> Result<Self> {
+        let Message::Hello { version, rom_crc, delay, state } = hello else {
+            return Err(NetplayError::Unexpected.into());
+        };
+        if *version != PROTOCOL_VERSION {
+            return Err(NetplayError::UnsupportedVersion { version: *version }.into());
+        }
+        let expected = crate::crc32(emulator.cpu().memory.rom());
+        if *rom_crc != expected {
+            return Err(NetplayError::RomMismatch { expected, found: *rom_crc }.into());
+        }
+
+        emulator.load_state_bytes(state)?;
+        Ok(Self::new(Side::Guest, *delay as u32))
+    }
+
+    fn new(side: Side, delay: u32) -> Self {
+        // Nobody presses anything during the first frames, before the first inputs can arrive
+        let idle: VecDeque<Buttons> = (0..delay).map(|_| Buttons::empty()).collect();
+        Self {
+            side,
+            delay,
+            frame: 0,
+            local: idle.clone(),
+            remote: idle,
+            local_hashes: VecDeque::new(),
+            remote_hashes: VecDeque::new(),
+        }
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Frames between pressing a button and the emulators seeing it.
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+
+    /// Frames run since the session started.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Schedules the local player's `buttons` for the first frame without local input yet,
+    /// `delay` frames ahead, and returns the message announcing them. Returns `None` without
+    /// scheduling anything while that frame is further ahead, because the other side is late.
+    pub fn add_local_input(&mut self, buttons: Buttons) -> Option<Message> {
+        if self.local.len() as u32 > self.delay {
+            return None;
+        }
+        let frame = self.frame + self.local.len() as u64;
+        self.local.push_back(buttons);
+        Some(Message::Input { frame, buttons })
+    }
+
+    /// Handles a message from the other side.
+    pub fn receive(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Input { frame, buttons } => {
+                let expected = self.frame + self.remote.len() as u64;
+                if frame != expected {
+                    return Err(NetplayError::OutOfOrder { expected, found: frame }.into());
+                }
+                self.remote.push_back(buttons);
+            }
+            Message::Hash { frame, hash } => {
+                self.remote_hashes.push_back((frame, hash));
+                self.compare_hashes()?;
+            }
+            Message::Hello { .. } => return Err(NetplayError::Unexpected.into()),
+        }
+        Ok(())
+    }
+
+    /// Buttons to hold during the next frame once both sides' inputs for it are in, `None` while
+    /// waiting for the other side. Counts the frame as run.
+    pub fn next_frame(&mut self) -> Option<Buttons> {
+        if self.local.is_empty() || self.remote.is_empty() {
+            return None;
+        }
+        let (local, remote) = (self.local.pop_front().unwrap(), self.remote.pop_front().unwrap());
+        self.frame += 1;
+        Some(match self.side {
+            Side::Host => combine(local, remote),
+            Side::Guest => combine(remote, local),
+        })
+    }
+
+    /// Meant to be called after running the frame [`Session::next_frame`] gave the buttons for.
+    /// Every [`HASH_INTERVAL`] frames, returns the state hash to send to the other side.
+    pub fn end_frame(&mut self, emulator: &Emulator) -> Result<Option<Message>> {
+        if !self.frame.is_multiple_of(HASH_INTERVAL) {
+            return Ok(None);
+        }
+        let hash = emulator.state_hash();
+        self.local_hashes.push_back((self.frame, hash));
+        self.compare_hashes()?;
+        Ok(Some(Message::Hash { frame: self.frame, hash }))
+    }
+
+    fn compare_hashes(&mut self) -> Result<()> {
+        while let (Some(&(frame, local)), Some(&(remote_frame, remote))) = (self.local_hashes.front(), self.remote_hashes.front()) {
+            if frame != remote_frame {
+                return Err(NetplayError::OutOfOrder { expected: frame, found: remote_frame }.into());
+            }
+            if local != remote {
+                return Err(NetplayError::Desync { frame }.into());
+            }
+            self.local_hashes.pop_front();
+            self.remote_hashes.pop_front();
+        }
+        Ok(())
+    }
+}
+
+/// Buttons of the host and the guest held together. The guest's player 1 controls become the
+/// player 2 ones, everything else like coins and start buttons is shared.
+pub fn combine(host: Buttons, guest: Buttons) -> Buttons {
+    let mut guest_p2 = guest - (Buttons::P1_LEFT | Buttons::P1_RIGHT | Buttons::P1_SHOOT);
+    guest_p2.set(Buttons::P2_LEFT, guest.contains(Buttons::P1_LEFT));
+    guest_p2.set(Buttons::P2_RIGHT, guest.contains(Buttons::P1_RIGHT));
+    guest_p2.set(Buttons::P2_SHOOT, guest.contains(Buttons::P1_SHOOT));
+    host | guest_p2
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Button;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    type Peer = (Session, Emulator);
+
+    /// Runs both sides for `frames` frames, each side's input coming from `input`, delivering
+    /// messages right away. Returns the first error either side reports.
+    fn run(host: &mut Peer, guest: &mut Peer, frames: u64, input: impl Fn(Side, u64) -> Buttons) -> Result<()> {
+        for _ in 0..frames {
+            send_input(host, guest, &input)?;
+            send_input(guest, host, &input)?;
+            run_frame(host, guest)?;
+            run_frame(guest, host)?;
+        }
+        Ok(())
+    }
+
+    fn send_input(from: &mut Peer, to: &mut Peer, input: impl Fn(Side, u64) -> Buttons) -> Result<()> {
+        let buttons = input(from.0.side(), from.0.frame());
+        match from.0.add_local_input(buttons) {
+            Some(message) => to.0.receive(Message::from_bytes(&message.to_bytes())?),
+            None => Ok(()),
+        }
+    }
+
+    fn run_frame(side: &mut Peer, other: &mut Peer) -> Result<()> {
+        let buttons = side.0.next_frame().expect("both inputs are in");
+        side.1.set_buttons(buttons);
+        side.1.run_frame(&mut ())?;
+        if let Some(message) = side.0.end_frame(&side.1)? {
+            other.0.receive(message)?;
+        }
+        Ok(())
+    }
+
+    /// Credits two coins and starts a two-player game, the guest inserting one of the coins.
+    fn two_players(side: Side, frame: u64) -> Buttons {
+        match (side, frame) {
+            (Side::Host, 100..=104) | (Side::Guest, 120..=124) => Button::Coin.into(),
+            (Side::Host, 200..=204) => Button::P2Start.into(),
+            (Side::Host, 400..) if frame % 40 < 20 => Buttons::P1_LEFT | Buttons::P1_SHOOT,
+            (Side::Guest, 400..) if frame % 30 < 15 => Buttons::P1_RIGHT | Buttons::P1_SHOOT,
+            _ => Buttons::empty(),
+        }
+    }
+
+    fn start(delay: u8) -> (Peer, Peer) {
+        let mut host = Emulator::new(ROM);
+        host.run_frame(&mut ()).unwrap();
+        let (host_session, hello) = Session::host(&host, delay);
+        let hello = Message::from_bytes(&hello.to_bytes()).unwrap();
+
+        let mut guest = Emulator::new(ROM);
+        let guest_session = Session::join(&hello, &mut guest).unwrap();
+        assert_eq!(guest_session.delay(), delay as u32);
+        ((host_session, host), (guest_session, guest))
+    }
+
+    #[test]
+    fn test_lockstep() {
+        let (mut host, mut guest) = start(2);
+        run(&mut host, &mut guest, 2000, two_players).unwrap();
+        assert_eq!(host.0.frame(), 2000);
+        assert_eq!(host.1.state_hash(), guest.1.state_hash());
+
+        // Both players got to play
+        let state = crate::SpaceInvadersState::from_memory(&host.1.cpu().memory);
+        assert!(state.scores[0] > 0 && state.scores[1] > 0, "scores {:?}", state.scores);
+    }
+
+    #[test]
+    fn test_desync() {
+        let (mut host, mut guest) = start(0);
+        run(&mut host, &mut guest, 30, two_players).unwrap();
+        guest.1.cpu_mut().memory.write(0x20F8, 0x50);
+        let result = run(&mut host, &mut guest, HASH_INTERVAL, two_players);
+        assert!(matches!(result, Err(Error::Netplay(NetplayError::Desync { frame: HASH_INTERVAL }))));
+    }
+
+    #[test]
+    fn test_waits_for_the_other_side() {
+        let (mut session, _) = start(0).0;
+        assert!(session.add_local_input(Buttons::empty()).is_some());
+        assert!(session.add_local_input(Buttons::empty()).is_none());
+        assert_eq!(session.next_frame(), None);
+
+        session.receive(Message::Input { frame: 0, buttons: Buttons::P1_SHOOT }).unwrap();
+        assert_eq!(session.next_frame(), Some(Buttons::P2_SHOOT));
+        assert!(matches!(
+            session.receive(Message::Input { frame: 3, buttons: Buttons::empty() }),
+            Err(Error::Netplay(NetplayError::OutOfOrder { expected: 1, found: 3 }))
+        ));
+    }
+
+    #[test]
+    fn test_rom_mismatch() {
+        let (_, hello) = Session::host(&Emulator::new(ROM), 2);
+        let mut other = Emulator::new(&[0; 0x2000]);
+        assert!(matches!(Session::join(&hello, &mut other), Err(Error::Netplay(NetplayError::RomMismatch { .. }))));
+        assert!(Message::from_bytes(&[KIND_INPUT, 0]).is_err());
+    }
+}
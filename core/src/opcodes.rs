@@ -0,0 +1,291 @@
+//! Length, mnemonic and timing of every 8080 opcode, shared by the disassembler and the CPU's
+//! dispatch table, and checked against the CPU by its tests.
+//!
+//! Operands in mnemonics are written as `d8`, `d16` or `a16` placeholders, where the `Display`
+//! of [`Instruction`](crate::Instruction), which [`disasm`](crate::disasm) listings use, writes
+//! the actual values. Undocumented opcodes are listed under the instruction the 8080 actually
+//! executes for them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Opcode {
+    pub mnemonic: &'static str,
+    pub len: u8,
+    /// T-states, or for conditional calls and returns the T-states when the condition fails.
+    pub cycles: u8,
+    /// T-states when the condition of a conditional call or return holds.
+    pub cycles_taken: u8,
+}
+
+impl Opcode {
+    const fn new(mnemonic: &'static str, len: u8, cycles: u8) -> Self {
+        Self { mnemonic, len, cycles, cycles_taken: cycles }
+    }
+
+    const fn conditional(mnemonic: &'static str, len: u8, cycles: u8, cycles_taken: u8) -> Self {
+        Self { mnemonic, len, cycles, cycles_taken }
+    }
+}
+
+/// Opcodes Intel left undefined, which the 8080 runs as NOP, JMP, RET or CALL.
+pub(crate) fn undocumented(opcode: u8) -> bool {
+    matches!(opcode, 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xCB | 0xD9 | 0xDD | 0xED | 0xFD)
+}
+
+pub(crate) const OPCODES: [Opcode; 256] = [
+    /* 0x00 */ Opcode::new("NOP",        1,  4),
+    /* 0x01 */ Opcode::new("LXI B,d16",  3, 10),
+    /* 0x02 */ Opcode::new("STAX B",     1,  7),
+    /* 0x03 */ Opcode::new("INX B",      1,  5),
+    /* 0x04 */ Opcode::new("INR B",      1,  5),
+    /* 0x05 */ Opcode::new("DCR B",      1,  5),
+    /* 0x06 */ Opcode::new("MVI B,d8",   2,  7),
+    /* 0x07 */ Opcode::new("RLC",        1,  4),
+    /* 0x08 */ Opcode::new("NOP",        1,  4),
+    /* 0x09 */ Opcode::new("DAD B",      1, 10),
+    /* 0x0A */ Opcode::new("LDAX B",     1,  7),
+    /* 0x0B */ Opcode::new("DCX B",      1,  5),
+    /* 0x0C */ Opcode::new("INR C",      1,  5),
+    /* 0x0D */ Opcode::new("DCR C",      1,  5),
+    /* 0x0E */ Opcode::new("MVI C,d8",   2,  7),
+    /* 0x0F */ Opcode::new("RRC",        1,  4),
+    /* 0x10 */ Opcode::new("NOP",        1,  4),
+    /* 0x11 */ Opcode::new("LXI D,d16",  3, 10),
+    /* 0x12 */ Opcode::new("STAX D",     1,  7),
+    /* 0x13 */ Opcode::new("INX D",      1,  5),
+    /* 0x14 */ Opcode::new("INR D",      1,  5),
+    /* 0x15 */ Opcode::new("DCR D",      1,  5),
+    /* 0x16 */ Opcode::new("MVI D,d8",   2,  7),
+    /* 0x17 */ Opcode::new("RAL",        1,  4),
+    /* 0x18 */ Opcode::new("NOP",        1,  4),
+    /* 0x19 */ Opcode::new("DAD D",      1, 10),
+    /* 0x1A */ Opcode::new("LDAX D",     1,  7),
+    /* 0x1B */ Opcode::new("DCX D",      1,  5),
+    /* 0x1C */ Opcode::new("INR E",      1,  5),
+    /* 0x1D */ Opcode::new("DCR E",      1,  5),
+    /* 0x1E */ Opcode::new("MVI E,d8",   2,  7),
+    /* 0x1F */ Opcode::new("RAR",        1,  4),
+    /* 0x20 */ Opcode::new("NOP",        1,  4),
+    /* 0x21 */ Opcode::new("LXI H,d16",  3, 10),
+    /* 0x22 */ Opcode::new("SHLD a16",   3, 16),
+    /* 0x23 */ Opcode::new("INX H",      1,  5),
+    /* 0x24 */ Opcode::new("INR H",      1,  5),
+    /* 0x25 */ Opcode::new("DCR H",      1,  5),
+    /* 0x26 */ Opcode::new("MVI H,d8",   2,  7),
+    /* 0x27 */ Opcode::new("DAA",        1,  4),
+    /* 0x28 */ Opcode::new("NOP",        1,  4),
+    /* 0x29 */ Opcode::new("DAD H",      1, 10),
+    /* 0x2A */ Opcode::new("LHLD a16",   3, 16),
+    /* 0x2B */ Opcode::new("DCX H",      1,  5),
+    /* 0x2C */ Opcode::new("INR L",      1,  5),
+    /* 0x2D */ Opcode::new("DCR L",      1,  5),
+    /* 0x2E */ Opcode::new("MVI L,d8",   2,  7),
+    /* 0x2F */ Opcode::new("CMA",        1,  4),
+    /* 0x30 */ Opcode::new("NOP",        1,  4),
+    /* 0x31 */ Opcode::new("LXI SP,d16", 3, 10),
+    /* 0x32 */ Opcode::new("STA a16",    3, 13),
+    /* 0x33 */ Opcode::new("INX SP",     1,  5),
+    /* 0x34 */ Opcode::new("INR M",      1, 10),
+    /* 0x35 */ Opcode::new("DCR M",      1, 10),
+    /* 0x36 */ Opcode::new("MVI M,d8",   2, 10),
+    /* 0x37 */ Opcode::new("STC",        1,  4),
+    /* 0x38 */ Opcode::new("NOP",        1,  4),
+    /* 0x39 */ Opcode::new("DAD SP",     1, 10),
+    /* 0x3A */ Opcode::new("LDA a16",    3, 13),
+    /* 0x3B */ Opcode::new("DCX SP",     1,  5),
+    /* 0x3C */ Opcode::new("INR A",      1,  5),
+    /* 0x3D */ Opcode::new("DCR A",      1,  5),
+    /* 0x3E */ Opcode::new("MVI A,d8",   2,  7),
+    /* 0x3F */ Opcode::new("CMC",        1,  4),
+    /* 0x40 */ Opcode::new("MOV B,B",    1,  5),
+    /* 0x41 */ Opcode::new("MOV B,C",    1,  5),
+    /* 0x42 */ Opcode::new("MOV B,D",    1,  5),
+    /* 0x43 */ Opcode::new("MOV B,E",    1,  5),
+    /* 0x44 */ Opcode::new("MOV B,H",    1,  5),
+    /* 0x45 */ Opcode::new("MOV B,L",    1,  5),
+    /* 0x46 */ Opcode::new("MOV B,M",    1,  7),
+    /* 0x47 */ Opcode::new("MOV B,A",    1,  5),
+    /* 0x48 */ Opcode::new("MOV C,B",    1,  5),
+    /* 0x49 */ Opcode::new("MOV C,C",    1,  5),
+    /* 0x4A */ Opcode::new("MOV C,D",    1,  5),
+    /* 0x4B */ Opcode::new("MOV C,E",    1,  5),
+    /* 0x4C */ Opcode::new("MOV C,H",    1,  5),
+    /* 0x4D */ Opcode::new("MOV C,L",    1,  5),
+    /* 0x4E */ Opcode::new("MOV C,M",    1,  7),
+    /* 0x4F */ Opcode::new("MOV C,A",    1,  5),
+    /* 0x50 */ Opcode::new("MOV D,B",    1,  5),
+    /* 0x51 */ Opcode::new("MOV D,C",    1,  5),
+    /* 0x52 */ Opcode::new("MOV D,D",    1,  5),
+    /* 0x53 */ Opcode::new("MOV D,E",    1,  5),
+    /* 0x54 */ Opcode::new("MOV D,H",    1,  5),
+    /* 0x55 */ Opcode::new("MOV D,L",    1,  5),
+    /* 0x56 */ Opcode::new("MOV D,M",    1,  7),
+    /* 0x57 */ Opcode::new("MOV D,A",    1,  5),
+    /* 0x58 */ Opcode::new("MOV E,B",    1,  5),
+    /* 0x59 */ Opcode::new("MOV E,C",    1,  5),
+    /* 0x5A */ Opcode::new("MOV E,D",    1,  5),
+    /* 0x5B */ Opcode::new("MOV E,E",    1,  5),
+    /* 0x5C */ Opcode::new("MOV E,H",    1,  5),
+    /* 0x5D */ Opcode::new("MOV E,L",    1,  5),
+    /* 0x5E */ Opcode::new("MOV E,M",    1,  7),
+    /* 0x5F */ Opcode::new("MOV E,A",    1,  5),
+    /* 0x60 */ Opcode::new("MOV H,B",    1,  5),
+    /* 0x61 */ Opcode::new("MOV H,C",    1,  5),
+    /* 0x62 */ Opcode::new("MOV H,D",    1,  5),
+    /* 0x63 */ Opcode::new("MOV H,E",    1,  5),
+    /* 0x64 */ Opcode::new("MOV H,H",    1,  5),
+    /* 0x65 */ Opcode::new("MOV H,L",    1,  5),
+    /* 0x66 */ Opcode::new("MOV H,M",    1,  7),
+    /* 0x67 */ Opcode::new("MOV H,A",    1,  5),
+    /* 0x68 */ Opcode::new("MOV L,B",    1,  5),
+    /* 0x69 */ Opcode::new("MOV L,C",    1,  5),
+    /* 0x6A */ Opcode::new("MOV L,D",    1,  5),
+    /* 0x6B */ Opcode::new("MOV L,E",    1,  5),
+    /* 0x6C */ Opcode::new("MOV L,H",    1,  5),
+    /* 0x6D */ Opcode::new("MOV L,L",    1,  5),
+    /* 0x6E */ Opcode::new("MOV L,M",    1,  7),
+    /* 0x6F */ Opcode::new("MOV L,A",    1,  5),
+    /* 0x70 */ Opcode::new("MOV M,B",    1,  7),
+    /* 0x71 */ Opcode::new("MOV M,C",    1,  7),
+    /* 0x72 */ Opcode::new("MOV M,D",    1,  7),
+    /* 0x73 */ Opcode::new("MOV M,E",    1,  7),
+    /* 0x74 */ Opcode::new("MOV M,H",    1,  7),
+    /* 0x75 */ Opcode::new("MOV M,L",    1,  7),
+    /* 0x76 */ Opcode::new("HLT",        1,  7),
+    /* 0x77 */ Opcode::new("MOV M,A",    1,  7),
+    /* 0x78 */ Opcode::new("MOV A,B",    1,  5),
+    /* 0x79 */ Opcode::new("MOV A,C",    1,  5),
+    /* 0x7A */ Opcode::new("MOV A,D",    1,  5),
+    /* 0x7B */ Opcode::new("MOV A,E",    1,  5),
+    /* 0x7C */ Opcode::new("MOV A,H",    1,  5),
+    /* 0x7D */ Opcode::new("MOV A,L",    1,  5),
+    /* 0x7E */ Opcode::new("MOV A,M",    1,  7),
+    /* 0x7F */ Opcode::new("MOV A,A",    1,  5),
+    /* 0x80 */ Opcode::new("ADD B",      1,  4),
+    /* 0x81 */ Opcode::new("ADD C",      1,  4),
+    /* 0x82 */ Opcode::new("ADD D",      1,  4),
+    /* 0x83 */ Opcode::new("ADD E",      1,  4),
+    /* 0x84 */ Opcode::new("ADD H",      1,  4),
+    /* 0x85 */ Opcode::new("ADD L",      1,  4),
+    /* 0x86 */ Opcode::new("ADD M",      1,  7),
+    /* 0x87 */ Opcode::new("ADD A",      1,  4),
+    /* 0x88 */ Opcode::new("ADC B",      1,  4),
+    /* 0x89 */ Opcode::new("ADC C",      1,  4),
+    /* 0x8A */ Opcode::new("ADC D",      1,  4),
+    /* 0x8B */ Opcode::new("ADC E",      1,  4),
+    /* 0x8C */ Opcode::new("ADC H",      1,  4),
+    /* 0x8D */ Opcode::new("ADC L",      1,  4),
+    /* 0x8E */ Opcode::new("ADC M",      1,  7),
+    /* 0x8F */ Opcode::new("ADC A",      1,  4),
+    /* 0x90 */ Opcode::new("SUB B",      1,  4),
+    /* 0x91 */ Opcode::new("SUB C",      1,  4),
+    /* 0x92 */ Opcode::new("SUB D",      1,  4),
+    /* 0x93 */ Opcode::new("SUB E",      1,  4),
+    /* 0x94 */ Opcode::new("SUB H",      1,  4),
+    /* 0x95 */ Opcode::new("SUB L",      1,  4),
+    /* 0x96 */ Opcode::new("SUB M",      1,  7),
+    /* 0x97 */ Opcode::new("SUB A",      1,  4),
+    /* 0x98 */ Opcode::new("SBB B",      1,  4),
+    /* 0x99 */ Opcode::new("SBB C",      1,  4),
+    /* 0x9A */ Opcode::new("SBB D",      1,  4),
+    /* 0x9B */ Opcode::new("SBB E",      1,  4),
+    /* 0x9C */ Opcode::new("SBB H",      1,  4),
+    /* 0x9D */ Opcode::new("SBB L",      1,  4),
+    /* 0x9E */ Opcode::new("SBB M",      1,  7),
+    /* 0x9F */ Opcode::new("SBB A",      1,  4),
+    /* 0xA0 */ Opcode::new("ANA B",      1,  4),
+    /* 0xA1 */ Opcode::new("ANA C",      1,  4),
+    /* 0xA2 */ Opcode::new("ANA D",      1,  4),
+    /* 0xA3 */ Opcode::new("ANA E",      1,  4),
+    /* 0xA4 */ Opcode::new("ANA H",      1,  4),
+    /* 0xA5 */ Opcode::new("ANA L",      1,  4),
+    /* 0xA6 */ Opcode::new("ANA M",      1,  7),
+    /* 0xA7 */ Opcode::new("ANA A",      1,  4),
+    /* 0xA8 */ Opcode::new("XRA B",      1,  4),
+    /* 0xA9 */ Opcode::new("XRA C",      1,  4),
+    /* 0xAA */ Opcode::new("XRA D",      1,  4),
+    /* 0xAB */ Opcode::new("XRA E",      1,  4),
+    /* 0xAC */ Opcode::new("XRA H",      1,  4),
+    /* 0xAD */ Opcode::new("XRA L",      1,  4),
+    /* 0xAE */ Opcode::new("XRA M",      1,  7),
+    /* 0xAF */ Opcode::new("XRA A",      1,  4),
+    /* 0xB0 */ Opcode::new("ORA B",      1,  4),
+    /* 0xB1 */ Opcode::new("ORA C",      1,  4),
+    /* 0xB2 */ Opcode::new("ORA D",      1,  4),
+    /* 0xB3 */ Opcode::new("ORA E",      1,  4),
+    /* 0xB4 */ Opcode::new("ORA H",      1,  4),
+    /* 0xB5 */ Opcode::new("ORA L",      1,  4),
+    /* 0xB6 */ Opcode::new("ORA M",      1,  7),
+    /* 0xB7 */ Opcode::new("ORA A",      1,  4),
+    /* 0xB8 */ Opcode::new("CMP B",      1,  4),
+    /* 0xB9 */ Opcode::new("CMP C",      1,  4),
+    /* 0xBA */ Opcode::new("CMP D",      1,  4),
+    /* 0xBB */ Opcode::new("CMP E",      1,  4),
+    /* 0xBC */ Opcode::new("CMP H",      1,  4),
+    /* 0xBD */ Opcode::new("CMP L",      1,  4),
+    /* 0xBE */ Opcode::new("CMP M",      1,  7),
+    /* 0xBF */ Opcode::new("CMP A",      1,  4),
+    /* 0xC0 */ Opcode::conditional("RNZ",        1,  5, 11),
+    /* 0xC1 */ Opcode::new("POP B",      1, 10),
+    /* 0xC2 */ Opcode::new("JNZ a16",    3, 10),
+    /* 0xC3 */ Opcode::new("JMP a16",    3, 10),
+    /* 0xC4 */ Opcode::conditional("CNZ a16",    3, 11, 17),
+    /* 0xC5 */ Opcode::new("PUSH B",     1, 11),
+    /* 0xC6 */ Opcode::new("ADI d8",     2,  7),
+    /* 0xC7 */ Opcode::new("RST 0",      1, 11),
+    /* 0xC8 */ Opcode::conditional("RZ",         1,  5, 11),
+    /* 0xC9 */ Opcode::new("RET",        1, 10),
+    /* 0xCA */ Opcode::new("JZ a16",     3, 10),
+    /* 0xCB */ Opcode::new("JMP a16",    3, 10),
+    /* 0xCC */ Opcode::conditional("CZ a16",     3, 11, 17),
+    /* 0xCD */ Opcode::new("CALL a16",   3, 17),
+    /* 0xCE */ Opcode::new("ACI d8",     2,  7),
+    /* 0xCF */ Opcode::new("RST 1",      1, 11),
+    /* 0xD0 */ Opcode::conditional("RNC",        1,  5, 11),
+    /* 0xD1 */ Opcode::new("POP D",      1, 10),
+    /* 0xD2 */ Opcode::new("JNC a16",    3, 10),
+    /* 0xD3 */ Opcode::new("OUT d8",     2, 10),
+    /* 0xD4 */ Opcode::conditional("CNC a16",    3, 11, 17),
+    /* 0xD5 */ Opcode::new("PUSH D",     1, 11),
+    /* 0xD6 */ Opcode::new("SUI d8",     2,  7),
+    /* 0xD7 */ Opcode::new("RST 2",      1, 11),
+    /* 0xD8 */ Opcode::conditional("RC",         1,  5, 11),
+    /* 0xD9 */ Opcode::new("RET",        1, 10),
+    /* 0xDA */ Opcode::new("JC a16",     3, 10),
+    /* 0xDB */ Opcode::new("IN d8",      2, 10),
+    /* 0xDC */ Opcode::conditional("CC a16",     3, 11, 17),
+    /* 0xDD */ Opcode::new("CALL a16",   3, 17),
+    /* 0xDE */ Opcode::new("SBI d8",     2,  7),
+    /* 0xDF */ Opcode::new("RST 3",      1, 11),
+    /* 0xE0 */ Opcode::conditional("RPO",        1,  5, 11),
+    /* 0xE1 */ Opcode::new("POP H",      1, 10),
+    /* 0xE2 */ Opcode::new("JPO a16",    3, 10),
+    /* 0xE3 */ Opcode::new("XTHL",       1, 18),
+    /* 0xE4 */ Opcode::conditional("CPO a16",    3, 11, 17),
+    /* 0xE5 */ Opcode::new("PUSH H",     1, 11),
+    /* 0xE6 */ Opcode::new("ANI d8",     2,  7),
+    /* 0xE7 */ Opcode::new("RST 4",      1, 11),
+    /* 0xE8 */ Opcode::conditional("RPE",        1,  5, 11),
+    /* 0xE9 */ Opcode::new("PCHL",       1,  5),
+    /* 0xEA */ Opcode::new("JPE a16",    3, 10),
+    /* 0xEB */ Opcode::new("XCHG",       1,  4),
+    /* 0xEC */ Opcode::conditional("CPE a16",    3, 11, 17),
+    /* 0xED */ Opcode::new("CALL a16",   3, 17),
+    /* 0xEE */ Opcode::new("XRI d8",     2,  7),
+    /* 0xEF */ Opcode::new("RST 5",      1, 11),
+    /* 0xF0 */ Opcode::conditional("RP",         1,  5, 11),
+    /* 0xF1 */ Opcode::new("POP PSW",    1, 10),
+    /* 0xF2 */ Opcode::new("JP a16",     3, 10),
+    /* 0xF3 */ Opcode::new("DI",         1,  4),
+    /* 0xF4 */ Opcode::conditional("CP a16",     3, 11, 17),
+    /* 0xF5 */ Opcode::new("PUSH PSW",   1, 11),
+    /* 0xF6 */ Opcode::new("ORI d8",     2,  7),
+    /* 0xF7 */ Opcode::new("RST 6",      1, 11),
+    /* 0xF8 */ Opcode::conditional("RM",         1,  5, 11),
+    /* 0xF9 */ Opcode::new("SPHL",       1,  5),
+    /* 0xFA */ Opcode::new("JM a16",     3, 10),
+    /* 0xFB */ Opcode::new("EI",         1,  4),
+    /* 0xFC */ Opcode::conditional("CM a16",     3, 11, 17),
+    /* 0xFD */ Opcode::new("CALL a16",   3, 17),
+    /* 0xFE */ Opcode::new("CPI d8",     2,  7),
+    /* 0xFF */ Opcode::new("RST 7",      1, 11),
+];
@@ -0,0 +1,70 @@
+pub const PC_HISTORY_LEN: usize = 32;
+
+/// Ring buffer with the addresses of the most recently executed instructions, kept to explain
+/// how execution got to a crash.
+#[derive(Debug, Clone)]
+pub struct PcHistory {
+    pcs: [u16; PC_HISTORY_LEN],
+    next: usize,
+    len: usize,
+}
+
+impl PcHistory {
+    pub fn new() -> Self {
+        Self { pcs: [0; PC_HISTORY_LEN], next: 0, len: 0 }
+    }
+
+    pub fn push(&mut self, pc: u16) {
+        self.pcs[self.next] = pc;
+        self.next = (self.next + 1) % PC_HISTORY_LEN;
+        self.len = (self.len + 1).min(PC_HISTORY_LEN);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Address of the last instruction executed (or attempted).
+    pub fn last(&self) -> Option<u16> {
+        self.iter().last()
+    }
+
+    /// Oldest address first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+        let start = (self.next + PC_HISTORY_LEN - self.len) % PC_HISTORY_LEN;
+        (0..self.len).map(move |i| self.pcs[(start + i) % PC_HISTORY_LEN])
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for PcHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wraps_around() {
+        let mut history = PcHistory::new();
+        assert_eq!(history.last(), None);
+
+        for pc in 0..(PC_HISTORY_LEN as u16 + 5) {
+            history.push(pc);
+        }
+
+        assert_eq!(history.len(), PC_HISTORY_LEN);
+        assert_eq!(history.iter().next(), Some(5));
+        assert_eq!(history.last(), Some(PC_HISTORY_LEN as u16 + 4));
+    }
+}
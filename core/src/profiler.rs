@@ -0,0 +1,157 @@
+//! Execution counts and cycles per opcode and per address, see [`CPU::set_profiler`].
+//!
+//! Only instructions the CPU fetches are counted. Instructions supplied by interrupts and the
+//! idle cycles of a halted CPU aren't, so the totals are the time spent running code.
+//!
+//! [`CPU::set_profiler`]: crate::CPU::set_profiler
+
+use core::fmt::Write;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::vec;
+use crate::disasm;
+use crate::opcodes::OPCODES;
+use crate::Memory;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Counter {
+    pub executions: u64,
+    pub cycles: u64,
+}
+
+impl Counter {
+    fn add(&mut self, cycles: u32) {
+        self.executions += 1;
+        self.cycles += cycles as u64;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    opcodes: [Counter; 256],
+    addresses: Box<[Counter]>,
+    total: Counter,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self { opcodes: [Counter::default(); 256], addresses: vec![Counter::default(); 0x10000].into_boxed_slice(), total: Counter::default() }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn opcode(&self, opcode: u8) -> Counter {
+        self.opcodes[opcode as usize]
+    }
+
+    /// Instructions executed from `address`.
+    pub fn address(&self, address: u16) -> Counter {
+        self.addresses[address as usize]
+    }
+
+    pub fn total(&self) -> Counter {
+        self.total
+    }
+
+    /// Up to `count` addresses instructions ran from, the most cycles first.
+    pub fn hottest(&self, count: usize) -> Vec<(u16, Counter)> {
+        hottest(self.addresses.iter().enumerate().map(|(address, counter)| (address as u16, *counter)), count)
+    }
+
+    /// Up to `count` opcodes that ran, the most cycles first.
+    pub fn hottest_opcodes(&self, count: usize) -> Vec<(u8, Counter)> {
+        hottest(self.opcodes.iter().enumerate().map(|(opcode, counter)| (opcode as u8, *counter)), count)
+    }
+
+    /// The `count` hottest addresses, disassembled from `memory`, and opcodes, with their share
+    /// of the cycles counted.
+    pub fn report(&self, memory: &Memory, count: usize) -> String {
+        let mut report = String::new();
+        writeln!(report, "{} instructions, {} cycles", self.total.executions, self.total.cycles).unwrap();
+
+        writeln!(report, "\nHottest addresses").unwrap();
+        for (address, counter) in self.hottest(count) {
+            let bytes = [0, 1, 2].map(|i| memory[address.wrapping_add(i)]);
+            let instruction = disasm::disassemble(address, &bytes);
+            writeln!(report, "  {:04X}  {:>6.2}%  {:>12}  {}", address, self.share(counter), counter.executions, instruction).unwrap();
+        }
+
+        writeln!(report, "\nHottest opcodes").unwrap();
+        for (opcode, counter) in self.hottest_opcodes(count) {
+            let mnemonic = OPCODES[opcode as usize].mnemonic;
+            writeln!(report, "  {:02X}    {:>6.2}%  {:>12}  {}", opcode, self.share(counter), counter.executions, mnemonic).unwrap();
+        }
+        report
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn record(&mut self, address: u16, opcode: u8, cycles: u32) {
+        self.opcodes[opcode as usize].add(cycles);
+        self.addresses[address as usize].add(cycles);
+        self.total.add(cycles);
+    }
+
+    /// Percentage of the total cycles spent on `counter`.
+    fn share(&self, counter: Counter) -> f64 {
+        counter.cycles as f64 * 100.0 / self.total.cycles.max(1) as f64
+    }
+}
+
+fn hottest<T: Ord>(counters: impl Iterator<Item = (T, Counter)>, count: usize) -> Vec<(T, Counter)> {
+    let mut hottest: Vec<_> = counters.filter(|(_, counter)| counter.executions > 0).collect();
+    hottest.sort_by(|(a, a_counter), (b, b_counter)| b_counter.cycles.cmp(&a_counter.cycles).then(a.cmp(b)));
+    hottest.truncate(count);
+    hottest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CPU;
+
+    #[test]
+    fn test_counts() {
+        // MVI B,3; DCR B; JNZ $0002; HLT
+        let mut cpu = CPU::new(&[0x06, 0x03, 0x05, 0xC2, 0x02, 0x00, 0x76]);
+        cpu.set_profiler(Some(Profiler::new()));
+        while !cpu.halted() {
+            cpu.step().unwrap();
+        }
+        cpu.step().unwrap();
+
+        let profiler = cpu.profiler().unwrap();
+        assert_eq!(profiler.address(0x0002), Counter { executions: 3, cycles: 15 });
+        assert_eq!(profiler.opcode(0xC2), Counter { executions: 3, cycles: 30 });
+        assert_eq!(profiler.total(), Counter { executions: 8, cycles: 7 + 15 + 30 + 7 });
+        assert_eq!(profiler.hottest(2), [(0x0003, Counter { executions: 3, cycles: 30 }), (0x0002, Counter { executions: 3, cycles: 15 })]);
+        assert_eq!(profiler.hottest_opcodes(8).len(), 4);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x0000, 0x00, 4);
+        profiler.record(0x0000, 0x00, 4);
+        profiler.record(0x0001, 0x76, 8);
+
+        let memory = CPU::new(&[0x00, 0x76]).memory;
+        assert_eq!(profiler.report(&memory, 1), concat!(
+            "3 instructions, 16 cycles\n",
+            "\n",
+            "Hottest addresses\n",
+            "  0000   50.00%             2  00        NOP\n",
+            "\n",
+            "Hottest opcodes\n",
+            "  00     50.00%             2  NOP\n",
+        ));
+    }
+}
@@ -0,0 +1,100 @@
+//! Board-level behaviors that real hardware has and simpler emulation can do without.
+//!
+//! Space Invaders runs the same either way; they matter for homebrew, test ROMs and comparing
+//! traces against other emulators. [`Quirks::FAST`] is what [`Emulator::new`](crate::Emulator::new)
+//! starts with, and the known sets run with [`Quirks::ACCURATE`].
+//!
+//! DAA has no quirk: the CPU implements the 8080's exactly, edge cases included, and nothing on
+//! the board changes how it behaves.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    /// The shift amount latch on port 2 only has 3 bits. Without it the whole byte is kept, and
+    /// amounts of 8 and up shift the result further until it reads 0.
+    pub mask_shift_offset: bool,
+    /// Bits 1-3 of input port 0 are tied high on the board, so it reads 0x0E instead of 0.
+    pub port0_unused_high: bool,
+    /// The board raises interrupts in the middle of an instruction, so they're taken once the
+    /// following instruction finishes. Without it they're taken as soon as they're raised.
+    ///
+    /// The delay is always exactly that one instruction. On the board it depends on where in
+    /// the instruction the beam gets to the interrupt line, which isn't emulated: there's no
+    /// random jitter, so runs stay deterministic.
+    pub interrupt_latency: bool,
+}
+
+impl Quirks {
+    pub const ACCURATE: Self = Self {
+        mask_shift_offset: true,
+        port0_unused_high: true,
+        interrupt_latency: true,
+    };
+
+    /// Leaves out the quirks that cost time on every instruction or interrupt.
+    pub const FAST: Self = Self {
+        mask_shift_offset: true,
+        port0_unused_high: true,
+        interrupt_latency: false,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::FAST
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Emulator, ExecutionStatus};
+
+    fn run(program: &[u8], quirks: Quirks, steps: usize) -> Emulator {
+        let mut emulator = Emulator::new(program);
+        emulator.set_quirks(quirks);
+        for _ in 0..steps {
+            emulator.step().unwrap();
+        }
+        emulator
+    }
+
+    #[test]
+    fn test_mask_shift_offset() {
+        // MVI A,$FF; OUT 4; MVI A,$0C; OUT 2; IN 3
+        let program = [0x3E, 0xFF, 0xD3, 0x04, 0x3E, 0x0C, 0xD3, 0x02, 0xDB, 0x03];
+        let masked = Quirks { mask_shift_offset: true, ..Quirks::FAST };
+        let unmasked = Quirks { mask_shift_offset: false, ..Quirks::FAST };
+
+        // 0x0C & 7 = 4: the top 4 bits of 0xFF00 shifted left by 4
+        assert_eq!(run(&program, masked, 5).cpu().registers().a, 0xF0);
+        assert_eq!(run(&program, unmasked, 5).cpu().registers().a, 0x00);
+    }
+
+    #[test]
+    fn test_port0_unused_high() {
+        // IN 0
+        let high = Quirks { port0_unused_high: true, ..Quirks::FAST };
+        let low = Quirks { port0_unused_high: false, ..Quirks::FAST };
+
+        assert_eq!(run(&[0xDB, 0x00], high, 1).cpu().registers().a, 0x0E);
+        assert_eq!(run(&[0xDB, 0x00], low, 1).cpu().registers().a, 0x00);
+    }
+
+    #[test]
+    fn test_interrupt_latency() {
+        // LXI SP,$2400; NOP; NOP
+        let program = [0x31, 0x00, 0x24, 0x00, 0x00];
+
+        let mut immediate = run(&program, Quirks { interrupt_latency: false, ..Quirks::FAST }, 1);
+        immediate.interrupt(1);
+        assert_eq!(immediate.cpu().pc(), 0x0008);
+
+        let mut latent = run(&program, Quirks { interrupt_latency: true, ..Quirks::FAST }, 1);
+        latent.interrupt(1);
+        assert_eq!(latent.cpu().pc(), 0x0003);
+        assert_eq!(latent.step().unwrap(), ExecutionStatus::Continue(4 + 11));
+        assert_eq!(latent.cpu().pc(), 0x0008);
+        assert_eq!(latent.stats().interrupts, 1);
+    }
+}
@@ -0,0 +1,57 @@
+//! CRT timing of the Midway 8080 board, which is where the two screen interrupts come from.
+//!
+//! A 19.968 MHz crystal drives both the CPU, divided by 10, and the video counters, which count
+//! 320 pixel clocks of 4.992 MHz per line and 262 lines per frame. That makes every line exactly
+//! 128 CPU cycles and a frame 33,536 cycles, about 59.54 times a second. Only the first 224 lines
+//! are drawn; the board raises RST 1 when the beam reaches line 96 and RST 2 at line 224, where
+//! vertical blanking starts.
+//!
+//! Frames as [`Emulator::run_frame`](crate::Emulator::run_frame) counts them start at VBlank, so
+//! they run the blanking lines first and end with RST 2, right after the beam drew the last line.
+//! Lines are counted along the beam, which on the rotated monitor goes from left to right, and
+//! each covers 32 bytes of video RAM.
+
+/// CPU clock: the 19.968 MHz crystal divided by 10.
+pub const CLOCK_HZ: u32 = 1_996_800;
+pub const CYCLES_PER_LINE: u32 = 128;
+pub const LINES_PER_FRAME: u32 = 262;
+/// Lines the beam draws before reaching the bottom of the picture.
+pub const VISIBLE_LINES: u32 = 224;
+/// Line where the board raises RST 1, in the middle of the picture.
+pub const MID_SCREEN_LINE: u32 = 96;
+/// Line where the board raises RST 2 and vertical blanking starts.
+pub const VBLANK_LINE: u32 = VISIBLE_LINES;
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES_PER_FRAME;
+/// Frames per second of the real board, about 59.54.
+pub const FRAME_RATE: f64 = CLOCK_HZ as f64 / CYCLES_PER_FRAME as f64;
+/// Cycles into a frame where RST 1 is raised.
+pub const MID_SCREEN_CYCLE: u32 = cycle_of(MID_SCREEN_LINE);
+/// Bytes of video RAM the beam reads for each line.
+pub const BYTES_PER_LINE: usize = 32;
+
+/// Line the beam is on `cycle` cycles into a frame.
+pub const fn line_at(cycle: u32) -> u32 {
+    (VBLANK_LINE + cycle / CYCLES_PER_LINE) % LINES_PER_FRAME
+}
+
+/// Cycles into a frame where the beam reaches the start of `line`.
+pub const fn cycle_of(line: u32) -> u32 {
+    (line + LINES_PER_FRAME - VBLANK_LINE) % LINES_PER_FRAME * CYCLES_PER_LINE
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(CYCLES_PER_FRAME, 33_536);
+        assert_eq!(MID_SCREEN_CYCLE, 17_152);
+        assert_eq!(line_at(0), VBLANK_LINE);
+        assert_eq!(line_at(CYCLES_PER_FRAME - 1), VBLANK_LINE - 1);
+        assert_eq!(line_at(MID_SCREEN_CYCLE), MID_SCREEN_LINE);
+        assert_eq!(line_at(cycle_of(0)), 0);
+        assert_eq!(cycle_of(VBLANK_LINE), 0);
+        assert!((FRAME_RATE - 59.54).abs() < 0.01);
+    }
+}
@@ -0,0 +1,203 @@
+//! Rolling buffer of recent machine states for playing the game backwards, see [`Rewind`].
+//!
+//! Only the newest state is kept whole. Every older one is stored as its difference to the state
+//! recorded after it: the XOR of the two [`savestate`](crate::savestate) images with the runs of
+//! unchanged bytes left out. Consecutive frames only touch a few hundred bytes of RAM, so half a
+//! minute of per-frame states takes a fraction of what as many full states would.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use crate::{Emulator, Result};
+
+/// An older state as the bytes that differ from the next newer one, encoded as runs of
+/// `[unchanged count][changed count][changed bytes XOR the newer ones]` with LEB128 counts.
+#[derive(Debug, Clone)]
+struct Delta {
+    len: usize,
+    runs: Vec<u8>,
+}
+
+impl Delta {
+    fn new(older: &[u8], newer: &[u8]) -> Self {
+        let mut runs = Vec::new();
+        let mut i = 0;
+
+        while i < older.len() {
+            let unchanged = i;
+            while i < older.len() && older[i] == byte_at(newer, i) {
+                i += 1;
+            }
+            let changed = i;
+            if changed == older.len() {
+                break;
+            }
+            while i < older.len() && older[i] != byte_at(newer, i) {
+                i += 1;
+            }
+
+            write_count(&mut runs, changed - unchanged);
+            write_count(&mut runs, i - changed);
+            runs.extend((changed..i).map(|j| older[j] ^ byte_at(newer, j)));
+        }
+
+        Self { len: older.len(), runs }
+    }
+
+    fn apply(&self, newer: &[u8]) -> Vec<u8> {
+        let mut older: Vec<u8> = (0..self.len).map(|i| byte_at(newer, i)).collect();
+        let mut runs = &self.runs[..];
+        let mut i = 0;
+
+        while !runs.is_empty() {
+            i += read_count(&mut runs);
+            let changed = read_count(&mut runs);
+            for (byte, diff) in older[i..i + changed].iter_mut().zip(&runs[..changed]) {
+                *byte ^= diff;
+            }
+            runs = &runs[changed..];
+            i += changed;
+        }
+
+        older
+    }
+}
+
+/// States of different lengths are compared as if the shorter one was padded with zeros.
+fn byte_at(state: &[u8], i: usize) -> u8 {
+    state.get(i).copied().unwrap_or(0)
+}
+
+fn write_count(out: &mut Vec<u8>, mut count: usize) {
+    while count >= 0x80 {
+        out.push(count as u8 | 0x80);
+        count >>= 7;
+    }
+    out.push(count as u8);
+}
+
+fn read_count(runs: &mut &[u8]) -> usize {
+    let mut count = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = runs.split_first() {
+        *runs = rest;
+        count |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    count
+}
+
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    capacity: usize,
+    /// Newest state recorded, empty before the first one.
+    newest: Vec<u8>,
+    /// Every older state, the oldest first, each relative to the one after it.
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewind {
+    /// Keeps the `capacity` most recent states, e.g. 30 seconds' worth of frames.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), newest: Vec::new(), deltas: VecDeque::new() }
+    }
+
+    /// Records the state of `emulator`, dropping the oldest one when full. Meant to be called
+    /// once per frame, after [`Emulator::run_frame`] completes one.
+    pub fn push(&mut self, emulator: &Emulator) {
+        let state = emulator.save_state_bytes();
+        if !self.newest.is_empty() {
+            self.deltas.push_back(Delta::new(&self.newest, &state));
+        }
+        while self.deltas.len() >= self.capacity {
+            self.deltas.pop_front();
+        }
+        self.newest = state;
+    }
+
+    /// Drops the newest state and restores the one recorded before it, so calling this every
+    /// frame plays the game backwards. Returns `false` once only the oldest state is left.
+    pub fn rewind(&mut self, emulator: &mut Emulator) -> Result<bool> {
+        let Some(delta) = self.deltas.back() else { return Ok(false) };
+
+        let older = delta.apply(&self.newest);
+        emulator.load_state_bytes(&older)?;
+        self.deltas.pop_back();
+        self.newest = older;
+        Ok(true)
+    }
+
+    /// States recorded, including the newest one.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + !self.newest.is_empty() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest.clear();
+        self.deltas.clear();
+    }
+
+    /// Bytes taken by the recorded states.
+    pub fn memory_usage(&self) -> usize {
+        self.newest.len() + self.deltas.iter().map(|delta| delta.runs.len()).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    #[test]
+    fn test_delta_round_trip() {
+        let newer = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        for older in [&[1, 2, 0, 4, 5, 6, 7, 0, 0, 10][..], &newer[..], &[9; 300][..], &[1, 2][..], &[][..]] {
+            assert_eq!(Delta::new(older, &newer).apply(&newer), older);
+        }
+        assert!(Delta::new(&newer, &newer).runs.is_empty());
+    }
+
+    #[test]
+    fn test_rewinds_frames() {
+        let mut emulator = Emulator::new(ROM);
+        let mut rewind = Rewind::new(100);
+        let mut states = Vec::new();
+        for _ in 0..30 {
+            emulator.run_frame(&mut ()).unwrap();
+            rewind.push(&emulator);
+            states.push(emulator.save_state_bytes());
+        }
+        assert_eq!(rewind.len(), 30);
+        assert!(rewind.memory_usage() < states.iter().map(Vec::len).sum::<usize>() / 4);
+
+        for state in states.iter().rev().skip(1) {
+            assert!(rewind.rewind(&mut emulator).unwrap());
+            assert_eq!(&emulator.save_state_bytes(), state);
+        }
+        assert!(!rewind.rewind(&mut emulator).unwrap());
+        assert_eq!(rewind.len(), 1);
+    }
+
+    #[test]
+    fn test_drops_oldest() {
+        let mut emulator = Emulator::new(ROM);
+        let mut rewind = Rewind::new(5);
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            emulator.run_frame(&mut ()).unwrap();
+            rewind.push(&emulator);
+            states.push(emulator.save_state_bytes());
+        }
+        assert_eq!(rewind.len(), 5);
+
+        while rewind.rewind(&mut emulator).unwrap() {}
+        assert_eq!(emulator.save_state_bytes(), states[5]);
+    }
+}
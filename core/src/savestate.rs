@@ -0,0 +1,322 @@
+//! Binary save-state file format.
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic "SIST"
+//! 4       2     format version (little endian, like every other integer)
+//! 6       2     machine profile ID
+//! 8       4     CRC32 of the ROM the state was taken from
+//! 12      4     CRC32 of the payload
+//! 16      ..    payload: sections of [4-byte tag][u32 length][data]
+//! ```
+//!
+//! Unknown sections are skipped so newer files stay readable as long as the version allows it.
+//! States written by older versions are upgraded on load by the `MIGRATIONS` chain, which
+//! fills in whatever the newer layout expects with the defaults of a freshly created machine.
+//!
+//! | version | changes                                             |
+//! |---------|-----------------------------------------------------|
+//! | 1       | initial format                                      |
+//! | 2       | `STAT` section with the emulator counters           |
+//! | 3       | `INTR` section with the pending interrupt           |
+//! | 4       | halted flag at the end of the `CPU ` section        |
+//! | 5       | pending interrupt at the end of the `CPU ` section  |
+//! | 6       | `FRAM` section with the position in a stopped frame |
+
+use alloc::borrow::Cow;
+use core::fmt::{Display, Formatter};
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::vec;
+use crate::{checksum, Error, Result};
+
+pub const MAGIC: [u8; 4] = *b"SIST";
+pub const VERSION: u16 = 6;
+pub const MACHINE_SPACE_INVADERS: u16 = 1;
+
+const HEADER_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion { version: u16 },
+    MachineMismatch { expected: u16, found: u16 },
+    RomMismatch { expected: u32, found: u32 },
+    ChecksumMismatch,
+    Truncated,
+    MissingSection { tag: [u8; 4] },
+}
+
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a save state file"),
+            Self::UnsupportedVersion { version } => write!(f, "unsupported save state version {} (newest supported is {})", version, VERSION),
+            Self::MachineMismatch { expected, found } => write!(f, "save state is for machine {}, not {}", found, expected),
+            Self::RomMismatch { expected, found } => write!(f, "save state was taken with a different ROM (CRC32 {:08X}, loaded ROM is {:08X})", found, expected),
+            Self::ChecksumMismatch => write!(f, "save state is corrupted (checksum mismatch)"),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::MissingSection { tag } => write!(f, "save state is missing the {} section", String::from_utf8_lossy(tag).trim_end()),
+        }
+    }
+}
+
+impl From<SaveStateError> for Error {
+    fn from(e: SaveStateError) -> Self {
+        Error::SaveState(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub machine: u16,
+    pub rom_crc: u32,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Section<'a> {
+    pub tag: [u8; 4],
+    pub data: Cow<'a, [u8]>,
+}
+
+type Migration = fn(&mut Vec<Section>);
+
+/// `MIGRATIONS[n]` upgrades the sections of a version `n + 1` state to version `n + 2`.
+const MIGRATIONS: [Migration; VERSION as usize - 1] = [
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+];
+
+fn migrate_v1_to_v2(sections: &mut Vec<Section>) {
+    sections.push(Section { tag: *b"STAT", data: Cow::Owned(vec![0; 32]) });
+}
+
+fn migrate_v2_to_v3(sections: &mut Vec<Section>) {
+    sections.push(Section { tag: *b"INTR", data: Cow::Owned(vec![0, 0]) });
+}
+
+fn migrate_v3_to_v4(sections: &mut Vec<Section>) {
+    if let Some(cpu) = sections.iter_mut().find(|s| &s.tag == b"CPU ") {
+        cpu.data.to_mut().push(0);
+    }
+}
+
+fn migrate_v4_to_v5(sections: &mut Vec<Section>) {
+    if let Some(cpu) = sections.iter_mut().find(|s| &s.tag == b"CPU ") {
+        cpu.data.to_mut().extend_from_slice(&[0, 0]);
+    }
+}
+
+fn migrate_v5_to_v6(sections: &mut Vec<Section>) {
+    sections.push(Section { tag: *b"FRAM", data: Cow::Owned(vec![0; 6]) });
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StateWriter {
+    payload: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn section(&mut self, tag: &[u8; 4], data: &[u8]) {
+        self.payload.extend_from_slice(tag);
+        self.payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        self.payload.extend_from_slice(data);
+    }
+
+    pub fn finish(self, machine: u16, rom_crc: u32) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&machine.to_le_bytes());
+        out.extend_from_slice(&rom_crc.to_le_bytes());
+        out.extend_from_slice(&checksum::crc32(&self.payload).to_le_bytes());
+        out.extend_from_slice(&self.payload);
+        out
+    }
+}
+
+/// Validates the header and checksum and splits the payload into sections, upgraded to [`VERSION`].
+pub(crate) fn parse(data: &[u8]) -> Result<(Header, Vec<Section<'_>>)> {
+    let mut reader = StateReader::new(data);
+
+    if reader.bytes(4)? != MAGIC {
+        return Err(SaveStateError::BadMagic.into());
+    }
+
+    let header = Header {
+        version: reader.u16()?,
+        machine: reader.u16()?,
+        rom_crc: reader.u32()?,
+    };
+    if header.version == 0 || header.version > VERSION {
+        return Err(SaveStateError::UnsupportedVersion { version: header.version }.into());
+    }
+
+    let payload_crc = reader.u32()?;
+    let payload = reader.rest();
+    if checksum::crc32(payload) != payload_crc {
+        return Err(SaveStateError::ChecksumMismatch.into());
+    }
+
+    let mut reader = StateReader::new(payload);
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let tag = reader.bytes(4)?.try_into().unwrap();
+        let len = reader.u32()? as usize;
+        sections.push(Section { tag, data: Cow::Borrowed(reader.bytes(len)?) });
+    }
+
+    for migrate in &MIGRATIONS[header.version as usize - 1..] {
+        migrate(&mut sections);
+    }
+
+    Ok((header, sections))
+}
+
+pub(crate) fn find<'a>(sections: &'a [Section], tag: &[u8; 4]) -> Result<StateReader<'a>> {
+    sections.iter()
+        .find(|s| &s.tag == tag)
+        .map(|s| StateReader::new(&s.data))
+        .ok_or_else(|| SaveStateError::MissingSection { tag: *tag }.into())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len()).ok_or(SaveStateError::Truncated)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        self.pos = self.data.len();
+        rest
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Emulator;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    fn running_emulator() -> Emulator {
+        let mut emulator = Emulator::new(ROM);
+        for _ in 0..50_000 {
+            emulator.step().unwrap();
+        }
+        emulator
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let emulator = running_emulator();
+        let state = emulator.save_state_bytes();
+
+        let mut loaded = Emulator::new(ROM);
+        loaded.load_state_bytes(&state).unwrap();
+        assert_eq!(loaded.save_state_bytes(), state);
+        assert_eq!(loaded.cpu().pc(), emulator.cpu().pc());
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let emulator = running_emulator();
+        let mut file = Vec::new();
+        emulator.save_state(&mut file).unwrap();
+        assert_eq!(file, emulator.save_state_bytes());
+
+        let mut loaded = Emulator::new(ROM);
+        loaded.load_state(&mut file.as_slice()).unwrap();
+        assert_eq!(loaded.cpu().pc(), emulator.cpu().pc());
+
+        file.pop();
+        assert!(matches!(loaded.load_state(&mut file.as_slice()), Err(Error::SaveState(SaveStateError::ChecksumMismatch))));
+    }
+
+    #[test]
+    fn test_migrates_v1() {
+        let emulator = running_emulator();
+        let state = emulator.save_state_bytes();
+
+        // Rebuild the state the way version 1 wrote it: no STAT or INTR section, and no halted
+        // flag or pending interrupt in the CPU section
+        let (_, sections) = parse(&state).unwrap();
+        let mut writer = StateWriter::default();
+        for section in sections.iter().filter(|s| &s.tag != b"STAT" && &s.tag != b"INTR") {
+            let data = if &section.tag == b"CPU " { &section.data[..section.data.len() - 3] } else { &section.data[..] };
+            writer.section(&section.tag, data);
+        }
+        let mut v1 = writer.finish(MACHINE_SPACE_INVADERS, crate::crc32(ROM));
+        v1[4..6].copy_from_slice(&1u16.to_le_bytes());
+
+        let mut loaded = Emulator::new(ROM);
+        loaded.load_state_bytes(&v1).unwrap();
+        assert_eq!(loaded.cpu().pc(), emulator.cpu().pc());
+        assert_eq!(loaded.stats().instructions, 0);
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let mut state = running_emulator().save_state_bytes();
+        state[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        let mut emulator = Emulator::new(ROM);
+        assert!(matches!(emulator.load_state_bytes(&state), Err(Error::SaveState(SaveStateError::UnsupportedVersion { .. }))));
+    }
+
+    #[test]
+    fn test_rejects_other_rom() {
+        let state = running_emulator().save_state_bytes();
+        let mut other = Emulator::new(&[0; 16]);
+
+        assert!(matches!(other.load_state_bytes(&state), Err(Error::SaveState(SaveStateError::RomMismatch { .. }))));
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let mut state = running_emulator().save_state_bytes();
+        let mut emulator = Emulator::new(ROM);
+
+        state[HEADER_LEN + 20] ^= 0xFF;
+        assert!(matches!(emulator.load_state_bytes(&state), Err(Error::SaveState(SaveStateError::ChecksumMismatch))));
+        assert!(matches!(emulator.load_state_bytes(&state[..10]), Err(Error::SaveState(SaveStateError::Truncated))));
+        assert!(matches!(emulator.load_state_bytes(b"not a state file"), Err(Error::SaveState(SaveStateError::BadMagic))));
+    }
+}
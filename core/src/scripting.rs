@@ -0,0 +1,397 @@
+//! [Rhai](https://rhai.rs) scripts hooked into the emulator, for trainers, HUD data and bots that
+//! don't need the emulator rebuilt. Loading a script runs its top level, which registers callbacks:
+//!
+//! ```text
+//! on_frame_start(|| { ... });                        // before each frame
+//! on_frame_end(|| { ... });                          // after VBlank
+//! on_read(0x20EB, |address, value| { ... });         // also on_read(start, end, ...)
+//! on_write(0x21FF, |address, old, value| { ... });   // also on_write(start, end, ...)
+//! on_port_read(1, |port, value| { ... });
+//! on_port_write(3, |port, value| { ... });
+//! ```
+//!
+//! The top level and the callbacks can use:
+//!
+//! - `peek(address)` and `poke(address, value)`, which leaves ROM alone
+//! - `reg(name)` and `set_reg(name, value)` for `a` to `l`, `bc`, `de`, `hl`, `psw`, `sp` and `pc`
+//! - `press(button)`, `release(button)` and `held(button)`, with names as in
+//!   [`Button::from_name`]
+//! - `frame()`, the frames run so far
+//!
+//! Memory callbacks run once the instruction has finished, and see only the first watched access
+//! of each instruction, see [`Memory::add_watchpoint`](crate::Memory::add_watchpoint). They're
+//! ordinary watchpoints, so like any other they make the JIT fall back to interpreting.
+
+use core::cell::RefCell;
+use core::fmt::{Display, Formatter};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, AST, INT};
+use crate::{Access, Button, Emulator, EmulatorEvent, Error, FrameSink, FrameStatus, Register, RegisterPair, Result, WatchHit, Watchpoint};
+
+type RhaiResult<T> = core::result::Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    /// The script doesn't compile.
+    Syntax(String),
+    /// The top level or a callback failed.
+    Runtime(String),
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Syntax(e) => write!(f, "syntax error: {}", e),
+            Self::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<ScriptError> for Error {
+    fn from(e: ScriptError) -> Self {
+        Error::Script(e)
+    }
+}
+
+#[derive(Default)]
+struct Hooks {
+    frame_start: Vec<FnPtr>,
+    frame_end: Vec<FnPtr>,
+    memory: Vec<(Watchpoint, FnPtr)>,
+    ports: Vec<(u8, Access, FnPtr)>,
+}
+
+/// Holds the emulator while script code runs, so the functions the script calls can reach it.
+type Slot = Rc<RefCell<Option<Emulator>>>;
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    hooks: Rc<RefCell<Hooks>>,
+    slot: Slot,
+    /// Left with the caller in place of the emulator while it's in the slot.
+    spare: Option<Emulator>,
+}
+
+impl Script {
+    /// Compiles `source` and runs its top level on `emulator`.
+    pub fn load(source: &str, emulator: &mut Emulator) -> Result<Self> {
+        let hooks = Rc::default();
+        let slot = Rc::default();
+        let mut engine = Engine::new();
+        register_api(&mut engine, &hooks, &slot);
+
+        let ast = engine.compile(source).map_err(|e| ScriptError::Syntax(e.to_string()))?;
+        let mut script = Self { engine, ast, hooks, slot, spare: Some(Emulator::new(&[])) };
+        script.run(emulator, |script| script.engine.run_ast(&script.ast))?;
+        Ok(script)
+    }
+
+    /// Runs a frame like [`Emulator::run_frame`], calling the frame start callbacks first, the
+    /// memory and port ones as the accesses happen and the frame end ones after VBlank, and
+    /// passing every event on to `sink`. A failing callback stops the frame with its error.
+    /// Resuming a stopped frame doesn't call the frame start callbacks again.
+    pub fn run_frame(&mut self, emulator: &mut Emulator, sink: &mut impl FrameSink) -> Result<FrameStatus> {
+        if !emulator.mid_frame() {
+            let hooks = self.hooks.borrow().frame_start.clone();
+            for hook in &hooks {
+                self.call(emulator, hook, ())?;
+            }
+        }
+
+        let mut script_sink = ScriptSink { script: self, sink, error: None };
+        let status = emulator.run_frame(&mut script_sink)?;
+        match script_sink.error {
+            Some(e) => Err(e.into()),
+            None => Ok(status),
+        }
+    }
+
+    /// Removes the script's watchpoints from `emulator`, which would otherwise stop frames run
+    /// without it. Port logging stays on, since something else may rely on it.
+    pub fn unload(self, emulator: &mut Emulator) {
+        for (watchpoint, _) in &self.hooks.borrow().memory {
+            emulator.cpu_mut().memory.remove_watchpoint(watchpoint);
+        }
+    }
+
+    fn dispatch(&mut self, emulator: &mut Emulator, event: &EmulatorEvent) -> core::result::Result<(), ScriptError> {
+        match *event {
+            EmulatorEvent::Watchpoint(hit) => {
+                let hooks: Vec<FnPtr> = self.hooks.borrow().memory.iter()
+                    .filter(|(watchpoint, _)| watchpoint.matches(hit.address, hit.access))
+                    .map(|(_, hook)| hook.clone())
+                    .collect();
+                let address = hit.address as INT;
+                for hook in &hooks {
+                    match hit.access {
+                        Access::Read => self.call(emulator, hook, (address, hit.new as INT))?,
+                        Access::Write => self.call(emulator, hook, (address, hit.old as INT, hit.new as INT))?,
+                    }
+                }
+            }
+            EmulatorEvent::PortAccess(access) => {
+                let hooks: Vec<FnPtr> = self.hooks.borrow().ports.iter()
+                    .filter(|&&(port, kind, _)| port == access.port && kind == access.kind())
+                    .map(|(_, _, hook)| hook.clone())
+                    .collect();
+                for hook in &hooks {
+                    self.call(emulator, hook, (access.port as INT, access.value as INT))?;
+                }
+            }
+            EmulatorEvent::FrameComplete => {
+                let hooks = self.hooks.borrow().frame_end.clone();
+                for hook in &hooks {
+                    self.call(emulator, hook, ())?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Whether `hit` is one of the script's memory callbacks.
+    fn watches(&self, hit: &WatchHit) -> bool {
+        self.hooks.borrow().memory.iter().any(|(watchpoint, _)| watchpoint.matches(hit.address, hit.access))
+    }
+
+    fn call(&mut self, emulator: &mut Emulator, hook: &FnPtr, args: impl FuncArgs) -> core::result::Result<(), ScriptError> {
+        self.run(emulator, |script| hook.call::<Dynamic>(&script.engine, &script.ast, args).map(drop))
+    }
+
+    /// Runs `f` with `emulator` in the slot.
+    fn run<T>(&mut self, emulator: &mut Emulator, f: impl FnOnce(&Self) -> RhaiResult<T>) -> core::result::Result<T, ScriptError> {
+        let spare = self.spare.take().expect("script code doesn't nest");
+        *self.slot.borrow_mut() = Some(core::mem::replace(emulator, spare));
+        let result = f(self);
+        let lent = self.slot.borrow_mut().take().expect("the slot holds the emulator");
+        self.spare = Some(core::mem::replace(emulator, lent));
+        result.map_err(|e| ScriptError::Runtime(e.to_string()))
+    }
+}
+
+/// Hands the script's callbacks the events of a frame, before passing them on to `sink`.
+struct ScriptSink<'a, S> {
+    script: &'a mut Script,
+    sink: &'a mut S,
+    error: Option<ScriptError>,
+}
+
+impl<S: FrameSink> FrameSink for ScriptSink<'_, S> {
+    fn event(&mut self, emulator: &mut Emulator, event: EmulatorEvent) {
+        if self.error.is_none() {
+            self.error = self.script.dispatch(emulator, &event).err();
+        }
+        self.sink.event(emulator, event);
+    }
+
+    fn stop(&mut self, emulator: &mut Emulator) -> bool {
+        self.error.is_some() || self.sink.stop(emulator)
+    }
+
+    fn stop_at_watchpoint(&mut self, emulator: &mut Emulator, hit: &WatchHit) -> bool {
+        !self.script.watches(hit) && self.sink.stop_at_watchpoint(emulator, hit)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Reg {
+    Byte(Register),
+    Pair(RegisterPair),
+    Sp,
+    Pc,
+}
+
+impl Reg {
+    fn parse(name: &str) -> RhaiResult<Self> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "a" => Self::Byte(Register::A),
+            "b" => Self::Byte(Register::B),
+            "c" => Self::Byte(Register::C),
+            "d" => Self::Byte(Register::D),
+            "e" => Self::Byte(Register::E),
+            "h" => Self::Byte(Register::H),
+            "l" => Self::Byte(Register::L),
+            "bc" => Self::Pair(RegisterPair::BC),
+            "de" => Self::Pair(RegisterPair::DE),
+            "hl" => Self::Pair(RegisterPair::HL),
+            "psw" => Self::Pair(RegisterPair::PSW),
+            "sp" => Self::Sp,
+            "pc" => Self::Pc,
+            _ => return Err(format!("unknown register {}", name).into()),
+        })
+    }
+}
+
+fn with<T>(slot: &Slot, f: impl FnOnce(&mut Emulator) -> T) -> T {
+    f(slot.borrow_mut().as_mut().expect("script code only runs with the emulator in the slot"))
+}
+
+fn address(n: INT) -> RhaiResult<u16> {
+    u16::try_from(n).map_err(|_| format!("address out of range: {}", n).into())
+}
+
+fn byte(n: INT) -> RhaiResult<u8> {
+    u8::try_from(n).map_err(|_| format!("byte out of range: {}", n).into())
+}
+
+fn button(name: &str) -> RhaiResult<Button> {
+    Button::from_name(name).ok_or_else(|| format!("unknown button {}", name).into())
+}
+
+fn register_api(engine: &mut Engine, hooks: &Rc<RefCell<Hooks>>, slot: &Slot) {
+    let h = hooks.clone();
+    engine.register_fn("on_frame_start", move |hook: FnPtr| h.borrow_mut().frame_start.push(hook));
+    let h = hooks.clone();
+    engine.register_fn("on_frame_end", move |hook: FnPtr| h.borrow_mut().frame_end.push(hook));
+
+    for (name, access) in [("on_read", Access::Read), ("on_write", Access::Write)] {
+        let (h, s) = (hooks.clone(), slot.clone());
+        let watch = move |start: INT, end: INT, hook: FnPtr| -> RhaiResult<()> {
+            let range = address(start)?..=address(end)?;
+            let watchpoint = match access {
+                Access::Read => Watchpoint::read(range),
+                Access::Write => Watchpoint::write(range),
+            };
+            with(&s, |emulator| emulator.cpu_mut().memory.add_watchpoint(watchpoint));
+            h.borrow_mut().memory.push((watchpoint, hook));
+            Ok(())
+        };
+        let single = watch.clone();
+        engine.register_fn(name, watch);
+        engine.register_fn(name, move |at: INT, hook: FnPtr| single(at, at, hook));
+    }
+
+    for (name, access) in [("on_port_read", Access::Read), ("on_port_write", Access::Write)] {
+        let (h, s) = (hooks.clone(), slot.clone());
+        engine.register_fn(name, move |port: INT, hook: FnPtr| -> RhaiResult<()> {
+            let port = byte(port)?;
+            with(&s, |emulator| emulator.set_port_logging(true));
+            h.borrow_mut().ports.push((port, access, hook));
+            Ok(())
+        });
+    }
+
+    let s = slot.clone();
+    engine.register_fn("peek", move |at: INT| -> RhaiResult<INT> {
+        let at = address(at)?;
+        Ok(with(&s, |emulator| emulator.cpu().memory[at]) as INT)
+    });
+    let s = slot.clone();
+    engine.register_fn("poke", move |at: INT, value: INT| -> RhaiResult<()> {
+        let (at, value) = (address(at)?, byte(value)?);
+        with(&s, |emulator| emulator.cpu_mut().memory.write(at, value));
+        Ok(())
+    });
+
+    let s = slot.clone();
+    engine.register_fn("reg", move |name: &str| -> RhaiResult<INT> {
+        let reg = Reg::parse(name)?;
+        Ok(with(&s, |emulator| {
+            let cpu = emulator.cpu();
+            match reg {
+                Reg::Byte(register) => cpu.reg(register) as u16,
+                Reg::Pair(pair) => cpu.pair(pair),
+                Reg::Sp => cpu.sp(),
+                Reg::Pc => cpu.pc(),
+            }
+        }) as INT)
+    });
+    let s = slot.clone();
+    engine.register_fn("set_reg", move |name: &str, value: INT| -> RhaiResult<()> {
+        let reg = Reg::parse(name)?;
+        let value = match reg {
+            Reg::Byte(_) => byte(value)? as u16,
+            _ => address(value)?,
+        };
+        with(&s, |emulator| {
+            let cpu = emulator.cpu_mut();
+            match reg {
+                Reg::Byte(register) => cpu.set_reg(register, value as u8),
+                Reg::Pair(pair) => cpu.set_pair(pair, value),
+                Reg::Sp => cpu.set_sp(value),
+                Reg::Pc => cpu.set_pc(value),
+            }
+        });
+        Ok(())
+    });
+
+    let s = slot.clone();
+    engine.register_fn("press", move |name: &str| -> RhaiResult<()> {
+        let button = button(name)?;
+        with(&s, |emulator| emulator.button_press(button));
+        Ok(())
+    });
+    let s = slot.clone();
+    engine.register_fn("release", move |name: &str| -> RhaiResult<()> {
+        let button = button(name)?;
+        with(&s, |emulator| emulator.button_release(button));
+        Ok(())
+    });
+    let s = slot.clone();
+    engine.register_fn("held", move |name: &str| -> RhaiResult<bool> {
+        let button = button(name)?;
+        Ok(with(&s, |emulator| emulator.button_held(button)))
+    });
+
+    let s = slot.clone();
+    engine.register_fn("frame", move || with(&s, |emulator| emulator.stats().frames as INT));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+    const RAM_NUM_COINS: u16 = 0x20EB;
+
+    #[test]
+    fn test_hooks() {
+        let source = r#"
+            let writes = 0;
+            on_frame_start(|| {
+                if frame() == 100 { press("coin") } else if frame() == 105 { release("coin") }
+            });
+            // Every coin counts for five credits
+            on_write(0x20EB, |address, old, value| {
+                writes += 1;
+                if value == old + 1 { poke(address, old + 5) }
+            });
+            on_frame_end(|| if writes > 0 { set_reg("b", writes) });
+        "#;
+        let mut emulator = Emulator::new(ROM);
+        let mut script = Script::load(source, &mut emulator).unwrap();
+
+        for _ in 0..200 {
+            assert_eq!(script.run_frame(&mut emulator, &mut ()).unwrap(), FrameStatus::Complete);
+        }
+        assert_eq!(emulator.cpu().memory[RAM_NUM_COINS], 5);
+        assert_ne!(emulator.cpu().reg(Register::B), 0);
+    }
+
+    #[test]
+    fn test_other_watchpoints_stop() {
+        let mut emulator = Emulator::new(ROM);
+        let mut script = Script::load("on_read(0x20EB, |address, value| {});", &mut emulator).unwrap();
+        emulator.cpu_mut().memory.add_watchpoint(Watchpoint::write(0x2072..=0x2072));
+
+        let status = script.run_frame(&mut emulator, &mut ()).unwrap();
+        assert!(matches!(status, FrameStatus::Watchpoint(WatchHit { address: 0x2072, .. })));
+
+        script.unload(&mut emulator);
+        assert_eq!(emulator.cpu().memory.watchpoints(), [Watchpoint::write(0x2072..=0x2072)]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut emulator = Emulator::new(ROM);
+        assert!(matches!(Script::load("on_frame_end(||", &mut emulator), Err(Error::Script(ScriptError::Syntax(_)))));
+        assert!(matches!(Script::load("press(\"jump\")", &mut emulator), Err(Error::Script(ScriptError::Runtime(_)))));
+
+        let mut script = Script::load("on_frame_end(|| peek(0x10000));", &mut emulator).unwrap();
+        assert!(matches!(script.run_frame(&mut emulator, &mut ()), Err(Error::Script(ScriptError::Runtime(_)))));
+    }
+}
@@ -0,0 +1,124 @@
+//! The external shift register of Midway's 8080 boards.
+//!
+//! The 8080 can only shift by one bit per instruction, far too slow to draw sprites at any pixel,
+//! so the board has a 16-bit register and a barrel shifter next to it. Writing a byte shifts it
+//! into the top of the register, pushing the previous one into the bottom, and reading returns
+//! the 8 bits that start `offset` bits below the top. Space Invaders writes data to port 4, the
+//! offset to port 2 and reads the result from port 3; other games on the same board use other
+//! ports, so they can drive a [`ShiftRegister`] through its methods instead.
+
+use crate::{concat_u16, Quirks};
+use crate::io::IoDevice;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShiftRegister {
+    pub(crate) lo: u8,
+    pub(crate) hi: u8,
+    pub(crate) offset: u8,
+    /// Only the low 3 bits of the offset reach the chip, see [`Quirks::mask_shift_offset`].
+    mask_offset: bool,
+}
+
+impl ShiftRegister {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops all but the low 3 bits of offsets written from now on, like the real chip.
+    pub fn set_mask_offset(&mut self, mask: bool) {
+        self.mask_offset = mask;
+    }
+
+    pub(crate) fn set_quirks(&mut self, quirks: Quirks) {
+        self.set_mask_offset(quirks.mask_shift_offset);
+    }
+
+    /// Shifts `val` into the top of the register.
+    pub fn shift_in(&mut self, val: u8) {
+        self.lo = self.hi;
+        self.hi = val;
+    }
+
+    pub fn set_offset(&mut self, offset: u8) {
+        self.offset = if self.mask_offset { offset & 0x7 } else { offset };
+    }
+
+    pub fn offset(&self) -> u8 {
+        self.offset
+    }
+
+    /// The whole register, last byte shifted in at the top.
+    pub fn value(&self) -> u16 {
+        concat_u16!(self.hi, self.lo)
+    }
+
+    /// The 8 bits starting `offset` bits below the top, 0 once the offset is past the register.
+    pub fn result(&self) -> u8 {
+        ((self.value() as u32).checked_shl(self.offset as u32).unwrap_or(0) >> 8) as u8
+    }
+}
+
+/// Space Invaders' wiring: OUT 2 sets the offset, any other OUT shifts data in and every IN
+/// reads the result.
+impl IoDevice for ShiftRegister {
+    fn port_in(&mut self, _port: u8) -> u8 {
+        self.result()
+    }
+
+    fn port_out(&mut self, port: u8, val: u8) {
+        if port == 2 {
+            self.set_offset(val);
+        } else {
+            self.shift_in(val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shift() {
+        let mut shifter = ShiftRegister::new();
+        shifter.shift_in(0xAB);
+        assert_eq!(shifter.value(), 0xAB00);
+        shifter.shift_in(0xCD);
+        assert_eq!(shifter.value(), 0xCDAB);
+
+        assert_eq!(shifter.result(), 0xCD);
+        shifter.set_offset(4);
+        assert_eq!(shifter.result(), 0xDA);
+        shifter.set_offset(7);
+        assert_eq!(shifter.result(), 0xD5);
+    }
+
+    #[test]
+    fn test_offset_mask() {
+        let mut shifter = ShiftRegister::new();
+        shifter.shift_in(0x0F);
+        shifter.shift_in(0xF0);
+
+        shifter.set_offset(12);
+        assert_eq!(shifter.offset(), 12);
+        assert_eq!(shifter.result(), 0xF0);
+        shifter.set_offset(16);
+        assert_eq!(shifter.result(), 0x00);
+
+        shifter.set_mask_offset(true);
+        shifter.set_offset(12);
+        assert_eq!(shifter.offset(), 4);
+        assert_eq!(shifter.result(), 0x00);
+        shifter.set_offset(3);
+        assert_eq!(shifter.result(), 0x80);
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut shifter = ShiftRegister::new();
+        shifter.port_out(4, 0xFF);
+        shifter.port_out(4, 0x01);
+        shifter.port_out(2, 2);
+        assert_eq!(shifter.port_in(3), 0x07);
+    }
+}
@@ -0,0 +1,52 @@
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// Running totals collected by the [`Emulator`](crate::Emulator) since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EmulatorStats {
+    pub instructions: u64,
+    pub cycles: u64,
+    pub frames: u64,
+    pub interrupts: u64,
+    /// Instructions per second of real time, measured over the last completed second. Always 0
+    /// without the `std` feature, which has no clock to measure against.
+    pub ips: f64,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub(crate) struct IpsMeter {
+    window_start: Instant,
+    window_instructions: u64,
+}
+
+#[cfg(feature = "std")]
+impl IpsMeter {
+    pub fn new() -> Self {
+        Self { window_start: Instant::now(), window_instructions: 0 }
+    }
+
+    pub fn update(&mut self, stats: &mut EmulatorStats) {
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+
+        if elapsed >= 1.0 {
+            // Loading a state can take the count back to before the window started
+            stats.ips = stats.instructions.saturating_sub(self.window_instructions) as f64 / elapsed;
+            self.window_start = Instant::now();
+            self.window_instructions = stats.instructions;
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone)]
+pub(crate) struct IpsMeter;
+
+#[cfg(not(feature = "std"))]
+impl IpsMeter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn update(&mut self, _stats: &mut EmulatorStats) {}
+}
@@ -0,0 +1,170 @@
+//! Instruction trace recorded by [`Emulator::step`] while a [`Tracer`] is attached.
+//!
+//! Each entry is the machine state *before* an instruction runs, one per line:
+//!
+//! ```text
+//! PC:1A32 AF:4402 BC:0000 DE:1B00 HL:2000 SP:2400 CYC:42  1A        LDAX D
+//! ```
+//!
+//! The register columns use the layout most 8080 emulators can be made to log, so traces can be
+//! diffed with ordinary text tools, or checked against the core with the `difftrace` example
+//! using `--columns pc,af,bc,de,hl,sp`.
+//!
+//! [`Emulator::step`]: crate::Emulator::step
+
+use alloc::collections::VecDeque;
+use core::fmt::{Display, Formatter};
+use crate::disasm::{self, Instruction};
+use crate::{Registers, CPU};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    /// Cycles executed before the instruction, as counted by [`crate::EmulatorStats`].
+    pub cycle: u64,
+    pub registers: Registers,
+    pub instruction: Instruction,
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let r = &self.registers;
+        write!(
+            f,
+            "PC:{:04X} AF:{:02X}{:02X} BC:{:02X}{:02X} DE:{:02X}{:02X} HL:{:02X}{:02X} SP:{:04X} CYC:{}  {}",
+            r.pc, r.a, r.flags.to_psw(), r.b, r.c, r.d, r.e, r.h, r.l, r.sp, self.cycle, self.instruction,
+        )
+    }
+}
+
+/// Ring buffer of the last `capacity` traced instructions, optionally keeping only every
+/// `interval`th one to cut down the volume of long traces.
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+    interval: u64,
+    /// Instructions left to skip before the next one is recorded.
+    skip: u64,
+    dropped: u64,
+    enabled: bool,
+}
+
+impl Tracer {
+    /// # Panics
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "tracer capacity must be at least 1");
+        Self { entries: VecDeque::with_capacity(capacity), capacity, interval: 1, skip: 0, dropped: 0, enabled: true }
+    }
+
+    /// Keeps only every `interval`th instruction, starting with the next one. 0 is treated as 1.
+    pub fn set_interval(&mut self, interval: u64) {
+        self.interval = interval.max(1);
+        self.skip = 0;
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Pauses or resumes recording without losing the entries kept so far.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Records the instruction `cpu` is about to execute, if it's due.
+    pub fn record(&mut self, cpu: &CPU, cycle: u64) {
+        if !self.enabled {
+            return;
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        self.skip = self.interval - 1;
+
+        let pc = cpu.pc();
+        let bytes = [0, 1, 2].map(|i| cpu.memory[pc.wrapping_add(i)]);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+        self.entries.push_back(TraceEntry { cycle, registers: cpu.registers(), instruction: disasm::disassemble(pc, &bytes) });
+    }
+
+    /// Oldest entry first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    /// Removes and returns the recorded entries, oldest first, e.g. to write them to a file.
+    pub fn drain(&mut self) -> impl Iterator<Item = TraceEntry> + '_ {
+        self.entries.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries pushed out of the buffer before they were drained.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dropped = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Emulator;
+
+    fn traced(program: &[u8], tracer: Tracer, steps: usize) -> Vec<TraceEntry> {
+        let mut emulator = Emulator::new(program);
+        emulator.set_tracer(Some(tracer));
+        for _ in 0..steps {
+            emulator.step().unwrap();
+        }
+        emulator.tracer_mut().unwrap().drain().collect()
+    }
+
+    #[test]
+    fn test_format() {
+        // MVI A,$42; LXI B,$1234
+        let entries = traced(&[0x3E, 0x42, 0x01, 0x34, 0x12], Tracer::new(16), 2);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].to_string(), "PC:0000 AF:0002 BC:0000 DE:0000 HL:0000 SP:0000 CYC:0  3E 42     MVI A,$42");
+        assert_eq!(entries[1].to_string(), "PC:0002 AF:4202 BC:0000 DE:0000 HL:0000 SP:0000 CYC:7  01 34 12  LXI B,$1234");
+    }
+
+    #[test]
+    fn test_interval_and_capacity() {
+        let mut tracer = Tracer::new(2);
+        tracer.set_interval(3);
+        let entries = traced(&[0; 16], tracer, 10);
+
+        // Instructions 0, 3, 6 and 9 are due, only the last two fit
+        let pcs: Vec<u16> = entries.iter().map(|e| e.registers.pc).collect();
+        assert_eq!(pcs, [6, 9]);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut tracer = Tracer::new(16);
+        tracer.set_enabled(false);
+        assert!(traced(&[0; 4], tracer, 4).is_empty());
+    }
+}
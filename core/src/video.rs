@@ -0,0 +1,282 @@
+//! Turns video RAM into images a frontend can put on screen.
+//!
+//! Video RAM holds one bit per pixel in the order the beam draws them: 224 lines of 32 bytes,
+//! least significant bit first, so each line is 256 pixels. The monitor sits in the cabinet
+//! rotated 90° counterclockwise, which makes every line a column of the upright picture, drawn
+//! from the bottom up, with line 0 on the left. [`Renderer`] unpacks the bits into RGB24, RGBA32
+//! or one byte per pixel, either as the beam draws them or already upright, and [`DirtyLines`]
+//! lets it redraw only the lines that changed.
+
+use core::ops::Range;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::raster::{BYTES_PER_LINE, VISIBLE_LINES};
+
+/// Upright picture size, as the player sees it.
+pub const WIDTH: u32 = VISIBLE_LINES;
+pub const HEIGHT: u32 = BYTES_PER_LINE as u32 * 8;
+
+pub type Rgb = [u8; 3];
+pub const WHITE: Rgb = [0xFF; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    Rgb24,
+    /// RGB24 with an opaque alpha byte after every pixel.
+    Rgba32,
+    /// One byte per pixel, 1 if it's lit and 0 if not, for frontends with their own palette.
+    Indexed,
+}
+
+impl PixelFormat {
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb24 => 3,
+            Self::Rgba32 => 4,
+            Self::Indexed => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// As the beam draws it: 256 pixels wide and 224 tall, one row per line. Needs rotating 90°
+    /// counterclockwise to look right.
+    Native,
+    /// As the player sees it: [`WIDTH`] x [`HEIGHT`].
+    Upright,
+}
+
+impl Orientation {
+    /// Width and height of the image.
+    pub const fn size(self) -> (u32, u32) {
+        match self {
+            Self::Native => (HEIGHT, WIDTH),
+            Self::Upright => (WIDTH, HEIGHT),
+        }
+    }
+}
+
+/// Converts video RAM to images of one format and orientation, top row first with no padding
+/// between rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Renderer {
+    pub format: PixelFormat,
+    pub orientation: Orientation,
+}
+
+impl Renderer {
+    pub const fn new(format: PixelFormat, orientation: Orientation) -> Self {
+        Self { format, orientation }
+    }
+
+    pub const fn width(&self) -> u32 {
+        self.orientation.size().0
+    }
+
+    pub const fn height(&self) -> u32 {
+        self.orientation.size().1
+    }
+
+    /// Bytes per row of the image.
+    pub const fn pitch(&self) -> usize {
+        self.width() as usize * self.format.bytes_per_pixel()
+    }
+
+    /// Bytes of the whole image.
+    pub const fn image_len(&self) -> usize {
+        self.pitch() * self.height() as usize
+    }
+
+    /// A black image to render to.
+    pub fn buffer(&self) -> Vec<u8> {
+        let mut buffer = vec![0; self.image_len()];
+        if self.format == PixelFormat::Rgba32 {
+            buffer.iter_mut().skip(3).step_by(4).for_each(|alpha| *alpha = 0xFF);
+        }
+        buffer
+    }
+
+    /// Draws `vram`, e.g. [`Emulator::screen`](crate::Emulator::screen), into `out`. Unlit pixels
+    /// are black and `color` gives the color of the lit one at `x` and `y` of the upright picture
+    /// from the top left, whatever the orientation, so overlays only have to be laid out once.
+    /// [`PixelFormat::Indexed`] doesn't use it. Returns whether any pixel changed, so frontends
+    /// can skip uploading the image when nothing moved.
+    ///
+    /// # Panics
+    ///
+    /// If `out` is shorter than [`Renderer::image_len`].
+    pub fn render(&self, vram: &[u8], out: &mut [u8], color: impl Fn(u32, u32) -> Rgb) -> bool {
+        self.render_lines(vram, out, 0..VISIBLE_LINES, color)
+    }
+
+    /// Like [`Renderer::render`], but only draws `lines`, e.g. the ranges of
+    /// [`Emulator::take_dirty_lines`](crate::Emulator::take_dirty_lines). Lines are columns of
+    /// the upright picture and rows of the native one.
+    pub fn render_lines(&self, vram: &[u8], out: &mut [u8], lines: Range<u32>, color: impl Fn(u32, u32) -> Rgb) -> bool {
+        assert!(out.len() >= self.image_len(), "image buffer too small: {} < {}", out.len(), self.image_len());
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let width = self.width() as usize;
+        let mut changed = false;
+
+        let lines = lines.start.min(VISIBLE_LINES) as usize..lines.end.min(VISIBLE_LINES) as usize;
+        let bytes = lines.start * BYTES_PER_LINE..(lines.end * BYTES_PER_LINE).min(vram.len());
+        for (index, byte) in vram.get(bytes.clone()).unwrap_or_default().iter().enumerate() {
+            let index = bytes.start + index;
+            let line = index / BYTES_PER_LINE;
+            for bit in 0..8 {
+                let position = index % BYTES_PER_LINE * 8 + bit;
+                let (x, y) = (line as u32, HEIGHT - 1 - position as u32);
+                let lit = byte & 1 << bit != 0;
+
+                let pixel: [u8; 4] = match self.format {
+                    PixelFormat::Indexed => [lit as u8, 0, 0, 0],
+                    _ => {
+                        let [r, g, b] = if lit { color(x, y) } else { [0; 3] };
+                        [r, g, b, 0xFF]
+                    }
+                };
+
+                let offset = match self.orientation {
+                    Orientation::Native => (line * width + position) * bytes_per_pixel,
+                    Orientation::Upright => (y as usize * width + x as usize) * bytes_per_pixel,
+                };
+                let target = &mut out[offset..offset + bytes_per_pixel];
+                if *target != pixel[..bytes_per_pixel] {
+                    target.copy_from_slice(&pixel[..bytes_per_pixel]);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+/// A set of lines of the picture, see
+/// [`Emulator::take_dirty_lines`](crate::Emulator::take_dirty_lines).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DirtyLines([u64; 4]);
+
+impl DirtyLines {
+    pub const fn new() -> Self {
+        Self([0; 4])
+    }
+
+    /// Every line of the picture.
+    pub fn all() -> Self {
+        let mut lines = Self::new();
+        (0..VISIBLE_LINES).for_each(|line| lines.insert(line));
+        lines
+    }
+
+    pub fn insert(&mut self, line: u32) {
+        self.0[line as usize / 64] |= 1 << (line % 64);
+    }
+
+    pub fn contains(&self, line: u32) -> bool {
+        line < VISIBLE_LINES && self.0[line as usize / 64] & 1 << (line % 64) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    pub fn len(&self) -> u32 {
+        self.0.iter().map(|bits| bits.count_ones()).sum()
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = u32> {
+        (0..VISIBLE_LINES).filter(move |&line| self.contains(line))
+    }
+
+    /// Runs of consecutive lines, first to last, so a texture can be updated a block at a time.
+    pub fn ranges(self) -> impl Iterator<Item = Range<u32>> {
+        let mut line = 0;
+        core::iter::from_fn(move || {
+            let start = (line..VISIBLE_LINES).find(|&line| self.contains(line))?;
+            let end = (start..VISIBLE_LINES).find(|&line| !self.contains(line)).unwrap_or(VISIBLE_LINES);
+            line = end;
+            Some(start..end)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Video RAM with the first pixel of line 0 and the last pixel of line 1 lit.
+    fn vram() -> Vec<u8> {
+        let mut vram = vec![0; BYTES_PER_LINE * VISIBLE_LINES as usize];
+        vram[0] = 0x01;
+        vram[2 * BYTES_PER_LINE - 1] = 0x80;
+        vram
+    }
+
+    #[test]
+    fn test_upright() {
+        let renderer = Renderer::new(PixelFormat::Rgb24, Orientation::Upright);
+        let mut image = renderer.buffer();
+        assert_eq!(image.len(), 224 * 256 * 3);
+        assert!(renderer.render(&vram(), &mut image, |x, y| [x as u8, y as u8, 0x42]));
+
+        // Line 0 is the left column, drawn from the bottom
+        let bottom_left = (HEIGHT as usize - 1) * renderer.pitch();
+        assert_eq!(image[bottom_left..bottom_left + 3], [0, 255, 0x42]);
+        assert_eq!(image[3..6], [1, 0, 0x42]);
+        assert_eq!(image.iter().filter(|&&byte| byte != 0).count(), 4);
+
+        assert!(!renderer.render(&vram(), &mut image, |x, y| [x as u8, y as u8, 0x42]));
+    }
+
+    #[test]
+    fn test_native() {
+        let renderer = Renderer::new(PixelFormat::Rgba32, Orientation::Native);
+        assert_eq!((renderer.width(), renderer.height()), (256, 224));
+        let mut image = renderer.buffer();
+        renderer.render(&vram(), &mut image, |_, _| WHITE);
+
+        assert_eq!(image[0..4], [0xFF; 4]);
+        assert_eq!(image[4..8], [0, 0, 0, 0xFF]);
+        let end_of_line_1 = 2 * renderer.pitch() - 4;
+        assert_eq!(image[end_of_line_1..end_of_line_1 + 4], [0xFF; 4]);
+    }
+
+    #[test]
+    fn test_indexed() {
+        let renderer = Renderer::new(PixelFormat::Indexed, Orientation::Native);
+        let mut image = renderer.buffer();
+        renderer.render(&vram(), &mut image, |_, _| unreachable!());
+
+        assert_eq!(image.len(), 256 * 224);
+        assert_eq!(image[0], 1);
+        assert_eq!(image[2 * 256 - 1], 1);
+        assert_eq!(image.iter().map(|&pixel| pixel as u32).sum::<u32>(), 2);
+    }
+
+    #[test]
+    fn test_render_lines() {
+        let renderer = Renderer::new(PixelFormat::Indexed, Orientation::Native);
+        let mut image = renderer.buffer();
+        assert!(!renderer.render_lines(&vram(), &mut image, 2..VISIBLE_LINES, |_, _| WHITE));
+        assert!(renderer.render_lines(&vram(), &mut image, 1..2, |_, _| WHITE));
+        assert_eq!(image[0], 0);
+        assert_eq!(image[2 * 256 - 1], 1);
+    }
+
+    #[test]
+    fn test_dirty_lines() {
+        let mut lines = DirtyLines::new();
+        assert!(lines.is_empty());
+        for line in [0, 1, 2, 63, 64, 100, 223] {
+            lines.insert(line);
+        }
+        assert_eq!(lines.len(), 7);
+        assert!(lines.contains(64) && !lines.contains(65));
+        assert_eq!(lines.ranges().collect::<Vec<_>>(), [0..3, 63..65, 100..101, 223..224]);
+        let mut all = DirtyLines::all().ranges();
+        assert_eq!(all.next(), Some(0..VISIBLE_LINES));
+        assert_eq!(all.next(), None);
+    }
+}
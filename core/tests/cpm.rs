@@ -0,0 +1,63 @@
+use std::path::Path;
+use core::cpm::Cpm;
+
+/// Test programs aren't redistributable, so the exerciser tests only run when they're copied
+/// here (TST8080.COM, CPUTEST.COM, 8080EXM.COM) and pass trivially otherwise.
+const PROGRAM_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cpm");
+
+fn run_program(name: &str, max_cycles: u64) -> Option<String> {
+    let path = Path::new(PROGRAM_DIR).join(name);
+    let Ok(program) = std::fs::read(&path) else {
+        eprintln!("skipping {}: {} not found", name, path.display());
+        return None;
+    };
+
+    let mut cpm = Cpm::new(&program);
+    let exited = cpm.run(max_cycles).unwrap();
+    assert!(exited, "{} still running after {} cycles:\n{}", name, max_cycles, cpm.output());
+    Some(cpm.output().to_string())
+}
+
+#[test]
+fn prints_and_exits() {
+    // MVI C,9; LXI D,$0112; CALL 5; MVI C,2; MVI E,'!'; CALL 5; JMP 0; "HELLO$"
+    let mut program = vec![
+        0x0E, 0x09, 0x11, 0x12, 0x01, 0xCD, 0x05, 0x00,
+        0x0E, 0x02, 0x1E, b'!', 0xCD, 0x05, 0x00, 0xC3, 0x00, 0x00,
+    ];
+    program.extend_from_slice(b"HELLO$");
+
+    let mut cpm = Cpm::new(&program);
+    assert!(cpm.run(1_000).unwrap());
+    assert_eq!(cpm.output(), "HELLO!");
+}
+
+#[test]
+fn returning_from_the_program_exits() {
+    // RET
+    let mut cpm = Cpm::new(&[0xC9]);
+    assert!(cpm.run(100).unwrap());
+}
+
+#[test]
+fn tst8080() {
+    if let Some(output) = run_program("TST8080.COM", 10_000_000) {
+        assert!(output.contains("CPU IS OPERATIONAL"), "{}", output);
+    }
+}
+
+#[test]
+fn cputest() {
+    if let Some(output) = run_program("CPUTEST.COM", 1_000_000_000) {
+        assert!(output.contains("CPU TESTS OK"), "{}", output);
+    }
+}
+
+/// Takes tens of billions of cycles, run with `cargo test --release --features cpm -- --ignored`.
+#[test]
+#[ignore]
+fn exm8080() {
+    if let Some(output) = run_program("8080EXM.COM", 50_000_000_000) {
+        assert!(!output.contains("ERROR"), "{}", output);
+    }
+}
@@ -0,0 +1,50 @@
+use core::{Button, Emulator};
+
+const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+const FRAMES: u32 = 10_000;
+
+/// [`Emulator::state_hash`] after playing back `movie_input` for `FRAMES` frames.
+const EXPECTED_HASH: u64 = 0x13C4_C367_DA38_4DD4;
+
+/// Buttons held during a frame of the fixed input movie: insert a coin, start a
+/// one-player game, then sweep left and right while firing.
+fn movie_input(frame: u32) -> Vec<Button> {
+    match frame {
+        100..=104 => vec![Button::Coin],
+        200..=204 => vec![Button::P1Start],
+        300.. => {
+            let mut buttons = Vec::new();
+            match (frame / 60) % 3 {
+                0 => buttons.push(Button::P1Left),
+                1 => buttons.push(Button::P1Right),
+                _ => {}
+            }
+            if frame % 20 < 3 {
+                buttons.push(Button::P1Shoot);
+            }
+            buttons
+        }
+        _ => vec![],
+    }
+}
+
+#[test]
+fn test_fixed_movie() {
+    let mut emulator = Emulator::new(ROM);
+    let mut held: Vec<Button> = vec![];
+
+    for frame in 0..FRAMES {
+        for button in held.drain(..) {
+            emulator.button_release(button);
+        }
+
+        held = movie_input(frame);
+        for button in &held {
+            emulator.button_press(*button);
+        }
+
+        emulator.run_frame(&mut ()).expect("emulation error");
+    }
+
+    assert_eq!(emulator.state_hash(), EXPECTED_HASH, "final state hash diverged");
+}
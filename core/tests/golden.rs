@@ -0,0 +1,52 @@
+//! Plays `golden/invaders.inputs` and compares the state hash after every frame against
+//! `golden/invaders.hashes`, so any change in emulation behavior shows up as the first frame that
+//! differs. Rerun with `UPDATE_GOLDEN=1` to rewrite the golden file along with a change that
+//! alters it.
+
+use std::fs;
+use std::path::PathBuf;
+
+use core::movie::{Movie, Player};
+use core::Emulator;
+
+const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+const INPUTS: &str = include_str!("golden/invaders.inputs");
+
+fn golden_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/invaders.hashes")
+}
+
+/// State hash after each frame of the input script.
+fn run() -> Vec<u64> {
+    let mut emulator = Emulator::new(ROM);
+    let mut player = Player::start(Movie::from_script(INPUTS).unwrap(), &mut emulator).unwrap();
+
+    let mut hashes = Vec::new();
+    while player.next_frame(&mut emulator) {
+        emulator.run_frame(&mut ()).expect("emulation error");
+        hashes.push(emulator.state_hash());
+    }
+    hashes
+}
+
+#[test]
+fn test_golden_state_hashes() {
+    let hashes = run();
+    let path = golden_path();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let contents: String = hashes.iter().enumerate().map(|(frame, hash)| format!("{} {:016X}\n", frame, hash)).collect();
+        fs::write(&path, contents).unwrap();
+        return;
+    }
+
+    let golden = fs::read_to_string(&path).unwrap_or_else(|e| panic!("could not read {}: {}; run with UPDATE_GOLDEN=1 to create it", path.display(), e));
+    let golden: Vec<u64> = golden.lines()
+        .map(|line| line.split_whitespace().nth(1).and_then(|hash| u64::from_str_radix(hash, 16).ok()).expect("invalid golden line"))
+        .collect();
+
+    assert_eq!(hashes.len(), golden.len(), "the input script and the golden file have a different number of frames");
+    if let Some(frame) = (0..hashes.len()).find(|&frame| hashes[frame] != golden[frame]) {
+        panic!("state diverged from the golden run at frame {}: expected {:016X}, got {:016X}", frame, golden[frame], hashes[frame]);
+    }
+}
@@ -0,0 +1,153 @@
+#ifndef SPACE_INVADERS_H
+#define SPACE_INVADERS_H
+
+/* Generated with cbindgen. Do not edit by hand, run `cbindgen --config cbindgen.toml --output include/space_invaders.h` instead. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum SiStatus {
+  SI_STATUS_OK,
+  SI_STATUS_HALTED,
+  SI_STATUS_ERROR,
+  SI_STATUS_NULL_POINTER,
+  /**
+   * Stopped before the instruction at a breakpoint, see `si_emulator_set_breakpoint`.
+   */
+  SI_STATUS_BREAKPOINT,
+} SiStatus;
+
+typedef enum SiButton {
+  SI_BUTTON_COIN,
+  SI_BUTTON_P1_START,
+  SI_BUTTON_P2_START,
+  SI_BUTTON_P1_SHOOT,
+  SI_BUTTON_P2_SHOOT,
+  SI_BUTTON_P1_LEFT,
+  SI_BUTTON_P2_LEFT,
+  SI_BUTTON_P1_RIGHT,
+  SI_BUTTON_P2_RIGHT,
+  SI_BUTTON_TILT,
+} SiButton;
+
+/**
+ * Opaque emulator handle.
+ */
+typedef struct SiEmulator SiEmulator;
+
+/**
+ * Opaque snapshot of a complete emulator state.
+ */
+typedef struct SiState SiState;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an emulator running the given ROM image (up to 8 KiB).
+ *
+ * Returns null if `rom` is null.
+ *
+ * # Safety
+ *
+ * `rom` must point to `rom_len` readable bytes.
+ */
+struct SiEmulator *si_emulator_new(const uint8_t *rom, uintptr_t rom_len);
+
+/**
+ * Destroys an emulator created with `si_emulator_new`.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a pointer returned by `si_emulator_new` that was not freed yet.
+ */
+void si_emulator_free(struct SiEmulator *emu);
+
+/**
+ * Resets the CPU and clears RAM.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a valid emulator handle.
+ */
+enum SiStatus si_emulator_reset(struct SiEmulator *emu);
+
+/**
+ * Emulates one frame of the board (about 1/59.54s), including the mid-screen and VBlank
+ * interrupts. A frame stopped at a breakpoint is finished by the next call.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a valid emulator handle.
+ */
+enum SiStatus si_emulator_step_frame(struct SiEmulator *emu);
+
+/**
+ * Returns a pointer to the 1bpp video RAM (7 KiB, 32 bytes per column of 256 pixels, bottom to
+ * top) as the beam drew it during the last frame, and stores its length in `len` if not null.
+ *
+ * The pointer stays valid until the emulator is stepped, reset, loaded or freed.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a valid emulator handle, and `len` must be null or writable.
+ */
+const uint8_t *si_emulator_framebuffer(const struct SiEmulator *emu, uintptr_t *len);
+
+/**
+ * Presses or releases a cabinet button.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a valid emulator handle.
+ */
+enum SiStatus si_emulator_set_button(struct SiEmulator *emu, enum SiButton button, bool pressed);
+
+/**
+ * Sets or clears a breakpoint at `address`. `si_emulator_step_frame` returns
+ * `SI_STATUS_BREAKPOINT` before running the instruction there, cutting the frame short; the next
+ * call runs it.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a valid emulator handle.
+ */
+enum SiStatus si_emulator_set_breakpoint(struct SiEmulator *emu, uint16_t address, bool enabled);
+
+/**
+ * Captures the current emulator state. Free it with `si_state_free`.
+ *
+ * # Safety
+ *
+ * `emu` must be null or a valid emulator handle.
+ */
+struct SiState *si_emulator_save_state(const struct SiEmulator *emu);
+
+/**
+ * Restores a state captured with `si_emulator_save_state`. The state can be loaded any number of
+ * times.
+ *
+ * # Safety
+ *
+ * `emu` and `state` must each be null or a valid handle.
+ */
+enum SiStatus si_emulator_load_state(struct SiEmulator *emu,
+                                     const struct SiState *state);
+
+/**
+ * Destroys a state created with `si_emulator_save_state`.
+ *
+ * # Safety
+ *
+ * `state` must be null or a pointer returned by `si_emulator_save_state` that was not freed yet.
+ */
+void si_state_free(struct SiState *state);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SPACE_INVADERS_H */
@@ -0,0 +1,285 @@
+//! C ABI for embedding the emulator core in C/C++ front-ends.
+//!
+//! The matching header lives in `include/space_invaders.h` and is generated with cbindgen.
+
+use std::{ptr, slice};
+use core::{Button, Emulator, FrameStatus};
+
+/// Opaque emulator handle.
+pub struct SiEmulator {
+    emulator: Emulator,
+}
+
+/// Opaque snapshot of a complete emulator state.
+pub struct SiState {
+    emulator: Emulator,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum SiButton {
+    Coin,
+    P1Start,
+    P2Start,
+    P1Shoot,
+    P2Shoot,
+    P1Left,
+    P2Left,
+    P1Right,
+    P2Right,
+    Tilt,
+}
+
+impl From<SiButton> for Button {
+    fn from(button: SiButton) -> Self {
+        match button {
+            SiButton::Coin => Self::Coin,
+            SiButton::P1Start => Self::P1Start,
+            SiButton::P2Start => Self::P2Start,
+            SiButton::P1Shoot => Self::P1Shoot,
+            SiButton::P2Shoot => Self::P2Shoot,
+            SiButton::P1Left => Self::P1Left,
+            SiButton::P2Left => Self::P2Left,
+            SiButton::P1Right => Self::P1Right,
+            SiButton::P2Right => Self::P2Right,
+            SiButton::Tilt => Self::Tilt,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiStatus {
+    Ok,
+    Halted,
+    Error,
+    NullPointer,
+    /// Stopped before the instruction at a breakpoint, see `si_emulator_set_breakpoint`.
+    Breakpoint,
+}
+
+/// Creates an emulator running the given ROM image (up to 8 KiB).
+///
+/// Returns null if `rom` is null.
+///
+/// # Safety
+///
+/// `rom` must point to `rom_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_new(rom: *const u8, rom_len: usize) -> *mut SiEmulator {
+    if rom.is_null() { return ptr::null_mut(); }
+
+    let program = slice::from_raw_parts(rom, rom_len.min(0x2000));
+    Box::into_raw(Box::new(SiEmulator { emulator: Emulator::new(program) }))
+}
+
+/// Destroys an emulator created with `si_emulator_new`.
+///
+/// # Safety
+///
+/// `emu` must be null or a pointer returned by `si_emulator_new` that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_free(emu: *mut SiEmulator) {
+    if !emu.is_null() {
+        drop(Box::from_raw(emu));
+    }
+}
+
+/// Resets the CPU and clears RAM.
+///
+/// # Safety
+///
+/// `emu` must be null or a valid emulator handle.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_reset(emu: *mut SiEmulator) -> SiStatus {
+    let Some(emu) = emu.as_mut() else { return SiStatus::NullPointer };
+    emu.emulator.reset();
+    SiStatus::Ok
+}
+
+/// Emulates one frame of the board (about 1/59.54s), including the mid-screen and VBlank
+/// interrupts. A frame stopped at a breakpoint is finished by the next call.
+///
+/// # Safety
+///
+/// `emu` must be null or a valid emulator handle.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_step_frame(emu: *mut SiEmulator) -> SiStatus {
+    let Some(emu) = emu.as_mut() else { return SiStatus::NullPointer };
+
+    // Sound output is not exposed through the C API yet
+    match emu.emulator.run_frame(&mut ()) {
+        Ok(FrameStatus::Halted) => SiStatus::Halted,
+        Ok(FrameStatus::Breakpoint(_)) => SiStatus::Breakpoint,
+        Ok(_) => SiStatus::Ok,
+        Err(_) => SiStatus::Error,
+    }
+}
+
+/// Returns a pointer to the 1bpp video RAM (7 KiB, 32 bytes per column of 256 pixels, bottom to
+/// top) as the beam drew it during the last frame, and stores its length in `len` if not null.
+///
+/// The pointer stays valid until the emulator is stepped, reset, loaded or freed.
+///
+/// # Safety
+///
+/// `emu` must be null or a valid emulator handle, and `len` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_framebuffer(emu: *const SiEmulator, len: *mut usize) -> *const u8 {
+    let Some(emu) = emu.as_ref() else { return ptr::null() };
+    let screen = emu.emulator.screen();
+
+    if let Some(len) = len.as_mut() {
+        *len = screen.len();
+    }
+    screen.as_ptr()
+}
+
+/// Presses or releases a cabinet button.
+///
+/// # Safety
+///
+/// `emu` must be null or a valid emulator handle.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_set_button(emu: *mut SiEmulator, button: SiButton, pressed: bool) -> SiStatus {
+    let Some(emu) = emu.as_mut() else { return SiStatus::NullPointer };
+
+    emu.emulator.set_button(button.into(), pressed);
+    SiStatus::Ok
+}
+
+/// Sets or clears a breakpoint at `address`. `si_emulator_step_frame` returns
+/// `SI_STATUS_BREAKPOINT` before running the instruction there, cutting the frame short; the next
+/// call runs it.
+///
+/// # Safety
+///
+/// `emu` must be null or a valid emulator handle.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_set_breakpoint(emu: *mut SiEmulator, address: u16, enabled: bool) -> SiStatus {
+    let Some(emu) = emu.as_mut() else { return SiStatus::NullPointer };
+
+    if enabled {
+        emu.emulator.add_breakpoint(address);
+    } else {
+        emu.emulator.remove_breakpoint(address);
+    }
+    SiStatus::Ok
+}
+
+/// Captures the current emulator state. Free it with `si_state_free`.
+///
+/// # Safety
+///
+/// `emu` must be null or a valid emulator handle.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_save_state(emu: *const SiEmulator) -> *mut SiState {
+    let Some(emu) = emu.as_ref() else { return ptr::null_mut() };
+    Box::into_raw(Box::new(SiState { emulator: emu.emulator.clone() }))
+}
+
+/// Restores a state captured with `si_emulator_save_state`. The state can be loaded any number of
+/// times.
+///
+/// # Safety
+///
+/// `emu` and `state` must each be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn si_emulator_load_state(emu: *mut SiEmulator, state: *const SiState) -> SiStatus {
+    let (Some(emu), Some(state)) = (emu.as_mut(), state.as_ref()) else { return SiStatus::NullPointer };
+    emu.emulator = state.emulator.clone();
+    SiStatus::Ok
+}
+
+/// Destroys a state created with `si_emulator_save_state`.
+///
+/// # Safety
+///
+/// `state` must be null or a pointer returned by `si_emulator_save_state` that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn si_state_free(state: *mut SiState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../../frontend/assets/invaders");
+
+    unsafe fn framebuffer(emu: *const SiEmulator) -> Vec<u8> {
+        let mut len = 0;
+        let screen = si_emulator_framebuffer(emu, &mut len);
+        slice::from_raw_parts(screen, len).to_vec()
+    }
+
+    #[test]
+    fn test_frames_and_states() {
+        unsafe {
+            let emu = si_emulator_new(ROM.as_ptr(), ROM.len());
+            assert!(!emu.is_null());
+            for _ in 0..60 {
+                assert_eq!(si_emulator_step_frame(emu), SiStatus::Ok);
+            }
+
+            let state = si_emulator_save_state(emu);
+            assert!(!state.is_null());
+            for _ in 0..60 {
+                si_emulator_step_frame(emu);
+            }
+            let later = framebuffer(emu);
+            assert_eq!(later.len(), 0x1C00);
+
+            // Loading twice replays the same frames
+            for _ in 0..2 {
+                assert_eq!(si_emulator_load_state(emu, state), SiStatus::Ok);
+                for _ in 0..60 {
+                    si_emulator_step_frame(emu);
+                }
+                assert_eq!(framebuffer(emu), later);
+            }
+
+            si_state_free(state);
+            si_emulator_free(emu);
+        }
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        unsafe {
+            let emu = si_emulator_new(ROM.as_ptr(), ROM.len());
+            assert_eq!(si_emulator_set_breakpoint(emu, 0x0008, true), SiStatus::Ok);
+            assert_eq!(si_emulator_step_frame(emu), SiStatus::Breakpoint);
+
+            assert_eq!(si_emulator_set_breakpoint(emu, 0x0008, false), SiStatus::Ok);
+            assert_eq!(si_emulator_step_frame(emu), SiStatus::Ok);
+            si_emulator_free(emu);
+        }
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        unsafe {
+            assert!(si_emulator_new(ptr::null(), 0x2000).is_null());
+            assert_eq!(si_emulator_reset(ptr::null_mut()), SiStatus::NullPointer);
+            assert_eq!(si_emulator_step_frame(ptr::null_mut()), SiStatus::NullPointer);
+            assert_eq!(si_emulator_set_button(ptr::null_mut(), SiButton::Coin, true), SiStatus::NullPointer);
+            assert_eq!(si_emulator_set_breakpoint(ptr::null_mut(), 0, true), SiStatus::NullPointer);
+            assert!(si_emulator_framebuffer(ptr::null(), ptr::null_mut()).is_null());
+            assert!(si_emulator_save_state(ptr::null()).is_null());
+
+            let emu = si_emulator_new(ROM.as_ptr(), ROM.len());
+            assert!(!si_emulator_framebuffer(emu, ptr::null_mut()).is_null());
+            assert_eq!(si_emulator_load_state(emu, ptr::null()), SiStatus::NullPointer);
+            let state = si_emulator_save_state(emu);
+            assert_eq!(si_emulator_load_state(ptr::null_mut(), state), SiStatus::NullPointer);
+
+            si_state_free(state);
+            si_emulator_free(emu);
+            si_state_free(ptr::null_mut());
+            si_emulator_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Achievement unlocks, persisted to a local file and announced with on-screen toasts.
+//!
+//! The file has one `<id> <unix time>` line per unlocked achievement.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use core::{achievements, AchievementTracker, Emulator};
+
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Debug)]
+pub struct Achievements {
+    path: PathBuf,
+    tracker: AchievementTracker,
+    /// Titles waiting to be shown, the first one since the given instant.
+    toasts: VecDeque<(String, Option<Instant>)>,
+}
+
+impl Achievements {
+    /// Starts tracking the built-in achievements, skipping those already unlocked in `path`.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let mut tracker = AchievementTracker::new(achievements::builtin());
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                for id in contents.lines().filter_map(|line| line.split_whitespace().next()) {
+                    tracker.set_unlocked(id);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(Self { path, tracker, toasts: VecDeque::new() })
+    }
+
+    /// Must be called once per emulated frame.
+    pub fn update(&mut self, emulator: &Emulator) -> io::Result<()> {
+        let unlocked: Vec<_> = self.tracker.update(&emulator.cpu().memory).into_iter()
+            .map(|a| (a.id, a.title))
+            .collect();
+        if unlocked.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        for (id, title) in unlocked {
+            writeln!(file, "{} {}", id, timestamp)?;
+            self.toasts.push_back((title.to_string(), None));
+        }
+
+        Ok(())
+    }
+
+    /// Lines of the toast to show right now, if any.
+    pub fn toast(&mut self) -> Option<Vec<String>> {
+        if self.toasts.front().is_some_and(|(_, shown)| shown.is_some_and(|t| t.elapsed() >= TOAST_DURATION)) {
+            self.toasts.pop_front();
+        }
+
+        let (title, shown) = self.toasts.front_mut()?;
+        shown.get_or_insert_with(Instant::now);
+        Some(vec!["ACHIEVEMENT UNLOCKED".to_string(), title.clone()])
+    }
+
+    pub fn summary(&self) -> String {
+        let all = self.tracker.achievements();
+        let unlocked = all.iter().filter(|a| self.tracker.is_unlocked(a.id)).count();
+        format!("ACHIEVEMENTS  {}/{}", unlocked, all.len())
+    }
+}
@@ -1,14 +1,17 @@
-use sdl2::audio::{AudioCallback, AudioCVT, AudioDevice, AudioSpecDesired, AudioSpecWAV};
+use sdl2::audio::{AudioCallback, AudioCVT, AudioDevice, AudioSpecDesired, AudioSpecWAV, AudioStatus};
 use sdl2::AudioSubsystem;
 use sdl2::rwops::RWops;
 
-use core::Sound as GameSound;
+use core::{Emulator, Sound as GameSound};
 
 #[derive(Debug, Clone)]
 pub struct Sound {
     data: Vec<u8>,
     volume: f32,
-    position: usize,
+    /// Index into `data`, fractional when playing at a pitch other than the original.
+    position: f32,
+    /// Samples of `data` advanced per sample played, below 1 for a lower pitch.
+    rate: f32,
     loop_sound: bool,
 }
 
@@ -17,13 +20,13 @@ impl AudioCallback for Sound {
 
     fn callback(&mut self, out: &mut [Self::Channel]) {
         for dst in out.iter_mut() {
-            let pre_scale = *self.data.get(self.position).unwrap_or(&128);
+            let pre_scale = *self.data.get(self.position as usize).unwrap_or(&128);
             let scaled_signed_float = (pre_scale as f32 - 128.0) * self.volume;
             *dst = (scaled_signed_float + 128.0) as u8;
-            self.position += 1;
+            self.position += self.rate;
 
-            if self.loop_sound && self.position >= self.data.len() {
-                self.position = 0;
+            if self.loop_sound && self.position >= self.data.len() as f32 {
+                self.position = 0.0;
             }
         }
     }
@@ -39,6 +42,8 @@ pub struct AudioManager {
     bomp3: AudioDevice<Sound>,
     bomp4: AudioDevice<Sound>,
     ufo_explode: AudioDevice<Sound>,
+    /// Set while fast-forwarding, when new sounds aren't started.
+    muted: bool,
 }
 
 impl AudioManager {
@@ -53,13 +58,17 @@ impl AudioManager {
             bomp3: device_from_wav(include_bytes!("../assets/audio/6.wav"), &audio_subsystem, false)?,
             bomp4: device_from_wav(include_bytes!("../assets/audio/7.wav"), &audio_subsystem, false)?,
             ufo_explode: device_from_wav(include_bytes!("../assets/audio/8.wav"), &audio_subsystem, false)?,
+            muted: false,
         })
     }
 
     pub fn play(&mut self, sound: GameSound) {
+        if self.muted {
+            return;
+        }
         let device = self.match_device(sound);
 
-        device.lock().position = 0;
+        device.lock().position = 0.0;
         device.resume();
     }
 
@@ -80,6 +89,40 @@ impl AudioManager {
         self.ufo_explode.pause();
     }
 
+    /// Matches the looping UFO sound to `emulator` after its state was replaced, which raises no
+    /// sound events, and cuts the rest.
+    pub fn sync(&mut self, emulator: &Emulator) {
+        let ufo_playing = self.ufo.status() == AudioStatus::Playing;
+        self.stop_all();
+        match (emulator.sound_on(GameSound::UFO), ufo_playing) {
+            (true, true) => self.ufo.resume(),
+            (true, false) => self.play(GameSound::UFO),
+            (false, _) => {}
+        }
+    }
+
+    /// Stops every sound and keeps new ones from starting until unmuted.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if muted {
+            self.stop_all();
+        }
+    }
+
+    /// Mutes every sound above 1x, and lowers their pitch to match slow motion below it. Sounds
+    /// already playing go on when unmuted, so call [`AudioManager::sync`] afterwards.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.set_muted(speed > 1.0);
+        let rate = speed.min(1.0) as f32;
+        let devices = [
+            &mut self.ufo, &mut self.shoot, &mut self.player_die, &mut self.invader_die,
+            &mut self.bomp1, &mut self.bomp2, &mut self.bomp3, &mut self.bomp4, &mut self.ufo_explode,
+        ];
+        for device in devices {
+            device.lock().rate = rate;
+        }
+    }
+
     fn match_device(&mut self, sound: GameSound) -> &mut AudioDevice<Sound> {
         match sound {
             GameSound::UFO => &mut self.ufo,
@@ -105,7 +148,7 @@ pub fn device_from_wav(buf: &[u8], audio_subsystem: &AudioSubsystem, loop_sound:
         .open_playback(None, &audio_spec, move |spec| {
             let cvt = AudioCVT::new(wav.format, wav.channels, wav.freq, spec.format, spec.channels, spec.freq).expect("could not initialize audio CVT");
             let data = cvt.convert(wav.buffer().to_vec());
-            Sound { data, volume: 0.25, position: 0, loop_sound }
+            Sound { data, volume: 0.25, position: 0.0, rate: 1.0, loop_sound }
         })
 }
 
@@ -0,0 +1,170 @@
+//! Cheats toggled from the pause menu, remembered per ROM: the built-in ones and any a ROM's
+//! cheat file adds.
+//!
+//! The selection file has one `<rom crc32> <cheat id>` line per enabled cheat, so every ROM
+//! shares it while keeping its own selection. Cheat files are TOML, named after the ROM:
+//!
+//! ```toml
+//! # A cheat with the id of a built-in one replaces it.
+//! [[cheat]]
+//! id = "infinite_lives"
+//! name = "Infinite lives"
+//! # kind is "freeze" (written on every frame), "once" (written once when enabled) or "rom"
+//! # (patched into ROM and put back when disabled).
+//! patches = [
+//!     { address = 0x21FF, value = 3, kind = "freeze" },
+//! ]
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use core::{cheats, Cheat, CheatKind, Emulator, NamedCheat};
+
+/// Directory searched for `<rom name>.toml` cheat files.
+pub const DEFAULT_CHEAT_DIR: &str = "cheats";
+
+#[derive(Debug, Clone)]
+pub struct Cheats {
+    path: PathBuf,
+    rom_crc: u32,
+    cheats: Vec<NamedCheat>,
+    enabled: Vec<bool>,
+    /// Lines belonging to other ROMs, written back untouched.
+    other_lines: Vec<String>,
+}
+
+impl Cheats {
+    /// Loads the selection of `cheats` enabled for the ROM with checksum `rom_crc` and applies
+    /// them to `emulator`.
+    pub fn load(path: PathBuf, rom_crc: u32, cheats: Vec<NamedCheat>, emulator: &mut Emulator) -> io::Result<Self> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut enabled = vec![false; cheats.len()];
+        let mut other_lines = Vec::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.split_whitespace();
+            let crc = fields.next().and_then(|crc| u32::from_str_radix(crc, 16).ok());
+            let index = fields.next().and_then(|id| cheats.iter().position(|c| c.id == id));
+
+            match (crc, index) {
+                (Some(crc), Some(i)) if crc == rom_crc => enabled[i] = true,
+                _ => other_lines.push(line.to_string()),
+            }
+        }
+
+        let cheats = Self { path, rom_crc, cheats, enabled, other_lines };
+        cheats.apply(emulator);
+        Ok(cheats)
+    }
+
+    /// Brings the patches of `emulator` in line with the selection, after restoring an emulator
+    /// cloned before a cheat was toggled.
+    pub fn apply(&self, emulator: &mut Emulator) {
+        for (cheat, &on) in self.cheats.iter().zip(&self.enabled) {
+            cheat.disable(emulator);
+            if on {
+                cheat.enable(emulator);
+            }
+        }
+    }
+
+    pub fn any_enabled(&self) -> bool {
+        self.enabled.contains(&true)
+    }
+
+    /// Toggles the `index`th cheat (0-based) and saves the selection.
+    pub fn toggle(&mut self, index: usize, emulator: &mut Emulator) -> io::Result<()> {
+        let Some(cheat) = self.cheats.get(index) else { return Ok(()) };
+
+        self.enabled[index] = !self.enabled[index];
+        if self.enabled[index] {
+            cheat.enable(emulator);
+        } else {
+            cheat.disable(emulator);
+        }
+
+        let mut contents: String = self.other_lines.iter().map(|line| format!("{}\n", line)).collect();
+        for (cheat, _) in self.cheats.iter().zip(&self.enabled).filter(|(_, &on)| on) {
+            contents += &format!("{:08X} {}\n", self.rom_crc, cheat.id);
+        }
+        fs::write(&self.path, contents)
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec!["CHEATS (NUMBER KEYS TOGGLE)".to_string()];
+        lines.extend(self.cheats.iter().zip(&self.enabled).enumerate().map(|(i, (cheat, &on))| {
+            format!("{} [{}] {}", i + 1, if on { "X" } else { " " }, cheat.name.to_uppercase())
+        }));
+        lines
+    }
+}
+
+/// The built-in cheats, with the ones in the cheat file for the ROM named `rom_name` in `dir`
+/// added or replacing the built-in ones with the same id.
+pub fn find(dir: &Path, rom_name: &str) -> Result<Vec<NamedCheat>, String> {
+    let mut cheats = cheats::builtin();
+    let stem = Path::new(rom_name).file_stem().map_or(rom_name.into(), |stem| stem.to_string_lossy());
+    let path = dir.join(format!("{}.toml", stem));
+    if !path.is_file() {
+        return Ok(cheats);
+    }
+
+    for cheat in load_file(&path)? {
+        match cheats.iter_mut().find(|builtin| builtin.id == cheat.id) {
+            Some(builtin) => *builtin = cheat,
+            None => cheats.push(cheat),
+        }
+    }
+    Ok(cheats)
+}
+
+pub fn load_file(path: &Path) -> Result<Vec<NamedCheat>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse(contents: &str) -> Result<Vec<NamedCheat>, String> {
+    let table: Table = contents.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+    match table.get("cheat") {
+        Some(Value::Array(cheats)) => cheats.iter().enumerate()
+            .map(|(i, cheat)| parse_cheat(cheat).map_err(|e| format!("cheat {}: {}", i + 1, e)))
+            .collect(),
+        Some(_) => Err("cheat must be an array of tables ([[cheat]])".to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_cheat(value: &Value) -> Result<NamedCheat, String> {
+    let id = value.get("id").and_then(Value::as_str).ok_or("missing id")?;
+    if id.is_empty() || id.contains(char::is_whitespace) {
+        return Err(format!("invalid id {:?} (expected a word without spaces)", id));
+    }
+    let name = value.get("name").and_then(Value::as_str).unwrap_or(id);
+    let patches = value.get("patches").and_then(Value::as_array).ok_or("missing patches")?;
+    let patches = patches.iter().enumerate()
+        .map(|(i, patch)| parse_patch(patch).map_err(|e| format!("patch {}: {}", i + 1, e)))
+        .collect::<Result<_, _>>()?;
+
+    Ok(NamedCheat { id: id.to_string(), name: name.to_string(), patches })
+}
+
+fn parse_patch(value: &Value) -> Result<Cheat, String> {
+    let integer = |key: &str| value.get(key).and_then(Value::as_integer).ok_or(format!("missing {}", key));
+    let address = u16::try_from(integer("address")?).map_err(|_| "address must be 0x0000-0xFFFF")?;
+    let value_byte = u8::try_from(integer("value")?).map_err(|_| "value must be 0-255")?;
+    let kind = match value.get("kind").and_then(Value::as_str) {
+        Some("freeze") | None => CheatKind::Freeze,
+        Some("once") => CheatKind::Once,
+        Some("rom") => CheatKind::RomPatch,
+        Some(kind) => return Err(format!("unknown kind {} (expected freeze, once or rom)", kind)),
+    };
+    Ok(Cheat { address, value: value_byte, kind })
+}
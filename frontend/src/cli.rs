@@ -0,0 +1,340 @@
+use std::env;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use core::{Access, Quirks, RomWritePolicy, UndefinedOpcodePolicy, Watchpoint};
+use core::expr::Expr;
+use core::netplay::Side;
+use crate::cheats::DEFAULT_CHEAT_DIR;
+use crate::netplay::DEFAULT_DELAY;
+use crate::overlay::DEFAULT_OVERLAY_DIR;
+use crate::speed::{MAX_SPEED, MIN_SPEED};
+
+/// Directory scanned for the game launcher when no ROM is given.
+pub const DEFAULT_ROM_DIR: &str = "roms";
+/// Directory crash dumps are written to.
+pub const DEFAULT_DUMP_DIR: &str = "dumps";
+pub const DEFAULT_ACHIEVEMENTS_FILE: &str = "achievements.txt";
+pub const DEFAULT_LEADERBOARD_FILE: &str = "leaderboard.txt";
+pub const DEFAULT_SPLITS_FILE: &str = "splits.txt";
+pub const DEFAULT_CHEATS_FILE: &str = "cheats.txt";
+pub const DEFAULT_TRACE_FILE: &str = "trace.txt";
+/// Frames run by `--headless` and `--bench` unless `--frames` is given: ten seconds of emulated time.
+pub const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+
+/// Printed by `--help`.
+pub const USAGE: &str = "\
+Usage: frontend [options] [rom]
+
+Runs <rom>: a ROM image, one part of a split set (e.g. roms/invaders.h), the directory holding
+one, or a zipped set. A ROM that can't be loaded is an error, never replaced by another one.
+
+Without a ROM, lists the ones in the ROM directory (--rom-dir <dir>, roms by default) next to the
+built-in Space Invaders ROM, starting the built-in one right away if there are none. --headless,
+--bench and --stream run the built-in ROM unless given one.
+
+See README.md for the other options.
+";
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// ROM image to run directly, skipping the launcher. The built-in ROM only runs without one.
+    pub rom: Option<PathBuf>,
+    pub rom_dir: PathBuf,
+    pub dump_dir: PathBuf,
+    /// Overlay file used instead of the ROM's own or built-in one.
+    pub overlay: Option<PathBuf>,
+    /// Directory searched for `<rom name>.toml` overlays.
+    pub overlay_dir: PathBuf,
+    pub achievements_file: PathBuf,
+    pub leaderboard_file: PathBuf,
+    pub cheats_file: PathBuf,
+    /// Directory searched for `<rom name>.toml` cheat files.
+    pub cheat_dir: PathBuf,
+    /// Directory the save-state slots and the state saved at exit are kept in.
+    pub state_dir: PathBuf,
+    /// Continue from the state saved when the ROM was last closed.
+    pub resume: bool,
+    /// Directory the high score is kept in between runs, `None` to start from zero every time.
+    pub nvram_dir: Option<PathBuf>,
+    /// Emulation speed at launch, from quarter speed to 8x.
+    pub speed: f64,
+    /// File speedrun splits are exported to.
+    pub splits_file: PathBuf,
+    /// LiveSplit Server address (`host:port`) speedrun timer events are sent to.
+    pub livesplit: Option<String>,
+    /// Address the remote control server listens on, for WebSocket commands and HTTP requests.
+    pub listen: Option<String>,
+    /// Netplay side and the address to listen on as the host or connect to as the guest.
+    pub netplay: Option<(Side, String)>,
+    /// Frames of input delay when hosting netplay.
+    pub netplay_delay: u8,
+    /// Address to run without a window on, streaming frames to the viewers that connect.
+    pub stream: Option<String>,
+    /// Stream server to show the frames of instead of running a game.
+    pub view: Option<String>,
+    /// Start with the speedrun timer shown.
+    pub timer: bool,
+    /// Start with the score bar shown above the game.
+    pub hud: bool,
+    /// Print the usage and exit.
+    pub help: bool,
+    /// Run without a window, print performance and the final state hash, then exit.
+    pub headless: bool,
+    /// Run `frames` frames in the window without waiting for vsync or pacing, then print performance
+    /// and exit.
+    pub bench: bool,
+    pub frames: u64,
+    /// Where `--headless` writes the video RAM hash of every frame.
+    pub frame_hashes: Option<PathBuf>,
+    /// Frame hashes `--headless` compares its own against, reporting the first divergent frame.
+    pub check_frame_hashes: Option<PathBuf>,
+    /// File every IN and OUT is logged to.
+    pub port_log: Option<PathBuf>,
+    /// Start with instruction tracing on.
+    pub trace: bool,
+    /// File the instruction trace is written to once tracing is turned on.
+    pub trace_file: PathBuf,
+    /// Only every `trace_interval`th instruction is traced.
+    pub trace_interval: u64,
+    /// File the ROM coverage is written to at exit, as a bitmap if it ends in `.bin`.
+    pub coverage: Option<PathBuf>,
+    /// File the instruction profile is written to at exit.
+    pub profile: Option<PathBuf>,
+    /// Translate hot code to native code, see `core::Backend::Jit`.
+    pub jit: bool,
+    /// Rhai script hooked into the emulation, see `core::scripting`.
+    pub script: Option<PathBuf>,
+    /// Movie converted from the first path to the second, picking each format from the extension.
+    pub convert_movie: Option<(PathBuf, PathBuf)>,
+    /// Movie the game is recorded to, written at exit.
+    pub record: Option<PathBuf>,
+    /// Movie played back instead of keyboard input.
+    pub play: Option<PathBuf>,
+    /// Quirks preset overriding the one of the ROM's profile.
+    pub quirks: Option<Quirks>,
+    /// Reset the board when the game stops writing to the watchdog, like the real one does.
+    pub watchdog: bool,
+    /// Unimplemented opcodes executed as NOPs before emulation stops.
+    pub skip_unimplemented: u32,
+    pub undefined_opcodes: UndefinedOpcodePolicy,
+    pub rom_writes: RomWritePolicy,
+    /// Addresses execution pauses at before running the instruction there, if the condition
+    /// holds when there is one.
+    pub breakpoints: Vec<(u16, Option<Expr>)>,
+    /// Memory ranges execution pauses after accessing.
+    pub watchpoints: Vec<Watchpoint>,
+    /// Ports execution pauses after an IN from ([`Access::Read`]) or OUT to ([`Access::Write`]).
+    pub port_breaks: Vec<(u8, Access)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            rom: None,
+            rom_dir: PathBuf::from(DEFAULT_ROM_DIR),
+            dump_dir: PathBuf::from(DEFAULT_DUMP_DIR),
+            overlay: None,
+            overlay_dir: PathBuf::from(DEFAULT_OVERLAY_DIR),
+            achievements_file: PathBuf::from(DEFAULT_ACHIEVEMENTS_FILE),
+            leaderboard_file: PathBuf::from(DEFAULT_LEADERBOARD_FILE),
+            cheats_file: PathBuf::from(DEFAULT_CHEATS_FILE),
+            cheat_dir: PathBuf::from(DEFAULT_CHEAT_DIR),
+            state_dir: user_data_dir().join("states"),
+            resume: false,
+            nvram_dir: Some(user_data_dir().join("nvram")),
+            speed: 1.0,
+            splits_file: PathBuf::from(DEFAULT_SPLITS_FILE),
+            livesplit: None,
+            listen: None,
+            netplay: None,
+            netplay_delay: DEFAULT_DELAY,
+            stream: None,
+            view: None,
+            timer: false,
+            hud: false,
+            help: false,
+            headless: false,
+            bench: false,
+            frames: DEFAULT_HEADLESS_FRAMES,
+            frame_hashes: None,
+            check_frame_hashes: None,
+            port_log: None,
+            trace: false,
+            trace_file: PathBuf::from(DEFAULT_TRACE_FILE),
+            trace_interval: 1,
+            coverage: None,
+            profile: None,
+            jit: false,
+            script: None,
+            convert_movie: None,
+            record: None,
+            play: None,
+            quirks: None,
+            watchdog: false,
+            skip_unimplemented: 0,
+            undefined_opcodes: UndefinedOpcodePolicy::TreatAsDocumented,
+            rom_writes: RomWritePolicy::Ignore,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            port_breaks: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--listen" | "--remote" => options.listen = Some(value(&arg, args.next())?),
+                "--netplay-host" => options.netplay = Some((Side::Host, value(&arg, args.next())?)),
+                "--netplay-join" => options.netplay = Some((Side::Guest, value(&arg, args.next())?)),
+                "--netplay-delay" => {
+                    let delay = value(&arg, args.next())?;
+                    options.netplay_delay = delay.parse().map_err(|_| format!("invalid input delay: {}", delay))?;
+                }
+                "--stream" => options.stream = Some(value(&arg, args.next())?),
+                "--view" => options.view = Some(value(&arg, args.next())?),
+                "--help" | "-h" => options.help = true,
+                "--headless" => options.headless = true,
+                "--bench" => options.bench = true,
+                "--hud" => options.hud = true,
+                "--resume" => options.resume = true,
+                "--no-nvram" => options.nvram_dir = None,
+                "--nvram-dir" => options.nvram_dir = Some(value(&arg, args.next())?.into()),
+                "--speed" => {
+                    let speed = value(&arg, args.next())?;
+                    options.speed = speed.trim_end_matches(['x', 'X']).parse().ok()
+                        .filter(|speed| (MIN_SPEED..=MAX_SPEED).contains(speed))
+                        .ok_or_else(|| format!("invalid speed (expected {}x to {}x): {}", MIN_SPEED, MAX_SPEED, speed))?;
+                }
+                "--timer" => options.timer = true,
+                "--frames" => {
+                    let frames = value(&arg, args.next())?;
+                    options.frames = frames.parse().map_err(|_| format!("invalid frame count: {}", frames))?;
+                }
+                "--skip-unimplemented" => {
+                    let limit = value(&arg, args.next())?;
+                    options.skip_unimplemented = limit.parse().map_err(|_| format!("invalid opcode limit: {}", limit))?;
+                }
+                "--quirks" => {
+                    let preset = value(&arg, args.next())?;
+                    options.quirks = Some(match preset.as_str() {
+                        "accurate" => Quirks::ACCURATE,
+                        "fast" => Quirks::FAST,
+                        _ => return Err(format!("unknown quirks preset: {} (expected accurate or fast)", preset)),
+                    });
+                }
+                "--undefined-opcodes" => {
+                    let policy = value(&arg, args.next())?;
+                    options.undefined_opcodes = match policy.as_str() {
+                        "documented" => UndefinedOpcodePolicy::TreatAsDocumented,
+                        "nop" => UndefinedOpcodePolicy::Nop,
+                        "error" => UndefinedOpcodePolicy::Error,
+                        _ => return Err(format!("unknown undefined opcode policy: {} (expected documented, nop or error)", policy)),
+                    };
+                }
+                "--rom-writes" => {
+                    let policy = value(&arg, args.next())?;
+                    options.rom_writes = match policy.as_str() {
+                        "ignore" => RomWritePolicy::Ignore,
+                        "error" => RomWritePolicy::Error,
+                        _ => return Err(format!("unknown ROM write policy: {} (expected ignore or error)", policy)),
+                    };
+                }
+                "--frame-hashes" => options.frame_hashes = Some(value(&arg, args.next())?.into()),
+                "--check-frame-hashes" => options.check_frame_hashes = Some(value(&arg, args.next())?.into()),
+                "--break" => options.breakpoints.push((address(&value(&arg, args.next())?)?, None)),
+                "--break-if" => {
+                    let address = address(&value(&arg, args.next())?)?;
+                    let condition = value(&arg, args.next())?;
+                    let condition = condition.parse().map_err(|e| format!("invalid condition {:?}: {}", condition, e))?;
+                    options.breakpoints.push((address, Some(condition)));
+                }
+                "--watch" => options.watchpoints.push(Watchpoint::write(address_range(&value(&arg, args.next())?)?)),
+                "--watch-read" => options.watchpoints.push(Watchpoint::read(address_range(&value(&arg, args.next())?)?)),
+                "--break-in" => options.port_breaks.push((port(&value(&arg, args.next())?)?, Access::Read)),
+                "--break-out" => options.port_breaks.push((port(&value(&arg, args.next())?)?, Access::Write)),
+                "--port-log" => options.port_log = Some(value(&arg, args.next())?.into()),
+                "--coverage" => options.coverage = Some(value(&arg, args.next())?.into()),
+                "--profile" => options.profile = Some(value(&arg, args.next())?.into()),
+                "--jit" => options.jit = true,
+                "--script" => options.script = Some(value(&arg, args.next())?.into()),
+                "--watchdog" => options.watchdog = true,
+                "--trace" => options.trace = true,
+                "--trace-file" => options.trace_file = value(&arg, args.next())?.into(),
+                "--trace-every" => {
+                    let interval = value(&arg, args.next())?;
+                    options.trace_interval = match interval.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid trace interval: {}", interval)),
+                    };
+                }
+                "--convert-movie" => {
+                    let input = value(&arg, args.next())?;
+                    options.convert_movie = Some((input.into(), value(&arg, args.next())?.into()));
+                }
+                "--record" => options.record = Some(value(&arg, args.next())?.into()),
+                "--play" => options.play = Some(value(&arg, args.next())?.into()),
+                "--rom-dir" => options.rom_dir = value(&arg, args.next())?.into(),
+                "--overlay" => options.overlay = Some(value(&arg, args.next())?.into()),
+                "--overlay-dir" => options.overlay_dir = value(&arg, args.next())?.into(),
+                "--dump-dir" => options.dump_dir = value(&arg, args.next())?.into(),
+                "--achievements" => options.achievements_file = value(&arg, args.next())?.into(),
+                "--leaderboard" => options.leaderboard_file = value(&arg, args.next())?.into(),
+                "--cheats" => options.cheats_file = value(&arg, args.next())?.into(),
+                "--cheat-dir" => options.cheat_dir = value(&arg, args.next())?.into(),
+                "--state-dir" => options.state_dir = value(&arg, args.next())?.into(),
+                "--splits" => options.splits_file = value(&arg, args.next())?.into(),
+                "--livesplit" => options.livesplit = Some(value(&arg, args.next())?),
+                _ if !arg.starts_with('-') && options.rom.is_none() => options.rom = Some(arg.into()),
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Per-user directory for data kept between runs: `$XDG_DATA_HOME/space-invaders` (or
+/// `~/.local/share/space-invaders`), `~/Library/Application Support/space-invaders` on macOS and
+/// `%APPDATA%\space-invaders` on Windows. Relative to the working directory if none is set.
+pub fn user_data_dir() -> PathBuf {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.unwrap_or_default().join("space-invaders")
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("missing value for {}", arg))
+}
+
+/// Hex address, optionally prefixed with `$` or `0x`.
+fn address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {}", text))
+}
+
+fn port(text: &str) -> Result<u8, String> {
+    text.parse().map_err(|_| format!("invalid port: {}", text))
+}
+
+/// A single address or an inclusive `start-end` range.
+fn address_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (address(start)?, address(end)?),
+        None => (address(text)?, address(text)?),
+    };
+    if start > end {
+        return Err(format!("invalid address range: {}", text));
+    }
+    Ok(start..=end)
+}
@@ -0,0 +1,28 @@
+//! ROM coverage written at exit with `--coverage`, for telling code from data when
+//! disassembling a ROM.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use core::Emulator;
+use core::coverage::Coverage;
+
+/// Starts recording the coverage of `emulator`.
+pub fn start(emulator: &mut Emulator) {
+    emulator.cpu_mut().set_coverage(Some(Coverage::new()));
+}
+
+/// Writes the coverage of the first `rom_len` bytes of memory: a bitmap with one bit per byte,
+/// least significant first, if `path` ends in `.bin`, or else the text report of
+/// [`Coverage::report`].
+pub fn write(path: &Path, emulator: &Emulator, rom_len: usize) -> io::Result<()> {
+    let Some(coverage) = emulator.cpu().coverage() else { return Ok(()) };
+    let range = 0..=(rom_len.clamp(1, 0x10000) - 1) as u16;
+
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("bin")) {
+        fs::write(path, coverage.bitmap(range))
+    } else {
+        fs::write(path, coverage.report(&emulator.cpu().memory, range))
+    }
+}
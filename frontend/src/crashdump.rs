@@ -0,0 +1,78 @@
+//! Crash dumps written when the core stops with an error, to attach to bug reports.
+//!
+//! Each dump is a directory holding the machine state (`state.json`), the error and the recently
+//! executed addresses (`trace.txt`) and the last frame the player saw (`screen.bmp`).
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use core::Emulator;
+use crate::fault::Fault;
+use crate::{screenshot, HEIGHT, WIDTH};
+use crate::overlay::ColorOverlay;
+
+/// Writes a dump into a new `crash-<unix time>` directory under `dir` and returns its path.
+pub fn write(dir: &Path, emulator: &Emulator, fault: &Fault, overlay: &ColorOverlay) -> io::Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut path = dir.join(format!("crash-{}", timestamp));
+    for i in 1.. {
+        if !path.exists() {
+            break;
+        }
+        path = dir.join(format!("crash-{}-{}", timestamp, i));
+    }
+    fs::create_dir_all(&path)?;
+
+    fs::write(path.join("state.json"), emulator.to_debug_json())?;
+    fs::write(path.join("trace.txt"), trace(emulator, fault))?;
+    fs::write(path.join("screen.bmp"), bmp(&screenshot(emulator.screen(), overlay), WIDTH, HEIGHT))?;
+
+    Ok(path)
+}
+
+fn trace(emulator: &Emulator, fault: &Fault) -> String {
+    let mut out = format!("error: {}\npc: {:04X}\nopcode: {:02X}\n", fault.message, fault.pc, fault.opcode);
+    out += &format!("instructions: {}\nframes: {}\n\n", emulator.stats().instructions, emulator.stats().frames);
+
+    out += "recent pcs (oldest first):\n";
+    for pc in &fault.history {
+        let memory = &emulator.cpu().memory;
+        let bytes = (0..3).map(|i| format!("{:02X}", memory[pc.wrapping_add(i)])).collect::<Vec<_>>();
+        out += &format!("  {:04X}  {}\n", pc, bytes.join(" "));
+    }
+
+    out
+}
+
+/// Uncompressed 24-bit BMP from top-down RGB24 pixels.
+fn bmp(rgb: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row_len = (width * 3).next_multiple_of(4);
+    let data_len = row_len * height;
+    let mut out = Vec::with_capacity(54 + data_len as usize);
+
+    out.extend_from_slice(b"BM");
+    out.extend_from_slice(&(54 + data_len).to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&54u32.to_le_bytes());
+
+    out.extend_from_slice(&40u32.to_le_bytes());
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&24u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&data_len.to_le_bytes());
+    out.extend_from_slice(&[0; 16]);
+
+    // Rows are stored bottom-up, pixels as BGR
+    for row in rgb.chunks(width as usize * 3).rev() {
+        for pixel in row.chunks(3) {
+            out.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        out.resize(out.len() + (row_len - width * 3) as usize, 0);
+    }
+
+    out
+}
@@ -0,0 +1,376 @@
+//! Debugger in a second window, opened with F7: registers, the stack, disassembly around PC and
+//! a memory view, all live, with controls to step, step over calls and run to the line under the
+//! cursor.
+//!
+//! Stepping sets a [`Target`] and unpauses the game, and while one is set the frontend runs
+//! frames with [`DebugWindow::run_frame`], which stops them once it's reached. The emulator
+//! picks a stopped frame up where it left off, so interrupts fire at the same cycles whether the
+//! game runs or is stepped through.
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::VideoSubsystem;
+
+use core::disasm::{self, Instruction};
+use core::{Emulator, EmulatorEvent, FrameSink, FrameStatus, Instruction as Op, WatchHit};
+
+use crate::osd;
+
+const WIDTH: u32 = 760;
+const HEIGHT: u32 = 600;
+const SCALE: u32 = 2;
+const MARGIN: i32 = 16;
+/// Where the disassembly starts, right of the registers and stack.
+const DISASM_X: i32 = 260;
+const MEMORY_Y: i32 = 340;
+const BUTTONS_Y: i32 = 548;
+/// Instructions listed before and after PC.
+const DISASM_BEFORE: usize = 5;
+const DISASM_AFTER: usize = 8;
+const STACK_WORDS: u16 = 6;
+const MEMORY_ROWS: u16 = 8;
+const MEMORY_ROW_LEN: u16 = 16;
+/// Memory shown when the window opens, the start of RAM.
+const RAM_START: u16 = 0x2000;
+
+const TITLE: Color = Color::RGB(255, 255, 0);
+const TEXT: Color = Color::WHITE;
+const CURRENT: Color = Color::GREEN;
+const BREAKPOINT: Color = Color::RED;
+const SELECTED: Color = Color::RGB(32, 48, 96);
+
+/// Where execution stops next, set by the stepping controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Stop once this many instructions ran since power-on.
+    Instructions(u64),
+    /// Stop at `pc` with the stack back at `sp` or above, i.e. once a call made at `sp` returned.
+    Return { pc: u16, sp: u16 },
+    Address(u16),
+}
+
+impl Target {
+    pub fn reached(&self, emulator: &Emulator) -> bool {
+        let cpu = emulator.cpu();
+        match *self {
+            Self::Instructions(count) => emulator.stats().instructions >= count,
+            Self::Return { pc, sp } => cpu.pc() == pc && cpu.sp() >= sp,
+            Self::Address(pc) => cpu.pc() == pc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Step,
+    StepOver,
+    RunToCursor,
+    Run,
+    Pause,
+}
+
+impl Control {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Step => "S: STEP",
+            Self::StepOver => "O: STEP OVER",
+            Self::RunToCursor => "C: RUN TO CURSOR",
+            Self::Run => "R: RUN",
+            Self::Pause => "R: PAUSE",
+        }
+    }
+}
+
+pub struct DebugWindow {
+    canvas: WindowCanvas,
+    target: Option<Target>,
+    /// Line selected in the disassembly, the address run to cursor stops at. Follows PC until
+    /// one is picked.
+    cursor: Option<u16>,
+    memory_address: u16,
+    /// Addresses of the disassembly lines last drawn, top to bottom.
+    listing: Vec<u16>,
+}
+
+impl DebugWindow {
+    pub fn open(video: &VideoSubsystem) -> Result<Self, String> {
+        let window = video.window("Space Invaders Debugger", WIDTH, HEIGHT).build().map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        Ok(Self {
+            canvas,
+            target: None,
+            cursor: None,
+            memory_address: RAM_START,
+            listing: Vec::new(),
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// Whether frames should run through [`DebugWindow::run_frame`].
+    pub fn stepping(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Like [`Emulator::run_frame`], but also stops with [`FrameStatus::Stopped`] once the target
+    /// is reached.
+    pub fn run_frame(&mut self, emulator: &mut Emulator, sink: &mut impl FrameSink) -> core::Result<FrameStatus> {
+        let Some(target) = self.target else { return emulator.run_frame(sink) };
+        let status = emulator.run_frame(&mut TargetSink { target, sink })?;
+        if target.reached(emulator) {
+            self.target = None;
+        }
+        Ok(status)
+    }
+
+    /// Handles an event sent to this window, stepping by unpausing the game with a target set.
+    /// Returns `false` once the window should close.
+    pub fn handle_event(&mut self, event: &Event, emulator: &mut Emulator, paused: &mut bool) -> bool {
+        match *event {
+            Event::Window { win_event: WindowEvent::Close, .. } => return false,
+            Event::KeyDown { keycode: Some(keycode), .. } => match keycode {
+                Keycode::Escape | Keycode::F7 => return false,
+                Keycode::S => self.control(Control::Step, emulator, paused),
+                Keycode::O => self.control(Control::StepOver, emulator, paused),
+                Keycode::C => self.control(Control::RunToCursor, emulator, paused),
+                Keycode::R => self.control(if *paused { Control::Run } else { Control::Pause }, emulator, paused),
+                Keycode::B => self.toggle_breakpoint(emulator),
+                Keycode::Up => self.move_cursor(emulator, -1),
+                Keycode::Down => self.move_cursor(emulator, 1),
+                Keycode::PageUp => self.scroll_memory(-(MEMORY_ROWS as i32)),
+                Keycode::PageDown => self.scroll_memory(MEMORY_ROWS as i32),
+                Keycode::Home => self.memory_address = RAM_START,
+                _ => {}
+            },
+            Event::MouseWheel { y, .. } => self.scroll_memory(-y),
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                if let Some((_, control)) = buttons(*paused).into_iter().find(|(rect, _)| rect.contains_point((x, y))) {
+                    self.control(control, emulator, paused);
+                } else if let Some(address) = self.line_at(x, y) {
+                    self.cursor = Some(address);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn control(&mut self, control: Control, emulator: &Emulator, paused: &mut bool) {
+        self.target = match control {
+            Control::Step => Some(Target::Instructions(emulator.stats().instructions + 1)),
+            Control::StepOver => Some(match current_instruction(emulator).decoded() {
+                Op::Call(_) | Op::CallIf(..) | Op::Rst(_) => {
+                    Target::Return { pc: current_instruction(emulator).next_address(), sp: emulator.cpu().sp() }
+                }
+                _ => Target::Instructions(emulator.stats().instructions + 1),
+            }),
+            Control::RunToCursor => match self.cursor {
+                Some(address) => Some(Target::Address(address)),
+                None => return,
+            },
+            Control::Run => None,
+            Control::Pause => {
+                *paused = true;
+                return;
+            }
+        };
+        *paused = false;
+    }
+
+    fn toggle_breakpoint(&mut self, emulator: &mut Emulator) {
+        let address = self.cursor.unwrap_or(emulator.cpu().pc());
+        if !emulator.remove_breakpoint(address) {
+            emulator.add_breakpoint(address);
+        }
+    }
+
+    fn move_cursor(&mut self, emulator: &Emulator, by: isize) {
+        let selected = self.cursor.unwrap_or(emulator.cpu().pc());
+        let Some(index) = self.listing.iter().position(|&address| address == selected) else { return };
+        let index = index.saturating_add_signed(by).min(self.listing.len() - 1);
+        self.cursor = Some(self.listing[index]);
+    }
+
+    fn scroll_memory(&mut self, rows: i32) {
+        self.memory_address = self.memory_address.wrapping_add_signed((rows * MEMORY_ROW_LEN as i32) as i16);
+    }
+
+    fn line_at(&self, x: i32, y: i32) -> Option<u16> {
+        if x < DISASM_X {
+            return None;
+        }
+        let line = (y - MARGIN - line_height()).div_euclid(line_height());
+        self.listing.get(usize::try_from(line).ok()?).copied()
+    }
+
+    /// Redraws the window. Whatever paused the game, a target, a breakpoint or the player, the
+    /// target is done with once it's paused.
+    pub fn draw(&mut self, emulator: &Emulator, paused: bool) -> Result<(), String> {
+        if paused {
+            self.target = None;
+        }
+
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+
+        let mut registers = vec![("REGISTERS".to_string(), TITLE)];
+        registers.extend(register_lines(emulator).into_iter().map(|line| (line, TEXT)));
+        registers.extend([(String::new(), TEXT), ("STACK".to_string(), TITLE)]);
+        let sp = emulator.cpu().sp();
+        registers.extend((0..STACK_WORDS).map(|i| {
+            let address = sp.wrapping_add(i * 2);
+            (format!("${:04X}  ${:04X}", address, read_word(emulator, address)), TEXT)
+        }));
+        self.draw_lines(MARGIN, MARGIN, &registers)?;
+
+        let pc = emulator.cpu().pc();
+        let listing = listing(emulator, pc);
+        self.listing = listing.iter().map(|instruction| instruction.address).collect();
+        let cursor = self.cursor.unwrap_or(pc);
+        if let Some(line) = self.listing.iter().position(|&address| address == cursor) {
+            let y = MARGIN + line_height() * (line as i32 + 1) - SCALE as i32;
+            self.canvas.set_draw_color(SELECTED);
+            self.canvas.fill_rect(Rect::new(DISASM_X - 4, y, WIDTH - DISASM_X as u32 - MARGIN as u32, line_height() as u32))?;
+        }
+        let breakpoints: Vec<u16> = emulator.breakpoints().collect();
+        let mut disassembly = vec![("DISASSEMBLY".to_string(), TITLE)];
+        disassembly.extend(listing.iter().map(|instruction| {
+            let marker = if instruction.address == pc { '>' } else { ' ' };
+            let line = format!("{} ${:04X}  {}", marker, instruction.address, instruction);
+            let color = match instruction.address {
+                address if breakpoints.contains(&address) => BREAKPOINT,
+                address if address == pc => CURRENT,
+                _ => TEXT,
+            };
+            (line, color)
+        }));
+        self.draw_lines(DISASM_X, MARGIN, &disassembly)?;
+
+        let mut memory = vec![("MEMORY  PGUP/PGDN: SCROLL  HOME: RAM".to_string(), TITLE)];
+        memory.extend((0..MEMORY_ROWS).map(|row| {
+            let address = self.memory_address.wrapping_add(row * MEMORY_ROW_LEN);
+            let bytes: Vec<String> = (0..MEMORY_ROW_LEN)
+                .map(|i| format!("{:02X}", emulator.read_memory(address.wrapping_add(i))))
+                .collect();
+            (format!("${:04X} {}", address, bytes.join(" ")), TEXT)
+        }));
+        self.draw_lines(MARGIN, MEMORY_Y, &memory)?;
+
+        for (rect, control) in buttons(paused) {
+            self.canvas.set_draw_color(TEXT);
+            self.canvas.draw_rect(rect)?;
+            let text_y = rect.y() + (rect.height() - osd::GLYPH_HEIGHT * SCALE) as i32 / 2;
+            osd::draw_text(&mut self.canvas, rect.x() + MARGIN / 2, text_y, SCALE, control.label(), TEXT)?;
+        }
+        let help = "B: BREAKPOINT AT CURSOR  UP/DOWN: MOVE CURSOR";
+        osd::draw_text(&mut self.canvas, MARGIN, BUTTONS_Y - line_height(), SCALE, help, TEXT)?;
+
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn draw_lines(&mut self, x: i32, y: i32, lines: &[(String, Color)]) -> Result<(), String> {
+        for (i, (line, color)) in lines.iter().enumerate() {
+            osd::draw_text(&mut self.canvas, x, y + line_height() * i as i32, SCALE, line, *color)?;
+        }
+        Ok(())
+    }
+}
+
+/// Passes everything on to the frontend's sink, also stopping once the target is reached.
+struct TargetSink<'a, S> {
+    target: Target,
+    sink: &'a mut S,
+}
+
+impl<S: FrameSink> FrameSink for TargetSink<'_, S> {
+    fn event(&mut self, emulator: &mut Emulator, event: EmulatorEvent) {
+        self.sink.event(emulator, event);
+    }
+
+    fn stop(&mut self, emulator: &mut Emulator) -> bool {
+        self.sink.stop(emulator) || self.target.reached(emulator)
+    }
+
+    fn stop_at_watchpoint(&mut self, emulator: &mut Emulator, hit: &WatchHit) -> bool {
+        self.sink.stop_at_watchpoint(emulator, hit)
+    }
+}
+
+fn line_height() -> i32 {
+    osd::line_height(SCALE) as i32
+}
+
+/// The step, step over, run to cursor and run or pause buttons along the bottom.
+fn buttons(paused: bool) -> Vec<(Rect, Control)> {
+    let controls = [Control::Step, Control::StepOver, Control::RunToCursor, if paused { Control::Run } else { Control::Pause }];
+    let mut x = MARGIN;
+    controls.into_iter().map(|control| {
+        let width = osd::text_width(control.label(), SCALE) + MARGIN as u32;
+        let rect = Rect::new(x, BUTTONS_Y, width, line_height() as u32 + SCALE * 4);
+        x += width as i32 + MARGIN / 2;
+        (rect, control)
+    }).collect()
+}
+
+fn register_lines(emulator: &Emulator) -> Vec<String> {
+    let r = emulator.cpu().registers();
+    vec![
+        format!("PC {:04X}  SP {:04X}", r.pc, r.sp),
+        format!("A  {:02X}    {}", r.a, r.flags),
+        format!("B  {:02X}    C  {:02X}", r.b, r.c),
+        format!("D  {:02X}    E  {:02X}", r.d, r.e),
+        format!("H  {:02X}    L  {:02X}", r.h, r.l),
+        format!("INT {}", if emulator.cpu().interrupts_enabled() { "ON" } else { "OFF" }),
+        format!("CYC {}", emulator.stats().cycles),
+    ]
+}
+
+fn read_word(emulator: &Emulator, address: u16) -> u16 {
+    let memory = &emulator.cpu().memory;
+    u16::from_le_bytes([memory[address], memory[address.wrapping_add(1)]])
+}
+
+fn instruction_at(emulator: &Emulator, address: u16) -> Instruction {
+    let memory = &emulator.cpu().memory;
+    let bytes = [0, 1, 2].map(|i| memory[address.wrapping_add(i)]);
+    disasm::disassemble(address, &bytes)
+}
+
+fn current_instruction(emulator: &Emulator) -> Instruction {
+    instruction_at(emulator, emulator.cpu().pc())
+}
+
+/// Instructions around `pc`. Code can't be decoded backwards, so the ones before it come from
+/// the earliest start up to three bytes per instruction back whose instructions land on `pc`.
+fn listing(emulator: &Emulator, pc: u16) -> Vec<Instruction> {
+    let mut before = Vec::new();
+    for back in (1..=DISASM_BEFORE as u16 * 3).rev() {
+        let mut address = pc.wrapping_sub(back);
+        let mut decoded = Vec::new();
+        while pc.wrapping_sub(address) <= back && address != pc {
+            let instruction = instruction_at(emulator, address);
+            address = instruction.next_address();
+            decoded.push(instruction);
+        }
+        if address == pc {
+            before = decoded;
+            break;
+        }
+    }
+
+    let mut listing: Vec<Instruction> = before.split_off(before.len().saturating_sub(DISASM_BEFORE));
+    let mut address = pc;
+    for _ in 0..=DISASM_AFTER {
+        let instruction = instruction_at(emulator, address);
+        address = instruction.next_address();
+        listing.push(instruction);
+    }
+    listing
+}
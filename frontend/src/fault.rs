@@ -0,0 +1,54 @@
+//! Report shown when the core stops running the game, so a crash doesn't look like a freeze.
+
+use std::path::PathBuf;
+use core::Emulator;
+
+/// Recent PCs listed in the report, at most.
+const HISTORY_SHOWN: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub message: String,
+    pub pc: u16,
+    pub opcode: u8,
+    /// Oldest first, ending with `pc`.
+    pub history: Vec<u16>,
+    /// Crash dump written for this fault, if any.
+    pub dump: Option<PathBuf>,
+}
+
+impl Fault {
+    pub fn new(emulator: &Emulator, message: String) -> Self {
+        let pc = emulator.pc_history().last().unwrap_or(emulator.cpu().pc());
+        Self {
+            message,
+            pc,
+            opcode: emulator.cpu().memory[pc],
+            history: emulator.pc_history().iter().collect(),
+            dump: None,
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            "EMULATION STOPPED".to_string(),
+            String::new(),
+            self.message.clone(),
+            format!("PC ${:04X}  OPCODE ${:02X}", self.pc, self.opcode),
+            String::new(),
+            "RECENT PCS".to_string(),
+        ];
+
+        let recent: Vec<_> = self.history.iter().rev().take(HISTORY_SHOWN).rev().collect();
+        lines.extend(recent.chunks(4).map(|chunk| {
+            chunk.iter().map(|pc| format!("${:04X}", pc)).collect::<Vec<_>>().join(" ")
+        }));
+
+        if let Some(dump) = &self.dump {
+            lines.extend([String::new(), "CRASH DUMP SAVED TO".to_string(), dump.display().to_string()]);
+        }
+
+        lines.extend([String::new(), "R: RESET   Q: QUIT".to_string()]);
+        lines
+    }
+}
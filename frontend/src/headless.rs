@@ -0,0 +1,134 @@
+//! Runs the emulator without a window or audio, as fast as possible, for benchmarking and
+//! checking that a build still emulates the same way.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use core::{Emulator, EmulatorEvent, FrameHashChain};
+use core::movie::Player;
+use crate::port_log::PortLog;
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub frames: u64,
+    pub instructions: u64,
+    pub elapsed: Duration,
+    /// [`Emulator::state_hash`] after the last frame.
+    pub state_hash: u64,
+    /// Video RAM hash of every frame, see [`Emulator::frame_hash`].
+    pub frame_hashes: Vec<u64>,
+    pub frame_chain: u64,
+}
+
+impl Report {
+    pub fn performance(&self) -> Performance {
+        Performance { frames: self.frames, instructions: self.instructions, elapsed: self.elapsed }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.performance())?;
+        writeln!(f, "state hash:   {:016X}", self.state_hash)?;
+        write!(f, "frame chain:  {:016X}", self.frame_chain)
+    }
+}
+
+/// How fast a run went, as printed by `--headless` and `--bench`.
+#[derive(Debug, Clone)]
+pub struct Performance {
+    pub frames: u64,
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl Display for Performance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        writeln!(f, "frames:       {}", self.frames)?;
+        writeln!(f, "instructions: {}", self.instructions)?;
+        writeln!(f, "time:         {:.3} s", seconds)?;
+        writeln!(f, "fps:          {:.1}", self.frames as f64 / seconds)?;
+        write!(f, "mips:         {:.2}", self.instructions as f64 / seconds / 1_000_000.0)
+    }
+}
+
+/// Runs `frames` frames, writing port accesses to `port_log` if given and holding the buttons of
+/// `player`'s movie, which fails at the first frame that doesn't match the recording. Stops early
+/// at a frame boundary once `shutdown` is requested, reporting the frames run so far.
+pub fn run(emulator: &mut Emulator, frames: u64, mut port_log: Option<&mut PortLog>, mut player: Option<&mut Player>, shutdown: &Shutdown) -> Result<Report, String> {
+    let start = Instant::now();
+    let mut frame_hashes = Vec::with_capacity(frames as usize);
+    let mut chain = FrameHashChain::new();
+
+    for _ in 0..frames {
+        if shutdown.requested() {
+            break;
+        }
+
+        if let Some(player) = player.as_deref_mut() {
+            player.next_frame(emulator);
+        }
+
+        let mut log_error = None;
+        let mut on_event = |emulator: &mut Emulator, event| match (event, port_log.as_deref_mut()) {
+            (EmulatorEvent::PortAccess(access), Some(log)) => {
+                if let Err(e) = log.write(&access) {
+                    log_error.get_or_insert(e);
+                }
+            }
+            (EmulatorEvent::WatchdogReset, _) => crate::watchdog_reset(emulator),
+            _ => {}
+        };
+        emulator.run_frame(&mut on_event).map_err(|e| e.to_string())?;
+        if let Some(e) = log_error {
+            return Err(format!("could not write port log: {}", e));
+        }
+        if let Some(player) = player.as_deref() {
+            player.check_frame(emulator).map_err(|e| e.to_string())?;
+        }
+
+        let frame_hash = emulator.frame_hash();
+        frame_hashes.push(frame_hash);
+        chain.push(frame_hash);
+    }
+
+    if let Some(log) = port_log {
+        log.flush().map_err(|e| format!("could not write port log: {}", e))?;
+    }
+
+    Ok(Report {
+        frames: frame_hashes.len() as u64,
+        instructions: emulator.stats().instructions,
+        elapsed: start.elapsed(),
+        state_hash: emulator.state_hash(),
+        frame_hashes,
+        frame_chain: chain.value(),
+    })
+}
+
+/// Writes one `<frame> <frame hash> <chain value>` line per frame.
+pub fn write_frame_hashes(path: &Path, frame_hashes: &[u64]) -> io::Result<()> {
+    let mut chain = FrameHashChain::new();
+    let contents: String = frame_hashes.iter().enumerate()
+        .map(|(frame, &hash)| format!("{} {:016X} {:016X}\n", frame, hash, chain.push(hash)))
+        .collect();
+    fs::write(path, contents)
+}
+
+/// Reads the frame hashes written by [`write_frame_hashes`].
+pub fn read_frame_hashes(path: &Path) -> Result<Vec<u64>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    contents.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            line.split_whitespace().nth(1)
+                .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+                .ok_or_else(|| format!("{}:{}: invalid frame hash line", path.display(), i + 1))
+        })
+        .collect()
+}
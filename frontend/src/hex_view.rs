@@ -0,0 +1,170 @@
+//! Hex view of memory drawn over the game, opened with F8. The game keeps running underneath, so
+//! values change live, and typing two hex digits over a RAM cell pokes them in.
+
+use sdl2::keyboard::Keycode;
+
+use core::Emulator;
+
+/// Bytes on a row.
+const ROW_LEN: u16 = 8;
+const ROWS: u16 = 16;
+const PAGE: u16 = ROW_LEN * ROWS;
+const RAM_START: u16 = 0x2000;
+
+#[derive(Debug, Clone)]
+pub struct HexView {
+    /// Address of the top left cell.
+    top: u16,
+    cursor: u16,
+    /// First digit typed over the cursor's cell, written once the second one is.
+    high_nibble: Option<u8>,
+    /// Digits of the address being typed after G, `None` when not going anywhere.
+    goto: Option<String>,
+    /// Why the last edit didn't go through.
+    error: Option<String>,
+}
+
+impl HexView {
+    pub fn new() -> Self {
+        Self { top: RAM_START, cursor: RAM_START, high_nibble: None, goto: None, error: None }
+    }
+
+    /// Handles a key pressed while the view is open, returning `false` once it should close.
+    pub fn handle_key(&mut self, keycode: Keycode, emulator: &mut Emulator) -> bool {
+        if let Some(digits) = &mut self.goto {
+            match keycode {
+                Keycode::Return | Keycode::KpEnter => {
+                    if let Ok(address) = u16::from_str_radix(digits, 16) {
+                        self.top = address - address % ROW_LEN;
+                        self.move_to(address);
+                    }
+                    self.goto = None;
+                }
+                Keycode::Backspace => { digits.pop(); }
+                Keycode::Escape => self.goto = None,
+                _ => if let Some(digit) = hex_digit(keycode).filter(|_| digits.len() < 4) {
+                    digits.push(char::from_digit(digit as u32, 16).unwrap());
+                },
+            }
+            return true;
+        }
+
+        match keycode {
+            Keycode::Up => self.move_to(self.cursor.wrapping_sub(ROW_LEN)),
+            Keycode::Down => self.move_to(self.cursor.wrapping_add(ROW_LEN)),
+            Keycode::Left => self.move_to(self.cursor.wrapping_sub(1)),
+            Keycode::Right => self.move_to(self.cursor.wrapping_add(1)),
+            Keycode::PageUp => self.scroll(-(ROWS as i32)),
+            Keycode::PageDown => self.scroll(ROWS as i32),
+            Keycode::Home => {
+                self.top = RAM_START;
+                self.move_to(RAM_START);
+            }
+            Keycode::G => {
+                self.goto = Some(String::new());
+                self.high_nibble = None;
+            }
+            Keycode::Backspace => self.high_nibble = None,
+            Keycode::Escape | Keycode::F8 => return false,
+            _ => if let Some(digit) = hex_digit(keycode) {
+                self.type_digit(digit, emulator);
+            },
+        }
+        true
+    }
+
+    /// Scrolls by `rows`, up when negative, taking the cursor along.
+    pub fn scroll(&mut self, rows: i32) {
+        let offset = (rows * ROW_LEN as i32) as i16;
+        self.top = self.top.wrapping_add_signed(offset);
+        self.cursor = self.cursor.wrapping_add_signed(offset);
+        self.high_nibble = None;
+    }
+
+    fn move_to(&mut self, address: u16) {
+        self.cursor = address;
+        self.high_nibble = None;
+        self.error = None;
+
+        // Keep the cursor on screen
+        let row = address - address % ROW_LEN;
+        if row.wrapping_sub(self.top) >= PAGE {
+            self.top = match row.wrapping_sub(self.top) >= 0x8000 {
+                true => row,
+                false => row.wrapping_sub(PAGE - ROW_LEN),
+            };
+        }
+    }
+
+    fn type_digit(&mut self, digit: u8, emulator: &mut Emulator) {
+        let Some(high) = self.high_nibble.take() else {
+            self.high_nibble = Some(digit);
+            return;
+        };
+
+        if emulator.write_memory(self.cursor, high << 4 | digit) {
+            self.move_to(self.cursor.wrapping_add(1));
+        } else {
+            self.error = Some(format!("${:04X} IS NOT RAM", self.cursor));
+        }
+    }
+
+    pub fn lines(&self, emulator: &Emulator) -> Vec<String> {
+        let mut lines = vec!["MEMORY".to_string(), String::new()];
+        lines.extend((0..ROWS).map(|row| {
+            let address = self.top.wrapping_add(row * ROW_LEN);
+            let mut line = format!("${:04X}", address);
+            for i in 0..ROW_LEN {
+                let cell = address.wrapping_add(i);
+                let value = emulator.read_memory(cell);
+                // The cursor's brackets take the place of the spaces around it
+                match (cell == self.cursor, self.high_nibble) {
+                    (true, Some(high)) => line.push_str(&format!("[{:X}_]", high)),
+                    (true, None) => line.push_str(&format!("[{:02X}]", value)),
+                    (false, _) if i > 0 && cell.wrapping_sub(1) == self.cursor => line.push_str(&format!("{:02X}", value)),
+                    (false, _) => line.push_str(&format!(" {:02X}", value)),
+                }
+            }
+            line
+        }));
+        lines.push(String::new());
+
+        lines.push(match (&self.goto, &self.error) {
+            (Some(digits), _) => format!("GOTO ${:_<4}", digits.to_uppercase()),
+            (None, Some(error)) => error.clone(),
+            (None, None) if !emulator.cpu().memory.writable(self.cursor) => format!("${:04X} ROM", self.cursor),
+            (None, None) => format!("${:04X} = {:02X}", self.cursor, emulator.read_memory(self.cursor)),
+        });
+        lines.push("0-F EDIT  G GOTO  ESC CLOSE".to_string());
+        lines
+    }
+}
+
+impl Default for HexView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hex_digit(keycode: Keycode) -> Option<u8> {
+    let digit = match keycode {
+        Keycode::Num0 | Keycode::Kp0 => 0,
+        Keycode::Num1 | Keycode::Kp1 => 1,
+        Keycode::Num2 | Keycode::Kp2 => 2,
+        Keycode::Num3 | Keycode::Kp3 => 3,
+        Keycode::Num4 | Keycode::Kp4 => 4,
+        Keycode::Num5 | Keycode::Kp5 => 5,
+        Keycode::Num6 | Keycode::Kp6 => 6,
+        Keycode::Num7 | Keycode::Kp7 => 7,
+        Keycode::Num8 | Keycode::Kp8 => 8,
+        Keycode::Num9 | Keycode::Kp9 => 9,
+        Keycode::A => 0xA,
+        Keycode::B => 0xB,
+        Keycode::C => 0xC,
+        Keycode::D => 0xD,
+        Keycode::E => 0xE,
+        Keycode::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
@@ -0,0 +1,45 @@
+//! Score and lives bar drawn above the game screen, read from game RAM every frame.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use core::SpaceInvadersState;
+use crate::osd;
+
+/// Height of the bar in window pixels.
+pub const HUD_HEIGHT: u32 = 40;
+
+const SCALE: u32 = 2;
+
+pub fn draw(canvas: &mut WindowCanvas, state: &SpaceInvadersState) -> Result<(), String> {
+    let (width, _) = canvas.output_size()?;
+    let [p1, p2] = state.scores;
+    let [p1_ships, p2_ships] = state.ships;
+
+    let columns = [
+        ("1UP", format!("{:04}", p1), Color::WHITE),
+        ("HI", format!("{:04}", state.high_score), Color::YELLOW),
+        ("2UP", format!("{:04}", p2), Color::WHITE),
+        ("SHIPS", format!("{} {}", p1_ships, p2_ships), Color::GREEN),
+    ];
+
+    let (scale_x, scale_y) = canvas.scale();
+    canvas.set_scale(1.0, 1.0)?;
+    canvas.set_draw_color(Color::RGB(16, 16, 24));
+    let result = canvas.fill_rect(Rect::new(0, 0, width, HUD_HEIGHT));
+    canvas.set_scale(scale_x, scale_y)?;
+    result?;
+
+    let column_width = width / columns.len() as u32;
+    for (i, (label, value, color)) in columns.iter().enumerate() {
+        let center = (column_width * i as u32 + column_width / 2) as i32;
+        let label_x = center - osd::text_width(label, 1) as i32 / 2;
+        let value_x = center - osd::text_width(value, SCALE) as i32 / 2;
+
+        osd::draw_text(canvas, label_x, 4, 1, label, Color::GRAY)?;
+        osd::draw_text(canvas, value_x, 4 + osd::line_height(1) as i32, SCALE, value, *color)?;
+    }
+
+    Ok(())
+}
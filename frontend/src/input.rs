@@ -4,17 +4,18 @@ use core::{Emulator, Button};
 
 pub fn handle_keydown(keycode: Keycode, emulator: &mut Emulator) {
     if let Some(button) = map_keycode(keycode) {
-        emulator.button_press(button);
+        emulator.set_button(button, true);
     }
 }
 
 pub fn handle_keyup(keycode: Keycode, emulator: &mut Emulator) {
     if let Some(button) = map_keycode(keycode) {
-        emulator.button_release(button);
+        emulator.set_button(button, false);
     }
 }
 
-fn map_keycode(keycode: Keycode) -> Option<Button> {
+/// Button `keycode` is bound to, if any.
+pub fn map_keycode(keycode: Keycode) -> Option<Button> {
     Some(match keycode {
         Keycode::C => Button::Coin,
         Keycode::Return => Button::P1Start,
@@ -25,6 +26,7 @@ fn map_keycode(keycode: Keycode) -> Option<Button> {
         Keycode::A => Button::P2Left,
         Keycode::D => Button::P2Right,
         Keycode::W | Keycode::Space => Button::P2Shoot,
+        Keycode::T => Button::Tilt,
         _ => return None,
     })
 }
\ No newline at end of file
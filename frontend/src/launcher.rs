@@ -0,0 +1,146 @@
+//! Game selection menu shown when no ROM is given on the command line.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use sdl2::EventPump;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+
+use core::{GameInfo, Profile, SetInfo};
+use core::games::{self, ROM_SIZE};
+use crate::{osd, rom};
+use crate::rom::LoadedRom;
+
+const SCALE: u32 = 2;
+const VISIBLE_ENTRIES: usize = 14;
+
+#[derive(Debug, Clone)]
+pub struct RomEntry {
+    pub name: String,
+    /// `None` for the ROM compiled into the binary.
+    pub path: Option<PathBuf>,
+    pub rom: Vec<u8>,
+    /// Known good dump the ROM matches.
+    pub game: Option<&'static GameInfo>,
+    pub set: Option<&'static SetInfo>,
+}
+
+impl RomEntry {
+    pub fn new(name: String, path: Option<PathBuf>, rom: LoadedRom) -> Self {
+        let game = games::identify(&rom.data);
+        Self { name, path, rom: rom.data, game, set: rom.set }
+    }
+
+    /// Default DIP switches and overlay of the recognized set.
+    pub fn profile(&self) -> Option<Profile> {
+        self.set.and_then(|set| set.profile)
+    }
+
+    pub fn label(&self) -> String {
+        match self.set {
+            Some(set) => format!("{} - {}", self.name, set.title),
+            None => format!("{} - unknown rom", self.name),
+        }
+    }
+
+    /// Second menu line: machine profile, or why the ROM didn't match any known game.
+    pub fn status(&self) -> String {
+        if let Some(game) = self.game {
+            return format!("ok, {}", game.machine.name());
+        }
+
+        let closest = games::GAMES.iter()
+            .map(|game| (game, game.bad_parts(&self.rom)))
+            .filter(|(game, bad)| bad.len() < game.parts.len())
+            .min_by_key(|(_, bad)| bad.len());
+
+        match (closest, self.profile()) {
+            (Some((game, bad)), _) if !bad.is_empty() => format!("bad {} dump: {}", game.name, bad.join(", ")),
+            (_, Some(profile)) => format!("unverified dump, {}", profile.machine.name()),
+            _ => "crc not recognized".to_string(),
+        }
+    }
+}
+
+/// Reads every ROM image, split ROM set and zipped ROM set in `dir`, sorted by name.
+/// A missing directory yields no entries, and files that fail to load are skipped.
+pub fn scan(dir: &Path) -> io::Result<Vec<RomEntry>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for dir_entry in read_dir {
+        let path = dir_entry?.path();
+        let Ok(metadata) = fs::metadata(&path) else { continue };
+        let is_rom = rom::is_zip(&path) || (1..=ROM_SIZE as u64).contains(&metadata.len());
+        if !metadata.is_file() || !is_rom {
+            continue;
+        }
+
+        // A split set is listed once, under its first part
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if games::find_part(&name).is_some_and(|(_, part)| part.offset != 0) {
+            continue;
+        }
+
+        match rom::load(&path) {
+            Ok(rom) => {
+                entries.push(RomEntry::new(name, Some(path), rom));
+            }
+            Err(e) => eprintln!("skipping {}", e),
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Shows the menu until a game is picked. Returns `None` if the window was closed.
+pub fn choose(canvas: &mut WindowCanvas, event_pump: &mut EventPump, entries: &[RomEntry]) -> Result<Option<usize>, String> {
+    let mut selected: usize = 0;
+
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return Ok(None),
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } => selected = selected.checked_sub(1).unwrap_or(entries.len() - 1),
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } => selected = (selected + 1) % entries.len(),
+                Event::KeyDown { keycode: Some(Keycode::Return | Keycode::Space), .. } => return Ok(Some(selected)),
+                _ => {}
+            }
+        }
+
+        draw(canvas, entries, selected)?;
+        canvas.present();
+    }
+}
+
+fn draw(canvas: &mut WindowCanvas, entries: &[RomEntry], selected: usize) -> Result<(), String> {
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+
+    let line = osd::line_height(SCALE) as i32;
+    osd::draw_text(canvas, 8, 8, SCALE, "SELECT GAME", Color::WHITE)?;
+
+    let first = selected.saturating_sub(VISIBLE_ENTRIES - 1);
+    for (i, entry) in entries.iter().enumerate().skip(first).take(VISIBLE_ENTRIES) {
+        let y = 8 + line * (2 + 2 * (i - first) as i32);
+        let (marker, color) = if i == selected { ("> ", Color::YELLOW) } else { ("  ", Color::WHITE) };
+        let status_color = match (entry.game, entry.set) {
+            (Some(_), _) => Color::GREEN,
+            (None, Some(_)) => Color::YELLOW,
+            (None, None) => Color::RED,
+        };
+
+        osd::draw_text(canvas, 8, y, SCALE, &format!("{}{}", marker, entry.label()), color)?;
+        osd::draw_text(canvas, 8 + osd::text_width("  ", SCALE) as i32, y + line, 1, &entry.status(), status_color)?;
+    }
+
+    Ok(())
+}
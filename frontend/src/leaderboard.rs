@@ -0,0 +1,154 @@
+//! Local high-score table per ROM, with initials entered in an on-screen dialog.
+//!
+//! The file has one `<rom crc32> <initials> <score> <unix time>` line per entry, so every ROM
+//! shares it while keeping its own table.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sdl2::keyboard::Keycode;
+
+pub const MAX_ENTRIES: usize = 10;
+const INITIALS_LEN: usize = 3;
+/// Saved for players who quit before entering their initials.
+pub const UNKNOWN_INITIALS: &str = "???";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub initials: String,
+    pub score: u32,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Leaderboard {
+    path: PathBuf,
+    rom_crc: u32,
+    /// Best first.
+    entries: Vec<Entry>,
+    /// Lines belonging to other ROMs, written back untouched.
+    other_lines: Vec<String>,
+}
+
+impl Leaderboard {
+    pub fn load(path: PathBuf, rom_crc: u32) -> io::Result<Self> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        let mut other_lines = Vec::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            match parse_line(line) {
+                Some((crc, entry)) if crc == rom_crc => entries.push(entry),
+                _ => other_lines.push(line.to_string()),
+            }
+        }
+        entries.sort_by_key(|entry| Reverse(entry.score));
+
+        Ok(Self { path, rom_crc, entries, other_lines })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Whether `score` would make it into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0 && (self.entries.len() < MAX_ENTRIES || self.entries.last().is_some_and(|e| score > e.score))
+    }
+
+    pub fn add(&mut self, initials: &str, score: u32) -> io::Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let position = self.entries.iter().position(|e| score > e.score).unwrap_or(self.entries.len());
+        self.entries.insert(position, Entry { initials: initials.to_string(), score, timestamp });
+        self.entries.truncate(MAX_ENTRIES);
+
+        let mut contents: String = self.other_lines.iter().map(|line| format!("{}\n", line)).collect();
+        for entry in &self.entries {
+            contents += &format!("{:08X} {} {} {}\n", self.rom_crc, entry.initials, entry.score, entry.timestamp);
+        }
+        fs::write(&self.path, contents)
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec!["HIGH SCORES".to_string()];
+        if self.entries.is_empty() {
+            lines.push("NO SCORES YET".to_string());
+        }
+        lines.extend(self.entries.iter().enumerate().map(|(i, entry)| {
+            format!("{:>2}. {:<3} {:04}  {}", i + 1, entry.initials, entry.score, format_date(entry.timestamp))
+        }));
+        lines
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u32, Entry)> {
+    let mut fields = line.split_whitespace();
+    let crc = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let initials = fields.next()?.to_string();
+    let score = fields.next()?.parse().ok()?;
+    let timestamp = fields.next()?.parse().ok()?;
+    Some((crc, Entry { initials, score, timestamp }))
+}
+
+/// `YYYY-MM-DD` (UTC) of a Unix timestamp.
+fn format_date(timestamp: u64) -> String {
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Dialog asking for the initials of a player who made it into the table.
+#[derive(Debug, Clone)]
+pub struct InitialsPrompt {
+    pub player: usize,
+    pub score: u32,
+    pub initials: String,
+}
+
+impl InitialsPrompt {
+    pub fn new(player: usize, score: u32) -> Self {
+        Self { player, score, initials: String::new() }
+    }
+
+    /// Returns `true` once the initials are confirmed.
+    pub fn handle_key(&mut self, keycode: Keycode) -> bool {
+        let name = keycode.name();
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => return !self.initials.is_empty(),
+            Keycode::Backspace => {
+                self.initials.pop();
+            }
+            _ if name.len() == 1 && name.chars().all(|c| c.is_ascii_alphanumeric()) && self.initials.len() < INITIALS_LEN => {
+                self.initials += &name.to_ascii_uppercase();
+            }
+            _ => {}
+        }
+        false
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let cursor = if self.initials.len() < INITIALS_LEN { "_" } else { "" };
+        vec![
+            format!("PLAYER {} - NEW HIGH SCORE!", self.player + 1),
+            format!("SCORE {:04}", self.score),
+            String::new(),
+            format!("INITIALS: {}{}", self.initials, cursor),
+            "ENTER TO CONFIRM".to_string(),
+        ]
+    }
+}
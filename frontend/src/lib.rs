@@ -1,53 +1,90 @@
 pub mod input;
 pub mod audio;
+pub mod cli;
+pub mod osd;
+pub mod launcher;
+pub mod rom;
+pub mod headless;
+pub mod fault;
+pub mod crashdump;
+pub mod session;
+pub mod achievements;
+pub mod leaderboard;
+pub mod hud;
+pub mod speedrun;
+pub mod practice;
+pub mod cheats;
+pub mod movie;
+pub mod port_log;
+pub mod overlay;
+pub mod shutdown;
+pub mod slots;
+pub mod nvram;
+pub mod speed;
+pub mod netplay;
+pub mod png;
+pub mod stream;
+pub mod debug_window;
+pub mod hex_view;
+#[cfg(feature = "tracer")]
+pub mod trace_log;
+#[cfg(feature = "coverage")]
+pub mod coverage_file;
+#[cfg(feature = "profiler")]
+pub mod profile;
+#[cfg(feature = "remote")]
+pub mod remote;
 
+use std::ops::Range;
+
+use colored::Colorize;
 use sdl2::keyboard::Mod;
 use sdl2::pixels::Color;
+use core::Emulator;
+use core::video::{Orientation, PixelFormat, Renderer};
+use overlay::ColorOverlay;
 
-pub const WIDTH: u32 = 224;
-pub const HEIGHT: u32 = 256;
+pub const WIDTH: u32 = core::video::WIDTH;
+pub const HEIGHT: u32 = core::video::HEIGHT;
+/// Frame rate of the real board, which the window paces itself to.
+pub const FPS: f64 = core::raster::FRAME_RATE;
 
 pub fn has_ctrl(keymod: Mod) -> bool {
     keymod.contains(Mod::RCTRLMOD) || keymod.contains(Mod::LCTRLMOD)
 }
 
-pub fn update_pixel_data(pixel_data: &mut [u8], video_ram: &[u8]) -> bool {
-    let mut update = false;
-
-    for (b, byte) in video_ram.iter().enumerate() {
-        let offset = b * 8;
-
-        for bit in 0..8 {
-            let full_index = offset + bit;
-            let data_index = full_index * 3;
-
-            let color = if byte & (1 << bit) == 0 {
-                Color::BLACK
-            } else {
-                let x = full_index as u32 / HEIGHT;
-                let y = HEIGHT - (full_index as u32 % HEIGHT);
-                match_pixel_color(x, y)
-            };
-
-            let (r, g, b) = color.rgb();
-
-            if pixel_data[data_index] != r || pixel_data[data_index + 1] != g || pixel_data[data_index + 2] != b {
-                pixel_data[data_index + 0] = r;
-                pixel_data[data_index + 1] = g;
-                pixel_data[data_index + 2] = b;
-                update = true;
-            }
-        }
-    }
+pub fn has_shift(keymod: Mod) -> bool {
+    keymod.contains(Mod::RSHIFTMOD) || keymod.contains(Mod::LSHIFTMOD)
+}
+
+/// Warns that the game hung long enough for the watchdog to reset the board.
+pub fn watchdog_reset(emulator: &Emulator) {
+    let message = format!("watchdog reset the board at frame {}", emulator.stats().frames);
+    eprintln!("{} {}", "Warning:".yellow().bold(), message.yellow());
+}
+
+/// Renders the upright RGB24 images the window and crash dumps show.
+pub const RENDERER: Renderer = Renderer::new(PixelFormat::Rgb24, Orientation::Upright);
+
+/// Draws `lines` of `video_ram` into an upright RGB24 image of `WIDTH` x `HEIGHT`, where they're
+/// columns, returning whether any pixel changed.
+pub fn update_pixel_data(pixel_data: &mut [u8], video_ram: &[u8], lines: Range<u32>, overlay: &ColorOverlay) -> bool {
+    RENDERER.render_lines(video_ram, pixel_data, lines, |x, y| overlay.color(x, y).rgb().into())
+}
 
-    update
+/// Upright RGB24 image of the screen, `WIDTH` x `HEIGHT`, top row first.
+pub fn screenshot(video_ram: &[u8], overlay: &ColorOverlay) -> Vec<u8> {
+    let mut image = RENDERER.buffer();
+    update_pixel_data(&mut image, video_ram, 0..WIDTH, overlay);
+    image
 }
 
+/// Midway's gel overlay, see [`ColorOverlay::color`] for the coordinates.
 pub fn match_pixel_color(x: u32, y: u32) -> Color {
     match y {
-        33..=64 => Color::RED,
-        185..=240 => Color::GREEN,
-        241..=HEIGHT if x > 16 && x <= 134 => Color::GREEN,
+        32..=63 => Color::RED,
+        184..=239 => Color::GREEN,
+        240.. if x > 16 && x <= 134 => Color::GREEN,
         _ => Color::WHITE,
     }
 }
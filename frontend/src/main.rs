@@ -1,35 +1,94 @@
 #![windows_subsystem = "windows"]
 
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 use colored::Colorize;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::rect::Rect;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Texture, WindowCanvas};
+use sdl2::EventPump;
 
-use core::{Emulator, ExecutionStatus, EmulatorEvent, Sound};
-use frontend::input;
-use frontend::{WIDTH, HEIGHT};
+use core::{Backend, Buttons, Emulator, EmulatorEvent, FrameStatus, MachineKind, Overlay, Profile, Quirks, Rewind, Sound, SpaceInvadersState};
+use core::games;
+use core::movie::{Player, Recorder};
+use core::netplay::Side;
+use core::video::DirtyLines;
+use frontend::{cli, crashdump, headless, hud, input, launcher, movie, osd, overlay, rom};
+use frontend::hud::HUD_HEIGHT;
+use frontend::{WIDTH, HEIGHT, FPS};
 use frontend::audio::AudioManager;
+use frontend::cli::Options;
+use frontend::fault::Fault;
+use frontend::session::SessionStats;
+use frontend::leaderboard::{InitialsPrompt, Leaderboard, UNKNOWN_INITIALS};
+use frontend::achievements::Achievements;
+use frontend::speedrun::Speedrun;
+use frontend::cheats::{self, Cheats};
+use frontend::port_log::PortLog;
+use frontend::shutdown::Shutdown;
+use frontend::slots::{Slots, SLOTS};
+use frontend::nvram::Nvram;
+use frontend::speed::Speed;
+use frontend::overlay::OverlaySwitch;
+use frontend::practice::{MenuAction, Practice, PracticeMenu, Preset};
+use frontend::launcher::RomEntry;
+use frontend::netplay::Netplay;
+use frontend::stream::{StreamServer, StreamViewer, Update};
+use frontend::debug_window::DebugWindow;
+use frontend::hex_view::HexView;
+#[cfg(feature = "tracer")]
+use frontend::trace_log::TraceLog;
+#[cfg(feature = "coverage")]
+use frontend::coverage_file;
+#[cfg(feature = "profiler")]
+use frontend::profile;
+use frontend::rom::LoadedRom;
+#[cfg(feature = "remote")]
+use frontend::remote::{RemoteServer, RemoteSink};
+#[cfg(feature = "scripting")]
+use core::scripting::Script;
 
+const BUILTIN_ROM: &[u8] = include_bytes!("../assets/invaders");
+/// Used for ROMs that weren't recognized as any known set.
+const DEFAULT_PROFILE: Profile = Profile { machine: MachineKind::SpaceInvaders, dip_switches: 0, overlay: Overlay::Gel, quirks: Quirks::ACCURATE };
 const SCALE_X: f32 = 2.0;
 const SCALE_Y: f32 = 2.5;
-const FPS: f64 = 60.0;
-const CYCLES_PER_FRAME: u32 = (2_000_000.0 / FPS) as u32;
+const WINDOW_WIDTH: u32 = (WIDTH as f32 * SCALE_X) as u32;
+const WINDOW_HEIGHT: u32 = (HEIGHT as f32 * SCALE_Y) as u32;
+const CHEATS_ACTIVE: &str = "CHEATS ON";
+/// Frames of gameplay Backspace can rewind.
+const REWIND_FRAMES: usize = 30 * FPS as usize;
 
 fn main() {
-    let program = include_bytes!("../assets/invaders");
-
-    run(program).unwrap_or_else(|e| {
+    Options::parse(std::env::args().skip(1)).and_then(run).unwrap_or_else(|e| {
         eprintln!("{} {}", "Error:".red().bold(), e.to_string().red())
     });
 }
 
-fn run(program: &[u8]) -> Result<(), String> {
+fn run(options: Options) -> Result<(), String> {
+    if options.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    let shutdown = Shutdown::install().map_err(|e| format!("could not install signal handlers: {}", e))?;
+    if options.headless {
+        return run_headless(&options, &shutdown);
+    }
+    if let Some(addr) = &options.stream {
+        return run_stream(&options, addr, &shutdown);
+    }
+    if let Some((input, output)) = &options.convert_movie {
+        let movie = movie::convert(input, output)?;
+        println!("Converted {} frames ({} rerecords) to {}", movie.frames.len(), movie.rerecords, output.display());
+        return Ok(());
+    }
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("Space Invaders", (WIDTH as f32 * SCALE_X) as u32, (HEIGHT as f32 * SCALE_Y) as u32)
+        .window("Space Invaders", WINDOW_WIDTH, WINDOW_HEIGHT)
         .position_centered()
         .build().expect("could not build window");
 
@@ -37,87 +96,602 @@ fn run(program: &[u8]) -> Result<(), String> {
     let mut audio = AudioManager::new(audio_subsystem)?;
 
     let mut event_pump = sdl_context.event_pump()?;
-    let mut canvas = window.into_canvas().present_vsync().build().expect("could not build renderer");
+    let canvas = if options.bench { window.into_canvas() } else { window.into_canvas().present_vsync() };
+    let mut canvas = canvas.build().expect("could not build renderer");
 
     canvas.set_scale(SCALE_X, SCALE_Y)?;
     canvas.present();
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, HEIGHT, WIDTH)
+        .create_texture_target(PixelFormatEnum::RGB24, WIDTH, HEIGHT)
         .expect("could not create texture");
 
     let mut pixel_data = [0; (WIDTH * HEIGHT * 3) as usize];
 
-    let mut emulator = Emulator::new(program);
-    let mut save_state: Option<Emulator> = None;
+    if let Some(addr) = &options.view {
+        return run_viewer(addr, &options, &mut canvas, &mut texture, &mut event_pump, &mut audio, &shutdown);
+    }
+
+    let Some(entry) = select_rom(&options, &mut canvas, &mut event_pump)? else { return Ok(()) };
+    let profile = entry.profile().unwrap_or(DEFAULT_PROFILE);
+    let mut overlay = OverlaySwitch::new(match &options.overlay {
+        Some(path) => overlay::load(path)?,
+        None => overlay::find(&options.overlay_dir, &entry.name)?.unwrap_or(profile.overlay.into()),
+    });
+    let mut overlay_changed = false;
+
+    let mut emulator = new_emulator(&entry.rom, profile.machine, &options);
+    emulator.set_dip_switches(profile.dip_switches);
+    emulator.set_quirks(options.quirks.unwrap_or(profile.quirks));
+    emulator.set_watchdog(options.watchdog);
+    emulator.set_skip_unimplemented_opcodes(options.skip_unimplemented);
+    emulator.cpu_mut().set_undefined_opcode_policy(options.undefined_opcodes);
+    emulator.cpu_mut().set_rom_write_policy(options.rom_writes);
+    for (address, condition) in &options.breakpoints {
+        match condition {
+            Some(condition) => emulator.add_conditional_breakpoint(*address, condition.clone()),
+            None => emulator.add_breakpoint(*address),
+        }
+    }
+    for &watchpoint in &options.watchpoints {
+        emulator.cpu_mut().memory.add_watchpoint(watchpoint);
+    }
+    for &(port, access) in &options.port_breaks {
+        emulator.add_port_break(port, access);
+    }
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+    let mut slots = Slots::new(options.state_dir.clone(), core::crc32(&entry.rom));
+    // Movies replay from power-on RAM, so they neither load nor save it
+    let nvram = match &options.nvram_dir {
+        Some(dir) if options.record.is_none() && options.play.is_none() && !options.bench => {
+            Some(Nvram::new(dir.clone(), core::crc32(&entry.rom)))
+        }
+        _ => None,
+    };
+    if let Some(Err(e)) = nvram.as_ref().map(|nvram| nvram.restore(&mut emulator)) {
+        eprintln!("{} {}", "Warning:".yellow().bold(), e.yellow());
+    }
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    let mut rewinding = false;
     let mut paused = false;
+    let mut advance_frame = false;
+    let mut speed = Speed::new(options.speed);
+    audio.set_speed(speed.factor());
+    audio.set_muted(options.bench);
+    let mut fault: Option<Fault> = None;
+    let mut session = SessionStats::new();
+    let mut leaderboard = Leaderboard::load(options.leaderboard_file.clone(), core::crc32(&entry.rom))
+        .map_err(|e| format!("could not read {}: {}", options.leaderboard_file.display(), e))?;
+    let mut prompts: VecDeque<InitialsPrompt> = VecDeque::new();
+    let mut achievements = Achievements::load(options.achievements_file.clone())
+        .map_err(|e| format!("could not read {}: {}", options.achievements_file.display(), e))?;
+    let cheat_list = cheats::find(&options.cheat_dir, &entry.name)?;
+    let mut cheats = Cheats::load(options.cheats_file.clone(), core::crc32(&entry.rom), cheat_list, &mut emulator)
+        .map_err(|e| format!("could not read {}: {}", options.cheats_file.display(), e))?;
+    if options.resume {
+        match slots.resume(&mut emulator) {
+            Ok(true) => {
+                cheats.apply(&mut emulator);
+                audio.sync(&emulator);
+            }
+            Ok(false) => eprintln!("{} {}", "Warning:".yellow().bold(), "no saved state to resume for this ROM".yellow()),
+            Err(e) => eprintln!("{} {}", "Warning:".yellow().bold(), e.yellow()),
+        }
+    }
+    let mut player = match &options.play {
+        Some(path) => Some(Player::start(movie::load(path)?, &mut emulator).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => None,
+    };
+    let mut recorder = options.record.as_ref().map(|_| Recorder::start(&emulator));
+    let mut speedrun = Speedrun::new();
+    let mut practice: Option<Practice> = None;
+    let mut practice_menu: Option<PracticeMenu> = None;
+    let mut practice_preset = Preset::default();
+    let mut hex_view: Option<HexView> = None;
+    let mut port_log = match &options.port_log {
+        Some(path) => Some(PortLog::create(path, &mut emulator).map_err(|e| format!("could not create {}: {}", path.display(), e))?),
+        None => None,
+    };
+    #[cfg(feature = "tracer")]
+    let mut trace_log = if options.trace {
+        Some(TraceLog::create(&options.trace_file, options.trace_interval, &mut emulator)
+            .map_err(|e| format!("could not create {}: {}", options.trace_file.display(), e))?)
+    } else {
+        None
+    };
+    #[cfg(not(feature = "tracer"))]
+    if options.trace {
+        return Err("instruction tracing was not compiled in (enable the `tracer` feature)".to_string());
+    }
+    start_coverage(&options, &mut emulator)?;
+    start_profiler(&options, &mut emulator)?;
+    #[cfg(feature = "scripting")]
+    let mut script = match &options.script {
+        Some(path) => {
+            let source = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            Some(Script::load(&source, &mut emulator).map_err(|e| format!("{}: {}", path.display(), e))?)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "scripting"))]
+    if options.script.is_some() {
+        return Err("scripting was not compiled in (enable the `scripting` feature)".to_string());
+    }
+    if let Some(address) = &options.livesplit {
+        speedrun.connect_livesplit(address).map_err(|e| format!("could not connect to LiveSplit at {}: {}", address, e))?;
+    }
+
+    #[cfg(feature = "remote")]
+    let mut remote = match &options.listen {
+        Some(addr) => Some(RemoteServer::bind(addr).map_err(|e| format!("could not start remote server: {}", e))?),
+        None => None,
+    };
+    #[cfg(not(feature = "remote"))]
+    if options.listen.is_some() {
+        return Err("remote server support was not compiled in (enable the `remote` feature)".to_string());
+    }
+
+    let mut netplay = match &options.netplay {
+        Some((Side::Host, addr)) => Some(Netplay::host(addr, &emulator, options.netplay_delay)?),
+        Some((Side::Guest, addr)) => {
+            let netplay = Netplay::join(addr, &mut emulator)?;
+            audio.sync(&emulator);
+            Some(netplay)
+        }
+        None => None,
+    };
 
     let now = Instant::now();
     let mut frame: u64 = 0;
+    let bench_start = emulator.stats();
+
+    let mut show_stats = false;
+    let mut show_hud = false;
+    let mut show_timer = options.timer;
+    let mut force_redraw = true;
+    set_hud(&mut canvas, &mut show_hud, options.hud)?;
+    let mut fps = 0.0;
+    let mut fps_window_start = Instant::now();
+    let mut fps_window_frames = 0;
+    let mut debug_window: Option<DebugWindow> = None;
 
     'main: loop {
+        if shutdown.requested() {
+            break 'main;
+        }
+
         for event in event_pump.poll_iter() {
+            if let Some(window) = &mut debug_window {
+                if event.get_window_id() == Some(window.id()) {
+                    if !window.handle_event(&event, &mut emulator, &mut paused) {
+                        debug_window = None;
+                    }
+                    continue;
+                }
+            }
+
             match event {
-                Event::Quit { .. } => break 'main,
+                // With the debugger open, closing the game window no longer quits by itself
+                Event::Quit { .. } | Event::Window { win_event: WindowEvent::Close, .. } => break 'main,
+                Event::KeyDown { keycode: Some(keycode), .. } if !prompts.is_empty() => {
+                    let prompt = prompts.front_mut().unwrap();
+                    if prompt.handle_key(keycode) {
+                        if let Err(e) = leaderboard.add(&prompt.initials, prompt.score) {
+                            eprintln!("{} could not save leaderboard: {}", "Warning:".yellow().bold(), e);
+                        }
+                        prompts.pop_front();
+                    }
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } if practice_menu.is_some() => {
+                    match practice_menu.as_mut().unwrap().handle_key(keycode) {
+                        Some(MenuAction::Start(preset)) => {
+                            practice_preset = preset;
+                            practice = Some(Practice::start(&mut emulator, preset));
+                            practice_menu = None;
+                        }
+                        Some(MenuAction::Stop) => {
+                            practice = None;
+                            practice_menu = None;
+                        }
+                        Some(MenuAction::Close) => practice_menu = None,
+                        None => {}
+                    }
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } if hex_view.is_some() => {
+                    let view = hex_view.as_mut().unwrap();
+                    if !view.handle_key(keycode, &mut emulator) {
+                        hex_view = None;
+                    }
+                }
+                Event::MouseWheel { y, .. } if hex_view.is_some() => hex_view.as_mut().unwrap().scroll(-y),
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } if paused && !frontend::has_ctrl(keymod) && cheat_index(keycode).is_some() => {
+                    if let Err(e) = cheats.toggle(cheat_index(keycode).unwrap(), &mut emulator) {
+                        eprintln!("{} could not save cheats: {}", "Warning:".yellow().bold(), e);
+                    }
+                }
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } if frontend::has_ctrl(keymod) && slot_index(keycode).is_some() => {
+                    let slot = slot_index(keycode).unwrap();
+                    if frontend::has_shift(keymod) {
+                        match slots.load(slot, &mut emulator) {
+                            Ok(true) => {
+                                cheats.apply(&mut emulator);
+                                audio.sync(&emulator);
+                                fault = None;
+                            }
+                            Ok(false) => {}
+                            Err(e) => eprintln!("{} {}", "Warning:".yellow().bold(), e),
+                        }
+                    } else if let Err(e) = slots.save(slot, &emulator) {
+                        eprintln!("{} {}", "Warning:".yellow().bold(), e);
+                    }
+                }
                 Event::KeyDown { keycode: Some(keycode), keymod, .. } if frontend::has_ctrl(keymod) => {
                     match keycode {
                         Keycode::Q => break 'main,
-                        Keycode::S => save_state = Some(emulator.clone()),
-                        Keycode::D => {
-                            if let Some(state) = &save_state {
-                                emulator = state.clone();
-                            }
-                        }
                         Keycode::R => {
-                            emulator.cpu_mut().reset();
+                            emulator.reset();
                             audio.stop_all();
+                            fault = None;
                         }
+                        Keycode::E => match speedrun.export(&options.splits_file) {
+                            Ok(()) => println!("Splits exported to {}", options.splits_file.display()),
+                            Err(e) => eprintln!("{} could not export splits: {}", "Warning:".yellow().bold(), e),
+                        },
                         _ => {}
                     };
                 }
+                Event::KeyDown { keycode: Some(Keycode::R), .. } if fault.is_some() => {
+                    emulator.reset();
+                    fault = None;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Q), .. } if fault.is_some() => break 'main,
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => paused = !paused,
-                Event::KeyDown { keycode: Some(k), .. } => input::handle_keydown(k, &mut emulator),
-                Event::KeyUp { keycode: Some(k), .. } => input::handle_keyup(k, &mut emulator),
+                Event::KeyDown { keycode: Some(Keycode::Period), .. } if paused => advance_frame = true,
+                Event::KeyDown { keycode: Some(Keycode::Minus | Keycode::KpMinus), .. } => change_speed(&mut speed, Speed::slower, &mut audio, &emulator),
+                Event::KeyDown { keycode: Some(Keycode::Equals | Keycode::KpPlus), .. } => change_speed(&mut speed, Speed::faster, &mut audio, &emulator),
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => show_stats = !show_stats,
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => show_timer = !show_timer,
+                Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                    overlay.next();
+                    overlay_changed = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                    debug_window = match debug_window.take() {
+                        Some(_) => None,
+                        None => Some(DebugWindow::open(&video_subsystem)?),
+                    };
+                }
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => hex_view = Some(HexView::new()),
+                Event::KeyDown { keycode: Some(Keycode::F4), .. } => practice_menu = Some(PracticeMenu::new(practice_preset)),
+                #[cfg(feature = "tracer")]
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => match &mut trace_log {
+                    Some(log) => println!("Tracing {}", if log.toggle(&mut emulator) { "resumed" } else { "paused" }),
+                    None => match TraceLog::create(&options.trace_file, options.trace_interval, &mut emulator) {
+                        Ok(log) => {
+                            println!("Tracing to {}", options.trace_file.display());
+                            trace_log = Some(log);
+                        }
+                        Err(e) => eprintln!("{} could not create {}: {}", "Warning:".yellow().bold(), options.trace_file.display(), e),
+                    },
+                },
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                    let show = !show_hud;
+                    set_hud(&mut canvas, &mut show_hud, show)?;
+                    force_redraw = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => rewinding = true,
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => rewinding = false,
+                Event::KeyDown { keycode: Some(k), .. } if player.is_none() => input::handle_keydown(k, &mut emulator),
+                Event::KeyUp { keycode: Some(k), .. } if player.is_none() => input::handle_keyup(k, &mut emulator),
                 _ => {}
             }
         }
 
-        if !paused {
-            let mut cycles = 0;
-            let mut isr_done = false;
+        #[cfg(feature = "remote")]
+        if let Some(remote) = &mut remote {
+            if remote.poll(&mut emulator, &mut paused, overlay.overlay()) {
+                cheats.apply(&mut emulator);
+                audio.sync(&emulator);
+                fault = None;
+            }
+        }
+
+        // Game frames to run before the next one is shown: several when fast-forwarding, none on
+        // some when in slow motion, and exactly one when stepping through them while paused
+        let advance = std::mem::take(&mut advance_frame);
+        let frames = if advance {
+            1
+        } else if !paused && prompts.is_empty() && practice_menu.is_none() {
+            speed.frames()
+        } else {
+            0
+        };
+        for _ in 0..frames {
+            if rewinding {
+                match rewind.rewind(&mut emulator) {
+                    Ok(true) => {
+                        audio.sync(&emulator);
+                        fault = None;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        eprintln!("{} could not rewind: {}", "Warning:".yellow().bold(), e);
+                        rewind.clear();
+                    }
+                }
+            } else if fault.is_none() {
+                // A frame stopped partway through gets its input when it starts, not again on resuming
+                let frame_start = !emulator.mid_frame();
+                if let Some(movie) = player.as_mut().filter(|_| frame_start) {
+                    if !movie.next_frame(&mut emulator) {
+                        println!("Movie finished after {} frames", movie.frame());
+                        player = None;
+                    }
+                }
+                if let Some(session) = netplay.as_mut().filter(|_| frame_start) {
+                    match session.next_frame(&mut emulator) {
+                        Ok(true) => {}
+                        // Still waiting for the other player's buttons, try again next time
+                        Ok(false) => break,
+                        Err(e) => {
+                            eprintln!("{} netplay stopped: {}", "Warning:".yellow().bold(), e.yellow());
+                            netplay = None;
+                        }
+                    }
+                }
+
+                let on_event = |emulator: &mut Emulator, event| match event {
+                    EmulatorEvent::PlaySound(sound) => audio.play(sound),
+                    EmulatorEvent::StopSound(Sound::UFO) => audio.stop(Sound::UFO),
+                    EmulatorEvent::SkippedOpcode { pc, opcode } => {
+                        let message = format!("skipped unimplemented opcode 0x{:02X} at ${:04X} ({} of {})",
+                            opcode, pc, emulator.skipped_opcodes(), options.skip_unimplemented);
+                        eprintln!("{} {}", "Warning:".yellow().bold(), message.yellow());
+                    }
+                    EmulatorEvent::PortAccess(access) => {
+                        if let Some(Err(e)) = port_log.as_mut().map(|log| log.write(&access)) {
+                            eprintln!("{} could not write port log: {}", "Warning:".yellow().bold(), e);
+                            emulator.set_port_logging(false);
+                            port_log = None;
+                        }
+                    }
+                    EmulatorEvent::WatchdogReset => frontend::watchdog_reset(emulator),
+                    EmulatorEvent::GameOver if practice.is_none() && !cheats.any_enabled() => {
+                        let scores = SpaceInvadersState::from_memory(&emulator.cpu().memory).scores;
+                        for (player, score) in scores.into_iter().enumerate() {
+                            if leaderboard.qualifies(score) {
+                                prompts.push_back(InitialsPrompt::new(player, score));
+                            }
+                        }
+                    }
+                    _ => {}
+                };
+                #[cfg(feature = "remote")]
+                let mut sink = RemoteSink::new(remote.as_mut(), on_event);
+                #[cfg(not(feature = "remote"))]
+                let mut sink = on_event;
+                let status = match debug_window.as_mut() {
+                    Some(window) if window.stepping() => window.run_frame(&mut emulator, &mut sink),
+                    #[cfg(feature = "scripting")]
+                    _ => match &mut script {
+                        Some(script) => script.run_frame(&mut emulator, &mut sink),
+                        None => emulator.run_frame(&mut sink),
+                    },
+                    #[cfg(not(feature = "scripting"))]
+                    _ => emulator.run_frame(&mut sink),
+                };
 
-            while cycles < CYCLES_PER_FRAME {
-                let status = emulator.step().map_err(|e| e.to_string())?;
                 match status {
-                    ExecutionStatus::Continue(c) => cycles += c * 4,
-                    ExecutionStatus::Halt => break,
+                    Ok(FrameStatus::Complete) => {}
+                    Ok(FrameStatus::Breakpoint(address)) => {
+                        println!("Breakpoint at ${:04X}, press Esc to continue", address);
+                        paused = true;
+                    }
+                    Ok(FrameStatus::PortBreak(access)) => {
+                        println!("Port break: {}, press Esc to continue", access.to_string().trim_start());
+                        paused = true;
+                    }
+                    Ok(FrameStatus::Watchpoint(hit)) => {
+                        println!("Watchpoint: {}, press Esc to continue", hit);
+                        paused = true;
+                    }
+                    Ok(FrameStatus::Stopped) => paused = true,
+                    Ok(FrameStatus::Halted) => fault = Some(Fault::new(&emulator, "CPU halted with interrupts disabled".to_string())),
+                    #[cfg(feature = "scripting")]
+                    Err(core::Error::Script(e)) => {
+                        eprintln!("{} script stopped: {}", "Warning:".yellow().bold(), e);
+                        if let Some(script) = script.take() {
+                            script.unload(&mut emulator);
+                        }
+                    }
+                    Err(e) => {
+                        let mut crash = Fault::new(&emulator, e.to_string());
+                        match crashdump::write(&options.dump_dir, &emulator, &crash, overlay.overlay()) {
+                            Ok(path) => crash.dump = Some(path),
+                            Err(e) => eprintln!("{} could not write crash dump: {}", "Warning:".yellow().bold(), e),
+                        }
+                        fault = Some(crash);
+                    }
                 }
 
-                // Handle sounds
-                if let Some(event) = emulator.event() {
-                    match event {
-                        EmulatorEvent::PlaySound(sound) => audio.play(sound),
-                        EmulatorEvent::StopSound(Sound::UFO) => audio.stop(Sound::UFO),
-                        _ => {}
+                if fault.is_some() {
+                    audio.stop_all();
+                } else if !emulator.mid_frame() {
+                    rewind.push(&emulator);
+                    if let Some(Err(e)) = player.as_ref().map(|movie| movie.check_frame(&emulator)) {
+                        eprintln!("{} {}, stopping playback", "Warning:".yellow().bold(), e);
+                        player = None;
+                    }
+                    if let Some(recorder) = &mut recorder {
+                        recorder.record_frame(&emulator);
+                    }
+                    if let Some(practice) = &mut practice {
+                        if let Err(e) = practice.update(&mut emulator) {
+                            eprintln!("{} {}", "Warning:".yellow().bold(), e.yellow());
+                        }
+                    }
+                    let state = SpaceInvadersState::from_memory(&emulator.cpu().memory);
+                    session.update(&state);
+                    if practice.is_none() && !cheats.any_enabled() {
+                        if let Err(e) = achievements.update(&emulator) {
+                            eprintln!("{} could not save achievements: {}", "Warning:".yellow().bold(), e);
+                        }
+                    }
+                    if let Err(e) = speedrun.update(&state) {
+                        eprintln!("{} lost connection to LiveSplit: {}", "Warning:".yellow().bold(), e);
                     }
                 }
+                if let Some(Err(e)) = netplay.as_mut().filter(|_| !emulator.mid_frame()).map(|session| session.end_frame(&mut emulator)) {
+                    eprintln!("{} netplay stopped: {}", "Warning:".yellow().bold(), e.yellow());
+                    netplay = None;
+                }
 
-                // Mid-line interrupt
-                if !isr_done && cycles >= CYCLES_PER_FRAME / 2 {
-                    emulator.cpu_mut().interrupt(1);
-                    isr_done = true;
+                #[cfg(feature = "tracer")]
+                if let Some(Err(e)) = trace_log.as_mut().map(|log| log.write(&mut emulator)) {
+                    eprintln!("{} could not write trace: {}", "Warning:".yellow().bold(), e);
+                    emulator.set_tracer(None);
+                    trace_log = None;
                 }
             }
 
-            emulator.cpu_mut().interrupt(2); // VBlank interrupt
+            // A breakpoint or game over stops fast-forward on the frame it happened
+            if paused || fault.is_some() || !prompts.is_empty() {
+                break;
+            }
+        }
+
+        let mut dirty_lines = emulator.take_dirty_lines();
+        if std::mem::take(&mut overlay_changed) {
+            dirty_lines = DirtyLines::all();
+        }
+        let mut pixels_changed = false;
+        for lines in dirty_lines.ranges() {
+            if frontend::update_pixel_data(&mut pixel_data, emulator.screen(), lines.clone(), overlay.overlay()) {
+                let rect = Rect::new(lines.start as i32, 0, lines.len() as u32, HEIGHT);
+                let start = lines.start as usize * frontend::RENDERER.format.bytes_per_pixel();
+                texture.update(rect, &pixel_data[start..], frontend::RENDERER.pitch()).unwrap();
+                pixels_changed = true;
+            }
         }
 
-        if frontend::update_pixel_data(&mut pixel_data, emulator.video_ram()) {
-            texture.update(None, &pixel_data, HEIGHT as usize * 3).unwrap();
-            canvas.copy_ex(&texture, None, Rect::from_center(canvas.viewport().center(), HEIGHT, WIDTH), -90.0, None, false, false)?;
+        let toast = achievements.toast();
+        let message = speed.message()
+            .or_else(|| overlay.message())
+            .or_else(|| slots.message().map(String::from));
+        let redraw = pixels_changed || show_stats || show_timer || paused || fault.is_some() || toast.is_some() || message.is_some()
+            || !prompts.is_empty() || practice_menu.is_some() || hex_view.is_some();
+        if redraw || force_redraw || show_hud || options.bench {
+            force_redraw = false;
+            canvas.set_draw_color(Color::BLACK);
+            canvas.clear();
+
+            let hud_offset = if show_hud { (HUD_HEIGHT as f32 / SCALE_Y) as i32 } else { 0 };
+            let game_center = Point::new(WIDTH as i32 / 2, hud_offset + HEIGHT as i32 / 2);
+            canvas.copy(&texture, None, Rect::from_center(game_center, WIDTH, HEIGHT))?;
+
+            if show_hud {
+                hud::draw(&mut canvas, &SpaceInvadersState::from_memory(&emulator.cpu().memory))?;
+            }
+
+            if cheats.any_enabled() {
+                let (window_width, _) = canvas.output_size()?;
+                let x = window_width as i32 - osd::text_width(CHEATS_ACTIVE, 2) as i32 - 8;
+                let y = if show_hud { HUD_HEIGHT as i32 + 8 } else { 8 };
+                osd::draw_text(&mut canvas, x, y, 2, CHEATS_ACTIVE, Color::RED)?;
+            }
+
+            if let Some(message) = message {
+                let (window_width, _) = canvas.output_size()?;
+                let x = window_width.saturating_sub(osd::text_width(&message, 2) + 16) as i32 / 2;
+                let y = if show_hud { HUD_HEIGHT as i32 + 8 } else { 8 };
+                osd::draw_panel(&mut canvas, x, y, 2, &[message], Color::GREEN)?;
+            }
+
+            if show_stats {
+                let stats = emulator.stats();
+                let lines = [
+                    format!("FPS     {:.1}", fps),
+                    format!("MIPS    {:.2}", stats.ips / 1_000_000.0),
+                    format!("INSTR   {}", stats.instructions),
+                    format!("CYCLES  {}", stats.cycles),
+                    format!("FRAMES  {}", stats.frames),
+                    format!("IRQS    {}", stats.interrupts),
+                ];
+                osd::draw_panel(&mut canvas, 4, 4, 2, &lines, Color::YELLOW)?;
+            }
+
+            if show_timer {
+                let lines = speedrun.lines();
+                let (window_width, _) = canvas.output_size()?;
+                let width = lines.iter().map(|l| osd::text_width(l, 2)).max().unwrap_or(0) + 16;
+                osd::draw_panel(&mut canvas, window_width.saturating_sub(width + 4) as i32, 420, 2, &lines, Color::CYAN)?;
+            }
+
+            if paused {
+                let mut lines = vec!["PAUSED".to_string(), String::new()];
+                lines.extend(session.lines());
+                lines.push(achievements.summary());
+                if let Some(practice) = &practice {
+                    lines.extend(practice.lines());
+                }
+                lines.push(String::new());
+                lines.extend(cheats.lines());
+                lines.push(String::new());
+                lines.extend(leaderboard.lines());
+                osd::draw_panel(&mut canvas, 16, 80, 2, &lines, Color::WHITE)?;
+            }
+
+            if let Some(toast) = &toast {
+                let (window_width, _) = canvas.output_size()?;
+                let x = window_width.saturating_sub(osd::text_width(&toast[0], 2) + 16) as i32 / 2;
+                osd::draw_panel(&mut canvas, x, 560, 2, toast, Color::YELLOW)?;
+            }
+
+            if let Some(menu) = &practice_menu {
+                osd::draw_panel(&mut canvas, 16, 160, 2, &menu.lines(), Color::CYAN)?;
+            }
+
+            if let Some(view) = &hex_view {
+                osd::draw_panel(&mut canvas, 16, 80, 2, &view.lines(&emulator), Color::GREEN)?;
+            }
+
+            if let Some(prompt) = prompts.front() {
+                osd::draw_panel(&mut canvas, 16, 240, 2, &prompt.lines(), Color::GREEN)?;
+            }
+
+            if let Some(fault) = &fault {
+                osd::draw_panel(&mut canvas, 16, 160, 2, &fault.lines(), Color::RED)?;
+            }
+
             canvas.present();
         }
+        if let Some(window) = &mut debug_window {
+            window.draw(&emulator, paused)?;
+        }
+
+        fps_window_frames += 1;
+        let fps_elapsed = fps_window_start.elapsed().as_secs_f64();
+        if fps_elapsed >= 1.0 {
+            fps = fps_window_frames as f64 / fps_elapsed;
+            fps_window_start = Instant::now();
+            fps_window_frames = 0;
+        }
+
+        if options.bench {
+            let stats = emulator.stats();
+            if stats.frames - bench_start.frames >= options.frames || fault.is_some() {
+                let performance = headless::Performance {
+                    frames: stats.frames - bench_start.frames,
+                    instructions: stats.instructions - bench_start.instructions,
+                    elapsed: now.elapsed(),
+                };
+                println!("{}", performance);
+                break 'main;
+            }
+            continue;
+        }
 
         frame += 1;
         let next_frame = ((1_000.0 / FPS) * frame as f64) as u64;
@@ -125,5 +699,323 @@ fn run(program: &[u8]) -> Result<(), String> {
         spin_sleep::sleep(Duration::from_millis(sleep_ms));
     }
 
+    // Whether the window was closed or a signal asked to stop, save what's still pending
+    for prompt in prompts.drain(..) {
+        let initials = if prompt.initials.is_empty() { UNKNOWN_INITIALS } else { &prompt.initials };
+        if let Err(e) = leaderboard.add(initials, prompt.score) {
+            eprintln!("{} could not save leaderboard: {}", "Warning:".yellow().bold(), e);
+        }
+    }
+    if let (Some(path), Some(recorder)) = (&options.record, recorder) {
+        let movie = recorder.finish();
+        match movie::save(path, &movie) {
+            Ok(()) => println!("Movie of {} frames ({} rerecords) written to {}", movie.frames.len(), movie.rerecords, path.display()),
+            Err(e) => eprintln!("{} {}", "Warning:".yellow().bold(), e),
+        }
+    }
+    if let Some(Err(e)) = nvram.as_ref().map(|nvram| nvram.save(&emulator)) {
+        eprintln!("{} {}", "Warning:".yellow().bold(), e.yellow());
+    }
+    // A faulted machine isn't worth resuming, and a benchmark didn't play anything to resume
+    if fault.is_none() && !options.bench {
+        match slots.autosave(&emulator) {
+            Ok(()) => println!("State saved, continue with --resume"),
+            Err(e) => eprintln!("{} {}", "Warning:".yellow().bold(), e),
+        }
+    }
+    if let Some(Err(e)) = port_log.as_mut().map(|log| log.flush()) {
+        eprintln!("{} could not write port log: {}", "Warning:".yellow().bold(), e);
+    }
+    #[cfg(feature = "tracer")]
+    if let Some(Err(e)) = trace_log.as_mut().map(|log| log.flush()) {
+        eprintln!("{} could not write trace: {}", "Warning:".yellow().bold(), e);
+    }
+    #[cfg(feature = "coverage")]
+    if let Some(path) = &options.coverage {
+        match coverage_file::write(path, &emulator, entry.rom.len()) {
+            Ok(()) => println!("Coverage written to {}", path.display()),
+            Err(e) => eprintln!("{} could not write {}: {}", "Warning:".yellow().bold(), path.display(), e),
+        }
+    }
+    #[cfg(feature = "profiler")]
+    if let Some(path) = &options.profile {
+        match profile::write(path, &emulator) {
+            Ok(()) => println!("Profile written to {}", path.display()),
+            Err(e) => eprintln!("{} could not write {}: {}", "Warning:".yellow().bold(), path.display(), e),
+        }
+    }
+
+    println!("{}", "Session statistics".bold());
+    for line in session.lines() {
+        println!("  {}", line.to_lowercase());
+    }
+
+    Ok(())
+}
+
+/// Applies a speed hotkey, bringing back the sounds cut while fast-forwarding once it ends.
+fn change_speed(speed: &mut Speed, change: fn(&mut Speed) -> bool, audio: &mut AudioManager, emulator: &Emulator) {
+    let was_fast_forward = speed.is_fast_forward();
+    if change(speed) {
+        audio.set_speed(speed.factor());
+        if was_fast_forward && !speed.is_fast_forward() {
+            audio.sync(emulator);
+        }
+    }
+}
+
+fn run_headless(options: &Options, shutdown: &Shutdown) -> Result<(), String> {
+    if options.script.is_some() {
+        return Err("--script can't be used with --headless".to_string());
+    }
+    if options.netplay.is_some() {
+        return Err("netplay can't be used with --headless".to_string());
+    }
+    let rom = windowless_rom(options)?;
+    let mut emulator = windowless_emulator(&rom, options);
+    start_coverage(options, &mut emulator)?;
+    start_profiler(options, &mut emulator)?;
+
+    let mut port_log = match &options.port_log {
+        Some(path) => Some(PortLog::create(path, &mut emulator).map_err(|e| format!("could not create {}: {}", path.display(), e))?),
+        None => None,
+    };
+
+    let mut player = match &options.play {
+        Some(path) => Some(Player::start(movie::load(path)?, &mut emulator).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => None,
+    };
+    let frames = player.as_ref().map_or(options.frames, |player| player.movie().frames.len() as u64);
+
+    let report = headless::run(&mut emulator, frames, port_log.as_mut(), player.as_mut(), shutdown)?;
+    println!("{}", report);
+
+    #[cfg(feature = "coverage")]
+    if let Some(path) = &options.coverage {
+        coverage_file::write(path, &emulator, rom.data.len()).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+    #[cfg(feature = "profiler")]
+    if let Some(path) = &options.profile {
+        profile::write(path, &emulator).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+
+    if let Some(path) = &options.frame_hashes {
+        headless::write_frame_hashes(path, &report.frame_hashes)
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+    if let Some(path) = &options.check_frame_hashes {
+        let expected = headless::read_frame_hashes(path)?;
+        match core::first_divergence(&expected, &report.frame_hashes) {
+            Some(frame) => return Err(format!("framebuffer diverged from {} at frame {}", path.display(), frame)),
+            None => println!("frame hashes match {}", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Streams the game to the viewers connecting to `addr` in real time, holding the buttons they
+/// send, until shutdown is requested.
+fn run_stream(options: &Options, addr: &str, shutdown: &Shutdown) -> Result<(), String> {
+    if options.script.is_some() {
+        return Err("--script can't be used with --stream".to_string());
+    }
+    if options.netplay.is_some() {
+        return Err("netplay can't be used with --stream".to_string());
+    }
+    let rom = windowless_rom(options)?;
+    let mut emulator = windowless_emulator(&rom, options);
+    let mut server = StreamServer::bind(addr).map_err(|e| format!("could not start stream server on {}: {}", addr, e))?;
+    println!("Streaming on {}", addr);
+
+    let frame_time = Duration::from_secs_f64(1.0 / FPS);
+    let mut next_frame = Instant::now();
+    while !shutdown.requested() {
+        if let Some(buttons) = server.buttons() {
+            emulator.set_buttons(buttons);
+        }
+
+        let mut on_event = |emulator: &mut Emulator, event: EmulatorEvent| match event {
+            EmulatorEvent::WatchdogReset => frontend::watchdog_reset(emulator),
+            event => server.send_event(&event),
+        };
+        emulator.run_frame(&mut on_event).map_err(|e| e.to_string())?;
+        server.send_frame(emulator.screen());
+
+        next_frame += frame_time;
+        spin_sleep::sleep(next_frame.saturating_duration_since(Instant::now()));
+    }
     Ok(())
 }
+
+/// Shows the frames of the stream server at `addr` and sends it the buttons held on the keyboard,
+/// until the window is closed.
+fn run_viewer(addr: &str, options: &Options, canvas: &mut WindowCanvas, texture: &mut Texture, event_pump: &mut EventPump, audio: &mut AudioManager, shutdown: &Shutdown) -> Result<(), String> {
+    let overlay = match &options.overlay {
+        Some(path) => overlay::load(path)?,
+        None => Overlay::Gel.into(),
+    };
+    let mut viewer = StreamViewer::connect(addr)?;
+    println!("Viewing {}", addr);
+
+    let mut pixel_data = [0; (WIDTH * HEIGHT * 3) as usize];
+    let mut held = Buttons::empty();
+    while !shutdown.requested() {
+        let mut buttons = held;
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => return Ok(()),
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    if let Some(button) = input::map_keycode(keycode) {
+                        buttons.insert(button.into());
+                    }
+                }
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(button) = input::map_keycode(keycode) {
+                        buttons.remove(button.into());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if buttons != held {
+            viewer.send_buttons(buttons)?;
+            held = buttons;
+        }
+
+        for update in viewer.updates()? {
+            match update {
+                Update::Frame(screen) => {
+                    frontend::update_pixel_data(&mut pixel_data, &screen, 0..WIDTH, &overlay);
+                    texture.update(None, &pixel_data, frontend::RENDERER.pitch()).map_err(|e| e.to_string())?;
+                }
+                Update::PlaySound(sound) => audio.play(sound),
+                Update::StopSound(sound) => audio.stop(sound),
+            }
+        }
+
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas.copy(texture, None, Rect::new(0, 0, WIDTH, HEIGHT))?;
+        canvas.present();
+    }
+    Ok(())
+}
+
+/// The ROM given on the command line, or the built-in one if none was, for the modes without a
+/// launcher.
+fn windowless_rom(options: &Options) -> Result<LoadedRom, String> {
+    let rom = match &options.rom {
+        Some(path) => rom::load(path)?,
+        None => LoadedRom { data: BUILTIN_ROM.to_vec(), set: games::find_set("invaders"), warnings: Vec::new() },
+    };
+    print_warnings(&rom);
+    Ok(rom)
+}
+
+/// Creates the emulator for `rom` with its profile and the command line's settings, for the modes
+/// without a window.
+fn windowless_emulator(rom: &LoadedRom, options: &Options) -> Emulator {
+    let profile = rom.set.and_then(|set| set.profile).unwrap_or(DEFAULT_PROFILE);
+    let mut emulator = new_emulator(&rom.data, profile.machine, options);
+    emulator.set_dip_switches(profile.dip_switches);
+    emulator.set_quirks(options.quirks.unwrap_or(profile.quirks));
+    emulator.set_watchdog(options.watchdog);
+    emulator.set_skip_unimplemented_opcodes(options.skip_unimplemented);
+    emulator.cpu_mut().set_undefined_opcode_policy(options.undefined_opcodes);
+    emulator.cpu_mut().set_rom_write_policy(options.rom_writes);
+    emulator
+}
+
+/// Creates the emulator for `rom` on `machine`, with the JIT if `--jit` was given and it's available.
+fn new_emulator(rom: &[u8], machine: MachineKind, options: &Options) -> Emulator {
+    let backend = if options.jit { Backend::Jit } else { Backend::Interpreter };
+    let emulator = Emulator::with_machine(rom, machine.machine()).with_backend(backend);
+    if emulator.backend() != backend {
+        eprintln!("{} {}", "Warning:".yellow().bold(), "JIT not available (it needs the `jit` feature and a machine Cranelift supports), interpreting instead".yellow());
+    }
+    emulator
+}
+
+/// Starts recording ROM coverage if `--coverage` was given.
+#[cfg(feature = "coverage")]
+fn start_coverage(options: &Options, emulator: &mut Emulator) -> Result<(), String> {
+    if options.coverage.is_some() {
+        coverage_file::start(emulator);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "coverage"))]
+fn start_coverage(options: &Options, _emulator: &mut Emulator) -> Result<(), String> {
+    match options.coverage {
+        Some(_) => Err("coverage recording was not compiled in (enable the `coverage` feature)".to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Starts profiling instructions if `--profile` was given.
+#[cfg(feature = "profiler")]
+fn start_profiler(options: &Options, emulator: &mut Emulator) -> Result<(), String> {
+    if options.profile.is_some() {
+        profile::start(emulator);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "profiler"))]
+fn start_profiler(options: &Options, _emulator: &mut Emulator) -> Result<(), String> {
+    match options.profile {
+        Some(_) => Err("profiling was not compiled in (enable the `profiler` feature)".to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Cheat toggled by a number key in the pause menu, 0-based.
+fn cheat_index(keycode: Keycode) -> Option<usize> {
+    let digit: usize = keycode.name().parse().ok()?;
+    digit.checked_sub(1)
+}
+
+/// Save-state slot of a number key held with Ctrl.
+fn slot_index(keycode: Keycode) -> Option<u8> {
+    keycode.name().parse().ok().filter(|&slot| slot < SLOTS)
+}
+
+/// Grows the window to make room for the score bar above the game, or shrinks it back.
+fn set_hud(canvas: &mut WindowCanvas, show_hud: &mut bool, show: bool) -> Result<(), String> {
+    let height = if show { WINDOW_HEIGHT + HUD_HEIGHT } else { WINDOW_HEIGHT };
+    canvas.window_mut().set_size(WINDOW_WIDTH, height).map_err(|e| e.to_string())?;
+    *show_hud = show;
+    Ok(())
+}
+
+fn print_warnings(rom: &LoadedRom) {
+    for warning in &rom.warnings {
+        eprintln!("{} {}", "Warning:".yellow().bold(), warning.yellow());
+    }
+}
+
+/// Loads the ROM given on the command line, or lets the user pick one from the ROM directory.
+/// The built-in ROM is always offered, and runs directly when the directory has no ROMs.
+fn select_rom(options: &Options, canvas: &mut WindowCanvas, event_pump: &mut EventPump) -> Result<Option<RomEntry>, String> {
+    if let Some(path) = &options.rom {
+        let rom = rom::load(path)?;
+        print_warnings(&rom);
+
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+        return Ok(Some(RomEntry::new(name, Some(path.clone()), rom)));
+    }
+
+    let mut entries = launcher::scan(&options.rom_dir)
+        .map_err(|e| format!("could not read ROM directory {}: {}", options.rom_dir.display(), e))?;
+    let builtin = LoadedRom { data: BUILTIN_ROM.to_vec(), set: games::find_set("invaders"), warnings: Vec::new() };
+    entries.insert(0, RomEntry::new("built-in".to_string(), None, builtin));
+
+    // A benchmark shouldn't wait for a choice, so it runs the built-in ROM
+    if entries.len() == 1 || options.bench {
+        return Ok(Some(entries.swap_remove(0)));
+    }
+
+    let choice = launcher::choose(canvas, event_pump, &entries)?;
+    Ok(choice.map(|i| entries.swap_remove(i)))
+}
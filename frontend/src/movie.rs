@@ -0,0 +1,83 @@
+//! Reading and writing input movies, either in the native format or as BizHawk `.bk2` archives.
+
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use core::movie::{Bk2, Movie};
+
+const HEADER: &str = "Header.txt";
+const INPUT_LOG: &str = "Input Log.txt";
+const CORE_STATE: &str = "Core.bin";
+const FRAME_HASHES: &str = "FrameHashes.txt";
+
+pub fn is_bk2(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("bk2"))
+}
+
+/// Reads a movie, as a `.bk2` archive if the extension says so.
+pub fn load(path: &Path) -> Result<Movie, String> {
+    let data = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let movie = if is_bk2(path) {
+        read_bk2(&data).and_then(|bk2| Movie::from_bk2(&bk2).map_err(|e| e.to_string()))
+    } else {
+        Movie::from_bytes(&data).map_err(|e| e.to_string())
+    };
+    movie.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Writes a movie, as a `.bk2` archive if the extension says so.
+pub fn save(path: &Path, movie: &Movie) -> Result<(), String> {
+    let data = if is_bk2(path) { write_bk2(&movie.to_bk2())? } else { movie.to_bytes() };
+    fs::write(path, data).map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+/// Converts between formats, picked from the extension of each path.
+pub fn convert(input: &Path, output: &Path) -> Result<Movie, String> {
+    let movie = load(input)?;
+    save(output, &movie)?;
+    Ok(movie)
+}
+
+fn read_bk2(data: &[u8]) -> Result<Bk2, String> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let mut read = |name: &str| -> Result<Option<Vec<u8>>, String> {
+        let mut file = match archive.by_name(name) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut contents).map_err(|e| e.to_string())?;
+        Ok(Some(contents))
+    };
+    let text = |contents: Option<Vec<u8>>| contents.map(|c| String::from_utf8_lossy(&c).into_owned());
+
+    let header = text(read(HEADER)?).ok_or_else(|| format!("missing {}", HEADER))?;
+    let input_log = text(read(INPUT_LOG)?).ok_or_else(|| format!("missing {}", INPUT_LOG))?;
+    let core_state = read(CORE_STATE)?;
+    let frame_hashes = text(read(FRAME_HASHES)?);
+    Ok(Bk2 { header, input_log, core_state, frame_hashes })
+}
+
+fn write_bk2(bk2: &Bk2) -> Result<Vec<u8>, String> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut files = vec![(HEADER, bk2.header.as_bytes()), (INPUT_LOG, bk2.input_log.as_bytes())];
+    if let Some(state) = &bk2.core_state {
+        files.push((CORE_STATE, state));
+    }
+    if let Some(hashes) = &bk2.frame_hashes {
+        files.push((FRAME_HASHES, hashes.as_bytes()));
+    }
+
+    for (name, data) in files {
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        zip.write_all(data).map_err(|e| e.to_string())?;
+    }
+    Ok(zip.finish().map_err(|e| e.to_string())?.into_inner())
+}
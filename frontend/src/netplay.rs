@@ -0,0 +1,123 @@
+//! Lockstep netplay over TCP, see `core::netplay`. Every message goes out with its length before
+//! it, as a little-endian u32.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use core::netplay::{Message, Session};
+use core::{Buttons, Emulator};
+
+/// Frames of input delay the host picks unless `--netplay-delay` is given, enough to hide a
+/// round trip of about 30 ms.
+pub const DEFAULT_DELAY: u8 = 2;
+/// Largest message accepted, with room for the save state in the hello.
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+pub struct Netplay {
+    session: Session,
+    stream: TcpStream,
+    incoming: Receiver<Result<Message, String>>,
+    /// Buttons held on the local keyboard, put back after each frame, which holds both players'.
+    held: Buttons,
+}
+
+impl Netplay {
+    /// Waits for the other player to connect to `addr`, then starts a session from the current
+    /// state of `emulator` with them as player 2.
+    pub fn host(addr: &str, emulator: &Emulator, delay: u8) -> Result<Self, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("could not listen on {}: {}", addr, e))?;
+        println!("Waiting for player 2 on {}", addr);
+        let (mut stream, peer) = listener.accept().map_err(|e| format!("could not accept player 2: {}", e))?;
+
+        let (session, hello) = Session::host(emulator, delay);
+        write_message(&mut stream, &hello).map_err(|e| format!("could not send the start state: {}", e))?;
+        println!("Player 2 joined from {}", peer);
+        Self::start(session, stream).map_err(|e| e.to_string())
+    }
+
+    /// Connects to the host at `addr` and joins its session as player 2, loading its state into
+    /// `emulator`.
+    pub fn join(addr: &str, emulator: &mut Emulator) -> Result<Self, String> {
+        let mut stream = TcpStream::connect(addr).map_err(|e| format!("could not connect to {}: {}", addr, e))?;
+        let hello = read_message(&mut stream)?;
+        let session = Session::join(&hello, emulator).map_err(|e| e.to_string())?;
+        println!("Joined {} as player 2 with {} frames of input delay", addr, session.delay());
+        Self::start(session, stream).map_err(|e| e.to_string())
+    }
+
+    fn start(session: Session, stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let (tx, incoming) = mpsc::channel();
+        thread::spawn(move || loop {
+            let message = read_message(&mut reader);
+            let failed = message.is_err();
+            if tx.send(message).is_err() || failed {
+                break;
+            }
+        });
+
+        Ok(Self { session, stream, incoming, held: Buttons::empty() })
+    }
+
+    /// Sends the buttons held locally when they're due, takes in what the other player sent and,
+    /// once both players' buttons for the next frame are in, holds them on `emulator`. Returns
+    /// `false` while waiting for the other player.
+    pub fn next_frame(&mut self, emulator: &mut Emulator) -> Result<bool, String> {
+        if let Some(message) = self.session.add_local_input(emulator.buttons()) {
+            self.send(&message)?;
+        }
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => self.session.receive(message?).map_err(|e| e.to_string())?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err("the other player left".to_string()),
+            }
+        }
+
+        let Some(buttons) = self.session.next_frame() else { return Ok(false) };
+        self.held = emulator.buttons();
+        emulator.set_buttons(buttons);
+        Ok(true)
+    }
+
+    /// Meant to be called after running the frame [`Netplay::next_frame`] prepared. Sends the
+    /// state hash when it's due and puts the local buttons back.
+    pub fn end_frame(&mut self, emulator: &mut Emulator) -> Result<(), String> {
+        let hash = self.session.end_frame(emulator).map_err(|e| e.to_string());
+        emulator.set_buttons(self.held);
+        match hash? {
+            Some(message) => self.send(&message),
+            None => Ok(()),
+        }
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), String> {
+        write_message(&mut self.stream, message).map_err(|e| format!("lost connection to the other player: {}", e))
+    }
+}
+
+fn write_message(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    let bytes = message.to_bytes();
+    let mut frame = (bytes.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(&bytes);
+    stream.write_all(&frame)
+}
+
+fn read_message(stream: &mut TcpStream) -> Result<Message, String> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => "the other player left".to_string(),
+        _ => format!("lost connection to the other player: {}", e),
+    })?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(format!("netplay message too long ({} bytes)", len));
+    }
+
+    let mut bytes = vec![0; len];
+    stream.read_exact(&mut bytes).map_err(|e| format!("lost connection to the other player: {}", e))?;
+    Message::from_bytes(&bytes).map_err(|e| e.to_string())
+}
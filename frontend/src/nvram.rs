@@ -0,0 +1,47 @@
+//! The high score and whatever else the game keeps between plays, saved at exit and put back at
+//! the next start like MAME's NVRAM, see [`core::machine::Machine::nvram`].
+//!
+//! The ROM with CRC32 `crc` keeps it in `<crc>.nv` in the NVRAM directory, the bytes as they are
+//! in game RAM.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use core::Emulator;
+
+#[derive(Debug, Clone)]
+pub struct Nvram {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Nvram {
+    pub fn new(dir: PathBuf, rom_crc: u32) -> Self {
+        let path = dir.join(format!("{:08X}.nv", rom_crc));
+        Self { dir, path }
+    }
+
+    /// Hands the saved NVRAM to `emulator`, which puts it back once the game has booted.
+    /// Returns whether there was any.
+    pub fn restore(&self, emulator: &mut Emulator) -> Result<bool, String> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                emulator.restore_nvram(&data);
+                Ok(true)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("could not read {}: {}", self.path.display(), e)),
+        }
+    }
+
+    pub fn save(&self, emulator: &Emulator) -> Result<(), String> {
+        let data = emulator.nvram();
+        if data.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&self.path, data))
+            .map_err(|e| format!("could not save {}: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,167 @@
+//! Colors laid over the monochrome screen: one of the built-in cabinet overlays, or one loaded
+//! from a TOML file to recreate other cabinet variants or invent new color schemes.
+//!
+//! ```toml
+//! # Color of pixels outside every region, white if left out
+//! default = "#FFFFFF"
+//!
+//! # Regions are checked in order and the first one containing a pixel colors it.
+//! # rect is x, y, width and height in upright screen pixels from the top left (224x256).
+//! [[region]]
+//! rect = [0, 32, 224, 32]
+//! color = "#FF0000"
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use sdl2::pixels::Color;
+use toml::{Table, Value};
+
+use core::Overlay;
+
+/// Directory searched for `<rom name>.toml` overlays.
+pub const DEFAULT_OVERLAY_DIR: &str = "overlays";
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorOverlay {
+    Builtin(Overlay),
+    Custom(CustomOverlay),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomOverlay {
+    default: Color,
+    regions: Vec<Region>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    color: Color,
+}
+
+impl Region {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
+}
+
+impl ColorOverlay {
+    /// Color of a lit pixel at column `x` and row `y` of the upright screen, from the top left.
+    pub fn color(&self, x: u32, y: u32) -> Color {
+        match self {
+            Self::Builtin(Overlay::Gel) => crate::match_pixel_color(x, y),
+            Self::Builtin(Overlay::Monochrome) => Color::WHITE,
+            Self::Custom(custom) => custom.regions.iter()
+                .find(|region| region.contains(x, y))
+                .map_or(custom.default, |region| region.color),
+        }
+    }
+}
+
+/// The overlays a hotkey cycles through: black and white, the gel strips and the custom overlay
+/// the game started with, if any.
+#[derive(Debug, Clone)]
+pub struct OverlaySwitch {
+    overlays: Vec<(&'static str, ColorOverlay)>,
+    current: usize,
+    changed: Option<Instant>,
+}
+
+impl OverlaySwitch {
+    pub fn new(overlay: ColorOverlay) -> Self {
+        let mut overlays = vec![
+            ("BLACK AND WHITE", ColorOverlay::Builtin(Overlay::Monochrome)),
+            ("GEL", ColorOverlay::Builtin(Overlay::Gel)),
+        ];
+        let current = match overlays.iter().position(|(_, builtin)| *builtin == overlay) {
+            Some(current) => current,
+            None => {
+                overlays.push(("CUSTOM", overlay));
+                overlays.len() - 1
+            }
+        };
+        Self { overlays, current, changed: None }
+    }
+
+    pub fn overlay(&self) -> &ColorOverlay {
+        &self.overlays[self.current].1
+    }
+
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.overlays.len();
+        self.changed = Some(Instant::now());
+    }
+
+    /// The new overlay, for a couple of seconds after switching.
+    pub fn message(&self) -> Option<String> {
+        self.changed
+            .filter(|changed| changed.elapsed() < MESSAGE_DURATION)
+            .map(|_| format!("OVERLAY {}", self.overlays[self.current].0))
+    }
+}
+
+impl From<Overlay> for ColorOverlay {
+    fn from(overlay: Overlay) -> Self {
+        Self::Builtin(overlay)
+    }
+}
+
+/// Loads the overlay for the ROM named `rom_name` from `dir`, if there is one.
+pub fn find(dir: &Path, rom_name: &str) -> Result<Option<ColorOverlay>, String> {
+    let stem = Path::new(rom_name).file_stem().map_or(rom_name.into(), |stem| stem.to_string_lossy());
+    let path = dir.join(format!("{}.toml", stem));
+    if path.is_file() { load(&path).map(Some) } else { Ok(None) }
+}
+
+pub fn load(path: &Path) -> Result<ColorOverlay, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse(contents: &str) -> Result<ColorOverlay, String> {
+    let table: Table = contents.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+
+    let default = match table.get("default") {
+        Some(value) => parse_color(value)?,
+        None => Color::WHITE,
+    };
+
+    let regions = match table.get("region") {
+        Some(Value::Array(regions)) => regions.iter().enumerate()
+            .map(|(i, region)| parse_region(region).map_err(|e| format!("region {}: {}", i + 1, e)))
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("region must be an array of tables ([[region]])".to_string()),
+        None => Vec::new(),
+    };
+
+    Ok(ColorOverlay::Custom(CustomOverlay { default, regions }))
+}
+
+fn parse_region(value: &Value) -> Result<Region, String> {
+    let rect = value.get("rect").and_then(Value::as_array).ok_or("missing rect")?;
+    let rect: Vec<u32> = rect.iter()
+        .map(|n| n.as_integer().and_then(|n| u32::try_from(n).ok()))
+        .collect::<Option<_>>()
+        .filter(|rect: &Vec<u32>| rect.len() == 4)
+        .ok_or("rect must be 4 non-negative integers: x, y, width, height")?;
+    let color = parse_color(value.get("color").ok_or("missing color")?)?;
+
+    Ok(Region { x: rect[0], y: rect[1], width: rect[2], height: rect[3], color })
+}
+
+/// `"#RRGGBB"`
+fn parse_color(value: &Value) -> Result<Color, String> {
+    let text = value.as_str().unwrap_or_default();
+    let rgb = text.strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("invalid color {} (expected \"#RRGGBB\")", value))?;
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
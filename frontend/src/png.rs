@@ -0,0 +1,51 @@
+//! Minimal PNG encoder for screenshots served by the remote server. The image data is stored
+//! uncompressed, which is plenty for a 224x256 screen.
+
+use core::crc32;
+
+/// Largest stored deflate block.
+const STORED_BLOCK: usize = 0xFFFF;
+
+/// 8-bit RGB PNG of `pixels`, three bytes each, as returned by [`crate::screenshot`].
+pub fn encode(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    // Every row starts with filter type 0, none
+    let raw: Vec<u8> = pixels.chunks(width as usize * 3)
+        .flat_map(|row| std::iter::once(0).chain(row.iter().copied()))
+        .collect();
+
+    // zlib header, stored deflate blocks, Adler-32
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(STORED_BLOCK).count();
+    for (i, block) in raw.chunks(STORED_BLOCK).enumerate() {
+        zlib.push((i + 1 == blocks) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, RGB, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1A\n".to_vec();
+    for (kind, data) in [(b"IHDR", &header), (b"IDAT", &zlib), (b"IEND", &Vec::new())] {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
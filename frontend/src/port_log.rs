@@ -0,0 +1,30 @@
+//! Log of every IN and OUT the CPU executes, for debugging the shift register and sound latches.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use core::{Emulator, PortAccess};
+
+#[derive(Debug)]
+pub struct PortLog {
+    writer: BufWriter<File>,
+}
+
+impl PortLog {
+    /// Creates the log at `path` and turns on port logging in `emulator`.
+    pub fn create(path: &Path, emulator: &mut Emulator) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "#        cycle pc    dir port value")?;
+        emulator.set_port_logging(true);
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, access: &PortAccess) -> io::Result<()> {
+        writeln!(self.writer, "{}", access)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
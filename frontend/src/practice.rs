@@ -0,0 +1,226 @@
+//! Practice mode: patches the next game to start at a chosen wave with a chosen number of ships
+//! and shield state, and optionally restores the start of the current wave whenever a ship is lost.
+
+use sdl2::keyboard::Keycode;
+
+use core::{Cheat, Emulator};
+
+const RAM_PLAYER_ALIVE: u16 = 0x2015;
+const RAM_NUM_ALIENS: u16 = 0x2082;
+const RAM_NUM_COINS: u16 = 0x20EB;
+const RAM_GAME_MODE: u16 = 0x20EF;
+const RAM_P1_SHIELDS: u16 = 0x2142;
+const SHIELDS_LEN: u16 = 0xB0;
+const RAM_P1_REF_ALIEN_Y: u16 = 0x21FC;
+const RAM_P1_RACK_COUNT: u16 = 0x21FE;
+const RAM_P1_SHIPS: u16 = 0x21FF;
+const ALIENS_PER_WAVE: u8 = 55;
+
+/// Video RAM address of the bottom-left corner of the leftmost shield. Each shield is 22 columns
+/// of 2 bytes, 45 columns apart.
+const VRAM_SHIELDS: u16 = 0x2806;
+const SHIELD_COLUMNS: u16 = 22;
+const SHIELD_SPACING: u16 = 45 * 32;
+
+/// Starting row of the aliens for each wave, the first one followed by the table at $1DA3. The
+/// game cycles through the last eight, so waves past the ninth look like earlier ones.
+const ALIEN_START_Y: [u8; 9] = [0x78, 0x60, 0x50, 0x48, 0x48, 0x48, 0x40, 0x40, 0x40];
+pub const MAX_WAVE: u8 = ALIEN_START_Y.len() as u8;
+pub const MAX_SHIPS: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+    /// 1-based.
+    pub wave: u8,
+    pub ships: u8,
+    pub shields: bool,
+    /// Restore the start of the wave when a ship is lost.
+    pub retry: bool,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self { wave: 1, ships: 3, shields: true, retry: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for a game to start.
+    Idle,
+    /// Waiting for the game to set up the player, so the patches aren't overwritten.
+    Starting,
+    /// Waiting for the aliens and shields of the next wave to be drawn.
+    WaitingForWave,
+    /// Shield patches are queued, snapshot on the next frame.
+    WaveStarting,
+    Playing,
+}
+
+#[derive(Debug)]
+pub struct Practice {
+    preset: Preset,
+    state: State,
+    /// Save state from the start of the current wave.
+    snapshot: Option<Vec<u8>>,
+    retries: u32,
+    last: Option<(bool, u8)>,
+}
+
+impl Practice {
+    /// Arms `preset` for the next game, adding a credit so it can be started right away.
+    pub fn start(emulator: &mut Emulator, preset: Preset) -> Self {
+        let coins = emulator.cpu().memory[RAM_NUM_COINS];
+        if coins < 0x99 {
+            // BCD increment
+            let coins = if coins & 0x0F == 9 { coins + 7 } else { coins + 1 };
+            emulator.add_cheat(Cheat::once(RAM_NUM_COINS, coins));
+        }
+
+        Self { preset, state: State::Idle, snapshot: None, retries: 0, last: None }
+    }
+
+    /// Must be called once per emulated frame.
+    pub fn update(&mut self, emulator: &mut Emulator) -> Result<(), String> {
+        let memory = &emulator.cpu().memory;
+        let in_game = memory[RAM_GAME_MODE] & 1 != 0;
+        let player_alive = memory[RAM_PLAYER_ALIVE];
+        let ships = memory[RAM_P1_SHIPS];
+        let aliens = memory[RAM_NUM_ALIENS];
+        let Some((was_in_game, last_player_alive)) = self.last.replace((in_game, player_alive)) else { return Ok(()) };
+
+        if !in_game {
+            self.state = State::Idle;
+            self.snapshot = None;
+            return Ok(());
+        }
+
+        match self.state {
+            State::Idle if !was_in_game => self.state = State::Starting,
+            State::Starting if ships != 0 => {
+                let wave = self.preset.wave.clamp(1, MAX_WAVE);
+                emulator.add_cheat(Cheat::once(RAM_P1_RACK_COUNT, wave - 1));
+                emulator.add_cheat(Cheat::once(RAM_P1_REF_ALIEN_Y, ALIEN_START_Y[wave as usize - 1]));
+                // The ship in play isn't counted
+                emulator.add_cheat(Cheat::once(RAM_P1_SHIPS, self.preset.ships.clamp(1, MAX_SHIPS) - 1));
+                self.state = State::WaitingForWave;
+            }
+            State::WaitingForWave if aliens == ALIENS_PER_WAVE && shields_drawn(emulator) => {
+                if !self.preset.shields {
+                    remove_shields(emulator);
+                }
+                self.state = State::WaveStarting;
+            }
+            State::WaveStarting => {
+                self.snapshot = Some(emulator.save_state_bytes());
+                self.state = State::Playing;
+            }
+            State::Playing if aliens == 0 => self.state = State::WaitingForWave,
+            State::Playing if self.preset.retry && player_alive != 0xFF && last_player_alive == 0xFF => {
+                if let Some(snapshot) = &self.snapshot {
+                    emulator.load_state_bytes(snapshot).map_err(|e| format!("could not restore wave start: {}", e))?;
+                    self.last = None;
+                    self.retries += 1;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let status = match self.state {
+            State::Idle => "PRESS START",
+            State::Starting | State::WaitingForWave | State::WaveStarting => "STARTING",
+            State::Playing => "PLAYING",
+        };
+        vec![
+            format!("PRACTICE      {}", status),
+            format!("RETRIES       {}", self.retries),
+        ]
+    }
+}
+
+fn shield_addresses() -> impl Iterator<Item = u16> {
+    (0..4).flat_map(|shield| {
+        let start = VRAM_SHIELDS + shield * SHIELD_SPACING;
+        (0..SHIELD_COLUMNS).flat_map(move |column| [start + column * 32, start + column * 32 + 1])
+    })
+}
+
+fn shields_drawn(emulator: &Emulator) -> bool {
+    shield_addresses().any(|address| emulator.cpu().memory[address] != 0)
+}
+
+/// Clears the shields from the screen, and from the copy kept for the other player's turns.
+fn remove_shields(emulator: &mut Emulator) {
+    for address in shield_addresses().chain(RAM_P1_SHIELDS..RAM_P1_SHIELDS + SHIELDS_LEN) {
+        emulator.add_cheat(Cheat::once(address, 0));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Start(Preset),
+    Stop,
+    Close,
+}
+
+/// Menu choosing the practice preset.
+#[derive(Debug, Clone)]
+pub struct PracticeMenu {
+    preset: Preset,
+    selected: usize,
+}
+
+impl PracticeMenu {
+    const FIELDS: usize = 4;
+
+    pub fn new(preset: Preset) -> Self {
+        Self { preset, selected: 0 }
+    }
+
+    pub fn handle_key(&mut self, keycode: Keycode) -> Option<MenuAction> {
+        match keycode {
+            Keycode::Up => self.selected = (self.selected + Self::FIELDS - 1) % Self::FIELDS,
+            Keycode::Down => self.selected = (self.selected + 1) % Self::FIELDS,
+            Keycode::Left => self.change(-1),
+            Keycode::Right => self.change(1),
+            Keycode::Return | Keycode::KpEnter => return Some(MenuAction::Start(self.preset)),
+            Keycode::Delete | Keycode::Backspace => return Some(MenuAction::Stop),
+            Keycode::Escape | Keycode::F4 => return Some(MenuAction::Close),
+            _ => {}
+        }
+        None
+    }
+
+    fn change(&mut self, delta: i8) {
+        let preset = &mut self.preset;
+        match self.selected {
+            0 => preset.wave = preset.wave.saturating_add_signed(delta).clamp(1, MAX_WAVE),
+            1 => preset.ships = preset.ships.saturating_add_signed(delta).clamp(1, MAX_SHIPS),
+            2 => preset.shields = !preset.shields,
+            _ => preset.retry = !preset.retry,
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let on_off = |on| if on { "ON" } else { "OFF" };
+        let fields = [
+            format!("WAVE     < {} >", self.preset.wave),
+            format!("SHIPS    < {} >", self.preset.ships),
+            format!("SHIELDS  < {} >", on_off(self.preset.shields)),
+            format!("RETRY    < {} >", on_off(self.preset.retry)),
+        ];
+
+        let mut lines = vec!["PRACTICE MODE".to_string(), String::new()];
+        lines.extend(fields.iter().enumerate().map(|(i, field)| {
+            format!("{} {}", if i == self.selected { ">" } else { " " }, field)
+        }));
+        lines.push(String::new());
+        lines.push("ENTER START  DEL STOP".to_string());
+        lines.push("ESC CLOSE".to_string());
+        lines
+    }
+}
@@ -0,0 +1,22 @@
+//! Instruction profile written at exit with `--profile`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use core::Emulator;
+use core::profiler::Profiler;
+
+/// Addresses and opcodes listed in the report.
+const HOTTEST: usize = 32;
+
+/// Starts profiling the instructions `emulator` runs.
+pub fn start(emulator: &mut Emulator) {
+    emulator.cpu_mut().set_profiler(Some(Profiler::new()));
+}
+
+/// Writes the hottest addresses and opcodes, see [`Profiler::report`].
+pub fn write(path: &Path, emulator: &Emulator) -> io::Result<()> {
+    let Some(profiler) = emulator.cpu().profiler() else { return Ok(()) };
+    fs::write(path, profiler.report(&emulator.cpu().memory, HOTTEST))
+}
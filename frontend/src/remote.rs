@@ -0,0 +1,342 @@
+//! WebSocket server accepting JSON commands to control and debug the running emulator.
+//!
+//! Commands are objects tagged by `cmd`, e.g.
+//! `{"cmd": "read_memory", "address": 8192, "length": 16}`. Every command gets a reply, and
+//! emulator events are pushed to all connected clients.
+//!
+//! Plain HTTP requests on the same port are answered too, for tools that only want a picture or
+//! a state: `GET /screenshot.png` and `GET /state`.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message as WsMessage, WebSocket};
+
+use core::{Button, Emulator, EmulatorEvent, ExecutionStatus, FrameSink};
+use core::debugger::{Breakpoint, Debugger, History, StepResult};
+
+use crate::overlay::ColorOverlay;
+use crate::{png, screenshot, HEIGHT, WIDTH};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    Pause,
+    Resume,
+    Step {
+        #[serde(default = "default_step_count")]
+        count: u32,
+    },
+    StepBack {
+        #[serde(default = "default_step_count")]
+        count: u32,
+    },
+    ReadMemory { address: u16, length: u16 },
+    WriteMemory { address: u16, data: Vec<u8> },
+    SetBreakpoint {
+        address: u16,
+        #[serde(flatten)]
+        breakpoint: Breakpoint,
+    },
+    ClearBreakpoint { address: u16 },
+    Press { button: Button },
+    Release { button: Button },
+    SaveState,
+    LoadState { data: Vec<u8> },
+    Screenshot,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Ok,
+    Error { message: String },
+    Memory { address: u16, data: Vec<u8> },
+    Stepped { pc: u16 },
+    Paused { paused: bool },
+    Breakpoint { address: u16 },
+    Log { line: String },
+    Event { event: EmulatorEvent },
+    State { data: Vec<u8> },
+    Screenshot { png: Vec<u8> },
+}
+
+/// Instructions between history snapshots. Interrupts add two more snapshots per frame.
+const HISTORY_INTERVAL: u64 = 10_000;
+/// Roughly the last two seconds of execution.
+const HISTORY_SNAPSHOTS: usize = 600;
+/// Largest HTTP request head read before deciding between HTTP and a WebSocket handshake.
+const MAX_REQUEST_HEAD: usize = 4096;
+
+struct Request {
+    command: Command,
+    reply: Sender<Message>,
+}
+
+pub struct RemoteServer {
+    requests: Receiver<Request>,
+    clients: Arc<Mutex<Vec<Sender<Message>>>>,
+    debugger: Debugger,
+}
+
+impl RemoteServer {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (request_tx, requests) = mpsc::channel();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accept_clients = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let request_tx = request_tx.clone();
+                let clients = Arc::clone(&accept_clients);
+                thread::spawn(move || {
+                    if let Err(e) = serve_client(stream, request_tx, &clients) {
+                        eprintln!("remote client disconnected: {}", e);
+                    }
+                });
+            }
+        });
+
+        let mut debugger = Debugger::new();
+        debugger.enable_history(History::new(HISTORY_INTERVAL, HISTORY_SNAPSHOTS));
+
+        Ok(Self { requests, clients, debugger })
+    }
+
+    /// Executes every command received since the last call, taking screenshots through
+    /// `overlay`. Returns whether a client loaded a state.
+    pub fn poll(&mut self, emulator: &mut Emulator, paused: &mut bool, overlay: &ColorOverlay) -> bool {
+        let mut loaded = false;
+        while let Ok(Request { command, reply }) = self.requests.try_recv() {
+            let load = matches!(command, Command::LoadState { .. });
+            let message = self.execute(command, emulator, paused, overlay);
+            loaded |= load && matches!(message, Message::Ok);
+            let _ = reply.send(message);
+        }
+        loaded
+    }
+
+    /// Runs the actions of the breakpoint at the current PC and returns whether execution should
+    /// pause.
+    pub fn check_breakpoint(&mut self, emulator: &mut Emulator) -> bool {
+        let stop = self.debugger.check(emulator);
+
+        for line in self.debugger.take_output() {
+            self.broadcast(&Message::Log { line });
+        }
+        if stop {
+            self.broadcast(&Message::Breakpoint { address: emulator.cpu().pc() });
+        }
+
+        stop
+    }
+
+    pub fn broadcast(&self, message: &Message) {
+        self.clients.lock().unwrap().retain(|client| client.send(message.clone()).is_ok());
+    }
+
+    pub fn broadcast_event(&self, event: &EmulatorEvent) {
+        match event {
+            EmulatorEvent::VBlank | EmulatorEvent::FrameComplete | EmulatorEvent::Debug(_) => {}
+            event => self.broadcast(&Message::Event { event: event.clone() }),
+        }
+    }
+
+    fn execute(&mut self, command: Command, emulator: &mut Emulator, paused: &mut bool, overlay: &ColorOverlay) -> Message {
+        match command {
+            Command::Pause | Command::Resume => {
+                *paused = matches!(command, Command::Pause);
+                self.broadcast(&Message::Paused { paused: *paused });
+                Message::Ok
+            }
+            Command::Step { count } => {
+                for _ in 0..count {
+                    match self.debugger.step(emulator) {
+                        Ok(StepResult::Executed(ExecutionStatus::Continue(_) | ExecutionStatus::PortBreak { .. })) => {}
+                        Ok(StepResult::Executed(ExecutionStatus::Halt)) => break,
+                        Ok(StepResult::Break(address) | StepResult::Executed(ExecutionStatus::Breakpoint(address))) => {
+                            self.broadcast(&Message::Breakpoint { address });
+                            break;
+                        }
+                        Err(e) => return Message::Error { message: e.to_string() },
+                    }
+
+                    while let Some(event) = emulator.event() {
+                        self.broadcast_event(&event);
+                    }
+                }
+                Message::Stepped { pc: emulator.cpu().pc() }
+            }
+            Command::StepBack { count } => {
+                for _ in 0..count {
+                    match self.debugger.step_back(emulator) {
+                        Ok(true) => {}
+                        Ok(false) => return Message::Error { message: "no earlier history recorded".to_string() },
+                        Err(e) => return Message::Error { message: e.to_string() },
+                    }
+                }
+                Message::Stepped { pc: emulator.cpu().pc() }
+            }
+            Command::ReadMemory { address, length } => {
+                let data = (0..length).map(|i| emulator.read_memory(address.wrapping_add(i))).collect();
+                Message::Memory { address, data }
+            }
+            Command::WriteMemory { address, data } => {
+                let addresses = (0..data.len() as u16).map(|i| address.wrapping_add(i));
+                if let Some(at) = addresses.clone().find(|&at| !emulator.cpu().memory.writable(at)) {
+                    return Message::Error { message: format!("cannot write to ${:04X}, it's not RAM", at) };
+                }
+
+                for (at, val) in addresses.zip(data) {
+                    emulator.write_memory(at, val);
+                }
+                Message::Ok
+            }
+            Command::SetBreakpoint { address, breakpoint } => {
+                self.debugger.set_breakpoint(address, breakpoint);
+                Message::Ok
+            }
+            Command::ClearBreakpoint { address } => {
+                self.debugger.remove_breakpoint(address);
+                Message::Ok
+            }
+            Command::Press { button } => {
+                emulator.button_press(button);
+                Message::Ok
+            }
+            Command::Release { button } => {
+                emulator.button_release(button);
+                Message::Ok
+            }
+            Command::SaveState => Message::State { data: emulator.save_state_bytes() },
+            Command::LoadState { data } => match emulator.load_state_bytes(&data) {
+                Ok(()) => Message::Ok,
+                Err(e) => Message::Error { message: e.to_string() },
+            },
+            Command::Screenshot => {
+                let image = screenshot(emulator.video_ram(), overlay);
+                Message::Screenshot { png: png::encode(&image, WIDTH, HEIGHT) }
+            }
+        }
+    }
+}
+
+fn serve_client(stream: TcpStream, requests: Sender<Request>, clients: &Mutex<Vec<Sender<Message>>>) -> Result<(), String> {
+    let head = peek_head(&stream).map_err(|e| e.to_string())?;
+    if !head.to_ascii_lowercase().contains("upgrade: websocket") {
+        return serve_http(stream, &head, &requests).map_err(|e| e.to_string());
+    }
+
+    let (reply, outgoing) = mpsc::channel();
+    clients.lock().unwrap().push(reply.clone());
+
+    let mut socket = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    socket.get_ref().set_read_timeout(Some(Duration::from_millis(10))).map_err(|e| e.to_string())?;
+
+    loop {
+        match socket.read() {
+            Ok(WsMessage::Text(text)) => {
+                match serde_json::from_str::<Command>(&text) {
+                    Ok(command) => {
+                        let request = Request { command, reply: reply.clone() };
+                        requests.send(request).map_err(|e| e.to_string())?;
+                    }
+                    Err(e) => send(&mut socket, &Message::Error { message: e.to_string() })?,
+                }
+            }
+            Ok(WsMessage::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+
+        while let Ok(message) = outgoing.try_recv() {
+            send(&mut socket, &message)?;
+        }
+    }
+}
+
+/// Waits for the head of the client's first request without consuming it, so a WebSocket
+/// handshake can still read it.
+fn peek_head(stream: &TcpStream) -> io::Result<String> {
+    let mut buf = [0; MAX_REQUEST_HEAD];
+    loop {
+        let len = stream.peek(&mut buf)?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(end) = buf[..len].windows(4).position(|window| window == b"\r\n\r\n") {
+            return Ok(String::from_utf8_lossy(&buf[..end + 4]).into_owned());
+        }
+        if len == buf.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too long"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Answers a single plain HTTP request, running its command like a WebSocket client's.
+fn serve_http(mut stream: TcpStream, head: &str, requests: &Sender<Request>) -> io::Result<()> {
+    stream.read_exact(&mut vec![0; head.len()])?;
+
+    let mut request_line = head.split_whitespace();
+    let (command, content_type) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/screenshot.png")) => (Command::Screenshot, "image/png"),
+        (Some("GET"), Some("/state")) => (Command::SaveState, "application/octet-stream"),
+        _ => return respond(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    };
+
+    let (reply, response) = mpsc::channel();
+    let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "emulator closed");
+    requests.send(Request { command, reply }).map_err(|_| closed())?;
+    match response.recv().map_err(|_| closed())? {
+        Message::Screenshot { png: body } | Message::State { data: body } => respond(&mut stream, "200 OK", content_type, &body),
+        message => respond(&mut stream, "500 Internal Server Error", "application/json", encode(&message).as_bytes()),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    stream.write_all(body)
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: &Message) -> Result<(), String> {
+    socket.send(WsMessage::Text(encode(message))).map_err(|e| e.to_string())
+}
+
+fn encode(message: &Message) -> String {
+    serde_json::to_string(message).expect("could not serialize remote message")
+}
+
+fn default_step_count() -> u32 { 1 }
+
+/// Runs a frame for the window, pushing its events to the remote clients, if any, before handing
+/// them to `on_event`, and stopping it at their breakpoints.
+pub struct RemoteSink<'a, F> {
+    remote: Option<&'a mut RemoteServer>,
+    on_event: F,
+}
+
+impl<'a, F: FnMut(&mut Emulator, EmulatorEvent)> RemoteSink<'a, F> {
+    pub fn new(remote: Option<&'a mut RemoteServer>, on_event: F) -> Self {
+        Self { remote, on_event }
+    }
+}
+
+impl<F: FnMut(&mut Emulator, EmulatorEvent)> FrameSink for RemoteSink<'_, F> {
+    fn event(&mut self, emulator: &mut Emulator, event: EmulatorEvent) {
+        if let Some(remote) = &self.remote {
+            remote.broadcast_event(&event);
+        }
+        (self.on_event)(emulator, event);
+    }
+
+    fn stop(&mut self, emulator: &mut Emulator) -> bool {
+        self.remote.as_deref_mut().is_some_and(|remote| remote.check_breakpoint(emulator))
+    }
+}
@@ -0,0 +1,203 @@
+//! Loading ROM images from disk: a single file, a MAME-style set split into one file per chip
+//! (`invaders.h`, `.g`, `.f`, `.e`), either named directly or as the directory holding them, or
+//! a zipped set.
+
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+use core::games::{self, RomSet, SetInfo, ROM_SIZE};
+
+#[derive(Debug, Clone)]
+pub struct LoadedRom {
+    pub data: Vec<u8>,
+    /// Set recognized from the ROM hash, or else from the file names.
+    pub set: Option<&'static SetInfo>,
+    pub warnings: Vec<String>,
+}
+
+/// Reads a ROM image. Split and zipped sets are assembled from their parts. Parts that don't match
+/// the known good dump, and images that don't match any, are reported as warnings.
+pub fn load(path: &Path) -> Result<LoadedRom, String> {
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+    let path_name = path.to_string_lossy();
+
+    let mut loaded = if path.is_dir() {
+        let files = read_parts(path, |_| true)?;
+        load_parts(files, &path_name).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else if let Some((game, _)) = games::find_part(&path_name) {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let files = read_parts(dir, |name| game.parts.iter().any(|part| part.name.eq_ignore_ascii_case(name)))?;
+        load_parts(files, &path_name).map_err(|e| format!("{}: {} (the other parts are read from the same directory)", path.display(), e))?
+    } else {
+        let data = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        if is_zip(path) {
+            load_zip(&data, &path_name).map_err(|e| format!("{}: {}", path.display(), e))?
+        } else if data.is_empty() {
+            return Err(format!("{} is empty", path.display()));
+        } else if data.len() > ROM_SIZE {
+            return Err(format!("{} is too big to be a ROM image ({} bytes)", path.display(), data.len()));
+        } else {
+            LoadedRom { set: games::recognize([path_name.as_ref()]), data, warnings: Vec::new() }
+        }
+    };
+
+    match games::identify(&loaded.data) {
+        Some(game) => loaded.set = game.set().or(loaded.set),
+        // Bad parts of a known set are already reported one by one
+        None if loaded.warnings.is_empty() => loaded.warnings.push(format!(
+            "{} (CRC32 {:08X}) doesn't match any known good dump, running it unverified",
+            path.display(), core::crc32(&loaded.data),
+        )),
+        None => {}
+    }
+    if let Some(set @ SetInfo { profile: None, .. }) = loaded.set {
+        return Err(format!("{}: {}", path.display(), unsupported(set)));
+    }
+
+    Ok(loaded)
+}
+
+pub fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+fn load_zip(data: &[u8], zip_name: &str) -> Result<LoadedRom, String> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let mut files = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+        // Skip directories and anything too big to be a ROM chip
+        if file.is_dir() || file.size() > ROM_SIZE as u64 {
+            continue;
+        }
+
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut contents).map_err(|e| e.to_string())?;
+        files.push((file.name().to_string(), contents));
+    }
+
+    load_parts(files, zip_name)
+}
+
+/// Reads the files in `dir` small enough to be ROM chips whose names pass `filter`.
+fn read_parts(dir: &Path, filter: impl Fn(&str) -> bool) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let read_dir = fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    let mut files = Vec::new();
+
+    for entry in read_dir {
+        let path = entry.map_err(|e| format!("could not read {}: {}", dir.display(), e))?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let is_part = fs::metadata(&path).is_ok_and(|metadata| metadata.is_file() && metadata.len() <= ROM_SIZE as u64);
+        if is_part && filter(&name) {
+            let data = fs::read(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            files.push((name, data));
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Assembles a set from its parts, named `set_name` or after the files in it.
+fn load_parts(files: Vec<(String, Vec<u8>)>, set_name: &str) -> Result<LoadedRom, String> {
+    let names = std::iter::once(set_name).chain(files.iter().map(|(name, _)| name.as_str()));
+    let set = games::recognize(names);
+    if let Some(set @ SetInfo { profile: None, .. }) = set {
+        return Err(unsupported(set));
+    }
+
+    let parts: Vec<_> = files.iter().map(|(name, data)| (name.as_str(), data.as_slice())).collect();
+    match games::assemble(&parts) {
+        Ok(RomSet { rom, game, bad_parts }) => {
+            let warnings = bad_parts.iter().map(|part| format!("{} does not match the known {} dump", part, game.name)).collect();
+            Ok(LoadedRom { data: rom, set: game.set().or(set), warnings })
+        }
+        // Sets whose chip layout isn't known can still be loaded from a single full image
+        Err(e) => match files.as_slice() {
+            [(_, image)] if image.len() == ROM_SIZE => Ok(LoadedRom { data: image.clone(), set, warnings: Vec::new() }),
+            _ => match set {
+                Some(set) => Err(format!("{} ({})", e, set.title)),
+                None => Err(e.to_string()),
+            },
+        },
+    }
+}
+
+fn unsupported(set: &SetInfo) -> String {
+    format!("{} needs hardware that isn't emulated", set.title)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const ROM: &[u8] = include_bytes!("../assets/invaders");
+
+    /// Empty directory for a test's files, named after it.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("space-invaders-rom-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes the parts of the split set to `dir`, cut to the given lengths.
+    fn write_parts(dir: &Path, lengths: [usize; 4]) {
+        for (i, (name, len)) in ["invaders.h", "invaders.g", "invaders.f", "invaders.e"].into_iter().zip(lengths).enumerate() {
+            fs::write(dir.join(name), &ROM[i * 0x800..i * 0x800 + len]).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_split_set() {
+        let dir = test_dir("split");
+        write_parts(&dir, [0x800; 4]);
+
+        let rom = load(&dir.join("invaders.h")).unwrap();
+        assert_eq!(rom.data, ROM);
+        assert!(rom.warnings.is_empty());
+        assert_eq!(load(&dir).unwrap().data, ROM);
+
+        fs::remove_file(dir.join("invaders.e")).unwrap();
+        let error = load(&dir.join("invaders.g")).unwrap_err();
+        assert!(error.contains("invaders set is missing invaders.e"), "{}", error);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_short_part() {
+        let dir = test_dir("short-part");
+        write_parts(&dir, [0x800, 0x800, 0x400, 0x800]);
+
+        let error = load(&dir.join("invaders.h")).unwrap_err();
+        assert!(error.contains("invaders.f should be 2048 bytes, found 1024"), "{}", error);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_bad_file() {
+        let dir = test_dir("bad-file");
+
+        let error = load(&dir.join("missing.bin")).unwrap_err();
+        assert!(error.ends_with("missing.bin does not exist"), "{}", error);
+
+        fs::write(dir.join("empty.bin"), []).unwrap();
+        let error = load(&dir.join("empty.bin")).unwrap_err();
+        assert!(error.ends_with("empty.bin is empty"), "{}", error);
+
+        fs::write(dir.join("big.bin"), vec![0; ROM_SIZE + 1]).unwrap();
+        let error = load(&dir.join("big.bin")).unwrap_err();
+        assert!(error.contains("too big to be a ROM image"), "{}", error);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
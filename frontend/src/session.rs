@@ -0,0 +1,109 @@
+//! Statistics about the current session, derived from game RAM once per frame.
+
+use std::time::{Duration, Instant};
+use core::SpaceInvadersState;
+use crate::FPS;
+
+/// Values sampled at the end of a frame.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    in_game: bool,
+    coins: u32,
+    aliens: u8,
+    shot_active: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    start: Instant,
+    game_frames: u64,
+    credits_used: u32,
+    waves_cleared: u32,
+    shots_fired: u32,
+    hits: u32,
+    best_score: u32,
+    last: Option<Sample>,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            game_frames: 0,
+            credits_used: 0,
+            waves_cleared: 0,
+            shots_fired: 0,
+            hits: 0,
+            best_score: 0,
+            last: None,
+        }
+    }
+
+    /// Must be called once per emulated frame.
+    pub fn update(&mut self, state: &SpaceInvadersState) {
+        let sample = Sample {
+            in_game: state.in_game,
+            coins: state.credits,
+            aliens: state.num_aliens,
+            shot_active: state.player_shot.is_some(),
+        };
+
+        self.best_score = state.scores.into_iter().fold(self.best_score, u32::max);
+
+        if let Some(last) = self.last.filter(|_| sample.in_game) {
+            self.game_frames += 1;
+
+            if sample.shot_active && !last.shot_active {
+                self.shots_fired += 1;
+            }
+            // Only one alien can die per frame; bigger changes happen when the players swap racks
+            if last.aliens > 0 && sample.aliens == last.aliens - 1 {
+                self.hits += 1;
+                if sample.aliens == 0 {
+                    self.waves_cleared += 1;
+                }
+            }
+        }
+
+        if let Some(last) = self.last {
+            self.credits_used += last.coins.saturating_sub(sample.coins);
+        }
+
+        self.last = Some(sample);
+    }
+
+    pub fn play_time(&self) -> Duration {
+        Duration::from_secs_f64(self.game_frames as f64 / FPS)
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.shots_fired as f64
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("SESSION       {}", format_duration(self.start.elapsed())),
+            format!("PLAY TIME     {}", format_duration(self.play_time())),
+            format!("CREDITS USED  {}", self.credits_used),
+            format!("WAVES CLEARED {}", self.waves_cleared),
+            format!("SHOTS FIRED   {}", self.shots_fired),
+            format!("HIT RATE      {:.1}%", self.hit_rate() * 100.0),
+            format!("BEST SCORE    {:04}", self.best_score),
+        ]
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
@@ -0,0 +1,38 @@
+//! Turns SIGINT, SIGTERM and SIGHUP into a flag the main loops poll, so the emulator stops at a
+//! frame boundary and saves everything instead of being killed halfway through a write.
+//!
+//! A second signal while shutting down exits right away, in case the shutdown itself hangs.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn install() -> io::Result<Self> {
+        let requested = Arc::new(AtomicBool::new(false));
+
+        #[cfg(unix)]
+        let signals = [SIGINT, SIGTERM, signal_hook::consts::SIGHUP];
+        #[cfg(not(unix))]
+        let signals = [SIGINT, SIGTERM];
+
+        for signal in signals {
+            // Registered first, so it only exits if the flag was already set by an earlier signal
+            flag::register_conditional_shutdown(signal, 1, Arc::clone(&requested))?;
+            flag::register(signal, Arc::clone(&requested))?;
+        }
+        Ok(Self { requested })
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,105 @@
+//! Save-state slots, saved with Ctrl+0..9 and loaded with Ctrl+Shift+0..9, and the state saved
+//! automatically at exit that `--resume` continues from.
+//!
+//! Slot `n` of the ROM with CRC32 `crc` is the file `<crc>-<n>.state` in the state directory and
+//! the automatic one is `<crc>-auto.state`, both in the [`core::savestate`] format, so they
+//! survive restarts and every ROM keeps its own.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use core::Emulator;
+
+pub const SLOTS: u8 = 10;
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct Slots {
+    dir: PathBuf,
+    rom_crc: u32,
+    /// Outcome of the last save or load, and when it happened.
+    message: Option<(String, Instant)>,
+}
+
+impl Slots {
+    pub fn new(dir: PathBuf, rom_crc: u32) -> Self {
+        Self { dir, rom_crc, message: None }
+    }
+
+    pub fn path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("{:08X}-{}.state", self.rom_crc, slot))
+    }
+
+    pub fn autosave_path(&self) -> PathBuf {
+        self.dir.join(format!("{:08X}-auto.state", self.rom_crc))
+    }
+
+    /// Writes the state of `emulator` to `slot`, replacing what was there.
+    pub fn save(&mut self, slot: u8, emulator: &Emulator) -> Result<(), String> {
+        let result = self.write(&self.path(slot), emulator);
+        self.show(match result {
+            Ok(()) => format!("SAVED SLOT {}", slot),
+            Err(_) => format!("COULD NOT SAVE SLOT {}", slot),
+        });
+        result
+    }
+
+    /// Restores `slot` into `emulator`, returning whether there was a state to restore. The
+    /// emulator is left untouched if the slot is empty or can't be loaded.
+    pub fn load(&mut self, slot: u8, emulator: &mut Emulator) -> Result<bool, String> {
+        let result = read(&self.path(slot), emulator);
+        self.show(match result {
+            Ok(true) => format!("LOADED SLOT {}", slot),
+            Ok(false) => format!("SLOT {} IS EMPTY", slot),
+            Err(_) => format!("COULD NOT LOAD SLOT {}", slot),
+        });
+        result
+    }
+
+    /// Writes the state of `emulator` for [`Slots::resume`] to pick up on the next launch.
+    pub fn autosave(&self, emulator: &Emulator) -> Result<(), String> {
+        self.write(&self.autosave_path(), emulator)
+    }
+
+    /// Restores the state written by [`Slots::autosave`], returning whether there was one.
+    pub fn resume(&self, emulator: &mut Emulator) -> Result<bool, String> {
+        read(&self.autosave_path(), emulator)
+    }
+
+    /// Outcome of the last save or load, for a couple of seconds after it happened.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref()
+            .filter(|(_, shown)| shown.elapsed() < MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+
+    fn show(&mut self, message: String) {
+        self.message = Some((message, Instant::now()));
+    }
+
+    /// Writes to a temporary file first, so a failed save doesn't destroy the state at `path`.
+    fn write(&self, path: &Path, emulator: &Emulator) -> Result<(), String> {
+        let write_state = || -> core::Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            let temp = path.with_extension("tmp");
+            let mut writer = BufWriter::new(File::create(&temp)?);
+            emulator.save_state(&mut writer)?;
+            writer.flush()?;
+            fs::rename(&temp, path)?;
+            Ok(())
+        };
+        write_state().map_err(|e| format!("could not write {}: {}", path.display(), e))
+    }
+}
+
+fn read(path: &Path, emulator: &mut Emulator) -> Result<bool, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(format!("could not read {}: {}", path.display(), e)),
+    };
+    emulator.load_state(&mut BufReader::new(file)).map_err(|e| format!("could not load {}: {}", path.display(), e))?;
+    Ok(true)
+}
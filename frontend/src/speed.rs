@@ -0,0 +1,68 @@
+//! Emulation speed, from slow motion to fast-forward. Frames of the game are always emulated
+//! whole, so a speed other than 1x changes how many of them run per frame shown on screen.
+
+use std::time::{Duration, Instant};
+
+/// Speeds the hotkeys step through, slowest first.
+pub const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+pub const MIN_SPEED: f64 = SPEEDS[0];
+pub const MAX_SPEED: f64 = SPEEDS[SPEEDS.len() - 1];
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct Speed {
+    factor: f64,
+    /// Fraction of a game frame owed from earlier ones, so 0.25x runs one every fourth.
+    pending: f64,
+    changed: Option<Instant>,
+}
+
+impl Speed {
+    /// `factor` is clamped to [`MIN_SPEED`]..=[`MAX_SPEED`].
+    pub fn new(factor: f64) -> Self {
+        Self { factor: factor.clamp(MIN_SPEED, MAX_SPEED), pending: 0.0, changed: None }
+    }
+
+    pub fn factor(&self) -> f64 {
+        self.factor
+    }
+
+    pub fn is_fast_forward(&self) -> bool {
+        self.factor > 1.0
+    }
+
+    /// Steps up to the next of [`SPEEDS`], returning whether the speed changed.
+    pub fn faster(&mut self) -> bool {
+        let next = SPEEDS.into_iter().find(|&speed| speed > self.factor);
+        self.set(next)
+    }
+
+    /// Steps down to the previous of [`SPEEDS`], returning whether the speed changed.
+    pub fn slower(&mut self) -> bool {
+        let previous = SPEEDS.into_iter().rev().find(|&speed| speed < self.factor);
+        self.set(previous)
+    }
+
+    /// Game frames to run during the next frame shown.
+    pub fn frames(&mut self) -> u32 {
+        self.pending += self.factor;
+        let frames = self.pending.floor();
+        self.pending -= frames;
+        frames as u32
+    }
+
+    /// The new speed, for a couple of seconds after it changed.
+    pub fn message(&self) -> Option<String> {
+        self.changed
+            .filter(|changed| changed.elapsed() < MESSAGE_DURATION)
+            .map(|_| format!("SPEED {}X", self.factor))
+    }
+
+    fn set(&mut self, factor: Option<f64>) -> bool {
+        let Some(factor) = factor else { return false };
+        self.factor = factor;
+        self.pending = 0.0;
+        self.changed = Some(Instant::now());
+        true
+    }
+}
@@ -0,0 +1,115 @@
+//! Speedrun timer driven by game RAM: a run starts with a game, splits on every cleared wave and
+//! stops at game over.
+//!
+//! Time is counted in emulated frames, so slowdowns and pauses don't affect it. Timer events can
+//! also be forwarded to a LiveSplit Server component over TCP.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::Path;
+
+use core::SpaceInvadersState;
+use crate::FPS;
+
+/// Splits shown in the overlay, most recent last.
+const VISIBLE_SPLITS: usize = 5;
+
+#[derive(Debug)]
+pub struct Speedrun {
+    /// Frames since the current run started.
+    frames: u64,
+    running: bool,
+    /// Run time at each cleared wave.
+    splits: Vec<u64>,
+    /// Game mode and number of aliens on the previous frame.
+    last: Option<(bool, u8)>,
+    livesplit: Option<TcpStream>,
+}
+
+impl Speedrun {
+    pub fn new() -> Self {
+        Self { frames: 0, running: false, splits: Vec::new(), last: None, livesplit: None }
+    }
+
+    /// Forwards starts, splits and game overs to a LiveSplit Server at `address` (`host:port`).
+    pub fn connect_livesplit(&mut self, address: &str) -> io::Result<()> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        self.livesplit = Some(stream);
+        Ok(())
+    }
+
+    /// Must be called once per emulated frame. Fails if LiveSplit can't be reached anymore, in
+    /// which case the connection is dropped.
+    pub fn update(&mut self, state: &SpaceInvadersState) -> io::Result<()> {
+        let in_game = state.in_game;
+        let aliens = state.num_aliens;
+        let Some((was_in_game, last_aliens)) = self.last.replace((in_game, aliens)) else { return Ok(()) };
+
+        if self.running {
+            self.frames += 1;
+        }
+
+        if in_game && !was_in_game {
+            self.frames = 0;
+            self.running = true;
+            self.splits.clear();
+            self.send(&["reset", "starttimer"])?;
+        } else if !in_game && was_in_game && self.running {
+            self.running = false;
+            self.send(&["pause"])?;
+        } else if self.running && last_aliens == 1 && aliens == 0 {
+            self.splits.push(self.frames);
+            self.send(&["split"])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let state = if self.running { "" } else { " (STOPPED)" };
+        let mut lines = vec![format!("TIME    {}{}", format_time(self.frames), state)];
+
+        let first = self.splits.len().saturating_sub(VISIBLE_SPLITS);
+        for (i, &split) in self.splits.iter().enumerate().skip(first) {
+            lines.push(format!("WAVE {:<2} {}", i + 1, format_time(split)));
+        }
+        lines
+    }
+
+    /// Appends the splits of the current (or last) run to `path`, one line per wave with the
+    /// total and segment time.
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "# {} waves, {}", self.splits.len(), format_time(self.frames))?;
+
+        let mut previous = 0;
+        for (i, &split) in self.splits.iter().enumerate() {
+            writeln!(file, "wave {:<2} {:>10} {:>10}", i + 1, format_time(split), format_time(split - previous))?;
+            previous = split;
+        }
+        writeln!(file)
+    }
+
+    fn send(&mut self, commands: &[&str]) -> io::Result<()> {
+        let Some(stream) = &mut self.livesplit else { return Ok(()) };
+        let result = commands.iter().try_for_each(|command| write!(stream, "{}\r\n", command));
+        if result.is_err() {
+            self.livesplit = None;
+        }
+        result
+    }
+}
+
+impl Default for Speedrun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `m:ss.cc` of a frame count.
+fn format_time(frames: u64) -> String {
+    let centis = (frames as f64 / FPS * 100.0) as u64;
+    format!("{}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100)
+}
@@ -0,0 +1,241 @@
+//! Framebuffer streaming over TCP. `--stream <addr>` runs the emulator without a window and sends
+//! every frame to the viewers connected to it, which show it with `--view <addr>` and send back
+//! the buttons held on their keyboard.
+//!
+//! Every message goes out with its length before it, as a little-endian u32, and starts with a
+//! tag byte:
+//!
+//! ```text
+//! server -> viewer
+//!   0  frame       the screen as the emulator keeps it, 1 bit per pixel, 7168 bytes
+//!   1  play sound  sound, u8 in `SOUNDS` order
+//!   2  stop sound  sound, u8
+//! viewer -> server
+//!   0  buttons     held buttons, u16 `Buttons` bits
+//! ```
+//!
+//! Frames are only sent when the picture changed, so a still screen costs nothing.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use core::{Buttons, EmulatorEvent, Sound};
+
+const FRAME: u8 = 0;
+const PLAY_SOUND: u8 = 1;
+const STOP_SOUND: u8 = 2;
+const BUTTONS: u8 = 0;
+/// Largest message accepted, a frame with its tag.
+const MAX_MESSAGE_LEN: usize = 1 + (crate::WIDTH * crate::HEIGHT / 8) as usize;
+/// How long a viewer may stall the server before it's dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sounds in the order of their ids on the wire.
+const SOUNDS: [Sound; 9] = [
+    Sound::UFO,
+    Sound::Shoot,
+    Sound::PlayerDie,
+    Sound::InvaderDie,
+    Sound::Bomp1,
+    Sound::Bomp2,
+    Sound::Bomp3,
+    Sound::Bomp4,
+    Sound::UFOExplode,
+];
+
+pub struct StreamServer {
+    viewers: Arc<Mutex<Vec<TcpStream>>>,
+    /// Set when a viewer connects, so the next frame goes out even if it didn't change.
+    new_viewer: Arc<AtomicBool>,
+    buttons: Receiver<Buttons>,
+    last_frame: Vec<u8>,
+}
+
+impl StreamServer {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let viewers = Arc::new(Mutex::new(Vec::new()));
+        let new_viewer = Arc::new(AtomicBool::new(false));
+        let (tx, buttons) = mpsc::channel();
+
+        let accept_viewers = Arc::clone(&viewers);
+        let accept_new_viewer = Arc::clone(&new_viewer);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(mut reader) = stream.try_clone() else { continue };
+                if stream.set_nodelay(true).and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT))).is_err() {
+                    continue;
+                }
+                if let Ok(peer) = stream.peer_addr() {
+                    println!("Viewer connected from {}", peer);
+                }
+                accept_viewers.lock().unwrap().push(stream);
+                accept_new_viewer.store(true, Ordering::Relaxed);
+
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    while let Ok(message) = read_message(&mut reader) {
+                        if let [BUTTONS, low, high] = message[..] {
+                            if tx.send(Buttons::from_bits_truncate(u16::from_le_bytes([low, high]))).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    // Let go of whatever the viewer held when it left
+                    let _ = tx.send(Buttons::empty());
+                });
+            }
+        });
+
+        Ok(Self { viewers, new_viewer, buttons, last_frame: Vec::new() })
+    }
+
+    /// Buttons the viewers last sent, if they sent any since the last call.
+    pub fn buttons(&self) -> Option<Buttons> {
+        self.buttons.try_iter().last()
+    }
+
+    /// Sends `screen` to every viewer unless they already have it.
+    pub fn send_frame(&mut self, screen: &[u8]) {
+        if self.last_frame == screen && !self.new_viewer.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        self.last_frame = screen.to_vec();
+
+        let mut message = vec![FRAME];
+        message.extend_from_slice(screen);
+        self.broadcast(&message);
+    }
+
+    /// Forwards sound events to the viewers, ignoring everything else.
+    pub fn send_event(&self, event: &EmulatorEvent) {
+        let (tag, sound) = match event {
+            EmulatorEvent::PlaySound(sound) => (PLAY_SOUND, sound),
+            EmulatorEvent::StopSound(sound) => (STOP_SOUND, sound),
+            _ => return,
+        };
+        self.broadcast(&[tag, sound_id(sound)]);
+    }
+
+    fn broadcast(&self, message: &[u8]) {
+        self.viewers.lock().unwrap().retain_mut(|viewer| match write_message(viewer, message) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Viewer disconnected: {}", e);
+                false
+            }
+        });
+    }
+}
+
+/// What the server sent a viewer.
+#[derive(Debug, Clone)]
+pub enum Update {
+    Frame(Vec<u8>),
+    PlaySound(Sound),
+    StopSound(Sound),
+}
+
+pub struct StreamViewer {
+    stream: TcpStream,
+    incoming: Receiver<Result<Update, String>>,
+}
+
+impl StreamViewer {
+    pub fn connect(addr: &str) -> Result<Self, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| format!("could not connect to {}: {}", addr, e))?;
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+
+        let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let (tx, incoming) = mpsc::channel();
+        thread::spawn(move || loop {
+            let update = read_message(&mut reader)
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::UnexpectedEof => "the stream ended".to_string(),
+                    _ => format!("lost connection to the stream: {}", e),
+                })
+                .and_then(|message| decode_update(&message));
+            let failed = update.is_err();
+            if tx.send(update).is_err() || failed {
+                break;
+            }
+        });
+
+        Ok(Self { stream, incoming })
+    }
+
+    /// Everything the server sent since the last call, oldest first.
+    pub fn updates(&self) -> Result<Vec<Update>, String> {
+        let mut updates = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(update) => updates.push(update?),
+                Err(TryRecvError::Empty) => return Ok(updates),
+                Err(TryRecvError::Disconnected) => return Err("the stream ended".to_string()),
+            }
+        }
+    }
+
+    pub fn send_buttons(&mut self, buttons: Buttons) -> Result<(), String> {
+        let [low, high] = buttons.bits().to_le_bytes();
+        write_message(&mut self.stream, &[BUTTONS, low, high]).map_err(|e| format!("lost connection to the stream: {}", e))
+    }
+}
+
+impl Drop for StreamViewer {
+    fn drop(&mut self) {
+        // The reader thread's handle would keep the connection open otherwise
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn decode_update(message: &[u8]) -> Result<Update, String> {
+    match message {
+        [FRAME, screen @ ..] if screen.len() == MAX_MESSAGE_LEN - 1 => Ok(Update::Frame(screen.to_vec())),
+        [PLAY_SOUND, id] => sound(*id).map(Update::PlaySound),
+        [STOP_SOUND, id] => sound(*id).map(Update::StopSound),
+        _ => Err("malformed stream message".to_string()),
+    }
+}
+
+fn sound_id(sound: &Sound) -> u8 {
+    match sound {
+        Sound::UFO => 0,
+        Sound::Shoot => 1,
+        Sound::PlayerDie => 2,
+        Sound::InvaderDie => 3,
+        Sound::Bomp1 => 4,
+        Sound::Bomp2 => 5,
+        Sound::Bomp3 => 6,
+        Sound::Bomp4 => 7,
+        Sound::UFOExplode => 8,
+    }
+}
+
+fn sound(id: u8) -> Result<Sound, String> {
+    SOUNDS.get(id as usize).cloned().ok_or_else(|| format!("unknown sound {} in stream", id))
+}
+
+fn write_message(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+    let mut frame = (message.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(message);
+    stream.write_all(&frame)
+}
+
+fn read_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("stream message too long ({} bytes)", len)));
+    }
+
+    let mut message = vec![0; len];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
@@ -0,0 +1,61 @@
+//! Instruction trace written to a file, for diffing execution against other 8080 emulators.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use core::Emulator;
+use core::tracer::Tracer;
+
+/// Entries the tracer holds between writes, comfortably more than the instructions in a frame.
+const TRACE_BUFFER: usize = 1 << 16;
+
+#[derive(Debug)]
+pub struct TraceLog {
+    writer: BufWriter<File>,
+    interval: u64,
+}
+
+impl TraceLog {
+    /// Creates the trace at `path` and starts tracing every `interval`th instruction of `emulator`.
+    pub fn create(path: &Path, interval: u64, emulator: &mut Emulator) -> io::Result<Self> {
+        let log = Self { writer: BufWriter::new(File::create(path)?), interval };
+        emulator.set_tracer(Some(log.tracer()));
+        Ok(log)
+    }
+
+    /// Pauses or resumes tracing, returning whether it's now on. A tracer lost by loading a state
+    /// saved before tracing started is attached again.
+    pub fn toggle(&mut self, emulator: &mut Emulator) -> bool {
+        match emulator.tracer_mut() {
+            Some(tracer) => {
+                tracer.set_enabled(!tracer.enabled());
+                tracer.enabled()
+            }
+            None => {
+                emulator.set_tracer(Some(self.tracer()));
+                true
+            }
+        }
+    }
+
+    /// Writes out the instructions traced since the last call.
+    pub fn write(&mut self, emulator: &mut Emulator) -> io::Result<()> {
+        if let Some(tracer) = emulator.tracer_mut() {
+            for entry in tracer.drain() {
+                writeln!(self.writer, "{}", entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn tracer(&self) -> Tracer {
+        let mut tracer = Tracer::new(TRACE_BUFFER);
+        tracer.set_interval(self.interval);
+        tracer
+    }
+}